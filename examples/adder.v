@@ -0,0 +1,23 @@
+// Structural full adders chained into a 4-bit ripple-carry adder.
+module full_adder(input a, input b, input cin, output sum, output cout);
+    wire p;
+    xor x1(p, a, b);
+    xor x2(sum, p, cin);
+    assign cout = (a & b) | (p & cin);
+endmodule
+
+module adder4(
+    input  [3:0] a,
+    input  [3:0] b,
+    output [3:0] sum,
+    output       cout
+);
+    wire [4:0] carry;
+    assign carry[0] = 1'b0;
+    assign cout = carry[4];
+
+    full_adder fa0(a[0], b[0], carry[0], sum[0], carry[1]);
+    full_adder fa1(.a(a[1]), .b(b[1]), .cin(carry[1]), .sum(sum[1]), .cout(carry[2]));
+    full_adder fa2(.a(a[2]), .b(b[2]), .cin(carry[2]), .sum(sum[2]), .cout(carry[3]));
+    full_adder fa3(.a(a[3]), .b(b[3]), .cin(carry[3]), .sum(sum[3]), .cout(carry[4]));
+endmodule
@@ -0,0 +1,5 @@
+// Is a BCD digit 5 or more? Codes 10-15 never occur, so -O3 may give
+// them any output and the terms for 8 and 9 shrink to d3 alone.
+let d0; let d1; let d2; let d3;
+dontcare(d3 && (d2 || d1));
+five_up = (!d3 && d2 && (d1 || d0)) || (d3 && !d2 && !d1);
@@ -0,0 +1,5 @@
+// `clock(periodTicks)` emits a free-running clock; here it drives a register.
+let d;
+
+let clk = clock(10);
+q = dff(d, clk);
@@ -0,0 +1,4 @@
+// `counter(width, clk)` is an n-bit binary up-counter, bit 0 first.
+let clk;
+
+count = counter(3, clk);
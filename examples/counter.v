@@ -0,0 +1,24 @@
+// Behavioral Verilog: a combinational decoder and a clocked counter with a
+// synchronous reset.
+module blinky(clk, rst, mode, count, led);
+    input clk, rst;
+    input [1:0] mode;
+    output reg [2:0] count;
+    output reg led;
+
+    always @(posedge clk) begin
+        if (rst)
+            count <= 3'd0;
+        else
+            count <= count + 1;
+    end
+
+    always @(*) begin
+        case (mode)
+            2'd0: led = 1'b0;
+            2'd1: led = 1'b1;
+            2'd2: led = count[2];
+            default: led = count == 3'd7;
+        endcase
+    end
+endmodule
@@ -0,0 +1,5 @@
+// `dff(d, clk)` registers each bit of d on the rising edge of clk.
+let d;
+let clk;
+
+q = dff(d, clk);
@@ -0,0 +1,4 @@
+// `divide(clk, n)` slows a clock down by a power of two.
+let master = clock(8);
+
+slow = divide(master, 4);
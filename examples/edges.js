@@ -0,0 +1,5 @@
+// `rising(x)` / `falling(x)` pulse briefly on each transition of x.
+let button;
+
+up = rising(button);
+down = falling(button);
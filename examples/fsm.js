@@ -0,0 +1,20 @@
+// `fsm(clk, [states])` allocates a state register (first state at power-on).
+// A `switch` over it assigns the next state and per-state outputs.
+let clk;
+let go;
+let done;
+
+let state = fsm(clk, ["IDLE", "RUN", "STOP"]);
+switch (state) {
+    case "IDLE":
+        state = go ? "RUN" : "IDLE";
+        break;
+    case "RUN":
+        busy = true;
+        state = done ? "STOP" : "RUN";
+        break;
+    case "STOP":
+        finished = true;
+        state = "IDLE";
+        break;
+}
@@ -0,0 +1,6 @@
+// `jkff(j, k, clk)`: set on J, reset on K, toggle on both, hold otherwise.
+let j;
+let k;
+let clk;
+
+q = jkff(j, k, clk);
@@ -0,0 +1,11 @@
+// `rom(addr, [values])` decodes a constant table; `ram(addr, data, we, clk)`
+// is a bank of registers written on the clock edge while `we` is high.
+let a0;
+let a1;
+let d;
+let we;
+let clk;
+
+let addr = [a0, a1];
+table = rom(addr, [1, 2, 3, 0]);
+stored = ram(addr, d, we, clk);
@@ -0,0 +1,10 @@
+// `popcount(a)` counts set bits with a balanced adder tree; `parity(a)`
+// reduces a bus with a balanced XOR tree.
+let a;
+let b;
+let c;
+let d;
+
+let v = concat(a, b, c, d);
+count = popcount(v);
+odd = parity(v);
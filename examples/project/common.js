@@ -0,0 +1,4 @@
+// Shared by every JS target: the inputs and a signal derived from them.
+let a;
+let b;
+let both = a && b;
@@ -0,0 +1,2 @@
+and = both;
+nand = !both;
@@ -0,0 +1,3 @@
+// Cross-coupled NORs; `a` sets and `b` resets.
+q = !(b || nq);
+nq = !(a || q);
@@ -0,0 +1,5 @@
+// `pulse(x, ticks)` turns each rising edge of x into a pulse of exactly
+// `ticks` redstone ticks, e.g. to fire a dispenser once per lever flip.
+let lever;
+
+fire = pulse(lever, 8);
@@ -0,0 +1,7 @@
+// `repeat(n, i => ...)` stamps out n copies of its body with `i` bound to
+// 0..n-1 and concatenates the results into an n-bit bus (bit 0 first).
+let a;
+let b;
+
+let pair = repeat(2, i => i == 0 ? a : b);
+out = repeat(2, i => pair[1 - i] && !pair[i]);
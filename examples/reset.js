@@ -0,0 +1,8 @@
+// dff, counter, jkff and fsm take an optional trailing synchronous reset:
+// while it is high, the next clock edge clears the register.
+let clk;
+let rst;
+let d;
+
+q = dff(d, clk, rst);
+count = counter(4, clk, rst);
@@ -0,0 +1,9 @@
+// `bits(a, hi, lo)` extracts a sub-bus and `concat(a, b, ...)` joins buses,
+// with the first argument supplying the low bits.
+let a;
+let b;
+let c;
+
+let v = concat(a, b, c);
+let swapped = concat(bits(v, 2, 1), v[0]);
+out = bits(swapped, 1, 0);
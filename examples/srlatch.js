@@ -0,0 +1,5 @@
+// `srlatch(set, reset)` holds Q until the opposite input pulses.
+let set;
+let reset;
+
+q = srlatch(set, reset);
@@ -0,0 +1,9 @@
+// `let a: u4;` declares a 4-bit input with one lever per bit, named `a[0]`
+// (the low bit) to `a[3]`; `boolean` is a single bit. An annotated `let`
+// with a value is zero-extended or truncated to the declared width.
+let a: u4;
+let en: boolean;
+
+let low: u2 = a;
+count = popcount(a);
+out = en ? low : bits(a, 3, 2);
@@ -3,7 +3,7 @@
 
 use std::env;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader};
 
 fn main() -> anyhow::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -14,8 +14,13 @@ fn main() -> anyhow::Result<()> {
     let path = &args[1];
     let file = File::open(path)?;
     let mut buf = BufReader::new(file);
-    // Use hematite-nbt crate's Blob reader to read gzip-compressed NBT.
-    let blob = nbt::Blob::from_gzip_reader(&mut buf)?;
+    // Use hematite-nbt crate's Blob reader: gzip-compressed NBT, or plain
+    // NBT as written with --uncompressed.
+    let blob = if buf.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        nbt::Blob::from_gzip_reader(&mut buf)?
+    } else {
+        nbt::Blob::from_reader(&mut buf)?
+    };
 
     // Print the entire NBT tree (pretty-printed via Display impl)
     println!("{}", &blob);
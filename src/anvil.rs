@@ -0,0 +1,414 @@
+//! Anvil world output: writes the circuit straight into the region files
+//! of a Java Edition (1.18+) save, so it needs no pasting. The circuit's
+//! low corner goes at the given block position and its whole bounding box
+//! is replaced, air included. The chunks must already be generated, and
+//! the world must not be open in the game while it is written.
+
+use crate::schematic::{bounds, parse_state, sign_data, Blocks, Grid};
+use anyhow::Result;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use nbt::{Map, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECTOR: usize = 4096;
+
+/// A block to set: its position inside the chunk (x and z 0..16, y in world
+/// coordinates) and its block state.
+type Cell<'a> = (i32, i32, i32, &'a str);
+
+/// Write `blocks` into the world at `world`, low corner at `at`.
+pub fn write(blocks: &Blocks, world: &Path, at: (i32, i32, i32)) -> Result<()> {
+    let grid = Grid::new(blocks);
+    let (width, height, length) = grid.size;
+    let mut chunks: HashMap<(i32, i32), Vec<Cell>> = HashMap::new();
+    for y in 0..height {
+        for z in 0..length {
+            for x in 0..width {
+                let cell = grid.cells[((y * length + z) * width + x) as usize];
+                let (wx, wy, wz) = (at.0 + x, at.1 + y, at.2 + z);
+                chunks
+                    .entry((wx.div_euclid(16), wz.div_euclid(16)))
+                    .or_default()
+                    .push((
+                        wx.rem_euclid(16),
+                        wy,
+                        wz.rem_euclid(16),
+                        &grid.palette[cell as usize],
+                    ));
+            }
+        }
+    }
+    // Clock ticks, moved from build coordinates to world coordinates.
+    let (origin, _) = bounds(&blocks.placed);
+    let mut ticks: HashMap<(i32, i32), Vec<Value>> = HashMap::new();
+    for (x, y, z, name) in &blocks.scheduled {
+        let (wx, wy, wz) = (
+            x - origin.0 + at.0,
+            y - origin.1 + at.1,
+            z - origin.2 + at.2,
+        );
+        let mut tick = Map::new();
+        tick.insert("i".into(), Value::String(name.clone()));
+        tick.insert("x".into(), Value::Int(wx));
+        tick.insert("y".into(), Value::Int(wy));
+        tick.insert("z".into(), Value::Int(wz));
+        tick.insert("t".into(), Value::Int(1));
+        tick.insert("p".into(), Value::Int(0));
+        ticks
+            .entry((wx.div_euclid(16), wz.div_euclid(16)))
+            .or_default()
+            .push(Value::Compound(tick));
+    }
+    let mut entities: HashMap<(i32, i32), Vec<Value>> = HashMap::new();
+    for (x, y, z, lines) in &grid.signs {
+        let (wx, wy, wz) = (at.0 + x, at.1 + y, at.2 + z);
+        let mut entity = sign_data(lines);
+        entity.insert("id".into(), Value::String("minecraft:sign".into()));
+        entity.insert("x".into(), Value::Int(wx));
+        entity.insert("y".into(), Value::Int(wy));
+        entity.insert("z".into(), Value::Int(wz));
+        entity.insert("keepPacked".into(), Value::Byte(0));
+        entities
+            .entry((wx.div_euclid(16), wz.div_euclid(16)))
+            .or_default()
+            .push(Value::Compound(entity));
+    }
+    let low = at;
+    let high = (at.0 + width - 1, at.1 + height - 1, at.2 + length - 1);
+
+    let mut regions: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+    for &(cx, cz) in chunks.keys() {
+        regions
+            .entry((cx.div_euclid(32), cz.div_euclid(32)))
+            .or_default()
+            .push((cx, cz));
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as u32;
+    for ((rx, rz), coords) in regions {
+        let path = world.join("region").join(format!("r.{}.{}.mca", rx, rz));
+        let mut region = Region::read(&path).map_err(|e| {
+            anyhow::anyhow!("{}: {}; explore the area in game first", path.display(), e)
+        })?;
+        for (cx, cz) in coords {
+            let slot = (cx.rem_euclid(32) + cz.rem_euclid(32) * 32) as usize;
+            let Some(payload) = &region.chunks[slot] else {
+                anyhow::bail!(
+                    "chunk {}, {} has not been generated; explore the area in game first",
+                    cx,
+                    cz
+                );
+            };
+            let (name, mut chunk) = decode(payload)?;
+            let status = match chunk.get("Status") {
+                Some(Value::String(s)) => s.as_str(),
+                _ => "",
+            };
+            if !status.ends_with("full") {
+                anyhow::bail!(
+                    "chunk {}, {} is not fully generated; explore the area in game first",
+                    cx,
+                    cz
+                );
+            }
+            edit_chunk(
+                &mut chunk,
+                (cx, cz),
+                &chunks[&(cx, cz)],
+                ticks.remove(&(cx, cz)).unwrap_or_default(),
+                entities.remove(&(cx, cz)).unwrap_or_default(),
+                (low, high),
+            )?;
+            region.chunks[slot] = Some(encode(&name, chunk)?);
+            region.timestamps[slot] = now;
+        }
+        region.write(&path)?;
+    }
+    Ok(())
+}
+
+/// Set the chunk's blocks, replace block entities inside the written box
+/// with the circuit's own, add the pending ticks, and leave light and
+/// heightmaps for the game to redo.
+fn edit_chunk(
+    chunk: &mut Map<String, Value>,
+    (cx, cz): (i32, i32),
+    cells: &[Cell],
+    ticks: Vec<Value>,
+    entities: Vec<Value>,
+    (low, high): ((i32, i32, i32), (i32, i32, i32)),
+) -> Result<()> {
+    let Some(Value::List(sections)) = chunk.get_mut("sections") else {
+        anyhow::bail!(
+            "chunk {}, {} has no sections; is the world 1.18 or newer?",
+            cx,
+            cz
+        );
+    };
+    let mut by_section: HashMap<i32, Vec<&Cell>> = HashMap::new();
+    for cell in cells {
+        by_section
+            .entry(cell.1.div_euclid(16))
+            .or_default()
+            .push(cell);
+    }
+    for (sy, cells) in by_section {
+        let section = sections.iter_mut().find_map(|s| match s {
+            Value::Compound(m) if matches!(m.get("Y"), Some(Value::Byte(y)) if *y as i32 == sy) => {
+                Some(m)
+            }
+            _ => None,
+        });
+        let Some(section) = section else {
+            anyhow::bail!("y {} is outside the world's height", sy * 16);
+        };
+        let Some(Value::Compound(states)) = section.get_mut("block_states") else {
+            anyhow::bail!("chunk {}, {}: section {} has no block states", cx, cz, sy);
+        };
+        let (mut palette, mut indices) = unpack(states)?;
+        for &&(x, y, z, state) in &cells {
+            let i = ((y.rem_euclid(16) * 16 + z) * 16 + x) as usize;
+            indices[i] = match palette.iter().position(|p| p == state) {
+                Some(p) => p,
+                None => {
+                    palette.push(state.to_string());
+                    palette.len() - 1
+                }
+            };
+        }
+        pack(states, &palette, &indices);
+        section.remove("BlockLight");
+        section.remove("SkyLight");
+    }
+
+    let inside = |e: &Value| {
+        let Value::Compound(m) = e else { return false };
+        let coord = |k: &str| match m.get(k) {
+            Some(Value::Int(v)) => *v,
+            _ => i32::MIN,
+        };
+        let (x, y, z) = (coord("x"), coord("y"), coord("z"));
+        (low.0..=high.0).contains(&x)
+            && (low.1..=high.1).contains(&y)
+            && (low.2..=high.2).contains(&z)
+    };
+    match chunk.get_mut("block_entities") {
+        Some(Value::List(existing)) => {
+            existing.retain(|e| !inside(e));
+            existing.extend(entities);
+        }
+        _ => {
+            chunk.insert("block_entities".into(), Value::List(entities));
+        }
+    }
+    match chunk.get_mut("block_ticks") {
+        Some(Value::List(existing)) => {
+            existing.retain(|e| !inside(e));
+            existing.extend(ticks);
+        }
+        _ => {
+            chunk.insert("block_ticks".into(), Value::List(ticks));
+        }
+    }
+    chunk.insert("isLightOn".into(), Value::Byte(0));
+    chunk.remove("Heightmaps");
+    Ok(())
+}
+
+/// Bits per palette index in a section: at least 4, and indices never
+/// straddle two longs.
+fn bits_for(len: usize) -> usize {
+    (usize::BITS - (len - 1).leading_zeros()).max(4) as usize
+}
+
+/// A section's palette as state strings, and the palette index of each of
+/// its 4096 blocks in Y, Z, X order.
+fn unpack(states: &Map<String, Value>) -> Result<(Vec<String>, Vec<usize>)> {
+    let Some(Value::List(entries)) = states.get("palette") else {
+        anyhow::bail!("section without a palette");
+    };
+    let mut palette = Vec::new();
+    for entry in entries {
+        let Value::Compound(entry) = entry else {
+            anyhow::bail!("malformed palette entry");
+        };
+        let Some(Value::String(name)) = entry.get("Name") else {
+            anyhow::bail!("palette entry without a name");
+        };
+        let mut props: Vec<(&String, &String)> = match entry.get("Properties") {
+            Some(Value::Compound(p)) => p
+                .iter()
+                .filter_map(|(k, v)| match v {
+                    Value::String(v) => Some((k, v)),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        props.sort();
+        palette.push(if props.is_empty() {
+            name.clone()
+        } else {
+            let props: Vec<String> = props.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            format!("{}[{}]", name, props.join(","))
+        });
+    }
+    let mut indices = vec![0; 4096];
+    if palette.len() > 1 {
+        let Some(Value::LongArray(data)) = states.get("data") else {
+            anyhow::bail!("section without block data");
+        };
+        let bits = bits_for(palette.len());
+        let per_long = 64 / bits;
+        if data.len() < 4096_usize.div_ceil(per_long) {
+            anyhow::bail!("section block data is too short");
+        }
+        for (i, index) in indices.iter_mut().enumerate() {
+            let word = data[i / per_long] as u64;
+            *index = ((word >> ((i % per_long) * bits)) & ((1 << bits) - 1)) as usize;
+        }
+    }
+    Ok((palette, indices))
+}
+
+/// Store the blocks back, keeping only the palette entries still in use.
+fn pack(states: &mut Map<String, Value>, palette: &[String], indices: &[usize]) {
+    let mut remap = vec![usize::MAX; palette.len()];
+    let mut used = Vec::new();
+    let indices: Vec<usize> = indices
+        .iter()
+        .map(|&i| {
+            if remap[i] == usize::MAX {
+                remap[i] = used.len();
+                used.push(&palette[i]);
+            }
+            remap[i]
+        })
+        .collect();
+    let entries = used
+        .iter()
+        .map(|state| {
+            let (name, props) = parse_state(state);
+            let mut entry = Map::new();
+            entry.insert("Name".into(), Value::String(name));
+            if !props.is_empty() {
+                let props = props
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect();
+                entry.insert("Properties".into(), Value::Compound(props));
+            }
+            Value::Compound(entry)
+        })
+        .collect();
+    states.insert("palette".into(), Value::List(entries));
+    if used.len() == 1 {
+        states.remove("data");
+        return;
+    }
+    let bits = bits_for(used.len());
+    let per_long = 64 / bits;
+    let mut data = vec![0i64; indices.len().div_ceil(per_long)];
+    for (i, &index) in indices.iter().enumerate() {
+        data[i / per_long] |= (index as i64) << ((i % per_long) * bits);
+    }
+    states.insert("data".into(), Value::LongArray(data));
+}
+
+/// Decompress a chunk payload (compression byte, then data) into its root
+/// tag's name and contents.
+fn decode(payload: &[u8]) -> Result<(String, Map<String, Value>)> {
+    let mut raw = Vec::new();
+    match payload[0] {
+        1 => GzDecoder::new(&payload[1..]).read_to_end(&mut raw)?,
+        2 => ZlibDecoder::new(&payload[1..]).read_to_end(&mut raw)?,
+        3 => {
+            raw.extend_from_slice(&payload[1..]);
+            raw.len()
+        }
+        c => anyhow::bail!("unsupported chunk compression {}", c),
+    };
+    if raw.len() < 3 || raw[0] != 10 {
+        anyhow::bail!("chunk data is not a compound");
+    }
+    let name_len = u16::from_be_bytes([raw[1], raw[2]]) as usize;
+    let name = String::from_utf8_lossy(&raw[3..3 + name_len]).into_owned();
+    match Value::from_reader(10, &mut &raw[3 + name_len..])? {
+        Value::Compound(map) => Ok((name, map)),
+        _ => anyhow::bail!("chunk data is not a compound"),
+    }
+}
+
+/// Compress a chunk back into a zlib payload.
+fn encode(name: &str, chunk: Map<String, Value>) -> Result<Vec<u8>> {
+    let mut enc = ZlibEncoder::new(vec![2], Compression::default());
+    enc.write_all(&[10])?;
+    enc.write_all(&(name.len() as u16).to_be_bytes())?;
+    enc.write_all(name.as_bytes())?;
+    Value::Compound(chunk).to_writer(&mut enc)?;
+    Ok(enc.finish()?)
+}
+
+/// A region file: 32×32 chunks, each a compressed payload or missing.
+struct Region {
+    chunks: Vec<Option<Vec<u8>>>,
+    timestamps: Vec<u32>,
+}
+
+impl Region {
+    fn read(path: &Path) -> Result<Region> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < 2 * SECTOR {
+            anyhow::bail!("region file is truncated");
+        }
+        let word = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        let mut chunks = Vec::with_capacity(1024);
+        let mut timestamps = Vec::with_capacity(1024);
+        for i in 0..1024 {
+            let location = word(i * 4);
+            timestamps.push(word(SECTOR + i * 4));
+            let start = (location >> 8) as usize * SECTOR;
+            if location == 0 || start + 5 > bytes.len() {
+                chunks.push(None);
+                continue;
+            }
+            let len = word(start) as usize;
+            if len == 0 || start + 4 + len > bytes.len() {
+                anyhow::bail!("chunk {} of the region is truncated", i);
+            }
+            if bytes[start + 4] & 0x80 != 0 {
+                anyhow::bail!("chunks stored in separate .mcc files are not supported");
+            }
+            chunks.push(Some(bytes[start + 4..start + 4 + len].to_vec()));
+        }
+        Ok(Region { chunks, timestamps })
+    }
+
+    /// Write every chunk back, packed in order after the header.
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut out = vec![0u8; 2 * SECTOR];
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            out[SECTOR + i * 4..SECTOR + i * 4 + 4]
+                .copy_from_slice(&self.timestamps[i].to_be_bytes());
+            let Some(payload) = chunk else { continue };
+            let sectors = (payload.len() + 4).div_ceil(SECTOR);
+            if sectors > 255 {
+                anyhow::bail!("a chunk grew past 1 MiB");
+            }
+            let location = ((out.len() / SECTOR) as u32) << 8 | sectors as u32;
+            out[i * 4..i * 4 + 4].copy_from_slice(&location.to_be_bytes());
+            out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            out.extend_from_slice(payload);
+            out.resize(out.len().div_ceil(SECTOR) * SECTOR, 0);
+        }
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+}
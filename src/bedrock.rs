@@ -0,0 +1,389 @@
+//! Bedrock Edition structure files (`.mcstructure`), loaded with a
+//! structure block or `/structure load`. Block states are translated from
+//! their Java names to Bedrock's, and the file is little-endian NBT.
+
+use crate::schematic::{bounds, parse_state, Blocks, Grid};
+use anyhow::Result;
+use nbt::{Map, Value};
+use std::path::Path;
+
+/// Block state version of 1.21.0, stored with every palette entry.
+const BLOCK_VERSION: i32 = 18_163_713;
+
+/// Java blocks that keep their name and have no states on Bedrock.
+const SAME: &[&str] = &[
+    "minecraft:air",
+    "minecraft:stone",
+    "minecraft:cobblestone",
+    "minecraft:glass",
+    "minecraft:sandstone",
+    "minecraft:smooth_stone",
+    "minecraft:stone_bricks",
+    "minecraft:gold_block",
+    "minecraft:iron_block",
+    "minecraft:redstone_block",
+    "minecraft:glowstone",
+];
+
+/// Java colour prefixes of wool, concrete and stained glass, which Bedrock
+/// names the same way.
+const COLORS: &[&str] = &[
+    "white",
+    "orange",
+    "magenta",
+    "light_blue",
+    "yellow",
+    "lime",
+    "pink",
+    "gray",
+    "light_gray",
+    "cyan",
+    "purple",
+    "blue",
+    "brown",
+    "green",
+    "red",
+    "black",
+];
+
+/// Bedrock's horizontal `direction`, as used by repeaters and comparators.
+fn direction(facing: Option<&str>) -> i32 {
+    match facing {
+        Some("west") => 1,
+        Some("north") => 2,
+        Some("east") => 3,
+        _ => 0,
+    }
+}
+
+/// Bedrock name and states for a Java block state, or `None` when there is
+/// no translation.
+pub fn bedrock_state(
+    name: &str,
+    props: &[(String, String)],
+) -> Option<(String, Map<String, Value>)> {
+    let prop = |key: &str| {
+        props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+    let on = |key: &str| prop(key) == Some("true");
+    let bit = |set: bool| Value::Byte(set as i8);
+    let mut states = Map::new();
+    if SAME.contains(&name) {
+        return Some((name.to_string(), states));
+    }
+    let short = name.strip_prefix("minecraft:")?;
+    for suffix in ["_wool", "_concrete", "_stained_glass"] {
+        if let Some(color) = short.strip_suffix(suffix) {
+            COLORS.iter().find(|c| **c == color)?;
+            return Some((name.to_string(), states));
+        }
+    }
+    let bedrock = match short {
+        "slime_block" => "slime",
+        "quartz_block" => {
+            states.insert("pillar_axis".into(), Value::String("y".into()));
+            "quartz_block"
+        }
+        "redstone_lamp" if on("lit") => "lit_redstone_lamp",
+        "redstone_lamp" => "redstone_lamp",
+        "redstone_wire" => {
+            let power = prop("power").and_then(|p| p.parse().ok()).unwrap_or(0);
+            states.insert("redstone_signal".into(), Value::Int(power));
+            "redstone_wire"
+        }
+        "redstone_torch" | "redstone_wall_torch" => {
+            // Bedrock names the side the torch hangs from.
+            let side = match prop("facing") {
+                Some("east") => "west",
+                Some("west") => "east",
+                Some("south") => "north",
+                Some("north") => "south",
+                _ => "top",
+            };
+            states.insert("torch_facing_direction".into(), Value::String(side.into()));
+            if prop("lit") == Some("false") {
+                "unlit_redstone_torch"
+            } else {
+                "redstone_torch"
+            }
+        }
+        "repeater" => {
+            let delay: i32 = prop("delay").and_then(|d| d.parse().ok()).unwrap_or(1);
+            states.insert("direction".into(), Value::Int(direction(prop("facing"))));
+            states.insert("repeater_delay".into(), Value::Int(delay.clamp(1, 4) - 1));
+            if on("powered") {
+                "powered_repeater"
+            } else {
+                "unpowered_repeater"
+            }
+        }
+        "comparator" => {
+            states.insert("direction".into(), Value::Int(direction(prop("facing"))));
+            states.insert(
+                "output_subtract_bit".into(),
+                bit(prop("mode") == Some("subtract")),
+            );
+            states.insert("output_lit_bit".into(), bit(on("powered")));
+            if on("powered") {
+                "powered_comparator"
+            } else {
+                "unpowered_comparator"
+            }
+        }
+        "lever" => {
+            let along_x = matches!(prop("facing"), Some("east" | "west"));
+            let dir = match (prop("face"), along_x) {
+                (Some("floor"), true) => "up_east_west",
+                (Some("floor"), false) => "up_north_south",
+                (Some("ceiling"), true) => "down_east_west",
+                (Some("ceiling"), false) => "down_north_south",
+                _ => prop("facing").unwrap_or("north"),
+            };
+            states.insert("lever_direction".into(), Value::String(dir.into()));
+            states.insert("open_bit".into(), bit(on("powered")));
+            "lever"
+        }
+        "stone_button" | "oak_button" => {
+            let facing = match (prop("face"), prop("facing")) {
+                (Some("ceiling"), _) => 0,
+                (Some("floor"), _) => 1,
+                (_, Some("north")) => 2,
+                (_, Some("south")) => 3,
+                (_, Some("west")) => 4,
+                _ => 5,
+            };
+            states.insert("facing_direction".into(), Value::Int(facing));
+            states.insert("button_pressed_bit".into(), bit(on("powered")));
+            if short == "oak_button" {
+                "wooden_button"
+            } else {
+                "stone_button"
+            }
+        }
+        "stone_pressure_plate" | "oak_pressure_plate" => {
+            let signal = if on("powered") { 15 } else { 0 };
+            states.insert("redstone_signal".into(), Value::Int(signal));
+            if short == "oak_pressure_plate" {
+                "wooden_pressure_plate"
+            } else {
+                "stone_pressure_plate"
+            }
+        }
+        "powered_rail" => {
+            let along_x = prop("shape") == Some("east_west");
+            states.insert("rail_direction".into(), Value::Int(along_x as i32));
+            states.insert("rail_data_bit".into(), bit(on("powered")));
+            "golden_rail"
+        }
+        "waxed_copper_bulb" => {
+            states.insert("lit".into(), bit(on("lit")));
+            states.insert("powered_bit".into(), bit(on("powered")));
+            "waxed_copper_bulb"
+        }
+        "observer" => {
+            let facing = prop("facing").unwrap_or("south");
+            states.insert(
+                "minecraft:facing_direction".into(),
+                Value::String(facing.into()),
+            );
+            states.insert("powered_bit".into(), bit(on("powered")));
+            "observer"
+        }
+        "oak_sign" => {
+            let rotation = prop("rotation").and_then(|r| r.parse().ok()).unwrap_or(0);
+            states.insert("ground_sign_direction".into(), Value::Int(rotation));
+            "standing_sign"
+        }
+        "oak_wall_sign" => {
+            let facing = match prop("facing") {
+                Some("south") => 3,
+                Some("west") => 4,
+                Some("east") => 5,
+                _ => 2,
+            };
+            states.insert("facing_direction".into(), Value::Int(facing));
+            "wall_sign"
+        }
+        "oak_door" => {
+            let dir = match prop("facing") {
+                Some("south") => 1,
+                Some("west") => 2,
+                Some("north") => 3,
+                _ => 0,
+            };
+            states.insert("direction".into(), Value::Int(dir));
+            states.insert("upper_block_bit".into(), bit(prop("half") == Some("upper")));
+            states.insert("door_hinge_bit".into(), bit(prop("hinge") == Some("right")));
+            states.insert("open_bit".into(), bit(on("open")));
+            "wooden_door"
+        }
+        _ => return None,
+    };
+    Some((format!("minecraft:{}", bedrock), states))
+}
+
+/// Write `blocks` as a `.mcstructure`.
+pub fn write(blocks: &Blocks, path: &Path) -> Result<()> {
+    let grid = Grid::new(blocks);
+    let (width, height, length) = grid.size;
+    // Java states that differ only in what Bedrock leaves out (such as wire
+    // connections) share a palette entry.
+    let mut palette: Vec<Value> = Vec::new();
+    let mut remap = Vec::with_capacity(grid.palette.len());
+    for state in &grid.palette {
+        let (name, props) = parse_state(state);
+        let (name, states) = bedrock_state(&name, &props)
+            .ok_or_else(|| anyhow::anyhow!("`{}` has no Bedrock equivalent", state))?;
+        let mut entry = Map::new();
+        entry.insert("name".into(), Value::String(name));
+        entry.insert("states".into(), Value::Compound(states));
+        entry.insert("version".into(), Value::Int(BLOCK_VERSION));
+        let entry = Value::Compound(entry);
+        let index = match palette.iter().position(|e| *e == entry) {
+            Some(i) => i,
+            None => {
+                palette.push(entry);
+                palette.len() - 1
+            }
+        };
+        remap.push(index as i32);
+    }
+
+    // Bedrock orders blocks with Z fastest, then Y, then X.
+    let mut indices = Vec::with_capacity(grid.cells.len());
+    for x in 0..width {
+        for y in 0..height {
+            for z in 0..length {
+                let cell = grid.cells[((y * length + z) * width + x) as usize];
+                indices.push(Value::Int(remap[cell as usize]));
+            }
+        }
+    }
+    // The second layer holds waterlogging, which circuits never need.
+    let water = vec![Value::Int(-1); indices.len()];
+
+    // Clock torches get a pending tick so they start on load.
+    let (origin, _) = bounds(&blocks.placed);
+    let mut position_data = Map::new();
+    for (x, y, z, _) in &blocks.scheduled {
+        let (x, y, z) = (x - origin.0, y - origin.1, z - origin.2);
+        let mut tick = Map::new();
+        tick.insert("tick_delay".into(), Value::Int(1));
+        let mut data = Map::new();
+        data.insert(
+            "tick_queue_data".into(),
+            Value::List(vec![Value::Compound(tick)]),
+        );
+        position_data.insert(
+            ((x * height + y) * length + z).to_string(),
+            Value::Compound(data),
+        );
+    }
+    // Bedrock keeps a sign's lines as one string.
+    for (x, y, z, lines) in &grid.signs {
+        let mut text = Map::new();
+        text.insert(
+            "Text".into(),
+            Value::String(lines[..lines.len().min(4)].join("\n")),
+        );
+        let mut entity = Map::new();
+        entity.insert("id".into(), Value::String("Sign".into()));
+        entity.insert("FrontText".into(), Value::Compound(text));
+        entity.insert("x".into(), Value::Int(*x));
+        entity.insert("y".into(), Value::Int(*y));
+        entity.insert("z".into(), Value::Int(*z));
+        let mut data = Map::new();
+        data.insert("block_entity_data".into(), Value::Compound(entity));
+        position_data.insert(
+            ((x * height + y) * length + z).to_string(),
+            Value::Compound(data),
+        );
+    }
+
+    let mut default = Map::new();
+    default.insert("block_palette".into(), Value::List(palette));
+    default.insert("block_position_data".into(), Value::Compound(position_data));
+    let mut palettes = Map::new();
+    palettes.insert("default".into(), Value::Compound(default));
+    let mut structure = Map::new();
+    structure.insert(
+        "block_indices".into(),
+        Value::List(vec![Value::List(indices), Value::List(water)]),
+    );
+    structure.insert("entities".into(), Value::List(vec![]));
+    structure.insert("palette".into(), Value::Compound(palettes));
+    let mut root = Map::new();
+    root.insert("format_version".into(), Value::Int(1));
+    root.insert(
+        "size".into(),
+        Value::List(vec![
+            Value::Int(width),
+            Value::Int(height),
+            Value::Int(length),
+        ]),
+    );
+    root.insert("structure".into(), Value::Compound(structure));
+    root.insert(
+        "structure_world_origin".into(),
+        Value::List(vec![Value::Int(0); 3]),
+    );
+
+    let mut out = vec![10];
+    write_string(&mut out, "");
+    write_payload(&mut out, &Value::Compound(root));
+    std::fs::write(path, out)?;
+    Ok(())
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// A tag's payload in little-endian byte order; the NBT crate only writes
+/// big-endian.
+fn write_payload(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Byte(v) => out.push(*v as u8),
+        Value::Short(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Value::Int(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Value::Long(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Value::Float(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Value::Double(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Value::ByteArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_le_bytes());
+            out.extend(v.iter().map(|b| *b as u8));
+        }
+        Value::String(s) => write_string(out, s),
+        Value::List(items) => {
+            out.push(items.first().map_or(0, |i| i.id()));
+            out.extend_from_slice(&(items.len() as i32).to_le_bytes());
+            for item in items {
+                write_payload(out, item);
+            }
+        }
+        Value::Compound(map) => {
+            for (name, v) in map {
+                out.push(v.id());
+                write_string(out, name);
+                write_payload(out, v);
+            }
+            out.push(0);
+        }
+        Value::IntArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_le_bytes());
+            for i in v {
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+        }
+        Value::LongArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_le_bytes());
+            for i in v {
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+        }
+    }
+}
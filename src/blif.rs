@@ -0,0 +1,407 @@
+//! Berkeley BLIF frontend, for netlists already minimized by ABC or
+//! espresso. Supports `.names` covers, `.latch` and `.subckt`; the first
+//! model is the top level.
+
+use crate::compiler::{next_id, Circuit, Ctx};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+enum Item {
+    /// Inputs then output, with the cover rows `(input plane, output bit)`.
+    Names {
+        signals: Vec<String>,
+        cubes: Vec<(String, char)>,
+    },
+    Latch {
+        input: String,
+        output: String,
+        falling: bool,
+        clock: Option<String>,
+    },
+    Subckt {
+        model: String,
+        conns: Vec<(String, String)>,
+    },
+}
+
+struct Model {
+    name: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    items: Vec<(Item, usize)>,
+}
+
+/// Split into logical lines (comments stripped, `\` continuations joined),
+/// each tagged with its first physical line number.
+fn lines(src: &str) -> Vec<(Vec<String>, usize)> {
+    let mut out = Vec::new();
+    let mut pending: Option<(String, usize)> = None;
+    for (n, raw) in src.lines().enumerate() {
+        let text = raw.split('#').next().unwrap();
+        let (text, more) = match text.trim_end().strip_suffix('\\') {
+            Some(t) => (t, true),
+            None => (text, false),
+        };
+        let (mut acc, start) = pending.take().unwrap_or((String::new(), n + 1));
+        acc.push(' ');
+        acc.push_str(text);
+        if more {
+            pending = Some((acc, start));
+            continue;
+        }
+        let words: Vec<String> = acc.split_whitespace().map(String::from).collect();
+        if !words.is_empty() {
+            out.push((words, start));
+        }
+    }
+    out
+}
+
+fn parse(src: &str) -> Result<Vec<Model>> {
+    let mut models: Vec<Model> = Vec::new();
+    let mut in_exdc = false;
+    for (words, line) in lines(src) {
+        let at = |msg: String| anyhow::anyhow!("line {}: {}", line, msg);
+        let directive = words[0].as_str();
+        if directive == ".model" {
+            let name = words.get(1).cloned().unwrap_or_else(|| "top".into());
+            models.push(Model {
+                name,
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+                items: Vec::new(),
+            });
+            in_exdc = false;
+            continue;
+        }
+        // Everything until the next model is external don't-care logic.
+        if in_exdc {
+            continue;
+        }
+        if models.is_empty() {
+            // A file may leave out `.model` for a single anonymous model.
+            models.push(Model {
+                name: "top".into(),
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+                items: Vec::new(),
+            });
+        }
+        let m = models.last_mut().unwrap();
+        let args = words[1..].to_vec();
+        match directive {
+            ".inputs" => m.inputs.extend(args),
+            ".outputs" => m.outputs.extend(args),
+            ".clock" => {}
+            ".names" => {
+                if args.is_empty() {
+                    return Err(at(".names needs an output".into()));
+                }
+                m.items.push((
+                    Item::Names {
+                        signals: args,
+                        cubes: Vec::new(),
+                    },
+                    line,
+                ));
+            }
+            ".latch" => {
+                let (input, output) = match args.as_slice() {
+                    [i, o, ..] => (i.clone(), o.clone()),
+                    _ => return Err(at(".latch needs an input and an output".into())),
+                };
+                // `.latch in out [type control] [init]`
+                let (kind, clock, init) = match &args[2..] {
+                    [] => (None, None, None),
+                    [init] => (None, None, Some(init)),
+                    [kind, clock] => (Some(kind), Some(clock), None),
+                    [kind, clock, init] => (Some(kind), Some(clock), Some(init)),
+                    _ => return Err(at("too many .latch arguments".into())),
+                };
+                let falling = match kind.map(String::as_str) {
+                    None | Some("re") => false,
+                    Some("fe") => true,
+                    Some(k) => {
+                        return Err(at(format!(
+                            "latch type `{}` is not supported; only re and fe registers",
+                            k
+                        )))
+                    }
+                };
+                if init.is_some_and(|i| i == "1") {
+                    return Err(at(
+                        "registers power up at 0; initial value 1 is not supported".into(),
+                    ));
+                }
+                m.items.push((
+                    Item::Latch {
+                        input,
+                        output,
+                        falling,
+                        clock: clock.filter(|c| *c != "NIL").cloned(),
+                    },
+                    line,
+                ));
+            }
+            ".subckt" => {
+                let Some((model, pairs)) = args.split_first() else {
+                    return Err(at(".subckt needs a model name".into()));
+                };
+                let mut conns = Vec::new();
+                for pair in pairs {
+                    let Some((formal, actual)) = pair.split_once('=') else {
+                        return Err(at(format!("expected formal=actual, found `{}`", pair)));
+                    };
+                    conns.push((formal.to_string(), actual.to_string()));
+                }
+                m.items.push((
+                    Item::Subckt {
+                        model: model.clone(),
+                        conns,
+                    },
+                    line,
+                ));
+            }
+            ".exdc" => in_exdc = true,
+            ".end" => {}
+            ".gate" | ".mlatch" => {
+                return Err(at(format!(
+                    "{} needs a cell library; write the netlist with .names instead",
+                    directive
+                )))
+            }
+            d if d.starts_with('.') => return Err(at(format!("unsupported directive `{}`", d))),
+            _ => {
+                let Some((Item::Names { signals, cubes }, _)) = m.items.last_mut() else {
+                    return Err(at("cover row outside of .names".into()));
+                };
+                let n = signals.len() - 1;
+                let (plane, bit) = match words.as_slice() {
+                    [bit] if n == 0 => (String::new(), bit.as_str()),
+                    [plane, bit] if plane.len() == n => (plane.clone(), bit.as_str()),
+                    _ => return Err(at(format!("malformed cover row for {} inputs", n))),
+                };
+                if plane.chars().any(|c| !"01-".contains(c)) || (bit != "0" && bit != "1") {
+                    return Err(at(format!("malformed cover row `{}`", words.join(" "))));
+                }
+                let bit = bit.chars().next().unwrap();
+                if cubes.first().is_some_and(|(_, b)| *b != bit) {
+                    return Err(at("cover mixes on-set and off-set rows".into()));
+                }
+                cubes.push((plane, bit));
+            }
+        }
+    }
+    Ok(models)
+}
+
+struct Elab<'a> {
+    ctx: Ctx,
+    models: HashMap<&'a str, &'a Model>,
+    /// Reserved signal -> the signal driving it, for plain buffers and
+    /// top-level inputs.
+    alias: HashMap<String, String>,
+    driven: HashSet<String>,
+    /// Reserved signal -> hierarchical BLIF name, for error messages.
+    names: HashMap<String, String>,
+    /// Clocks are global in BLIF: top-level inputs by name, plus levers
+    /// added for latch clocks that nothing drives.
+    clocks: HashMap<String, String>,
+    /// Names of the levers added for clocks, in creation order.
+    extra_inputs: Vec<String>,
+    depth: usize,
+}
+
+impl<'a> Elab<'a> {
+    /// The reserved signal for BLIF name `name` in a model instance.
+    fn sig(&mut self, scope: &mut HashMap<String, String>, path: &str, name: &str) -> String {
+        if let Some(s) = scope.get(name) {
+            return s.clone();
+        }
+        let s = next_id();
+        self.names.insert(s.clone(), format!("{}{}", path, name));
+        scope.insert(name.to_string(), s.clone());
+        s
+    }
+
+    fn drive(&mut self, sig: &str) -> Result<()> {
+        if !self.driven.insert(sig.to_string()) {
+            anyhow::bail!("`{}` has multiple drivers", self.names[sig]);
+        }
+        Ok(())
+    }
+
+    /// Elaborate an instance of `m`. `scope` starts with the model's ports
+    /// bound to the caller's signals.
+    fn model(&mut self, m: &Model, path: &str, mut scope: HashMap<String, String>) -> Result<()> {
+        self.depth += 1;
+        if self.depth > 64 {
+            anyhow::bail!("model `{}` instantiates itself", m.name);
+        }
+        for (item, line) in &m.items {
+            self.item(item, path, &mut scope)
+                .map_err(|e| anyhow::anyhow!("line {}: {}", line, e))?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn item(&mut self, item: &Item, path: &str, scope: &mut HashMap<String, String>) -> Result<()> {
+        match item {
+            Item::Names { signals, cubes } => {
+                let (out, ins) = signals.split_last().unwrap();
+                let ins: Vec<String> = ins.iter().map(|n| self.sig(scope, path, n)).collect();
+                let out = self.sig(scope, path, out);
+                self.drive(&out)?;
+                self.cover(&ins, cubes, out);
+            }
+            Item::Latch {
+                input,
+                output,
+                falling,
+                clock,
+            } => {
+                let d = self.sig(scope, path, input);
+                let q = self.sig(scope, path, output);
+                self.drive(&q)?;
+                let name = clock.as_deref().unwrap_or("clk");
+                let clk = match (scope.get(name), self.clocks.get(name)) {
+                    (Some(local), _) => local.clone(),
+                    (None, Some(global)) => global.clone(),
+                    (None, None) => {
+                        let c = self.ctx.input(name);
+                        self.clocks.insert(name.to_string(), c.clone());
+                        self.extra_inputs.push(name.to_string());
+                        c
+                    }
+                };
+                let clk = if *falling {
+                    self.ctx.emit("NOT", vec![clk])
+                } else {
+                    clk
+                };
+                self.ctx.emit_into("DFF", vec![d, clk], q);
+            }
+            Item::Subckt { model, conns } => {
+                let Some(&sub) = self.models.get(model.as_str()) else {
+                    anyhow::bail!("unknown model `{}`", model);
+                };
+                let mut child = HashMap::new();
+                for (formal, actual) in conns {
+                    if !sub.inputs.contains(formal) && !sub.outputs.contains(formal) {
+                        anyhow::bail!("model `{}` has no port `{}`", model, formal);
+                    }
+                    child.insert(formal.clone(), self.sig(scope, path, actual));
+                }
+                let inst = format!("{}{}#{}.", path, model, self.depth);
+                let entered = self.ctx.enter_block(|_| inst.trim_end_matches('.').into());
+                let done = self.model(sub, &inst, child);
+                self.ctx.leave_block(entered);
+                done?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lower a sum-of-products cover driving the reserved signal `out`.
+    fn cover(&mut self, ins: &[String], cubes: &[(String, char)], out: String) {
+        let mut terms = Vec::new();
+        for (plane, _) in cubes {
+            let lits: Vec<String> = plane
+                .chars()
+                .zip(ins)
+                .filter(|(c, _)| *c != '-')
+                .map(|(c, s)| match c {
+                    '1' => s.clone(),
+                    _ => self.ctx.emit("NOT", vec![s.clone()]),
+                })
+                .collect();
+            let term = self
+                .ctx
+                .reduce_balanced(lits, |c, x, y| c.emit("AND", vec![x, y]))
+                .unwrap_or_else(|| "CONST_TRUE_SIG".into());
+            terms.push(term);
+        }
+        let sop = self.ctx.or_all(terms);
+        // An off-set cover lists where the output is 0.
+        if cubes.first().is_some_and(|(_, b)| *b == '0') {
+            self.ctx.emit_into("NOT", vec![sop], out);
+        } else {
+            self.alias.insert(out, sop);
+        }
+    }
+}
+
+/// Parse a BLIF file and lower its first model into a circuit.
+pub fn compile(src: &str) -> Result<Circuit> {
+    let models = parse(src)?;
+    let Some(top) = models.first() else {
+        anyhow::bail!("no model found");
+    };
+    let mut by_name = HashMap::new();
+    for m in &models {
+        if by_name.insert(m.name.as_str(), m).is_some() {
+            anyhow::bail!("model `{}` is defined twice", m.name);
+        }
+    }
+    let mut elab = Elab {
+        ctx: Ctx::new(),
+        models: by_name,
+        alias: HashMap::new(),
+        driven: HashSet::new(),
+        names: HashMap::new(),
+        clocks: HashMap::new(),
+        extra_inputs: Vec::new(),
+        depth: 0,
+    };
+    let mut scope = HashMap::new();
+    for name in &top.inputs {
+        let sig = elab.sig(&mut scope, "", name);
+        elab.drive(&sig)?;
+        let lever = elab.ctx.input(name);
+        elab.clocks.insert(name.clone(), lever.clone());
+        elab.alias.insert(sig, lever);
+    }
+    let outs: Vec<String> = top
+        .outputs
+        .iter()
+        .map(|n| elab.sig(&mut scope, "", n))
+        .collect();
+    elab.model(top, "", scope)?;
+    let outputs = outs
+        .into_iter()
+        .zip(&top.outputs)
+        .map(|(s, name)| (elab.ctx.emit("BUF", vec![s]), name.clone()))
+        .collect();
+
+    // Replace buffered signals with their drivers.
+    let Elab {
+        ctx,
+        alias,
+        names,
+        extra_inputs,
+        ..
+    } = elab;
+    let mut inputs = top.inputs.clone();
+    inputs.extend(extra_inputs);
+    let mut circuit = ctx.finish(inputs, outputs);
+    let produced: HashSet<String> = circuit.gates.iter().map(|g| g.output.clone()).collect();
+    for g in &mut circuit.gates {
+        for i in &mut g.inputs {
+            let mut seen = HashSet::new();
+            while let Some(next) = alias.get(i.as_str()) {
+                if !seen.insert(i.clone()) {
+                    anyhow::bail!("`{}` is a buffer loop", names[i.as_str()]);
+                }
+                *i = next.clone();
+            }
+            if !produced.contains(i.as_str()) {
+                anyhow::bail!(
+                    "`{}` is used but never driven",
+                    names.get(i.as_str()).unwrap_or(i)
+                );
+            }
+        }
+    }
+    Ok(circuit)
+}
@@ -0,0 +1,463 @@
+use crate::layout::Placer;
+use crate::optimizer::Goal;
+use crate::schematic::{parse_block, NetOrder};
+use anyhow::Result;
+use std::path::Path;
+
+/// Where the program comes from.
+#[derive(Clone)]
+pub enum Input {
+    Path(String),
+    /// `-`: JS source read from stdin.
+    Stdin,
+    /// JS source given with `-e`; free variables become inputs.
+    Inline(String),
+    /// `--project FILE`: every target of a project manifest.
+    Project(String),
+}
+
+/// Parsed command-line options.
+#[derive(Clone)]
+pub struct Options {
+    pub input: Input,
+    pub output: String,
+    /// Turn combinational feedback loops into SR latches instead of failing.
+    pub infer_latches: bool,
+    /// Build PLA planes from wide diode-matrix rows instead of 2-input gates.
+    pub pla_matrix: bool,
+    /// Rewrite the logic into NAND and NOR gates.
+    pub nand_nor: bool,
+    /// What the optimizer minimizes, from `--optimize`.
+    pub goal: Goal,
+    /// Optimization level, from `-O0` to `-O3`.
+    pub opt_level: u8,
+    /// How gates are placed, from `--placer`.
+    pub placer: Placer,
+    /// Layers the gate rows are stacked into, from `--layers`.
+    pub layers: usize,
+    /// `--max-size WxHxL`: the largest footprint the build may take.
+    pub max_size: Option<(i32, i32, i32)>,
+    /// Keep each module instance's gates together, from `--hierarchy`.
+    pub hierarchy: bool,
+    /// `--spacing X,Z`: blocks between gates in a row, and between rows.
+    pub spacing: (i32, i32),
+    /// `--layer-height N`: rise from one layer to the next.
+    pub layer_height: i32,
+    /// `--origin X,Y,Z`: where the first gate is placed.
+    pub origin: (i32, i32, i32),
+    /// `--aspect W:L`: the footprint's width over its length to aim for.
+    pub aspect: Option<f64>,
+    /// Pad early gate inputs with repeater delay, from `--equalize-delays`.
+    pub equalize_delays: bool,
+    /// `--wire-lanes N`: levels above the ground set aside for long nets.
+    pub wire_lanes: usize,
+    /// `--instant-wire N`: route length past which nets take instant-wire
+    /// segments.
+    pub instant_wire: Option<usize>,
+    /// `--tunnels`: let wires drop to a level beneath the gates.
+    pub tunnels: bool,
+    /// `--net-order O`: which nets are routed first.
+    pub net_order: NetOrder,
+    /// `--allow-shorts`: write the build even if two signals' wires connect.
+    pub allow_shorts: bool,
+    /// `--allow-unwired`: write the build even if a gate input cannot be
+    /// wired up.
+    pub allow_unwired: bool,
+    /// `--place-retries N`: times to spread the gates around unwired
+    /// inputs and route again.
+    pub place_retries: usize,
+    /// `--support-block B`: the block under the wires.
+    pub support_block: String,
+    /// `--floor-block B`: the block under the gates and where wires drop.
+    pub floor_block: String,
+    /// `--color-nets`: give each signal's wires a concrete colour of its own.
+    pub color_nets: bool,
+    /// Signs naming the inputs and outputs, unless `--no-labels`.
+    pub labels: bool,
+    /// Redstone lamps on the outputs, unless `--no-lamps`.
+    pub lamps: bool,
+    /// `--input-hardware H`: the INPUT parameter for inputs that pick none.
+    pub input_hardware: i64,
+    /// `--enclose B`: the block of a room built around the circuit.
+    pub enclose: Option<String>,
+    /// Sponge schematic version for `.schem` outputs.
+    pub sponge_version: u8,
+    /// `--name TEXT`: the schematic's name, else the output's file name.
+    pub name: Option<String>,
+    /// `--author TEXT`: the schematic's author.
+    pub author: String,
+    /// `--description TEXT`: the schematic's description.
+    pub description: String,
+    /// `--at X,Y,Z`: where the circuit goes when writing into a world.
+    pub at: Option<(i32, i32, i32)>,
+    /// `--verify`: read a `.litematic` back after writing it and check it.
+    pub verify: bool,
+    /// `--gzip-level N` of the NBT outputs (default 6), or `None` for
+    /// `--uncompressed`.
+    pub gzip: Option<u32>,
+    /// `--stdout`: write the NBT to stdout instead of the output file.
+    pub stdout: bool,
+    /// Side outputs written next to the main one, from `--emit`.
+    pub emit: Vec<String>,
+}
+
+pub fn usage(program: &str) -> String {
+    format!(
+        "Usage: {0} [options] input out.litematic\n       \
+         {0} [options] -e EXPR out.litematic\n       \
+         {0} [options] --project schemlogica.toml\n\
+         \n\
+         The input format follows its extension: .v for Verilog, .blif for BLIF,\n\
+         .csv for a truth table, .pla for Espresso PLA, .json for a gate netlist,\n\
+         .circ for Logisim, .dig for Digital, .dot for a Graphviz netlist,\n\
+         otherwise JS.\n\
+         An input of - reads JS from stdin.\n\
+         The output format follows its extension too: .schem for a Sponge\n\
+         schematic, .schematic for a legacy (1.12) WorldEdit schematic, .nbt\n\
+         for vanilla structure blocks, .mcfunction for setblock commands, .zip\n\
+         for a datapack with build and teardown functions, .mcstructure for\n\
+         Bedrock Edition, otherwise a litematic.\n\
+         An output directory holding level.dat is a world save (1.18+): the\n\
+         circuit is written into its generated chunks with its low corner at\n\
+         --at, replacing everything in its bounding box. Close the world first.\n\
+         \n\
+         Options:\n  \
+           -e EXPR           compile the JS statements EXPR, e.g. \"out = a && !b\"\n  \
+           --project FILE    build every target of a .json or .toml project\n  \
+           --infer-latches   build combinational feedback loops as SR latches\n  \
+           --pla-matrix      lay out .pla AND/OR planes as wide matrix rows\n  \
+           --nand-nor        synthesize the logic from NAND and NOR gates only\n  \
+           --optimize G      optimize for area (default), preferring compact torch\n                      \
+             logic, or for speed, preferring the fewest redstone ticks;\n                      \
+             gates map onto torch primitives (native, dual or behind a\n                      \
+             NOT) only, with no repeater or comparator variants\n  \
+           -O0 ... -O3       optimization level (default -O2): -O0 keeps the raw\n                      \
+             netlist, -O1 folds constants and drops dead gates, -O2 adds\n                      \
+             gate fusion, sharing and fanout buffering, -O3 adds two-level\n                      \
+             minimization\n  \
+           --placer P        place gates by simulated annealing (anneal, the\n                      \
+             default) or force-directed sweeps (force), which is faster\n                      \
+             on medium circuits but wires a little longer\n  \
+           --layers N        stack the gate rows into N layers (default 1), each\n                      \
+             --layer-height above the last, for a tower instead of a field\n  \
+           --max-size WxHxL  keep the build within W x H x L blocks (x, y, z),\n                      \
+             folding wide rows and stacking layers as needed\n  \
+           --hierarchy       give every module instance or builtin call a\n                      \
+             column of its own, so it is recognizable in the build\n  \
+           --spacing X,Z     blocks between gates in a row and between rows\n                      \
+             (default 12,16); routing needs room to get around gates\n  \
+           --layer-height N  blocks from one layer to the next (default 4,\n                      \
+             at least 3)\n  \
+           --origin X,Y,Z    position of the first gate (default 0,0,0)\n  \
+           --aspect W:L      aim for a footprint W:L wide (x) to long (z), e.g.\n                      \
+             1:1 for a square, by folding rows or setting them side by side\n  \
+           --equalize-delays pad the wires into each gate with repeater delay so\n                      \
+             all its inputs arrive on the same tick, against glitches\n  \
+           --wire-lanes N    levels above the ground long wires are spread\n                      \
+             over (default 2, 0 for none)\n  \
+           --instant-wire N  carry nets routed over more than N blocks along\n                      \
+             straight stretches on powered rails, read back by an observer,\n                      \
+             copper bulb and comparator (3 ticks per 13 blocks; needs\n                      \
+             1.21 blocks, so not for .schematic)\n  \
+           --tunnels         let wires drop to a level beneath the gates to\n                      \
+             pass under them\n  \
+           --net-order O     route nets in gate order (gates, the default),\n                      \
+             smallest first (size), on the longest path first\n                      \
+             (critical) or with the most sinks first (fanout)\n  \
+           --allow-shorts    write the build even where the wires of two\n                      \
+             signals connect, warning about each instead of failing\n  \
+           --allow-unwired   write the build even where a gate input cannot\n                      \
+             be wired up, warning and leaving it unconnected\n  \
+           --place-retries N spread the gates around inputs left unwired and\n                      \
+             route again, up to N times (default 3, 0 for none)\n  \
+           --support-block B block under the wires (default glass), e.g.\n                      \
+             white_concrete; a wire drops a level off the floor\n                      \
+             block unless this conducts\n  \
+           --floor-block B   block under the gates (default sandstone); must\n                      \
+             be solid\n  \
+           --color-nets      put each signal's wires on concrete of its own\n                      \
+             colour instead of the support block\n  \
+           --no-labels       leave out the signs naming each input's lever and\n                      \
+             each output\n  \
+           --no-lamps        leave out the redstone lamp lit by each output\n  \
+           --input-hardware H lever (default), button or plate (pressure plate)\n                      \
+             for the inputs that do not pick their own with input()\n  \
+           --enclose B       build a room of B blocks around the circuit, e.g.\n                      \
+             glass, with a floor, ceiling lights and a door by the levers\n  \
+           --schem-version N write .schem files as Sponge version 2 (default) or 3\n  \
+           --name TEXT       schematic name, as Litematica lists it (default the\n                      \
+             output's file name)\n  \
+           --author TEXT     schematic author (default schemlogica)\n  \
+           --description T   schematic description, for .litematic outputs\n  \
+           --at X,Y,Z        world position of the circuit's low corner\n  \
+           --verify          read a .litematic output back after writing it and\n                      \
+             check every block came back where it was placed\n  \
+           --gzip-level N    gzip level of .litematic, .schem, .schematic and\n                      \
+             .nbt outputs, from 0 to 9 (default 6)\n  \
+           --uncompressed    write those outputs as plain NBT, for diffing\n  \
+           --stdout          write those outputs to stdout instead; the output\n                      \
+             path still picks the format and names the --emit files\n  \
+           --emit KIND       also write a side output (repeatable):\n                      \
+             netlist   the optimized circuit and layout as .netlist.json\n                      \
+             svg       a top-down drawing with one group per Y level\n                      \
+             obj       a coloured 3D model (.obj and .mtl) for model viewers\n                      \
+             verilog   the optimized netlist as structural Verilog (.netlist.v)\n                      \
+             layers    print every Y level as an ASCII grid\n                      \
+             report    a Markdown summary of the build (.report.md)",
+        program
+    )
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options> {
+        let mut positional = Vec::new();
+        let mut inline = None;
+        let mut project = None;
+        let mut infer_latches = false;
+        let mut pla_matrix = false;
+        let mut nand_nor = false;
+        let mut goal = Goal::Area;
+        let mut opt_level = 2;
+        let mut placer = Placer::Anneal;
+        let mut layers = 1;
+        let mut max_size = None;
+        let mut hierarchy = false;
+        let grid = crate::layout::Grid::default();
+        let mut spacing = (grid.spacing_x, grid.spacing_z);
+        let mut layer_height = grid.layer_height;
+        let mut origin = grid.origin;
+        let mut aspect = None;
+        let mut equalize_delays = false;
+        let mut wire_lanes = 2;
+        let mut instant_wire = None;
+        let mut tunnels = false;
+        let mut net_order = NetOrder::Gates;
+        let mut allow_shorts = false;
+        let mut allow_unwired = false;
+        let mut place_retries = 3;
+        let mut support_block = "minecraft:glass".to_string();
+        let mut floor_block = crate::primitives::FLOOR_BLOCK.to_string();
+        let mut color_nets = false;
+        let mut labels = true;
+        let mut lamps = true;
+        let mut input_hardware = 0;
+        let mut enclose = None;
+        let mut sponge_version = 2;
+        let mut name = None;
+        let mut author = "schemlogica".to_string();
+        let mut description = String::new();
+        let mut at = None;
+        let mut verify = false;
+        let mut gzip = Some(6);
+        let mut stdout = false;
+        let mut emit = Vec::new();
+        let mut it = args.iter().skip(1);
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "-e" => match it.next() {
+                    Some(expr) => inline = Some(expr.clone()),
+                    None => anyhow::bail!("-e needs an expression"),
+                },
+                "--project" => match it.next() {
+                    Some(path) => project = Some(path.clone()),
+                    None => anyhow::bail!("--project needs a manifest path"),
+                },
+                "--infer-latches" => infer_latches = true,
+                "--pla-matrix" => pla_matrix = true,
+                "--nand-nor" => nand_nor = true,
+                "--optimize" => match it.next() {
+                    Some(name) => goal = Goal::parse(name)?,
+                    None => anyhow::bail!("--optimize needs area or speed"),
+                },
+                "--placer" => match it.next() {
+                    Some(name) => placer = Placer::parse(name)?,
+                    None => anyhow::bail!("--placer needs anneal or force"),
+                },
+                "--layers" => match it.next().map(|n| n.parse()) {
+                    Some(Ok(n)) if n >= 1 => layers = n,
+                    _ => anyhow::bail!("--layers needs a count of at least 1"),
+                },
+                "--max-size" => match it.next() {
+                    Some(size) => max_size = Some(crate::layout::parse_size(size)?),
+                    None => anyhow::bail!("--max-size needs a size like 64x32x64"),
+                },
+                "--hierarchy" => hierarchy = true,
+                "--spacing" => match it.next() {
+                    Some(text) => spacing = crate::layout::parse_spacing(text)?,
+                    None => anyhow::bail!("--spacing needs X,Z blocks like 12,16"),
+                },
+                "--layer-height" => match it.next().map(|n| n.parse()) {
+                    Some(Ok(n)) if n >= crate::layout::MIN_LAYER_HEIGHT => layer_height = n,
+                    _ => anyhow::bail!(
+                        "--layer-height needs a height of at least {}",
+                        crate::layout::MIN_LAYER_HEIGHT
+                    ),
+                },
+                "--origin" => match it.next() {
+                    Some(text) => origin = crate::layout::parse_origin(text)?,
+                    None => anyhow::bail!("--origin needs a position like 0,0,0"),
+                },
+                "--aspect" => match it.next() {
+                    Some(text) => aspect = Some(crate::layout::parse_aspect(text)?),
+                    None => anyhow::bail!("--aspect needs a ratio like 1:1"),
+                },
+                "--equalize-delays" => equalize_delays = true,
+                "--wire-lanes" => match it.next().map(|n| n.parse()) {
+                    Some(Ok(n)) => wire_lanes = n,
+                    _ => anyhow::bail!("--wire-lanes needs a count"),
+                },
+                "--instant-wire" => match it.next().map(|n| n.parse()) {
+                    Some(Ok(n)) => instant_wire = Some(n),
+                    _ => anyhow::bail!("--instant-wire needs a length in blocks"),
+                },
+                "--tunnels" => tunnels = true,
+                "--net-order" => match it.next() {
+                    Some(name) => net_order = NetOrder::parse(name)?,
+                    None => anyhow::bail!("--net-order needs gates, size, critical or fanout"),
+                },
+                "--allow-shorts" => allow_shorts = true,
+                "--allow-unwired" => allow_unwired = true,
+                "--place-retries" => match it.next().map(|n| n.parse()) {
+                    Some(Ok(n)) => place_retries = n,
+                    _ => anyhow::bail!("--place-retries needs a count"),
+                },
+                "--support-block" => match it.next() {
+                    Some(id) => support_block = parse_block(id)?,
+                    None => anyhow::bail!("--support-block needs a block id like glass"),
+                },
+                "--floor-block" => match it.next() {
+                    Some(id) => floor_block = parse_block(id)?,
+                    None => anyhow::bail!("--floor-block needs a block id like sandstone"),
+                },
+                "--color-nets" => color_nets = true,
+                "--no-labels" => labels = false,
+                "--no-lamps" => lamps = false,
+                "--input-hardware" => match it.next() {
+                    Some(name) => input_hardware = crate::primitives::input_hardware(name)?,
+                    None => anyhow::bail!("--input-hardware needs lever, button or plate"),
+                },
+                "--enclose" => match it.next() {
+                    Some(id) => enclose = Some(parse_block(id)?),
+                    None => anyhow::bail!("--enclose needs a block id like glass"),
+                },
+                "--schem-version" => match it.next().map(String::as_str) {
+                    Some("2") => sponge_version = 2,
+                    Some("3") => sponge_version = 3,
+                    _ => anyhow::bail!("--schem-version needs 2 or 3"),
+                },
+                "--name" => match it.next() {
+                    Some(text) => name = Some(text.clone()),
+                    None => anyhow::bail!("--name needs a name for the schematic"),
+                },
+                "--author" => match it.next() {
+                    Some(text) => author = text.clone(),
+                    None => anyhow::bail!("--author needs a name"),
+                },
+                "--description" => match it.next() {
+                    Some(text) => description = text.clone(),
+                    None => anyhow::bail!("--description needs some text"),
+                },
+                "--at" => {
+                    let coords: Option<Vec<i32>> = it
+                        .next()
+                        .map(|v| v.split(',').map(|c| c.trim().parse().ok()).collect())
+                        .unwrap_or_default();
+                    match coords.as_deref() {
+                        Some(&[x, y, z]) => at = Some((x, y, z)),
+                        _ => anyhow::bail!("--at needs a position like 100,64,-20"),
+                    }
+                }
+                "--verify" => verify = true,
+                "--gzip-level" => match it.next().map(|v| v.parse()) {
+                    Some(Ok(level)) if level <= 9 => gzip = Some(level),
+                    _ => anyhow::bail!("--gzip-level needs a level from 0 to 9"),
+                },
+                "--uncompressed" => gzip = None,
+                "--stdout" => stdout = true,
+                "--emit" => match it.next() {
+                    Some(kind) if crate::emit::KINDS.contains(&kind.as_str()) => {
+                        emit.push(kind.clone())
+                    }
+                    _ => anyhow::bail!("--emit needs one of: {}", crate::emit::KINDS.join(", ")),
+                },
+                a if a.starts_with("-O") => match a[2..].parse() {
+                    Ok(level) if level <= crate::optimizer::MAX_LEVEL => opt_level = level,
+                    _ => anyhow::bail!("Unknown optimization level {} (use -O0 to -O3)", a),
+                },
+                a if a.starts_with("--") => anyhow::bail!("Unknown option: {}", a),
+                _ => positional.push(arg.clone()),
+            }
+        }
+        let (input, output) = match project {
+            Some(path) => {
+                if inline.is_some() || !positional.is_empty() {
+                    anyhow::bail!("--project takes no other inputs or outputs");
+                }
+                if stdout {
+                    anyhow::bail!("--stdout takes a single output, not a --project");
+                }
+                // Each target names its own output.
+                (Input::Project(path), String::new())
+            }
+            None => match (inline, positional.as_slice()) {
+                (Some(expr), [output]) => (Input::Inline(expr), output.clone()),
+                (None, [input, output]) if input == "-" => (Input::Stdin, output.clone()),
+                (None, [input, output]) => (Input::Path(input.clone()), output.clone()),
+                (Some(_), _) => anyhow::bail!("Expected an output path after -e EXPR"),
+                (None, _) => anyhow::bail!("Expected an input and an output path"),
+            },
+        };
+        if stdout && verify {
+            anyhow::bail!("--verify reads the output file back, so it cannot go to --stdout");
+        }
+        let path = Path::new(&output);
+        let ext = path.extension().and_then(|e| e.to_str());
+        let world = path.join("level.dat").is_file();
+        if stdout && (world || matches!(ext, Some("mcfunction" | "zip" | "mcstructure"))) {
+            anyhow::bail!(
+                "--stdout only applies to .litematic, .schem, .schematic and .nbt outputs"
+            );
+        }
+        Ok(Options {
+            input,
+            output,
+            infer_latches,
+            pla_matrix,
+            nand_nor,
+            goal,
+            opt_level,
+            placer,
+            layers,
+            max_size,
+            hierarchy,
+            spacing,
+            layer_height,
+            origin,
+            aspect,
+            equalize_delays,
+            wire_lanes,
+            instant_wire,
+            tunnels,
+            net_order,
+            allow_shorts,
+            allow_unwired,
+            place_retries,
+            support_block,
+            floor_block,
+            color_nets,
+            labels,
+            lamps,
+            input_hardware,
+            enclose,
+            sponge_version,
+            name,
+            author,
+            description,
+            at,
+            verify,
+            gzip,
+            stdout,
+            emit,
+        })
+    }
+}
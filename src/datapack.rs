@@ -0,0 +1,108 @@
+//! Datapack output: a zip holding `pack.mcmeta` and `build`/`teardown`
+//! functions that place and remove the circuit relative to where they run,
+//! e.g. `/function adder:build`.
+
+use crate::schematic::{setblock_commands, Blocks};
+use anyhow::Result;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::Write;
+use std::path::Path;
+
+/// Pack format of 1.21; newer versions are listed as supported.
+const PACK_FORMAT: i32 = 48;
+
+/// Write the datapack for `blocks`, namespaced after the file name.
+pub fn write(blocks: &Blocks, path: &Path) -> Result<()> {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("circuit");
+    let namespace: String = stem
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mcmeta = serde_json::json!({
+        "pack": {
+            "pack_format": PACK_FORMAT,
+            "supported_formats": { "min_inclusive": PACK_FORMAT, "max_inclusive": 99 },
+            "description": format!("{} circuit, generated by schemlogica", stem),
+        }
+    });
+    let commands = setblock_commands(blocks);
+    let mut build = String::from("# Builds the circuit with its low corner one block east.\n");
+    let mut teardown = String::from("# Removes what build placed, top down.\n");
+    for cmd in &commands {
+        build.push_str(cmd);
+        build.push('\n');
+    }
+    for cmd in commands.iter().rev() {
+        // `setblock ~x ~y ~z state` -> the same position set to air.
+        let pos: Vec<&str> = cmd.split(' ').skip(1).take(3).collect();
+        teardown.push_str(&format!("setblock {} minecraft:air\n", pos.join(" ")));
+    }
+
+    let functions = format!("data/{}/function", namespace);
+    let files = [
+        (
+            "pack.mcmeta".to_string(),
+            serde_json::to_string_pretty(&mcmeta)?,
+        ),
+        (format!("{}/build.mcfunction", functions), build),
+        (format!("{}/teardown.mcfunction", functions), teardown),
+    ];
+    let mut zip = Vec::new();
+    let mut central = Vec::new();
+    for (name, text) in &files {
+        let mut crc = Crc::new();
+        crc.update(text.as_bytes());
+        let mut enc = DeflateEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(text.as_bytes())?;
+        let data = enc.finish()?;
+        let offset = zip.len() as u32;
+        // Version 2.0, no flags, deflate, dated 1980-01-01.
+        let fields = |out: &mut Vec<u8>| {
+            out.extend_from_slice(&20u16.to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&8u16.to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&0x21u16.to_le_bytes());
+            out.extend_from_slice(&crc.sum().to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+        };
+        zip.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        fields(&mut zip);
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(&data);
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        fields(&mut central);
+        // Comment length, disk, internal and external attributes.
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let central_offset = zip.len() as u32;
+    zip.extend_from_slice(&central);
+    zip.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    zip.extend_from_slice(&[0; 4]);
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&central_offset.to_le_bytes());
+    zip.extend_from_slice(&0u16.to_le_bytes());
+    std::fs::write(path, zip)?;
+    Ok(())
+}
@@ -0,0 +1,385 @@
+//! Importer for circuits saved by hneemann's Digital simulator (`.dig`).
+//! Maps gates, pins, tunnels, constants and D flip-flops onto gates and
+//! recovers connectivity from the wire geometry.
+//!
+//! Only single-bit designs without embedded circuits are supported.
+
+use crate::compiler::{next_id, Circuit, Ctx};
+use anyhow::Result;
+use roxmltree::Node;
+use std::collections::HashMap;
+
+type Point = (i32, i32);
+
+/// Digital's grid pitch; pin offsets are multiples of it.
+const SIZE: i32 = 20;
+
+/// A pin of an element: where it is and what it does.
+enum Port {
+    /// Drives the net with this signal.
+    Out(String),
+    /// Input `index` of element `comp`, inverted when `negated`.
+    In {
+        comp: usize,
+        index: usize,
+        negated: bool,
+    },
+    /// Output pin or LED named `label`.
+    Sink(String),
+    /// Joins every tunnel with the same net name.
+    Tunnel(String),
+}
+
+struct Element {
+    /// AND, OR, XOR, their inverted forms, NOT, or DFF.
+    kind: String,
+    pos: Point,
+    inputs: usize,
+    output: String,
+}
+
+/// The element's attribute entries, keyed by name, with their value node.
+fn attributes<'a>(node: Node<'a, 'a>) -> HashMap<&'a str, Node<'a, 'a>> {
+    node.children()
+        .find(|n| n.has_tag_name("elementAttributes"))
+        .into_iter()
+        .flat_map(|a| a.children().filter(|e| e.has_tag_name("entry")))
+        .filter_map(|entry| {
+            let mut values = entry.children().filter(|n| n.is_element());
+            let key = values.next()?.text()?;
+            Some((key, values.next()?))
+        })
+        .collect()
+}
+
+fn parse_pos(node: Node) -> Result<Point> {
+    let coord = |a: &str| -> Result<i32> {
+        Ok(node
+            .attribute(a)
+            .ok_or_else(|| anyhow::anyhow!("`{}` without {}", node.tag_name().name(), a))?
+            .parse()?)
+    };
+    Ok((coord("x")?, coord("y")?))
+}
+
+/// Place a pin offset relative to an element, applying its mirroring and
+/// its rotation in quarter turns.
+fn transform(pos: Point, offset: Point, rotation: i32, mirror: bool) -> Point {
+    let (x, y) = (offset.0, if mirror { -offset.1 } else { offset.1 });
+    let (dx, dy) = match rotation.rem_euclid(4) {
+        0 => (x, y),
+        1 => (y, -x),
+        2 => (-x, -y),
+        _ => (-y, x),
+    };
+    (pos.0 + dx, pos.1 + dy)
+}
+
+/// Union-find over grid points.
+struct Nets {
+    parent: HashMap<Point, Point>,
+}
+
+impl Nets {
+    fn find(&mut self, p: Point) -> Point {
+        let parent = *self.parent.entry(p).or_insert(p);
+        if parent == p {
+            return p;
+        }
+        let root = self.find(parent);
+        self.parent.insert(p, root);
+        root
+    }
+
+    fn union(&mut self, a: Point, b: Point) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent.insert(ra, rb);
+        }
+    }
+}
+
+/// Parse a `.dig` file and lower its circuit.
+pub fn compile(src: &str) -> Result<Circuit> {
+    let doc = roxmltree::Document::parse(src)?;
+    let circuit = doc.root_element();
+    if !circuit.has_tag_name("circuit") {
+        anyhow::bail!("not a Digital circuit");
+    }
+
+    let mut ctx = Ctx::new();
+    let mut inputs = Vec::new();
+    let mut ports: Vec<(Point, Port)> = Vec::new();
+    let mut elements: Vec<Element> = Vec::new();
+    let visual = circuit
+        .children()
+        .filter(|n| n.has_tag_name("visualElements"))
+        .flat_map(|v| v.children().filter(|n| n.has_tag_name("visualElement")));
+    for node in visual {
+        let name = node
+            .children()
+            .find(|n| n.has_tag_name("elementName"))
+            .and_then(|n| n.text())
+            .unwrap_or_default();
+        let pos = match node.children().find(|n| n.has_tag_name("pos")) {
+            Some(p) => parse_pos(p)?,
+            None => anyhow::bail!("{} without a position", name),
+        };
+        let attrs = attributes(node);
+        let at = format!("{} at ({},{})", name, pos.0, pos.1);
+        let int = |key: &str| -> Result<Option<i64>> {
+            match attrs.get(key).and_then(|v| v.text()) {
+                Some(t) => Ok(Some(t.trim().parse()?)),
+                None => Ok(None),
+            }
+        };
+        let flag = |key: &str| attrs.get(key).and_then(|v| v.text()) == Some("true");
+        if int("Bits")?.is_some_and(|b| b != 1) {
+            anyhow::bail!("{}: multi-bit components are not supported", at);
+        }
+        let rotation = attrs
+            .get("rotation")
+            .and_then(|r| r.attribute("rotation"))
+            .map_or(Ok(0), str::parse)?;
+        let mirror = flag("mirror");
+        let place = |offset: Point| transform(pos, offset, rotation, mirror);
+        let inverted: Vec<&str> = attrs
+            .get("inverterConfig")
+            .into_iter()
+            .flat_map(|c| c.children().filter_map(|n| n.text()))
+            .collect();
+        let label = attrs
+            .get("Label")
+            .and_then(|l| l.text())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| format!("{}_{}_{}", name.to_lowercase(), pos.0, pos.1));
+        match name {
+            "And" | "NAnd" | "Or" | "NOr" | "XOr" | "XNOr" => {
+                let n = int("Inputs")?.unwrap_or(2) as usize;
+                let kind = name.to_uppercase();
+                // Inverting gates add a bubble; the wide shape is one wider.
+                let mut width = if flag("wideShape") { 4 } else { 3 };
+                if kind.starts_with('N') || kind == "XNOR" {
+                    width += 1;
+                }
+                let comp = elements.len();
+                for index in 0..n {
+                    // Inputs are one grid step apart, skipping the middle row
+                    // for an even count so the output sits centred.
+                    let skip = if n.is_multiple_of(2) && index >= n / 2 { 1 } else { 0 };
+                    let y = (index as i32 + skip) * SIZE;
+                    ports.push((
+                        place((0, y)),
+                        Port::In {
+                            comp,
+                            index,
+                            negated: inverted.contains(&format!("In_{}", index + 1).as_str()),
+                        },
+                    ));
+                }
+                let output = next_id();
+                ports.push((
+                    place((width * SIZE, (n / 2) as i32 * SIZE)),
+                    Port::Out(output.clone()),
+                ));
+                elements.push(Element {
+                    kind,
+                    pos,
+                    inputs: n,
+                    output,
+                });
+            }
+            "Not" => {
+                let comp = elements.len();
+                ports.push((
+                    place((0, 0)),
+                    Port::In {
+                        comp,
+                        index: 0,
+                        negated: false,
+                    },
+                ));
+                let output = next_id();
+                let width = if flag("wideShape") { 3 } else { 2 };
+                ports.push((place((width * SIZE, 0)), Port::Out(output.clone())));
+                elements.push(Element {
+                    kind: "NOT".into(),
+                    pos,
+                    inputs: 1,
+                    output,
+                });
+            }
+            "D_FF" => {
+                let comp = elements.len();
+                for (index, pin) in ["D", "C"].iter().enumerate() {
+                    ports.push((
+                        place((0, index as i32 * SIZE)),
+                        Port::In {
+                            comp,
+                            index,
+                            negated: inverted.contains(pin),
+                        },
+                    ));
+                }
+                let output = next_id();
+                let not_q = ctx.emit("NOT", vec![output.clone()]);
+                ports.push((place((3 * SIZE, 0)), Port::Out(output.clone())));
+                ports.push((place((3 * SIZE, SIZE)), Port::Out(not_q)));
+                elements.push(Element {
+                    kind: "DFF".into(),
+                    pos,
+                    inputs: 2,
+                    output,
+                });
+            }
+            "In" | "Clock" | "Button" => {
+                if inputs.contains(&label) {
+                    anyhow::bail!("{}: input `{}` is defined twice", at, label);
+                }
+                ports.push((pos, Port::Out(ctx.input(&label))));
+                inputs.push(label);
+            }
+            "Out" | "LED" => ports.push((pos, Port::Sink(label))),
+            "Const" | "Ground" | "VDD" => {
+                let high = match name {
+                    "Const" => int("Value")?.unwrap_or(1) != 0,
+                    n => n == "VDD",
+                };
+                let sig = if high {
+                    "CONST_TRUE_SIG"
+                } else {
+                    "CONST_FALSE_SIG"
+                };
+                ports.push((pos, Port::Out(sig.into())));
+            }
+            "Tunnel" => {
+                let Some(net) = attrs.get("NetName").and_then(|n| n.text()) else {
+                    anyhow::bail!("{}: tunnel without a net name", at);
+                };
+                ports.push((pos, Port::Tunnel(net.to_string())));
+            }
+            "Text" | "Probe" | "Rectangle" | "Testcase" => {}
+            n if n.ends_with(".dig") => {
+                anyhow::bail!(
+                    "{}: embedded circuits are not supported; flatten the design",
+                    at
+                )
+            }
+            n => anyhow::bail!("{}: unsupported component `{}`", at, n),
+        }
+    }
+
+    let mut wires: Vec<(Point, Point)> = Vec::new();
+    let wire_nodes = circuit
+        .children()
+        .filter(|n| n.has_tag_name("wires"))
+        .flat_map(|w| w.children().filter(|n| n.has_tag_name("wire")));
+    for wire in wire_nodes {
+        let end = |tag: &str| -> Result<Point> {
+            match wire.children().find(|n| n.has_tag_name(tag)) {
+                Some(p) => parse_pos(p),
+                None => anyhow::bail!("wire without {}", tag),
+            }
+        };
+        wires.push((end("p1")?, end("p2")?));
+    }
+
+    // Connectivity: wire endpoints, plus any pin or endpoint that lands on
+    // another wire.
+    let mut nets = Nets {
+        parent: HashMap::new(),
+    };
+    let mut points: Vec<Point> = ports.iter().map(|(p, _)| *p).collect();
+    for &(a, b) in &wires {
+        nets.union(a, b);
+        points.push(a);
+        points.push(b);
+    }
+    for &p in &points {
+        for &(a, b) in &wires {
+            let on_x = a.0 == b.0 && p.0 == a.0 && p.1 > a.1.min(b.1) && p.1 < a.1.max(b.1);
+            let on_y = a.1 == b.1 && p.1 == a.1 && p.0 > a.0.min(b.0) && p.0 < a.0.max(b.0);
+            if on_x || on_y {
+                nets.union(p, a);
+            }
+        }
+    }
+    let mut tunnels: HashMap<&str, Point> = HashMap::new();
+    for (p, port) in &ports {
+        if let Port::Tunnel(net) = port {
+            match tunnels.get(net.as_str()) {
+                Some(&first) => nets.union(*p, first),
+                None => {
+                    tunnels.insert(net, *p);
+                }
+            }
+        }
+    }
+    let mut driver: HashMap<Point, String> = HashMap::new();
+    for (p, port) in &ports {
+        if let Port::Out(sig) = port {
+            let root = nets.find(*p);
+            if driver.insert(root, sig.clone()).is_some() {
+                anyhow::bail!("the net at ({},{}) has multiple drivers", p.0, p.1);
+            }
+        }
+    }
+
+    let mut element_inputs: Vec<Vec<Option<String>>> =
+        elements.iter().map(|e| vec![None; e.inputs]).collect();
+    let mut outputs = Vec::new();
+    for (p, port) in &ports {
+        let sig = driver.get(&nets.find(*p)).cloned();
+        match port {
+            Port::In {
+                comp,
+                index,
+                negated,
+            } => {
+                element_inputs[*comp][*index] = match sig {
+                    Some(s) if *negated => Some(ctx.emit("NOT", vec![s])),
+                    s => s,
+                }
+            }
+            Port::Sink(label) => {
+                let Some(sig) = sig else {
+                    anyhow::bail!("output `{}` at ({},{}) is not connected", label, p.0, p.1);
+                };
+                outputs.push((ctx.emit("BUF", vec![sig]), label.clone()));
+            }
+            _ => {}
+        }
+    }
+    for (e, ins) in elements.iter().zip(element_inputs) {
+        // Digital treats a floating input as an error, and so do we.
+        let Some(ins) = ins.into_iter().collect::<Option<Vec<String>>>() else {
+            anyhow::bail!(
+                "{} at ({},{}) has an unconnected input",
+                e.kind,
+                e.pos.0,
+                e.pos.1
+            );
+        };
+        lower_element(&mut ctx, e, ins);
+    }
+    Ok(ctx.finish(inputs, outputs))
+}
+
+/// Emit an element driving its reserved output. Multi-input XOR is odd
+/// parity, as in Digital.
+fn lower_element(ctx: &mut Ctx, e: &Element, ins: Vec<String>) {
+    let out = e.output.clone();
+    if ins.len() <= 2 {
+        ctx.emit_into(&e.kind, ins, out);
+        return;
+    }
+    let invert = e.kind.starts_with('N') || e.kind == "XNOR";
+    let value = match e.kind.trim_start_matches('N') {
+        "AND" => ctx.reduce_balanced(ins, |c, x, y| c.emit("AND", vec![x, y])),
+        "OR" => ctx.reduce_balanced(ins, |c, x, y| c.emit("OR", vec![x, y])),
+        _ => ctx.reduce_balanced(ins, |c, x, y| c.xor(x, y)),
+    };
+    let kind = if invert { "NOT" } else { "BUF" };
+    ctx.emit_into(kind, vec![value.unwrap()], out);
+}
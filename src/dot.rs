@@ -0,0 +1,326 @@
+//! Graphviz DOT input: a `digraph` whose nodes are gates and whose edges
+//! are nets, for hand-drawn or scripted circuits.
+//!
+//! ```dot
+//! digraph halfadder {
+//!     a [kind=INPUT]; b [kind=INPUT];
+//!     s [kind=XOR]; c [kind=AND];
+//!     a -> s; b -> s; a -> c; b -> c;
+//!     sum [kind=OUTPUT]; carry [kind=OUTPUT];
+//!     s -> sum; c -> carry;
+//! }
+//! ```
+//!
+//! Every node needs a `kind`: a gate kind, or OUTPUT for an output that
+//! takes one edge. Gate inputs follow edge order unless an edge sets
+//! `input=N`; parameters are given as `params="8"`. The result is checked
+//! like a JSON netlist.
+
+use crate::compiler::{Circuit, Gate};
+use crate::netlist;
+use anyhow::Result;
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Debug)]
+enum Tok {
+    Id(String),
+    Sym(&'static str),
+}
+
+fn lex(src: &str) -> Result<Vec<(usize, Tok)>> {
+    let mut toks = Vec::new();
+    let chars: Vec<char> = src.chars().collect();
+    let (mut i, mut line) = (0, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' || (c == '/' && next == Some('/')) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' {
+            let mut s = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' && chars.get(i + 1) == Some(&'"') {
+                    i += 1;
+                }
+                s.push(chars[i]);
+                i += 1;
+            }
+            if i == chars.len() {
+                anyhow::bail!("line {}: unterminated string", line);
+            }
+            i += 1;
+            toks.push((line, Tok::Id(s)));
+        } else if c.is_alphanumeric() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            toks.push((line, Tok::Id(chars[start..i].iter().collect())));
+        } else if c == '-' && next == Some('>') {
+            toks.push((line, Tok::Sym("->")));
+            i += 2;
+        } else if c == '-' && next == Some('-') {
+            anyhow::bail!(
+                "line {}: undirected edges are not supported; use a digraph",
+                line
+            );
+        } else {
+            let sym = match c {
+                '{' => "{",
+                '}' => "}",
+                '[' => "[",
+                ']' => "]",
+                ';' => ";",
+                ',' => ",",
+                '=' => "=",
+                ':' => anyhow::bail!("line {}: node ports are not supported", line),
+                c => anyhow::bail!("line {}: unexpected character `{}`", line, c),
+            };
+            toks.push((line, Tok::Sym(sym)));
+            i += 1;
+        }
+    }
+    Ok(toks)
+}
+
+struct Parser {
+    toks: Vec<(usize, Tok)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn line(&self) -> usize {
+        self.toks
+            .get(self.pos)
+            .or(self.toks.last())
+            .map_or(0, |(l, _)| *l)
+    }
+
+    fn eat(&mut self, sym: &'static str) -> bool {
+        if self.peek() == Some(&Tok::Sym(sym)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, sym: &'static str) -> Result<()> {
+        if !self.eat(sym) {
+            anyhow::bail!("line {}: expected `{}`", self.line(), sym);
+        }
+        Ok(())
+    }
+
+    fn id(&mut self) -> Result<String> {
+        match self.peek().cloned() {
+            Some(Tok::Id(s)) => {
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => anyhow::bail!("line {}: expected a name", self.line()),
+        }
+    }
+
+    /// `[a=b, c=d]`, possibly several lists in a row.
+    fn attrs(&mut self) -> Result<HashMap<String, String>> {
+        let mut attrs = HashMap::new();
+        while self.eat("[") {
+            while !self.eat("]") {
+                let key = self.id()?;
+                self.expect("=")?;
+                attrs.insert(key, self.id()?);
+                if !self.eat(",") {
+                    self.eat(";");
+                }
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/// Parse a DOT graph and check it as a netlist.
+pub fn compile(src: &str) -> Result<Circuit> {
+    let mut p = Parser {
+        toks: lex(src)?,
+        pos: 0,
+    };
+    if p.peek() == Some(&Tok::Id("strict".into())) {
+        p.pos += 1;
+    }
+    match p.id()?.as_str() {
+        "digraph" => {}
+        "graph" => anyhow::bail!("undirected graphs are not supported; use a digraph"),
+        w => anyhow::bail!("expected `digraph`, found `{}`", w),
+    }
+    if matches!(p.peek(), Some(Tok::Id(_))) {
+        p.pos += 1;
+    }
+    p.expect("{")?;
+
+    // Nodes in order of first mention, with their attributes.
+    let mut nodes: Vec<(String, HashMap<String, String>)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    // Per target: (source, explicit input index, line).
+    let mut edges: HashMap<String, Vec<(String, Option<usize>, usize)>> = HashMap::new();
+    let mut node = |name: &str, nodes: &mut Vec<(String, HashMap<String, String>)>| {
+        *index.entry(name.to_string()).or_insert_with(|| {
+            nodes.push((name.to_string(), HashMap::new()));
+            nodes.len() - 1
+        })
+    };
+    while !p.eat("}") {
+        if p.peek().is_none() {
+            anyhow::bail!("unexpected end of graph; missing `}}`");
+        }
+        let line = p.line();
+        let first = p.id()?;
+        match first.as_str() {
+            "subgraph" => anyhow::bail!("line {}: subgraphs are not supported", line),
+            // Default attribute statements carry only drawing hints.
+            "graph" | "node" | "edge" => {
+                p.attrs()?;
+            }
+            _ if p.eat("=") => {
+                p.id()?;
+            }
+            _ => {
+                let mut chain = vec![first];
+                while p.eat("->") {
+                    chain.push(p.id()?);
+                }
+                let attrs = p.attrs()?;
+                if chain.len() == 1 {
+                    let i = node(&chain[0], &mut nodes);
+                    nodes[i].1.extend(attrs);
+                } else {
+                    let input = match attrs.get("input") {
+                        Some(n) => Some(
+                            n.parse()
+                                .map_err(|_| anyhow::anyhow!("line {}: bad input `{}`", line, n))?,
+                        ),
+                        None => None,
+                    };
+                    for pair in chain.windows(2) {
+                        node(&pair[0], &mut nodes);
+                        node(&pair[1], &mut nodes);
+                        edges.entry(pair[1].clone()).or_default().push((
+                            pair[0].clone(),
+                            input,
+                            line,
+                        ));
+                    }
+                }
+            }
+        }
+        p.eat(";");
+    }
+    if p.peek().is_some() {
+        anyhow::bail!("line {}: text after the graph", p.line());
+    }
+
+    let signal = |name: &str, nodes: &[(String, HashMap<String, String>)]| -> String {
+        let kind = index
+            .get(name)
+            .and_then(|&i| nodes[i].1.get("kind"))
+            .map(|k| k.to_uppercase());
+        match kind.as_deref() {
+            Some("CONST_TRUE") => "CONST_TRUE_SIG".into(),
+            Some("CONST_FALSE") => "CONST_FALSE_SIG".into(),
+            _ => name.to_string(),
+        }
+    };
+    let mut gates = Vec::new();
+    let mut outputs = Vec::new();
+    for (name, attrs) in &nodes {
+        let Some(kind) = attrs.get("kind").map(|k| k.to_uppercase()) else {
+            anyhow::bail!("node `{}` has no kind", name);
+        };
+        // Inputs in edge order, with explicit `input=N` edges pinned first.
+        let incoming = edges.remove(name).unwrap_or_default();
+        let mut inputs: Vec<Option<String>> = Vec::new();
+        for (src, slot, line) in &incoming {
+            if let Some(slot) = *slot {
+                if inputs.len() <= slot {
+                    inputs.resize(slot + 1, None);
+                }
+                if inputs[slot].is_some() {
+                    anyhow::bail!(
+                        "line {}: input {} of `{}` is connected twice",
+                        line,
+                        slot,
+                        name
+                    );
+                }
+                inputs[slot] = Some(signal(src, &nodes));
+            }
+        }
+        for (src, _, _) in incoming.iter().filter(|(_, slot, _)| slot.is_none()) {
+            match inputs.iter().position(|i| i.is_none()) {
+                Some(free) => inputs[free] = Some(signal(src, &nodes)),
+                None => inputs.push(Some(signal(src, &nodes))),
+            }
+        }
+        let Some(inputs) = inputs.into_iter().collect::<Option<Vec<String>>>() else {
+            anyhow::bail!("node `{}` has a gap in its numbered inputs", name);
+        };
+        let params = match attrs.get("params") {
+            Some(list) => list
+                .split(',')
+                .map(|v| v.trim().parse())
+                .collect::<Result<Vec<i64>, _>>()
+                .map_err(|_| anyhow::anyhow!("node `{}`: bad params `{}`", name, list))?,
+            None => Vec::new(),
+        };
+        let (id, kind) = match kind.as_str() {
+            "CONST_TRUE" | "CONST_FALSE" => continue,
+            "OUTPUT" => {
+                if inputs.len() != 1 {
+                    anyhow::bail!("output `{}` needs exactly one edge", name);
+                }
+                outputs.push(name.clone());
+                (format!("out_{}", name), "BUF".to_string())
+            }
+            "INPUT" => (format!("in_{}", name), kind),
+            _ => (format!("g_{}", name), kind),
+        };
+        gates.push(Gate {
+            id,
+            kind,
+            inputs,
+            output: name.clone(),
+            params,
+            block: String::new(),
+        });
+    }
+    netlist::check(Circuit {
+        gates,
+        inputs: Vec::new(),
+        outputs,
+        output_names: Vec::new(),
+        dont_care: Vec::new(),
+    })
+}
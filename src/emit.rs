@@ -0,0 +1,807 @@
+//! Side outputs requested with `--emit`, written next to the main output
+//! for other tools and for debugging.
+
+use crate::compiler::Circuit;
+use crate::layout::Layout;
+use crate::primitives::primitive_for;
+use crate::schematic::{bounds, Blocks};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Kinds accepted by `--emit`.
+pub const KINDS: &[&str] = &["netlist", "svg", "obj", "verilog", "layers", "report"];
+
+/// Write one side output for the design built into `output`.
+pub fn emit(
+    kind: &str,
+    circuit: &Circuit,
+    layout: &Layout,
+    blocks: &Blocks,
+    output: &Path,
+) -> Result<()> {
+    match kind {
+        "netlist" => {
+            let path = sibling(output, "netlist.json");
+            write_netlist(circuit, layout, &path)?;
+            status!("Wrote {}", path.display());
+        }
+        "svg" => {
+            let path = sibling(output, "svg");
+            std::fs::write(&path, render_svg(circuit, layout, blocks))?;
+            status!("Wrote {}", path.display());
+        }
+        "obj" => {
+            let path = sibling(output, "obj");
+            let materials = sibling(output, "mtl");
+            let (obj, mtl) = render_obj(blocks, &materials);
+            std::fs::write(&path, obj)?;
+            std::fs::write(&materials, mtl)?;
+            status!("Wrote {} and {}", path.display(), materials.display());
+        }
+        "verilog" => {
+            // Not plain `.v`, which may well be the design's own source.
+            let path = sibling(output, "netlist.v");
+            let name = output
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("circuit");
+            std::fs::write(&path, render_verilog(circuit, name))?;
+            status!("Wrote {}", path.display());
+        }
+        "layers" => status!("{}", render_layers(blocks).trim_end()),
+        "report" => {
+            let path = sibling(output, "report.md");
+            let name = output
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("circuit");
+            std::fs::write(&path, render_report(circuit, blocks, name))?;
+            status!("Wrote {}", path.display());
+        }
+        _ => anyhow::bail!("unknown --emit kind `{}`", kind),
+    }
+    Ok(())
+}
+
+/// `output` with its extension replaced, e.g. `adder.netlist.json`.
+fn sibling(output: &Path, extension: &str) -> PathBuf {
+    output.with_extension(extension)
+}
+
+/// The optimized circuit as the JSON netlist frontend reads it, plus a
+/// `layout` list giving each gate's position.
+fn write_netlist(circuit: &Circuit, layout: &Layout, path: &Path) -> Result<()> {
+    let mut value = serde_json::to_value(circuit)?;
+    let placement: Vec<serde_json::Value> = layout
+        .positions
+        .iter()
+        .map(|(gate, x, y, z)| serde_json::json!({ "gate": gate, "x": x, "y": y, "z": z }))
+        .collect();
+    value["layout"] = serde_json::Value::Array(placement);
+    std::fs::write(path, serde_json::to_string_pretty(&value)? + "\n")?;
+    Ok(())
+}
+
+/// Pixels per block in the SVG.
+const CELL: i32 = 12;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A top-down view with one group per Y level, bottom first: solid blocks,
+/// wires as paths in the level's colour, components, and gate footprints
+/// labelled with their kind. Hiding groups in a viewer shows single levels.
+fn render_svg(circuit: &Circuit, layout: &Layout, blocks: &Blocks) -> String {
+    let ((min_x, min_y, min_z), (width, height, length)) = bounds(&blocks.placed);
+    // The first block placed at a position wins, as in the schematic.
+    let mut at: HashMap<(i32, i32, i32), &str> = HashMap::new();
+    for (x, y, z, name, _) in &blocks.placed {
+        at.entry((*x, *y, *z)).or_insert(name.as_str());
+    }
+    let mut cells: Vec<(&(i32, i32, i32), &&str)> = at.iter().collect();
+    cells.sort();
+    let kinds: HashMap<&str, &crate::compiler::Gate> =
+        circuit.gates.iter().map(|g| (g.id.as_str(), g)).collect();
+    let px = |x: i32| (x - min_x) * CELL;
+    let pz = |z: i32| (z - min_z) * CELL;
+    let wire = |pos: (i32, i32, i32)| {
+        matches!(
+            at.get(&pos).copied(),
+            Some("minecraft:redstone_wire" | "minecraft:repeater")
+        )
+    };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width * CELL,
+        length * CELL
+    );
+    svg.push_str(
+        "<style>\n\
+         .solid { fill: #999; fill-opacity: 0.25; }\n\
+         .wire { fill: none; stroke-width: 3; stroke-linecap: round; }\n\
+         .repeater { fill: #777; stroke: #333; }\n\
+         .torch { fill: #e22; }\n\
+         .input { fill: #a60; }\n\
+         .gate { fill: none; stroke: #246; stroke-dasharray: 4 2; }\n\
+         .label { font: 9px sans-serif; fill: #123; }\n\
+         </style>\n",
+    );
+    let _ = writeln!(svg, r##"<rect width="100%" height="100%" fill="#fff"/>"##);
+    for y in min_y..min_y + height {
+        let color = format!("hsl({}, 70%, 42%)", ((y - min_y) * 67).rem_euclid(360));
+        let _ = writeln!(svg, r#"<g id="y{0}" class="layer" data-y="{0}">"#, y);
+        let mut path = String::new();
+        for &(&(x, by, z), &name) in &cells {
+            if by != y {
+                continue;
+            }
+            let (cx, cz) = (px(x) + CELL / 2, pz(z) + CELL / 2);
+            match name {
+                "minecraft:redstone_wire" | "minecraft:repeater" => {
+                    let mut joined = false;
+                    for (dx, dz) in [(1, 0), (0, 1)] {
+                        if wire((x + dx, y, z + dz)) {
+                            let _ = write!(
+                                path,
+                                "M{} {}L{} {}",
+                                cx,
+                                cz,
+                                cx + dx * CELL,
+                                cz + dz * CELL
+                            );
+                            joined = true;
+                        }
+                    }
+                    let left = wire((x - 1, y, z)) || wire((x, y, z - 1));
+                    if !joined && !left {
+                        let _ = write!(path, "M{} {}l0 0", cx, cz);
+                    }
+                    if name == "minecraft:repeater" {
+                        let _ = writeln!(
+                            svg,
+                            r#"<rect class="repeater" x="{}" y="{}" width="{}" height="{}"/>"#,
+                            px(x) + 2,
+                            pz(z) + 2,
+                            CELL - 4,
+                            CELL - 4
+                        );
+                    }
+                }
+                "minecraft:redstone_torch" | "minecraft:redstone_wall_torch" => {
+                    let _ = writeln!(
+                        svg,
+                        r#"<circle class="torch" cx="{}" cy="{}" r="{}"/>"#,
+                        cx,
+                        cz,
+                        CELL / 3
+                    );
+                }
+                "minecraft:lever"
+                | "minecraft:stone_button"
+                | "minecraft:oak_button"
+                | "minecraft:stone_pressure_plate"
+                | "minecraft:oak_pressure_plate" => {
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect class="input" x="{}" y="{}" width="{}" height="{}"/>"#,
+                        px(x) + 3,
+                        pz(z) + 3,
+                        CELL - 6,
+                        CELL - 6
+                    );
+                }
+                _ => {
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect class="solid" x="{}" y="{}" width="{}" height="{}"/>"#,
+                        px(x),
+                        pz(z),
+                        CELL,
+                        CELL
+                    );
+                }
+            }
+        }
+        if !path.is_empty() {
+            let _ = writeln!(
+                svg,
+                r#"<path class="wire" stroke="{}" d="{}"/>"#,
+                color, path
+            );
+        }
+        for (id, gx, gy, gz) in &layout.positions {
+            if *gy != y {
+                continue;
+            }
+            let Some(gate) = kinds.get(id.as_str()) else {
+                continue;
+            };
+            let prim = primitive_for(&gate.kind, &gate.params);
+            let _ = writeln!(
+                svg,
+                r#"<g><title>{} ({})</title><rect class="gate" x="{}" y="{}" width="{}" height="{}"/><text class="label" x="{}" y="{}">{}</text></g>"#,
+                escape(id),
+                escape(&gate.kind),
+                px(*gx),
+                pz(*gz),
+                prim.size_x * CELL,
+                prim.size_z * CELL,
+                px(*gx) + 2,
+                pz(*gz) + 10,
+                escape(&gate.kind)
+            );
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Colour (and opacity) of a block in the 3D preview.
+fn block_color(name: &str) -> ([f32; 3], f32) {
+    match name.strip_prefix("minecraft:").unwrap_or(name) {
+        "redstone_wire" => ([0.80, 0.05, 0.05], 1.0),
+        "redstone_block" => ([0.65, 0.08, 0.05], 1.0),
+        "redstone_torch" | "redstone_wall_torch" => ([1.00, 0.25, 0.10], 1.0),
+        "repeater" | "comparator" => ([0.62, 0.60, 0.58], 1.0),
+        "redstone_lamp" => ([0.90, 0.70, 0.35], 1.0),
+        "waxed_copper_bulb" => ([0.75, 0.45, 0.30], 1.0),
+        "powered_rail" => ([0.80, 0.65, 0.30], 1.0),
+        "observer" => ([0.35, 0.35, 0.35], 1.0),
+        "lever" | "stone_button" | "oak_button" => ([0.45, 0.35, 0.20], 1.0),
+        "stone_pressure_plate" | "oak_pressure_plate" => ([0.55, 0.55, 0.55], 1.0),
+        "oak_sign" | "oak_wall_sign" => ([0.72, 0.58, 0.36], 1.0),
+        "glass" => ([0.75, 0.90, 0.95], 0.35),
+        "sandstone" => ([0.86, 0.80, 0.60], 1.0),
+        "cobblestone" | "stone" | "smooth_stone" | "stone_bricks" => ([0.50, 0.50, 0.50], 1.0),
+        "slime_block" => ([0.45, 0.80, 0.35], 0.8),
+        "gold_block" => ([0.95, 0.80, 0.25], 1.0),
+        "iron_block" => ([0.85, 0.85, 0.85], 1.0),
+        "quartz_block" => ([0.93, 0.91, 0.88], 1.0),
+        "glowstone" => ([0.98, 0.85, 0.55], 1.0),
+        "oak_door" => ([0.62, 0.48, 0.28], 1.0),
+        name if name.ends_with("_concrete") => (concrete_color(name), 1.0),
+        _ => ([0.70, 0.70, 0.70], 1.0),
+    }
+}
+
+/// Colour of a concrete block, as `--color-nets` puts under the wires.
+fn concrete_color(name: &str) -> [f32; 3] {
+    match name.trim_end_matches("_concrete") {
+        "white" => [0.81, 0.84, 0.85],
+        "orange" => [0.88, 0.38, 0.00],
+        "magenta" => [0.66, 0.19, 0.62],
+        "light_blue" => [0.14, 0.54, 0.78],
+        "yellow" => [0.94, 0.69, 0.08],
+        "lime" => [0.37, 0.66, 0.09],
+        "pink" => [0.84, 0.40, 0.56],
+        "gray" => [0.21, 0.22, 0.25],
+        "light_gray" => [0.49, 0.49, 0.45],
+        "cyan" => [0.08, 0.47, 0.53],
+        "purple" => [0.39, 0.12, 0.61],
+        "blue" => [0.17, 0.18, 0.56],
+        "brown" => [0.38, 0.24, 0.12],
+        "green" => [0.29, 0.36, 0.14],
+        "red" => [0.56, 0.13, 0.13],
+        "black" => [0.03, 0.04, 0.06],
+        _ => [0.70, 0.70, 0.70],
+    }
+}
+
+/// The box a block fills, in sixteenths: components are drawn at roughly
+/// their in-game size, everything else as a full cube.
+fn block_shape(name: &str) -> [i32; 6] {
+    match name.strip_prefix("minecraft:").unwrap_or(name) {
+        "redstone_wire" => [0, 0, 0, 16, 1, 16],
+        "repeater" | "comparator" => [0, 0, 0, 16, 2, 16],
+        "powered_rail" => [0, 0, 0, 16, 1, 16],
+        "redstone_torch" | "redstone_wall_torch" => [7, 0, 7, 9, 10, 9],
+        "lever" => [5, 0, 4, 11, 3, 12],
+        "stone_button" | "oak_button" => [5, 0, 6, 11, 2, 10],
+        "stone_pressure_plate" | "oak_pressure_plate" => [1, 0, 1, 15, 1, 15],
+        _ => [0, 0, 0, 16, 16, 16],
+    }
+}
+
+/// A Wavefront OBJ mesh of the blocks with one material per block type,
+/// and its MTL file. Faces between two full blocks are left out.
+fn render_obj(blocks: &Blocks, materials: &Path) -> (String, String) {
+    let ((min_x, min_y, min_z), _) = bounds(&blocks.placed);
+    let mut at: HashMap<(i32, i32, i32), &str> = HashMap::new();
+    for (x, y, z, name, _) in &blocks.placed {
+        at.entry((*x, *y, *z)).or_insert(name.as_str());
+    }
+    let full = |pos: (i32, i32, i32)| {
+        at.get(&pos)
+            .is_some_and(|n| block_shape(n) == [0, 0, 0, 16, 16, 16])
+    };
+    let mut by_name: HashMap<&str, Vec<(i32, i32, i32)>> = HashMap::new();
+    for (&pos, &name) in &at {
+        by_name.entry(name).or_default().push(pos);
+    }
+    let mut names: Vec<&str> = by_name.keys().copied().collect();
+    names.sort();
+
+    let mtl_name = materials
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut obj = format!("# Generated by schemlogica\nmtllib {}\n", mtl_name);
+    // One normal per face direction, in the order of FACES.
+    obj.push_str("vn 1 0 0\nvn -1 0 0\nvn 0 1 0\nvn 0 -1 0\nvn 0 0 1\nvn 0 0 -1\n");
+    // Each face: the neighbour it touches and its corners, as picks of the
+    // low (0) or high (1) bound per axis, counter-clockwise from outside.
+    type Face = ((i32, i32, i32), [[usize; 3]; 4]);
+    const FACES: [Face; 6] = [
+        ((1, 0, 0), [[1, 0, 0], [1, 1, 0], [1, 1, 1], [1, 0, 1]]),
+        ((-1, 0, 0), [[0, 0, 0], [0, 0, 1], [0, 1, 1], [0, 1, 0]]),
+        ((0, 1, 0), [[0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]]),
+        ((0, -1, 0), [[0, 0, 0], [1, 0, 0], [1, 0, 1], [0, 0, 1]]),
+        ((0, 0, 1), [[0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]]),
+        ((0, 0, -1), [[0, 0, 0], [0, 1, 0], [1, 1, 0], [1, 0, 0]]),
+    ];
+    let mut mtl = String::from("# Generated by schemlogica\n");
+    let mut vertices = 0;
+    for name in names {
+        let material = name.strip_prefix("minecraft:").unwrap_or(name);
+        let (rgb, alpha) = block_color(name);
+        let _ = writeln!(
+            mtl,
+            "newmtl {}\nKd {} {} {}\nd {}\n",
+            material, rgb[0], rgb[1], rgb[2], alpha
+        );
+        let _ = writeln!(obj, "g {}\nusemtl {}", material, material);
+        let shape = block_shape(name);
+        let mut positions = by_name[name].clone();
+        positions.sort();
+        for (x, y, z) in positions {
+            let origin = [x - min_x, y - min_y, z - min_z];
+            for (n, ((dx, dy, dz), corners)) in FACES.iter().enumerate() {
+                if full((x, y, z)) && full((x + dx, y + dy, z + dz)) {
+                    continue;
+                }
+                for corner in corners {
+                    let coord = |axis: usize| {
+                        origin[axis] as f32 + shape[axis + 3 * corner[axis]] as f32 / 16.0
+                    };
+                    let _ = writeln!(obj, "v {} {} {}", coord(0), coord(1), coord(2));
+                }
+                let v = vertices + 1;
+                let _ = writeln!(
+                    obj,
+                    "f {}//{n} {}//{n} {}//{n} {}//{n}",
+                    v,
+                    v + 1,
+                    v + 2,
+                    v + 3,
+                    n = n + 1
+                );
+                vertices += 4;
+            }
+        }
+    }
+    (obj, mtl)
+}
+
+/// Words that cannot name a net.
+const VERILOG_KEYWORDS: &[&str] = &[
+    "always",
+    "and",
+    "assign",
+    "begin",
+    "buf",
+    "case",
+    "default",
+    "else",
+    "end",
+    "endcase",
+    "endmodule",
+    "for",
+    "if",
+    "initial",
+    "inout",
+    "input",
+    "module",
+    "nand",
+    "negedge",
+    "nor",
+    "not",
+    "or",
+    "output",
+    "posedge",
+    "reg",
+    "wire",
+    "xnor",
+    "xor",
+];
+
+/// Legal, unique Verilog identifiers for the circuit's signals.
+#[derive(Default)]
+struct VerilogNames {
+    used: HashSet<String>,
+    names: HashMap<String, String>,
+}
+
+impl VerilogNames {
+    /// A new identifier based on `base`.
+    fn fresh(&mut self, base: &str) -> String {
+        let mut name: String = base
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            name.insert(0, '_');
+        }
+        if VERILOG_KEYWORDS.contains(&name.as_str()) {
+            name.push('_');
+        }
+        let mut unique = name.clone();
+        let mut n = 1;
+        while !self.used.insert(unique.clone()) {
+            unique = format!("{}_{}", name, n);
+            n += 1;
+        }
+        unique
+    }
+
+    fn get(&mut self, signal: &str) -> String {
+        if let Some(name) = self.names.get(signal) {
+            return name.clone();
+        }
+        let name = self.fresh(signal);
+        self.names.insert(signal.to_string(), name.clone());
+        name
+    }
+}
+
+/// `name[i]` split into its base and bit index.
+fn split_bit(name: &str) -> Option<(&str, usize)> {
+    name.strip_suffix(']')
+        .and_then(|s| s.rsplit_once('['))
+        .and_then(|(base, i)| Some((base, i.parse::<usize>().ok()?)))
+}
+
+/// The circuit as one structural Verilog module: gate primitives, clocked
+/// `always` blocks for flip-flops and cross-coupled NORs for latches.
+/// Timed parts (clocks, delays, edge pulses) use `#` delays with one time
+/// unit per redstone tick. Input and output buses like `a[0]`..`a[3]`
+/// become vector ports.
+fn render_verilog(circuit: &Circuit, module: &str) -> String {
+    let mut names = VerilogNames::default();
+    let module = names.fresh(module);
+
+    // Input ports, grouping `name[i]` bits into vectors when they are
+    // complete from bit 0.
+    let mut buses: Vec<String> = Vec::new();
+    let mut bits: HashMap<&str, Vec<usize>> = HashMap::new();
+    for input in &circuit.inputs {
+        match split_bit(input) {
+            Some((base, i)) => {
+                if !bits.contains_key(base) {
+                    buses.push(base.to_string());
+                }
+                bits.entry(base).or_default().push(i);
+            }
+            None => buses.push(input.clone()),
+        }
+    }
+    let mut ports = Vec::new();
+    let mut decls = Vec::new();
+    let mut port_of: HashMap<String, String> = HashMap::new();
+    for base in &buses {
+        let mut indices = bits.get(base.as_str()).cloned().unwrap_or_default();
+        indices.sort();
+        let vector = !indices.is_empty() && indices.iter().enumerate().all(|(n, &i)| n == i);
+        if vector {
+            let port = names.fresh(base);
+            decls.push(format!("    input [{}:0] {};", indices.len() - 1, port));
+            for i in indices {
+                port_of.insert(format!("{}[{}]", base, i), format!("{}[{}]", port, i));
+            }
+            ports.push(port);
+        } else if indices.is_empty() {
+            let port = names.fresh(base);
+            port_of.insert(base.clone(), port.clone());
+            decls.push(format!("    input {};", port));
+            ports.push(port);
+        } else {
+            for i in indices {
+                let bit = format!("{}[{}]", base, i);
+                let port = names.fresh(&bit);
+                port_of.insert(bit, port.clone());
+                decls.push(format!("    input {};", port));
+                ports.push(port);
+            }
+        }
+    }
+    // INPUT gates drive their port directly.
+    for g in circuit.gates.iter().filter(|g| g.kind == "INPUT") {
+        let input = g.id.strip_prefix("in_").unwrap_or(&g.id);
+        if let Some(port) = port_of.get(input) {
+            names.names.insert(g.output.clone(), port.clone());
+        }
+    }
+
+    let regs: HashSet<&str> = circuit
+        .gates
+        .iter()
+        .filter(|g| matches!(g.kind.as_str(), "DFF" | "CLOCK"))
+        .map(|g| g.output.as_str())
+        .collect();
+    let driven: HashMap<&str, &str> = circuit
+        .gates
+        .iter()
+        .map(|g| (g.output.as_str(), g.kind.as_str()))
+        .collect();
+    let mut body = Vec::new();
+
+    // Output ports take their source names, with buses grouped the same way
+    // as the inputs. Scalars carry their signal; buses look theirs up.
+    let mut groups: Vec<(&str, Option<&str>)> = Vec::new();
+    let mut bits: HashMap<&str, Vec<(usize, &str)>> = HashMap::new();
+    for (i, out) in circuit.outputs.iter().enumerate() {
+        let name = circuit.output_names.get(i).unwrap_or(out);
+        match split_bit(name) {
+            Some((base, i)) => {
+                if !bits.contains_key(base) {
+                    groups.push((base, None));
+                }
+                bits.entry(base).or_default().push((i, out));
+            }
+            None => groups.push((name, Some(out))),
+        }
+    }
+    let mut seen = HashSet::new();
+    for (base, scalar) in groups {
+        if let Some(out) = scalar {
+            // A scalar output names its signal outright when nothing else has.
+            let port = names.fresh(base);
+            let own_port = seen.insert(out)
+                && !names.names.contains_key(out)
+                && !matches!(driven.get(out), Some(&"INPUT") | None);
+            if own_port {
+                names.names.insert(out.to_string(), port.clone());
+            } else {
+                body.push(format!("    assign {} = {};", port, names.get(out)));
+            }
+            decls.push(format!("    output {};", port));
+            ports.push(port);
+            continue;
+        }
+        let mut members = bits.remove(base).unwrap_or_default();
+        members.sort();
+        if members.iter().enumerate().all(|(n, &(i, _))| n == i) {
+            let port = names.fresh(base);
+            decls.push(format!("    output [{}:0] {};", members.len() - 1, port));
+            for (i, out) in members {
+                body.push(format!("    assign {}[{}] = {};", port, i, names.get(out)));
+            }
+            ports.push(port);
+        } else {
+            for (i, out) in members {
+                let bit = format!("{}[{}]", base, i);
+                let port = names.fresh(&bit);
+                body.push(format!("    assign {} = {};", port, names.get(out)));
+                decls.push(format!("    output {};", port));
+                ports.push(port);
+            }
+        }
+    }
+
+    let mut timed = false;
+    let mut nets = Vec::new();
+    for g in &circuit.gates {
+        if g.kind == "INPUT" {
+            continue;
+        }
+        let out = names.get(&g.output);
+        let ins: Vec<String> = g.inputs.iter().map(|i| names.get(i)).collect();
+        if !ports.contains(&out) || regs.contains(g.output.as_str()) {
+            let kind = if regs.contains(g.output.as_str()) {
+                "reg"
+            } else {
+                "wire"
+            };
+            nets.push(format!("    {} {};", kind, out));
+        }
+        match g.kind.as_str() {
+            "CONST_TRUE" => body.push(format!("    assign {} = 1'b1;", out)),
+            "CONST_FALSE" => body.push(format!("    assign {} = 1'b0;", out)),
+            "BUF" | "NOT" | "AND" | "OR" | "NAND" | "NOR" | "XOR" | "XNOR" | "WIDE_AND"
+            | "WIDE_OR" => {
+                let prim = g.kind.trim_start_matches("WIDE_").to_lowercase();
+                body.push(format!("    {} ({}, {});", prim, out, ins.join(", ")));
+            }
+            "MUX" => body.push(format!(
+                "    assign {} = {} ? {} : {};",
+                out, ins[0], ins[1], ins[2]
+            )),
+            "DFF" => {
+                body.push(format!("    initial {} = 1'b0;", out));
+                body.push(format!(
+                    "    always @(posedge {}) {} <= {};",
+                    ins[1], out, ins[0]
+                ));
+            }
+            "SRLATCH" => {
+                let inverse = names.fresh(&format!("{}_n", g.output));
+                nets.push(format!("    wire {};", inverse));
+                body.push(format!("    nor ({}, {}, {});", out, ins[1], inverse));
+                body.push(format!("    nor ({}, {}, {});", inverse, ins[0], out));
+            }
+            "RISING" | "FALLING" => {
+                timed = true;
+                let late = names.fresh(&format!("{}_late", g.output));
+                nets.push(format!("    wire {};", late));
+                body.push(format!("    not #1 ({}, {});", late, ins[0]));
+                let gate = if g.kind == "RISING" { "and" } else { "nor" };
+                body.push(format!("    {} ({}, {}, {});", gate, out, ins[0], late));
+            }
+            "DELAY" => {
+                timed = true;
+                body.push(format!("    buf #{} ({}, {});", g.params[0], out, ins[0]));
+            }
+            "CLOCK" => {
+                timed = true;
+                body.push(format!("    initial {} = 1'b0;", out));
+                body.push(format!(
+                    "    always #{} {} = ~{};",
+                    g.params[0] / 2,
+                    out,
+                    out
+                ));
+            }
+            k => body.push(format!(
+                "    // unsupported gate kind {} driving {}",
+                k, out
+            )),
+        }
+    }
+
+    let mut v = String::from("// Generated by schemlogica from the optimized netlist.\n");
+    if timed {
+        v.push_str("// One time unit is one redstone tick.\n`timescale 100ms / 1ms\n");
+    }
+    if !regs.is_empty() {
+        v.push_str("// Flip-flops and clocks start low in the build.\n");
+    }
+    let _ = writeln!(v, "module {}({});", module, ports.join(", "));
+    for line in decls.iter().chain(&nets).chain(&body) {
+        v.push_str(line);
+        v.push('\n');
+    }
+    v.push_str("endmodule\n");
+    v
+}
+
+/// Every Y level as a text grid, bottom first, with X across and Z down:
+///
+/// ```text
+/// .  air        #  solid block   *  wire        i  torch
+/// >  repeater or comparator, pointing where its signal goes
+/// L  lever, button or plate      o  lamp or copper bulb
+/// =  powered rail                @  observer    S  sign
+/// D  door                        ?  anything else
+/// ```
+fn render_layers(blocks: &Blocks) -> String {
+    let ((min_x, min_y, min_z), (width, height, length)) = bounds(&blocks.placed);
+    let mut at: HashMap<(i32, i32, i32), char> = HashMap::new();
+    for (x, y, z, name, props) in &blocks.placed {
+        let facing = props
+            .iter()
+            .flatten()
+            .find(|(k, _)| k == "facing")
+            .map(|(_, v)| v.as_str());
+        let c = match name.strip_prefix("minecraft:").unwrap_or(name) {
+            "air" => '.',
+            "redstone_wire" => '*',
+            "redstone_torch" | "redstone_wall_torch" => 'i',
+            // `facing` is the input side.
+            "repeater" | "comparator" => match facing {
+                Some("west") => '>',
+                Some("east") => '<',
+                Some("north") => 'v',
+                _ => '^',
+            },
+            "lever"
+            | "stone_button"
+            | "oak_button"
+            | "stone_pressure_plate"
+            | "oak_pressure_plate" => 'L',
+            "redstone_lamp" | "waxed_copper_bulb" => 'o',
+            "powered_rail" => '=',
+            "observer" => '@',
+            "oak_sign" | "oak_wall_sign" => 'S',
+            "oak_door" => 'D',
+            "sandstone" | "glass" | "cobblestone" | "stone" | "smooth_stone" | "stone_bricks"
+            | "redstone_block" | "slime_block" | "gold_block" | "iron_block" | "quartz_block"
+            | "glowstone" => '#',
+            n if n.ends_with("_wool") || n.ends_with("_concrete") => '#',
+            _ => '?',
+        };
+        at.entry((*x, *y, *z)).or_insert(c);
+    }
+    let mut text = format!(
+        "{} x {} x {} blocks; x across, z down\n\
+         . air  # solid  * wire  i torch  ><^v repeater  L lever  o lamp\n\
+         = rail  @ observer  S sign  D door\n",
+        width, height, length
+    );
+    for y in min_y..min_y + height {
+        let _ = writeln!(text, "\ny={}", y);
+        for z in min_z..min_z + length {
+            text.extend((min_x..min_x + width).map(|x| at.get(&(x, y, z)).copied().unwrap_or('.')));
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// A Markdown summary of the build: size, gate mix, wiring and routing
+/// warnings.
+fn render_report(circuit: &Circuit, blocks: &Blocks, name: &str) -> String {
+    let (_, (width, height, length)) = bounds(&blocks.placed);
+    let mut kinds: HashMap<&str, usize> = HashMap::new();
+    for g in &circuit.gates {
+        *kinds.entry(g.kind.as_str()).or_default() += 1;
+    }
+    let mut kinds: Vec<(&str, usize)> = kinds.into_iter().collect();
+    kinds.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let count = |name: &str| {
+        blocks
+            .placed
+            .iter()
+            .filter(|(_, _, _, n, _)| n == name)
+            .count()
+    };
+    let logic = crate::optimizer::logic_gates(circuit);
+
+    let mut md = format!("# {} build report\n\n", name);
+    md.push_str("| | |\n|---|---|\n");
+    let _ = writeln!(md, "| Inputs | {} |", circuit.inputs.len());
+    let _ = writeln!(md, "| Outputs | {} |", circuit.outputs.len());
+    let _ = writeln!(md, "| Logic gates | {} |", logic);
+    let _ = writeln!(md, "| Logic depth | {} |", crate::optimizer::depth(circuit));
+    let _ = writeln!(md, "| Critical path | {} ticks |", crate::optimizer::delay(circuit));
+    let _ = writeln!(
+        md,
+        "| Footprint | {} × {} × {} (x × y × z) |",
+        width, height, length
+    );
+    let _ = writeln!(md, "| Volume | {} blocks |", width * height * length);
+    let _ = writeln!(md, "| Blocks placed | {} |", blocks.placed.len());
+    let _ = writeln!(
+        md,
+        "| Redstone wire | {} blocks |",
+        count("minecraft:redstone_wire")
+    );
+    let _ = writeln!(md, "| Repeaters | {} |", count("minecraft:repeater"));
+    let _ = writeln!(
+        md,
+        "| Torches | {} |",
+        count("minecraft:redstone_torch") + count("minecraft:redstone_wall_torch")
+    );
+
+    md.push_str("\n## Gates by kind\n\n| Kind | Count |\n|---|---:|\n");
+    for (kind, n) in kinds {
+        let _ = writeln!(md, "| {} | {} |", kind, n);
+    }
+
+    md.push_str("\n## Warnings\n\n");
+    if blocks.warnings.is_empty() {
+        md.push_str("None.\n");
+    }
+    for warning in &blocks.warnings {
+        let _ = writeln!(md, "- {}", warning);
+    }
+    md
+}
@@ -1,25 +1,200 @@
 use crate::compiler::Circuit;
+use crate::partition::{bisection_order, Graph};
 use crate::primitives::primitive_for;
+use anyhow::Result;
 use serde::Serialize;
 use std::collections::{HashMap, HashSet, VecDeque};
 
 // Layout constants
-const GATE_SPACING_X: i32 = 12;  // Horizontal spacing between gates
-const GATE_SPACING_Z: i32 = 16;  // Vertical spacing between gate rows (Increased for flat routing)
+pub(crate) const GATE_SPACING_X: i32 = 12;  // Horizontal spacing between gates
+pub(crate) const GATE_SPACING_Z: i32 = 16;  // Vertical spacing between gate rows (Increased for flat routing)
 const LAYOUT_START_X: i32 = 0;
 const LAYOUT_START_Y: i32 = 0;
 const LAYOUT_START_Z: i32 = 0;
+/// Height of one placement layer: clears the tallest primitive and the
+/// wires beneath it.
+const LAYER_HEIGHT: i32 = 4;
+/// Lowest layer height that still clears the tallest primitive.
+pub const MIN_LAYER_HEIGHT: i32 = 3;
+/// Logic rows wider than this many blocks wrap into several rows.
+const SNAKE_WIDTH: i32 = 128;
+/// Blocks of room opened up beside a gate the router could not wire up,
+/// each time the placement is spread.
+const SPREAD: i32 = 4;
 
 #[derive(Serialize)]
 pub struct Layout {
     pub positions: Vec<(String, i32, i32, i32)>,
+    /// X and Z range `(min_x, min_z, max_x, max_z)` that wires must stay
+    /// within, under `--max-size`.
+    #[serde(skip)]
+    pub area: Option<(i32, i32, i32, i32)>,
+    /// Where the first gate goes; wires run one block above its Y.
+    #[serde(skip)]
+    pub origin: (i32, i32, i32),
 }
 
-pub fn layout_circuit(circuit: &Circuit) -> Layout {
-    let mut positions = Vec::new();
-    
+/// Clearances between the placed gates, and where they start.
+#[derive(Clone, Copy)]
+pub struct Grid {
+    /// Blocks between gates in a row, from `--spacing`.
+    pub spacing_x: i32,
+    /// Least distance between the rows' starts, from `--spacing`.
+    pub spacing_z: i32,
+    /// Rise from one layer to the next, from `--layer-height`.
+    pub layer_height: i32,
+    /// The low corner of the first row, from `--origin`.
+    pub origin: (i32, i32, i32),
+}
+
+impl Default for Grid {
+    fn default() -> Grid {
+        Grid {
+            spacing_x: GATE_SPACING_X,
+            spacing_z: GATE_SPACING_Z,
+            layer_height: LAYER_HEIGHT,
+            origin: (LAYOUT_START_X, LAYOUT_START_Y, LAYOUT_START_Z),
+        }
+    }
+}
+
+/// How gates are arranged once the level-by-row placement is built.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Placer {
+    /// Simulated annealing: slow, but the shortest wiring.
+    Anneal,
+    /// Force-directed: pulls connected gates together in a few sweeps.
+    Force,
+}
+
+impl Placer {
+    pub fn parse(name: &str) -> Result<Placer> {
+        match name {
+            "anneal" => Ok(Placer::Anneal),
+            "force" => Ok(Placer::Force),
+            _ => anyhow::bail!("Unknown placer `{}` (expected anneal or force)", name),
+        }
+    }
+}
+
+pub struct Options {
+    pub placer: Placer,
+    /// Layers the rows are stacked into, from `--layers`.
+    pub layers: usize,
+    /// Largest footprint `(x, y, z)` allowed, from `--max-size`.
+    pub max_size: Option<(i32, i32, i32)>,
+    /// Keep each module instance's gates in a column of their own, from
+    /// `--hierarchy`.
+    pub hierarchy: bool,
+    pub grid: Grid,
+    pub directives: Directives,
+    /// Footprint width over length to aim for, from `--aspect`.
+    pub aspect: Option<f64>,
+}
+
+/// Placement constraints written into the source as comment lines.
+#[derive(Default)]
+pub struct Directives {
+    /// `@place NAME at X,Y,Z`: gates fixed at a position relative to the
+    /// origin. NAME is an input, `out[N]` for the N-th output, or a gate id
+    /// as `--emit netlist` lists them.
+    pub pins: Vec<(String, (i32, i32, i32))>,
+    /// `@inputs a, b, ...`: the levers these inputs drive come first in
+    /// the input row, in this order.
+    pub inputs: Vec<String>,
+}
+
+/// Collect the `@place` and `@inputs` directives from whole-line `//` or
+/// `#` comments, so every text format can carry them.
+pub fn parse_directives(code: &str) -> Result<Directives> {
+    let mut directives = Directives::default();
+    for (n, line) in code.lines().enumerate() {
+        let line = line.trim_start();
+        let Some(comment) = line.strip_prefix("//").or_else(|| line.strip_prefix('#')) else {
+            continue;
+        };
+        let comment = comment.trim();
+        if let Some(rest) = comment.strip_prefix("@place ") {
+            let pin = rest.split_once(" at ").and_then(|(name, at)| {
+                let coords: Option<Vec<i32>> =
+                    at.split(',').map(|c| c.trim().parse().ok()).collect();
+                match coords.as_deref() {
+                    Some(&[x, y, z]) => Some((name.trim().to_string(), (x, y, z))),
+                    _ => None,
+                }
+            });
+            match pin {
+                Some(pin) => directives.pins.push(pin),
+                None => anyhow::bail!(
+                    "line {}: expected `@place NAME at X,Y,Z`, found `{}`",
+                    n + 1,
+                    comment
+                ),
+            }
+        } else if let Some(rest) = comment.strip_prefix("@inputs ") {
+            let names = rest.split([',', ' ']).filter(|name| !name.is_empty());
+            directives.inputs.extend(names.map(String::from));
+        }
+    }
+    Ok(directives)
+}
+
+/// Parse a `--max-size` footprint written `WxHxL`, e.g. `64x32x64`.
+pub fn parse_size(text: &str) -> Result<(i32, i32, i32)> {
+    let dims: Option<Vec<i32>> = text
+        .split('x')
+        .map(|d| d.trim().parse().ok().filter(|&d| d > 0))
+        .collect();
+    match dims.as_deref() {
+        Some(&[w, h, l]) => Ok((w, h, l)),
+        _ => anyhow::bail!("Bad size `{}` (expected WxHxL, e.g. 64x32x64)", text),
+    }
+}
+
+/// Parse a `--spacing` written `X,Z`, e.g. `12,16`. Gates need two blocks
+/// between them for their ports, and rows one.
+pub fn parse_spacing(text: &str) -> Result<(i32, i32)> {
+    let dims: Option<Vec<i32>> = text.split(',').map(|d| d.trim().parse().ok()).collect();
+    match dims.as_deref() {
+        Some(&[x, z]) if x >= 2 && z >= 1 => Ok((x, z)),
+        _ => anyhow::bail!(
+            "Bad spacing `{}` (expected X,Z with X at least 2 and Z at least 1, e.g. 12,16)",
+            text
+        ),
+    }
+}
+
+/// Parse an `--aspect` ratio written `W:L`, e.g. `1:1` or `16:9`.
+pub fn parse_aspect(text: &str) -> Result<f64> {
+    let ratio = text.split_once(':').and_then(|(w, l)| {
+        let (w, l): (f64, f64) = (w.trim().parse().ok()?, l.trim().parse().ok()?);
+        (w > 0.0 && l > 0.0).then_some(w / l)
+    });
+    match ratio {
+        Some(ratio) => Ok(ratio),
+        None => anyhow::bail!(
+            "Bad aspect ratio `{}` (expected W:L, e.g. 1:1 or 16:9)",
+            text
+        ),
+    }
+}
+
+/// Parse an `--origin` written `X,Y,Z`, e.g. `0,0,0`.
+pub fn parse_origin(text: &str) -> Result<(i32, i32, i32)> {
+    let coords: Option<Vec<i32>> = text.split(',').map(|c| c.trim().parse().ok()).collect();
+    match coords.as_deref() {
+        Some(&[x, y, z]) => Ok((x, y, z)),
+        _ => anyhow::bail!("Bad origin `{}` (expected X,Y,Z, e.g. 0,0,0)", text),
+    }
+}
+
+pub fn layout_circuit(circuit: &Circuit, opts: &Options) -> Result<Layout> {
     if circuit.gates.is_empty() {
-        return Layout { positions };
+        return Ok(Layout {
+            positions: Vec::new(),
+            area: None,
+            origin: opts.grid.origin,
+        });
     }
     
     // Build dependency graph: gate_id -> list of gates that depend on it
@@ -85,34 +260,1098 @@ pub fn layout_circuit(circuit: &Circuit) -> Layout {
         }
     }
     
-    // Group gates by level
-    let mut gates_by_level: HashMap<usize, Vec<String>> = HashMap::new();
-    for gate in &circuit.gates {
-        let level = *levels.get(&gate.id).unwrap_or(&0);
-        gates_by_level.entry(level).or_insert_with(Vec::new).push(gate.id.clone());
+    // The INPUT levers make up the front row and the gates driving the
+    // outputs the back row, both in declaration order, so the build has a
+    // panel of levers on one face and its results on the opposite one.
+    // Inputs named by `@inputs` go first.
+    let declared = |names: &[String], key: &str| names.iter().position(|n| n == key);
+    let mut input_row: Vec<usize> = (0..circuit.gates.len())
+        .filter(|&i| circuit.gates[i].kind == "INPUT")
+        .collect();
+    let first = &opts.directives.inputs;
+    input_row.sort_by_key(|&i| {
+        let id = &circuit.gates[i].id;
+        let name = id.strip_prefix("in_").unwrap_or(id);
+        let rest = || declared(&circuit.inputs, name).unwrap_or(usize::MAX - first.len());
+        declared(first, name).unwrap_or_else(|| first.len() + rest())
+    });
+    let mut output_row: Vec<usize> = (0..circuit.gates.len())
+        .filter(|&i| circuit.gates[i].kind != "INPUT")
+        .filter(|&i| circuit.outputs.contains(&circuit.gates[i].output))
+        .collect();
+    output_row.sort_by_key(|&i| declared(&circuit.outputs, &circuit.gates[i].output));
+
+    // Group the rest by level.
+    let mut gates_by_level: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, gate) in circuit.gates.iter().enumerate() {
+        if !input_row.contains(&i) && !output_row.contains(&i) {
+            let level = *levels.get(&gate.id).unwrap_or(&0);
+            gates_by_level.entry(level).or_default().push(i);
+        }
     }
     
-    // Place gates level by level
+    // The level-by-row arrangement is the placer's starting point.
     let max_level = gates_by_level.keys().max().copied().unwrap_or(0);
-    
-    for level in 0..=max_level {
-        if let Some(gate_ids) = gates_by_level.get(&level) {
-            let mut current_x = LAYOUT_START_X;
-            let z = LAYOUT_START_Z + (level as i32) * GATE_SPACING_Z;
-            
-            for gate_id in gate_ids {
-                // Find the gate to get its kind
-                if let Some(gate) = circuit.gates.iter().find(|g| g.id == *gate_id) {
-                    let prim = primitive_for(&gate.kind);
-                    
-                    positions.push((gate_id.clone(), current_x, LAYOUT_START_Y, z));
-                    
-                    // Advance X by gate width plus spacing
-                    current_x += prim.size_x + GATE_SPACING_X;
+    let mut rows: Vec<Vec<usize>> = vec![input_row];
+    rows.extend((0..=max_level).map(|level| gates_by_level.remove(&level).unwrap_or_default()));
+    rows.push(output_row);
+    let mut io = (1, 1);
+    let grid = &opts.grid;
+    for name in &opts.directives.inputs {
+        if !circuit.inputs.contains(name) {
+            anyhow::bail!("`@inputs` names `{}`, which is not an input", name);
+        }
+    }
+    // Gates fixed by `@place` leave the rows and go where they are told.
+    let pins = pinned(circuit, &opts.directives, grid)?;
+    for row in &mut rows {
+        row.retain(|i| !pins.iter().any(|p| p.0 == *i));
+    }
+    let fixed: Vec<(String, i32, i32, i32)> = pins
+        .iter()
+        .map(|&(i, (x, y, z))| (circuit.gates[i].id.clone(), x, y, z))
+        .collect();
+    let placed = |rows: &[Vec<usize>], io, layers, columns: &[usize]| {
+        let mut positions = place(circuit, rows, io, layers, columns, grid);
+        positions.extend(fixed.iter().cloned());
+        positions
+    };
+    partition_rows(circuit, &mut rows, io, grid);
+    let mut columns = if opts.hierarchy {
+        block_columns(circuit, &rows, io)
+    } else {
+        vec![0; circuit.gates.len()]
+    };
+    rows = snake_rows(circuit, rows, io, grid);
+    let mut layers = opts.layers.max(1);
+    if let Some(ratio) = opts.aspect {
+        // Deep builds lay their rows out in bands side by side, wide ones
+        // fold their rows; whichever comes closest to the ratio wins.
+        let miss = |rows: &[Vec<usize>], io, columns: &[usize]| {
+            let (x, _, z) = extent(circuit, &placed(rows, io, layers, columns));
+            (f64::from(x) / f64::from(z) / ratio).ln().abs()
+        };
+        let mut best = (miss(&rows, io, &columns), rows.clone(), io, columns.clone());
+        let stride = columns.iter().max().map_or(1, |c| c + 1);
+        for bands in 2..=rows.len() - io.0 - io.1 {
+            let (banded, band) = band_rows(rows.clone(), io, bands, circuit.gates.len());
+            let banded_columns: Vec<usize> = columns
+                .iter()
+                .zip(&band)
+                .map(|(c, b)| b * stride + c)
+                .collect();
+            let m = miss(&banded, io, &banded_columns);
+            if m < best.0 {
+                best = (m, banded, io, banded_columns);
+            }
+        }
+        let widest = rows.iter().max_by_key(|r| r.len()).map_or(0, |r| r.len());
+        let width = rows
+            .iter()
+            .map(|r| row_extent(circuit, r, grid))
+            .max()
+            .unwrap_or(0);
+        for parts in 2..=widest as i32 {
+            let Ok((folded, folded_io)) = fold_rows(circuit, rows.clone(), io, width / parts, grid)
+            else {
+                break;
+            };
+            let m = miss(&folded, folded_io, &columns);
+            if m < best.0 {
+                best = (m, folded, folded_io, columns.clone());
+            }
+        }
+        (_, rows, io, columns) = best;
+    }
+    if let Some((w, h, l)) = opts.max_size {
+        (rows, io) = fold_rows(circuit, rows, io, w, grid)?;
+        // Stack more layers until the build is short enough, as long as
+        // the tower stays within the height limit.
+        let size = |layers| extent(circuit, &placed(&rows, io, layers, &columns));
+        while size(layers).2 > l && layers < rows.len() {
+            if size(layers + 1).1 > h {
+                break;
+            }
+            layers += 1;
+        }
+    }
+    let initial = rows.clone();
+    match opts.placer {
+        Placer::Anneal => anneal(circuit, &mut rows, io, layers, &columns, grid),
+        Placer::Force => force(circuit, &mut rows, io, layers, &columns, grid),
+    }
+    let mut positions = placed(&rows, io, layers, &columns);
+
+    let mut area = None;
+    if let Some((w, h, l)) = opts.max_size {
+        let fits = |(x, y, z): (i32, i32, i32)| x <= w && y <= h && z <= l;
+        // The placer may deepen a row past the limit; the folded rows
+        // as they were are the fallback.
+        if !fits(extent(circuit, &positions)) {
+            positions = placed(&initial, io, layers, &columns);
+        }
+        let size = extent(circuit, &positions);
+        if !fits(size) {
+            anyhow::bail!(
+                "the circuit needs {}x{}x{} blocks, more than --max-size {}x{}x{}",
+                size.0,
+                size.1,
+                size.2,
+                w,
+                h,
+                l
+            );
+        }
+        let (min_x, min_z) = (grid.origin.0 - 1, grid.origin.2);
+        area = Some((min_x, min_z, min_x + w - 1, min_z + l - 1));
+    }
+    let layout = Layout {
+        positions,
+        area,
+        origin: grid.origin,
+    };
+    check(circuit, &layout)?;
+    Ok(layout)
+}
+
+/// Open up room for wires around the `crowded` gates: every gate from a
+/// crowded gate's X on moves SPREAD blocks further in X, and every gate
+/// from its Z on moves SPREAD blocks further in Z, once for each such
+/// crowded column or row. Gates pinned by `@place` stay put. None if the
+/// spread placement no longer fits.
+pub fn spread(
+    circuit: &Circuit,
+    layout: &Layout,
+    opts: &Options,
+    crowded: &[&str],
+) -> Result<Option<Layout>> {
+    let pins = pinned(circuit, &opts.directives, &opts.grid)?;
+    let pinned: HashSet<&str> = pins
+        .iter()
+        .map(|p| circuit.gates[p.0].id.as_str())
+        .collect();
+    let (mut cuts_x, mut cuts_z) = (Vec::new(), Vec::new());
+    for (id, x, _, z) in &layout.positions {
+        if crowded.contains(&id.as_str()) && !pinned.contains(id.as_str()) {
+            cuts_x.push(*x);
+            cuts_z.push(*z);
+        }
+    }
+    cuts_x.sort_unstable();
+    cuts_x.dedup();
+    cuts_z.sort_unstable();
+    cuts_z.dedup();
+    let shift = |cuts: &[i32], at: i32| cuts.iter().filter(|&&c| c <= at).count() as i32 * SPREAD;
+    let positions = layout
+        .positions
+        .iter()
+        .map(|(id, x, y, z)| {
+            let (dx, dz) = if pinned.contains(id.as_str()) {
+                (0, 0)
+            } else {
+                (shift(&cuts_x, *x), shift(&cuts_z, *z))
+            };
+            (id.clone(), x + dx, *y, z + dz)
+        })
+        .collect();
+    let spread = Layout {
+        positions,
+        area: layout.area,
+        origin: layout.origin,
+    };
+    Ok(check(circuit, &spread).is_ok().then_some(spread))
+}
+
+/// Reject a placement the schematic cannot be built from: two gates whose
+/// footprints share a block, a port buried in another gate, or a port
+/// outside the `--max-size` area.
+fn check(circuit: &Circuit, layout: &Layout) -> Result<()> {
+    let index: HashMap<&str, usize> = circuit
+        .gates
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (g.id.as_str(), i))
+        .collect();
+    let name = |i: usize| format!("{} ({})", circuit.gates[i].id, circuit.gates[i].kind);
+    let mut owner: HashMap<(i32, i32, i32), usize> = HashMap::new();
+    let mut ports = Vec::new();
+    for (id, x, y, z) in &layout.positions {
+        let i = index[id.as_str()];
+        let gate = &circuit.gates[i];
+        let prim = primitive_for(&gate.kind, &gate.params);
+        for dx in 0..prim.size_x {
+            for dy in 0..prim.size_y {
+                for dz in 0..prim.size_z {
+                    let cell = (x + dx, y + dy, z + dz);
+                    if let Some(other) = owner.insert(cell, i) {
+                        anyhow::bail!(
+                            "gates {} and {} overlap at {:?}",
+                            name(other),
+                            name(i),
+                            cell
+                        );
+                    }
                 }
             }
         }
+        for &(px, py, pz) in prim.input_ports.iter().chain([&prim.output_port]) {
+            ports.push((i, (x + px, y + py, z + pz)));
+        }
+    }
+    for (i, (x, y, z)) in ports {
+        if let Some(&other) = owner.get(&(x, y, z)).filter(|&&o| o != i) {
+            anyhow::bail!(
+                "a port of gate {} at {:?} is inside gate {}",
+                name(i),
+                (x, y, z),
+                name(other)
+            );
+        }
+        if let Some((min_x, min_z, max_x, max_z)) = layout.area {
+            if !(min_x..=max_x).contains(&x) || !(min_z..=max_z).contains(&z) {
+                anyhow::bail!(
+                    "a port of gate {} at {:?} is outside the --max-size area",
+                    name(i),
+                    (x, y, z)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A gate `@place` fixes, and where.
+type Fixed = (usize, (i32, i32, i32));
+
+/// The gate every `@place` directive names, and where it goes. An input
+/// name wins over an `out[N]` output and a gate id.
+fn pinned(circuit: &Circuit, directives: &Directives, grid: &Grid) -> Result<Vec<Fixed>> {
+    let by_output = |sig: &String| circuit.gates.iter().position(|g| &g.output == sig);
+    let by_id = |id: &str| circuit.gates.iter().position(|g| g.id == id);
+    let mut pins: Vec<Fixed> = Vec::new();
+    for (name, (x, y, z)) in &directives.pins {
+        let output = name
+            .strip_prefix("out[")
+            .and_then(|n| n.strip_suffix(']')?.parse::<usize>().ok())
+            .and_then(|n| circuit.outputs.get(n));
+        let gate = if circuit.inputs.contains(name) {
+            by_id(&format!("in_{}", name))
+        } else {
+            output.and_then(by_output).or_else(|| by_id(name))
+        };
+        let Some(i) = gate else {
+            anyhow::bail!("`@place {}`: no input, output or gate by that name", name);
+        };
+        if pins.iter().any(|p| p.0 == i) {
+            let id = &circuit.gates[i].id;
+            anyhow::bail!("`@place {}`: gate {} is already placed", name, id);
+        }
+        let (ox, oy, oz) = grid.origin;
+        pins.push((i, (ox + x, oy + y, oz + z)));
+    }
+    Ok(pins)
+}
+
+/// Leading INPUT rows and trailing output rows among a placement's rows.
+type Io = (usize, usize);
+
+/// Tower and layer of every row. The logic rows stack into towers of
+/// `layers` layers; the IO rows stay on the ground, one per tower, so
+/// they form the build's front and back faces.
+fn towers(rows: usize, io: Io, layers: usize) -> Vec<(usize, usize)> {
+    let logic = rows - io.0 - io.1;
+    let logic_towers = logic.div_ceil(layers);
+    (0..rows)
+        .map(|r| {
+            if r < io.0 {
+                (r, 0)
+            } else if r < io.0 + logic {
+                (io.0 + (r - io.0) / layers, (r - io.0) % layers)
+            } else {
+                (io.0 + logic_towers + r - io.0 - logic, 0)
+            }
+        })
+        .collect()
+}
+
+/// Place the rows, dropping any the placer emptied. Each tower's rows are
+/// raised one layer above and staggered just past the one below, so no
+/// two gates share a column and the upper rows' wires can drop straight
+/// to the ground. The logic rows' gates are grouped by `columns`, each
+/// group starting where the widest of the group before it ends.
+fn place(
+    circuit: &Circuit,
+    rows: &[Vec<usize>],
+    io: Io,
+    layers: usize,
+    columns: &[usize],
+    grid: &Grid,
+) -> Vec<(String, i32, i32, i32)> {
+    let mut positions = Vec::new();
+    let kept = |range: std::ops::Range<usize>| rows[range].iter().filter(|r| !r.is_empty()).count();
+    let io = (kept(0..io.0), kept(rows.len() - io.1..rows.len()));
+    let mut rows: Vec<Vec<usize>> = rows.iter().filter(|r| !r.is_empty()).cloned().collect();
+    let logic = io.0..rows.len() - io.1;
+    let column_x = column_starts(&mut rows[logic.clone()], columns, |i| {
+        let gate = &circuit.gates[i];
+        primitive_for(&gate.kind, &gate.params).size_x + grid.spacing_x
+    });
+    let slots = towers(rows.len(), io, layers);
+    let (origin_x, origin_y, mut z) = grid.origin;
+    let mut offset = 0;
+    for (r, row) in rows.iter().enumerate() {
+        let (tower, layer) = slots[r];
+        if r > 0 && slots[r - 1].0 != tower {
+            z += grid.spacing_z.max(offset);
+            offset = 0;
+        }
+        let y = origin_y + layer as i32 * grid.layer_height;
+        let mut current_x = origin_x;
+        let mut depth = 0;
+        for &i in row.iter() {
+            let gate = &circuit.gates[i];
+            let prim = primitive_for(&gate.kind, &gate.params);
+            if logic.contains(&r) {
+                current_x = current_x.max(origin_x + column_x[columns[i]]);
+            }
+            positions.push((gate.id.clone(), current_x, y, z + offset));
+            // Advance X by gate width plus spacing
+            current_x += prim.size_x + grid.spacing_x;
+            depth = depth.max(prim.size_z);
+        }
+        offset += depth + 1;
+    }
+    positions
+}
+
+/// Sort every row's gates by column and return where each column starts:
+/// just past the widest run of gates in the column before it.
+fn column_starts(
+    rows: &mut [Vec<usize>],
+    columns: &[usize],
+    width: impl Fn(usize) -> i32,
+) -> Vec<i32> {
+    let mut column_x = vec![0; columns.iter().max().map_or(1, |c| c + 1) + 1];
+    for row in rows {
+        row.sort_by_key(|&i| columns[i]);
+        let mut used = vec![0; column_x.len()];
+        for &i in row.iter() {
+            used[columns[i] + 1] += width(i);
+        }
+        for (c, used) in used.into_iter().enumerate() {
+            column_x[c] = column_x[c].max(used);
+        }
+    }
+    for c in 1..column_x.len() {
+        column_x[c] += column_x[c - 1];
+    }
+    column_x
+}
+
+/// Size `(x, y, z)` of the box around the placed gates and their ports.
+fn extent(circuit: &Circuit, positions: &[(String, i32, i32, i32)]) -> (i32, i32, i32) {
+    let kinds: HashMap<&str, (&str, &[i64])> = circuit
+        .gates
+        .iter()
+        .map(|g| (g.id.as_str(), (g.kind.as_str(), g.params.as_slice())))
+        .collect();
+    let (mut lo, mut hi) = (
+        (i32::MAX, i32::MAX, i32::MAX),
+        (i32::MIN, i32::MIN, i32::MIN),
+    );
+    for (id, x, y, z) in positions {
+        let (kind, params) = kinds[id.as_str()];
+        let prim = primitive_for(kind, params);
+        let ports = prim.input_ports.iter().chain([&prim.output_port]);
+        for &(px, py, pz) in
+            ports.chain([&(0, 0, 0), &(prim.size_x, prim.size_y - 1, prim.size_z - 1)])
+        {
+            lo = (lo.0.min(x + px), lo.1.min(y + py), lo.2.min(z + pz));
+            hi = (hi.0.max(x + px), hi.1.max(y + py), hi.2.max(z + pz));
+        }
+    }
+    (hi.0 - lo.0 + 1, hi.1 - lo.1 + 1, hi.2 - lo.2 + 1)
+}
+
+/// Order the gates of every logic row by recursive min-cut bisection of
+/// the logic, so that tightly connected clusters share a stretch of X
+/// across all the rows. Gates wired to the IO rows are anchored at those
+/// gates' X, which decides which side of each cut goes left.
+fn partition_rows(circuit: &Circuit, rows: &mut [Vec<usize>], io: Io, grid: &Grid) {
+    let logic = io.0..rows.len() - io.1;
+    let cells: Vec<usize> = rows[logic.clone()].iter().flatten().copied().collect();
+    if cells.len() < 2 {
+        return;
+    }
+    let mut cell_of = HashMap::new();
+    for (c, &i) in cells.iter().enumerate() {
+        cell_of.insert(i, c);
+    }
+    // X of every IO gate's centre, as its row packs.
+    let mut io_x = HashMap::new();
+    let io_rows = rows.iter().enumerate().filter(|(r, _)| !logic.contains(r));
+    for (_, row) in io_rows {
+        let mut x = 0;
+        for &i in row {
+            let gate = &circuit.gates[i];
+            let size_x = primitive_for(&gate.kind, &gate.params).size_x;
+            io_x.insert(i, f64::from(x + size_x / 2));
+            x += size_x + grid.spacing_x;
+        }
+    }
+
+    // Every signal's gates, producer first, split into cells and IO.
+    let mut producer = HashMap::new();
+    for (i, gate) in circuit.gates.iter().enumerate() {
+        producer.insert(gate.output.as_str(), i);
+    }
+    let mut signals: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, gate) in circuit.gates.iter().enumerate() {
+        for input in &gate.inputs {
+            if let Some(&p) = producer.get(input.as_str()) {
+                signals
+                    .entry(input.as_str())
+                    .or_insert_with(|| vec![p])
+                    .push(i);
+            }
+        }
+    }
+    let mut signals: Vec<_> = signals.into_iter().collect();
+    signals.sort_unstable_by_key(|(name, _)| *name);
+
+    let mut nets = Vec::new();
+    let mut anchors = vec![Vec::new(); cells.len()];
+    for (_, gates) in signals {
+        let net: Vec<usize> = gates
+            .iter()
+            .filter_map(|i| cell_of.get(i).copied())
+            .collect();
+        for x in gates.iter().filter_map(|i| io_x.get(i)) {
+            for &c in &net {
+                anchors[c].push(*x);
+            }
+        }
+        if net.len() >= 2 {
+            nets.push(net);
+        }
+    }
+    let graph = Graph {
+        weight: cells
+            .iter()
+            .map(|&i| {
+                let gate = &circuit.gates[i];
+                primitive_for(&gate.kind, &gate.params).size_x + grid.spacing_x
+            })
+            .collect(),
+        nets,
+        anchor: anchors
+            .iter()
+            .map(|xs| (!xs.is_empty()).then(|| xs.iter().sum::<f64>() / xs.len() as f64))
+            .collect(),
+    };
+
+    let mut rank = HashMap::new();
+    for (r, c) in bisection_order(&graph).into_iter().enumerate() {
+        rank.insert(cells[c], r);
+    }
+    for row in &mut rows[logic] {
+        row.sort_by_key(|i| rank[i]);
+    }
+}
+
+/// Column of every gate under `--hierarchy`: the logic gates of each
+/// module instance share one, and the gates outside any share another.
+/// A gate the optimizer built joins the instance most of the gates it
+/// connects to are in, and an instance of a single gate is no instance.
+/// Columns run left to right by where partitioning put their gates.
+fn block_columns(circuit: &Circuit, rows: &[Vec<usize>], io: Io) -> Vec<usize> {
+    let logic: Vec<usize> = rows[io.0..rows.len() - io.1]
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+    let mut sizes: HashMap<&str, usize> = HashMap::new();
+    for &i in &logic {
+        *sizes.entry(circuit.gates[i].block.as_str()).or_default() += 1;
+    }
+    let mut block: HashMap<usize, &str> = HashMap::new();
+    for &i in &logic {
+        let name = circuit.gates[i].block.as_str();
+        if !name.is_empty() && sizes[name] > 1 {
+            block.insert(i, name);
+        }
+    }
+
+    let producer: HashMap<&str, usize> = logic
+        .iter()
+        .map(|&i| (circuit.gates[i].output.as_str(), i))
+        .collect();
+    let mut neighbours: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &i in &logic {
+        for input in &circuit.gates[i].inputs {
+            if let Some(&p) = producer.get(input.as_str()).filter(|&&p| p != i) {
+                neighbours.entry(i).or_default().push(p);
+                neighbours.entry(p).or_default().push(i);
+            }
+        }
+    }
+    loop {
+        let mut joined = Vec::new();
+        for &i in logic.iter().filter(|i| !block.contains_key(i)) {
+            let mut votes: HashMap<&str, usize> = HashMap::new();
+            for n in neighbours.get(&i).into_iter().flatten() {
+                if let Some(&name) = block.get(n) {
+                    *votes.entry(name).or_default() += 1;
+                }
+            }
+            let best = votes
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)));
+            if let Some((name, _)) = best {
+                joined.push((i, name));
+            }
+        }
+        if joined.is_empty() {
+            break;
+        }
+        block.extend(joined);
+    }
+
+    // Mean relative position of every block's gates within their rows.
+    let mut spot: HashMap<&str, (f64, f64)> = HashMap::new();
+    for row in &rows[io.0..rows.len() - io.1] {
+        for (k, i) in row.iter().enumerate() {
+            let name = block.get(i).copied().unwrap_or("");
+            let entry = spot.entry(name).or_default();
+            entry.0 += (k as f64 + 0.5) / row.len() as f64;
+            entry.1 += 1.0;
+        }
+    }
+    let mut order: Vec<(&str, f64)> = spot.into_iter().map(|(b, (sum, n))| (b, sum / n)).collect();
+    order.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(b.0)));
+    let mut columns = vec![0; circuit.gates.len()];
+    for &i in &logic {
+        let name = block.get(&i).copied().unwrap_or("");
+        columns[i] = order.iter().position(|&(b, _)| b == name).unwrap();
+    }
+    columns
+}
+
+/// Width in blocks of a row of gates, counting the input ports left of
+/// the first gate and the output port right of the last.
+fn row_extent(circuit: &Circuit, row: &[usize], grid: &Grid) -> i32 {
+    let widths = row.iter().map(|&i| {
+        let gate = &circuit.gates[i];
+        primitive_for(&gate.kind, &gate.params).size_x
+    });
+    widths.sum::<i32>() + grid.spacing_x * (row.len() as i32 - 1).max(0) + 2
+}
+
+/// Split a row into rows at most `width` blocks wide, in order. A gate
+/// wider than that on its own gets a row to itself.
+fn split_row(circuit: &Circuit, row: Vec<usize>, width: i32, grid: &Grid) -> Vec<Vec<usize>> {
+    let mut split = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+    for i in row {
+        current.push(i);
+        if current.len() > 1 && row_extent(circuit, &current, grid) > width {
+            current.pop();
+            split.push(std::mem::replace(&mut current, vec![i]));
+        }
+    }
+    split.push(current);
+    split
+}
+
+/// Wrap logic rows wider than `SNAKE_WIDTH` blocks (or the IO rows, if
+/// those are wider) into several rows. The wrapped rows run in
+/// alternating directions, so a level reads as one serpentine line and
+/// gates next to each other in it stay close at every turn.
+fn snake_rows(circuit: &Circuit, rows: Vec<Vec<usize>>, io: Io, grid: &Grid) -> Vec<Vec<usize>> {
+    let count = rows.len();
+    let width = rows[..io.0]
+        .iter()
+        .chain(&rows[count - io.1..])
+        .map(|r| row_extent(circuit, r, grid))
+        .fold(SNAKE_WIDTH, i32::max);
+    let mut snaked = Vec::new();
+    for (r, row) in rows.into_iter().enumerate() {
+        if r < io.0 || r >= count - io.1 {
+            snaked.push(row);
+            continue;
+        }
+        for (turn, mut part) in split_row(circuit, row, width, grid).into_iter().enumerate() {
+            if turn % 2 == 1 {
+                part.reverse();
+            }
+            snaked.push(part);
+        }
+    }
+    snaked
+}
+
+/// Lay the logic rows out in `bands` bands side by side along X, each
+/// running the other way from the one before so it starts next to where
+/// that one ended. Returns the rows, and the band of each of the
+/// `gates` gates.
+fn band_rows(
+    rows: Vec<Vec<usize>>,
+    io: Io,
+    bands: usize,
+    gates: usize,
+) -> (Vec<Vec<usize>>, Vec<usize>) {
+    let count = rows.len();
+    let per_band = (count - io.0 - io.1).div_ceil(bands);
+    let mut band = vec![0; gates];
+    let mut banded: Vec<Vec<usize>> = Vec::new();
+    let mut logic = vec![Vec::new(); per_band];
+    for (r, row) in rows.into_iter().enumerate() {
+        if r < io.0 || r >= count - io.1 {
+            if r >= count - io.1 && !logic.is_empty() {
+                banded.append(&mut logic);
+            }
+            banded.push(row);
+            continue;
+        }
+        let (b, k) = ((r - io.0) / per_band, (r - io.0) % per_band);
+        let k = if b % 2 == 1 { per_band - 1 - k } else { k };
+        for &i in &row {
+            band[i] = b;
+        }
+        logic[k].extend(row);
+    }
+    banded.append(&mut logic);
+    (banded, band)
+}
+
+/// Split rows wider than `width` blocks into several rows.
+fn fold_rows(
+    circuit: &Circuit,
+    rows: Vec<Vec<usize>>,
+    io: Io,
+    width: i32,
+    grid: &Grid,
+) -> Result<(Vec<Vec<usize>>, Io)> {
+    let (count, mut inputs, mut outputs) = (rows.len(), 0, 0);
+    let mut folded = Vec::new();
+    for (r, row) in rows.into_iter().enumerate() {
+        for &i in &row {
+            let extent = row_extent(circuit, &[i], grid);
+            if extent > width {
+                let gate = &circuit.gates[i];
+                anyhow::bail!(
+                    "gate {} ({}) is {} blocks wide with its ports, more than --max-size allows",
+                    gate.id,
+                    gate.kind,
+                    extent
+                );
+            }
+        }
+        let before = folded.len();
+        folded.extend(split_row(circuit, row, width, grid));
+        if r < io.0 {
+            inputs += folded.len() - before;
+        } else if r >= count - io.1 {
+            outputs += folded.len() - before;
+        }
+    }
+    Ok((folded, (inputs, outputs)))
+}
+
+/// Cooling factor applied to the temperature after each round.
+const COOLING: f64 = 0.9;
+/// Moves tried per movable gate at each temperature.
+const MOVES_PER_GATE: usize = 10;
+/// Temperature (in blocks of wire) at which annealing stops.
+const FINAL_TEMPERATURE: f64 = 0.05;
+
+/// Rearrange gates between and within the logic rows by simulated
+/// annealing to shorten the total half-perimeter wirelength of every net.
+/// The IO rows keep their gates in order, and no row grows wider than the
+/// widest row of the starting arrangement.
+fn anneal(
+    circuit: &Circuit,
+    rows: &mut [Vec<usize>],
+    io: Io,
+    layers: usize,
+    columns: &[usize],
+    grid: &Grid,
+) {
+    let mut state = Placement::new(circuit, rows, io, layers, columns, grid);
+    let movable: Vec<usize> = state.logic().flat_map(|r| state.rows[r].clone()).collect();
+    if movable.len() < 2 || state.nets.is_empty() {
+        return;
+    }
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+    // Start hot enough to accept a typical uphill move most of the time.
+    let mut sum = 0.0;
+    for _ in 0..movable.len() {
+        if let Some((delta, undo)) = state.try_move(&movable, &mut rng) {
+            sum += delta.unsigned_abs() as f64;
+            state.undo(undo);
+        }
+    }
+    let mut temperature = 2.0 * sum / movable.len() as f64;
+
+    let mut best_cost = state.cost;
+    let mut best_rows = state.rows.clone();
+    while temperature > FINAL_TEMPERATURE {
+        for _ in 0..MOVES_PER_GATE * movable.len() {
+            let Some((delta, undo)) = state.try_move(&movable, &mut rng) else {
+                continue;
+            };
+            if delta > 0 && rng.unit() >= (-(delta as f64) / temperature).exp() {
+                state.undo(undo);
+            } else if state.cost < best_cost {
+                best_cost = state.cost;
+                best_rows.clone_from(&state.rows);
+            }
+        }
+        temperature *= COOLING;
+    }
+    for (row, best) in rows.iter_mut().zip(best_rows) {
+        *row = best;
+    }
+}
+
+/// Force-directed sweeps before the placement is returned.
+const FORCE_SWEEPS: usize = 30;
+
+/// Pull every gate toward the centre of the gates it connects to, then
+/// legalize: gates fill the row nearest their target, left to right by
+/// target X, spilling into the nearest row with room. The best
+/// arrangement seen over all sweeps wins, so the result is never worse
+/// than the starting one.
+fn force(
+    circuit: &Circuit,
+    rows: &mut [Vec<usize>],
+    io: Io,
+    layers: usize,
+    columns: &[usize],
+    grid: &Grid,
+) {
+    let mut state = Placement::new(circuit, rows, io, layers, columns, grid);
+    if state.nets.is_empty() {
+        return;
+    }
+    let mut best_cost = state.cost;
+    let mut best_rows = state.rows.clone();
+    for _ in 0..FORCE_SWEEPS {
+        // Target of every movable gate: the mean X (in blocks) and row of
+        // the other pins on its nets.
+        let mut targets: Vec<(f64, f64, usize)> = Vec::new();
+        for r in state.logic() {
+            for &g in &state.rows[r] {
+                let (mut sx, mut sz, mut n) = (0.0, 0.0, 0.0);
+                for &net in &state.nets_of[g] {
+                    for &(other, _, _) in state.nets[net].iter().filter(|p| p.0 != g) {
+                        sx += (state.x[other] + state.width[other] / 2) as f64;
+                        sz += state.row_of[other] as f64;
+                        n += 1.0;
+                    }
+                }
+                if n == 0.0 {
+                    targets.push(((state.x[g] + state.width[g] / 2) as f64, r as f64, g));
+                } else {
+                    targets.push((sx / n, sz / n, g));
+                }
+            }
+        }
+
+        targets.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.total_cmp(&b.0)));
+        let logic = state.logic();
+        let (first, last) = (logic.start, logic.end - 1);
+        let mut placed: Vec<Vec<(f64, usize)>> = vec![Vec::new(); state.rows.len()];
+        let mut used = vec![0; state.rows.len()];
+        for (tx, tz, g) in targets {
+            let want = (tz.round() as usize).clamp(first, last);
+            let r = (want..=last)
+                .chain((first..want).rev())
+                .find(|&r| used[r] + state.width[g] <= state.max_width)
+                .unwrap_or(want);
+            used[r] += state.width[g];
+            placed[r].push((tx, g));
+        }
+        for r in logic {
+            let mut gates = std::mem::take(&mut placed[r]);
+            gates.sort_by(|a, b| a.0.total_cmp(&b.0));
+            state.rows[r] = gates.into_iter().map(|(_, g)| g).collect();
+        }
+        state.repack();
+        if state.cost < best_cost {
+            best_cost = state.cost;
+            best_rows.clone_from(&state.rows);
+        }
+    }
+    for (row, best) in rows.iter_mut().zip(best_rows) {
+        *row = best;
+    }
+}
+
+/// A pin of a net: a gate and a port offset within its primitive.
+type Pin = (usize, i32, i32);
+
+/// Row-based placement state with incrementally maintained net lengths.
+struct Placement {
+    rows: Vec<Vec<usize>>,
+    io: Io,
+    /// Tower and layer of every row.
+    slots: Vec<(usize, usize)>,
+    layers: usize,
+    grid: Grid,
+    width: Vec<i32>,
+    max_width: i32,
+    /// Column of every gate, and where each column of the logic rows
+    /// starts.
+    columns: Vec<usize>,
+    column_x: Vec<i32>,
+    /// Row and X offset of every gate.
+    row_of: Vec<usize>,
+    x: Vec<i32>,
+    nets: Vec<Vec<Pin>>,
+    nets_of: Vec<Vec<usize>>,
+    net_cost: Vec<i32>,
+    cost: i32,
+}
+
+/// What it takes to revert a move: the rows it touched and their old
+/// contents, plus the net lengths it changed.
+struct Undo {
+    rows: Vec<(usize, Vec<usize>)>,
+    nets: Vec<(usize, i32)>,
+}
+
+impl Placement {
+    fn new(
+        circuit: &Circuit,
+        rows: &[Vec<usize>],
+        io: Io,
+        layers: usize,
+        columns: &[usize],
+        grid: &Grid,
+    ) -> Placement {
+        let n = circuit.gates.len();
+        let prims: Vec<_> = circuit
+            .gates
+            .iter()
+            .map(|g| primitive_for(&g.kind, &g.params))
+            .collect();
+        let producer: HashMap<&str, usize> = circuit
+            .gates
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (g.output.as_str(), i))
+            .collect();
+        let mut by_signal: HashMap<&str, usize> = HashMap::new();
+        let mut nets: Vec<Vec<Pin>> = Vec::new();
+        for (i, g) in circuit.gates.iter().enumerate() {
+            for (port, input) in prims[i].input_ports.iter().zip(&g.inputs) {
+                let Some(&src) = producer.get(input.as_str()) else {
+                    continue;
+                };
+                let net = *by_signal.entry(input.as_str()).or_insert_with(|| {
+                    let (ox, _, oz) = prims[src].output_port;
+                    nets.push(vec![(src, ox, oz)]);
+                    nets.len() - 1
+                });
+                nets[net].push((i, port.0, port.2));
+            }
+        }
+        let mut nets_of = vec![Vec::new(); n];
+        for (net, pins) in nets.iter().enumerate() {
+            for &(g, _, _) in pins {
+                if nets_of[g].last() != Some(&net) {
+                    nets_of[g].push(net);
+                }
+            }
+        }
+        let mut state = Placement {
+            rows: rows.to_vec(),
+            io,
+            slots: towers(rows.len(), io, layers),
+            layers,
+            grid: *grid,
+            width: prims.iter().map(|p| p.size_x + grid.spacing_x).collect(),
+            max_width: 0,
+            columns: columns.to_vec(),
+            column_x: Vec::new(),
+            row_of: vec![0; n],
+            x: vec![0; n],
+            net_cost: vec![0; nets.len()],
+            nets,
+            nets_of,
+            cost: 0,
+        };
+        for r in 0..state.rows.len() {
+            state.max_width = state.max_width.max(state.row_width(r));
+        }
+        let logic = state.logic();
+        let width = &state.width;
+        state.column_x = column_starts(&mut state.rows[logic], columns, |g| width[g]);
+        state.repack();
+        state
+    }
+
+    /// Lay out every row and recompute every net length.
+    fn repack(&mut self) {
+        for r in 0..self.rows.len() {
+            self.pack(r);
+        }
+        for net in 0..self.nets.len() {
+            self.net_cost[net] = self.hpwl(net);
+        }
+        self.cost = self.net_cost.iter().sum();
+    }
+
+    /// Lay row `r` out from the left edge, a logic row column by column.
+    fn pack(&mut self, r: usize) {
+        let logic = self.logic().contains(&r);
+        if logic {
+            let columns = &self.columns;
+            self.rows[r].sort_by_key(|&g| columns[g]);
+        }
+        let mut x = 0;
+        for &g in &self.rows[r] {
+            if logic {
+                x = x.max(self.column_x[self.columns[g]]);
+            }
+            self.row_of[g] = r;
+            self.x[g] = x;
+            x += self.width[g];
+        }
+    }
+
+    fn row_width(&self, r: usize) -> i32 {
+        self.rows[r].iter().map(|&g| self.width[g]).sum()
+    }
+
+    /// The logic rows, between the IO rows, which gates may move among.
+    fn logic(&self) -> std::ops::Range<usize> {
+        self.io.0..self.rows.len() - self.io.1
+    }
+
+    /// Approximate Y and Z of row `r`, with the layers' stagger spread
+    /// evenly over the row pitch.
+    fn row_at(&self, r: usize) -> (i32, i32) {
+        let (tower, layer) = (self.slots[r].0 as i32, self.slots[r].1 as i32);
+        let pitch = self.grid.spacing_z;
+        let z = tower * pitch + layer * pitch / self.layers as i32;
+        (layer * self.grid.layer_height, z)
+    }
+
+    /// Half-perimeter of the bounding box around a net's pins.
+    fn hpwl(&self, net: usize) -> i32 {
+        let (mut min_x, mut max_x) = (i32::MAX, i32::MIN);
+        let (mut min_y, mut max_y) = (i32::MAX, i32::MIN);
+        let (mut min_z, mut max_z) = (i32::MAX, i32::MIN);
+        for &(g, dx, dz) in &self.nets[net] {
+            let x = self.x[g] + dx;
+            let (y, z) = self.row_at(self.row_of[g]);
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+            min_z = min_z.min(z + dz);
+            max_z = max_z.max(z + dz);
+        }
+        max_x - min_x + max_y - min_y + max_z - min_z
+    }
+
+    /// Swap two gates or move one into another row, keeping every row
+    /// within the width limit; returns the change in wirelength.
+    fn try_move(&mut self, movable: &[usize], rng: &mut Rng) -> Option<(i32, Undo)> {
+        let a = movable[rng.below(movable.len())];
+        let ra = self.row_of[a];
+        let ia = self.rows[ra].iter().position(|&g| g == a)?;
+        let mut touched = vec![(ra, self.rows[ra].clone())];
+        if rng.unit() < 0.5 {
+            let b = movable[rng.below(movable.len())];
+            let rb = self.row_of[b];
+            if a == b {
+                return None;
+            }
+            let ib = self.rows[rb].iter().position(|&g| g == b)?;
+            if ra != rb {
+                let grow = self.width[b] - self.width[a];
+                if self.row_width(ra) + grow > self.max_width
+                    || self.row_width(rb) - grow > self.max_width
+                {
+                    return None;
+                }
+                touched.push((rb, self.rows[rb].clone()));
+            }
+            self.rows[ra][ia] = b;
+            self.rows[rb][ib] = a;
+        } else {
+            let logic = self.logic();
+            let rb = logic.start + rng.below(logic.len());
+            if rb != ra {
+                if self.row_width(rb) + self.width[a] > self.max_width {
+                    return None;
+                }
+                touched.push((rb, self.rows[rb].clone()));
+            }
+            self.rows[ra].remove(ia);
+            let at = rng.below(self.rows[rb].len() + 1);
+            self.rows[rb].insert(at, a);
+        }
+
+        let mut nets: Vec<usize> = Vec::new();
+        for &(r, _) in &touched {
+            self.pack(r);
+            for &g in &self.rows[r] {
+                nets.extend(&self.nets_of[g]);
+            }
+        }
+        nets.sort_unstable();
+        nets.dedup();
+        let mut delta = 0;
+        let mut old = Vec::with_capacity(nets.len());
+        for net in nets {
+            let cost = self.hpwl(net);
+            delta += cost - self.net_cost[net];
+            old.push((net, self.net_cost[net]));
+            self.net_cost[net] = cost;
+        }
+        self.cost += delta;
+        Some((
+            delta,
+            Undo {
+                rows: touched,
+                nets: old,
+            },
+        ))
+    }
+
+    fn undo(&mut self, undo: Undo) {
+        for (r, row) in undo.rows {
+            self.rows[r] = row;
+            self.pack(r);
+        }
+        for (net, cost) in undo.nets {
+            self.cost += cost - self.net_cost[net];
+            self.net_cost[net] = cost;
+        }
+    }
+}
+
+/// A small xorshift generator, so placements are reproducible run to run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
     }
-    
-    Layout { positions }
 }
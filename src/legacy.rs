@@ -0,0 +1,186 @@
+//! Block states to the numeric IDs and data values of 1.12 and earlier,
+//! for the MCEdit/WorldEdit `.schematic` format.
+
+/// Blocks whose legacy form has no data beyond the ID.
+const SIMPLE: &[(&str, u8)] = &[
+    ("minecraft:air", 0),
+    ("minecraft:stone", 1),
+    ("minecraft:cobblestone", 4),
+    ("minecraft:glass", 20),
+    ("minecraft:sandstone", 24),
+    ("minecraft:gold_block", 41),
+    ("minecraft:iron_block", 42),
+    ("minecraft:glowstone", 89),
+    ("minecraft:smooth_stone", 43),
+    ("minecraft:stone_bricks", 98),
+    ("minecraft:redstone_lamp", 123),
+    ("minecraft:redstone_block", 152),
+    ("minecraft:quartz_block", 155),
+    ("minecraft:slime_block", 165),
+];
+
+/// Colours in their legacy data order, for wool, concrete and glass.
+const COLORS: &[&str] = &[
+    "white",
+    "orange",
+    "magenta",
+    "light_blue",
+    "yellow",
+    "lime",
+    "pink",
+    "gray",
+    "light_gray",
+    "cyan",
+    "purple",
+    "blue",
+    "brown",
+    "green",
+    "red",
+    "black",
+];
+
+/// Data value of a torch, lever or button attached to the side opposite
+/// `facing`.
+fn wall_data(facing: Option<&str>) -> u8 {
+    match facing {
+        Some("east") => 1,
+        Some("west") => 2,
+        Some("south") => 3,
+        Some("north") => 4,
+        _ => 5,
+    }
+}
+
+/// Legacy `(id, data)` for a block state, or `None` when 1.12 has no
+/// equivalent.
+pub fn legacy_id(name: &str, props: &[(String, String)]) -> Option<(u8, u8)> {
+    let prop = |key: &str| {
+        props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+    let on = |key: &str| prop(key) == Some("true");
+    if let Some(&(_, id)) = SIMPLE.iter().find(|(n, _)| *n == name) {
+        // A lit lamp is its own block.
+        if name == "minecraft:redstone_lamp" && on("lit") {
+            return Some((124, 0));
+        }
+        return Some((id, if id == 43 { 8 } else { 0 }));
+    }
+    let short = name.strip_prefix("minecraft:")?;
+    for (suffix, id) in [("_wool", 35), ("_concrete", 251), ("_stained_glass", 95)] {
+        if let Some(color) = short.strip_suffix(suffix) {
+            let data = COLORS.iter().position(|c| *c == color)?;
+            return Some((id, data as u8));
+        }
+    }
+    match short {
+        "redstone_wire" => {
+            let power = prop("power").and_then(|p| p.parse().ok()).unwrap_or(0);
+            Some((55, power))
+        }
+        "redstone_torch" | "redstone_wall_torch" => {
+            let id = if prop("lit") == Some("false") { 75 } else { 76 };
+            Some((id, wall_data(prop("facing"))))
+        }
+        "repeater" | "comparator" => {
+            let dir = match prop("facing") {
+                Some("west") => 1,
+                Some("north") => 2,
+                Some("east") => 3,
+                _ => 0,
+            };
+            if short == "comparator" {
+                let subtract = if prop("mode") == Some("subtract") {
+                    4
+                } else {
+                    0
+                };
+                let id = if on("powered") { 150 } else { 149 };
+                return Some((id, dir | subtract));
+            }
+            let delay: u8 = prop("delay").and_then(|d| d.parse().ok()).unwrap_or(1);
+            let id = if on("powered") { 94 } else { 93 };
+            Some((id, dir | (delay.clamp(1, 4) - 1) << 2))
+        }
+        "lever" | "stone_button" | "oak_button" => {
+            let facing = prop("facing");
+            let data = match prop("face") {
+                Some("floor") if short == "lever" => {
+                    if matches!(facing, Some("east" | "west")) {
+                        6
+                    } else {
+                        5
+                    }
+                }
+                Some("ceiling") if short == "lever" => {
+                    if matches!(facing, Some("east" | "west")) {
+                        0
+                    } else {
+                        7
+                    }
+                }
+                Some("floor") => 5,
+                Some("ceiling") => 0,
+                _ => wall_data(facing),
+            };
+            let id = match short {
+                "lever" => 69,
+                "stone_button" => 77,
+                _ => 143,
+            };
+            Some((id, data | if on("powered") { 8 } else { 0 }))
+        }
+        "stone_pressure_plate" | "oak_pressure_plate" => {
+            let id = if short == "oak_pressure_plate" {
+                72
+            } else {
+                70
+            };
+            Some((id, if on("powered") { 1 } else { 0 }))
+        }
+        "powered_rail" => {
+            let along_x = prop("shape") == Some("east_west");
+            Some((27, along_x as u8 | if on("powered") { 8 } else { 0 }))
+        }
+        "observer" => {
+            let data = match prop("facing") {
+                Some("down") => 0,
+                Some("up") => 1,
+                Some("north") => 2,
+                Some("south") => 3,
+                Some("west") => 4,
+                _ => 5,
+            };
+            Some((218, data | if on("powered") { 8 } else { 0 }))
+        }
+        "oak_sign" => Some((
+            63,
+            prop("rotation").and_then(|r| r.parse().ok()).unwrap_or(0),
+        )),
+        "oak_wall_sign" => {
+            let data = match prop("facing") {
+                Some("south") => 3,
+                Some("west") => 4,
+                Some("east") => 5,
+                _ => 2,
+            };
+            Some((68, data))
+        }
+        // The upper half keeps the hinge side; the lower, facing and open.
+        "oak_door" if prop("half") == Some("upper") => {
+            Some((64, 8 | if prop("hinge") == Some("right") { 1 } else { 0 }))
+        }
+        "oak_door" => {
+            let dir = match prop("facing") {
+                Some("south") => 1,
+                Some("west") => 2,
+                Some("north") => 3,
+                _ => 0,
+            };
+            Some((64, dir | if on("open") { 4 } else { 0 }))
+        }
+        _ => None,
+    }
+}
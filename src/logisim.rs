@@ -0,0 +1,422 @@
+//! Logisim (Evolution) `.circ` importer. Maps the main circuit's gates,
+//! pins, tunnels and constants onto gates, recovering connectivity from
+//! wire geometry the way Logisim does.
+//!
+//! Only single-bit designs without subcircuits are supported.
+
+use crate::compiler::{next_id, Circuit, Ctx};
+use anyhow::Result;
+use std::collections::HashMap;
+
+type Point = (i32, i32);
+
+#[derive(Clone, Copy)]
+enum Facing {
+    East,
+    West,
+    North,
+    South,
+}
+
+/// A pin of a component: where it is and what it does.
+enum Port {
+    /// Drives the net with this signal.
+    Out(String),
+    /// Gate input `index` of component `comp`, inverted when `negated`.
+    In {
+        comp: usize,
+        index: usize,
+        negated: bool,
+    },
+    /// Output pin or LED named `label`.
+    Sink(String),
+    /// Joins every tunnel with the same label.
+    Tunnel(String),
+}
+
+struct GateComp {
+    kind: String,
+    loc: Point,
+    inputs: usize,
+    /// Multi-input XOR/XNOR: odd parity instead of exactly one high.
+    odd: bool,
+    output: String,
+}
+
+fn parse_point(s: &str) -> Result<Point> {
+    let inner = s.trim().trim_start_matches('(').trim_end_matches(')');
+    let Some((x, y)) = inner.split_once(',') else {
+        anyhow::bail!("invalid location `{}`", s);
+    };
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+/// Offset a point `dx` back from the output against the facing direction
+/// and `dy` across it, following Logisim's port geometry.
+fn offset(loc: Point, facing: Facing, dx: i32, dy: i32) -> Point {
+    let (x, y) = loc;
+    match facing {
+        Facing::East => (x - dx, y + dy),
+        Facing::West => (x + dx, y + dy),
+        Facing::North => (x + dy, y + dx),
+        Facing::South => (x + dy, y - dx),
+    }
+}
+
+/// Logisim's input spacing for an n-input gate of the given size.
+fn input_dy(inputs: usize, size: i32, index: usize) -> i32 {
+    let (skip_start, skip_dist, skip_lower_even) = if inputs <= 3 {
+        if size < 40 {
+            (-5, 10, 10)
+        } else if size < 60 || inputs <= 2 {
+            (-10, 20, 20)
+        } else {
+            (-15, 30, 30)
+        }
+    } else if inputs == 4 && size >= 60 {
+        (-5, 20, 0)
+    } else {
+        (-5, 10, 10)
+    };
+    let (n, i) = (inputs as i32, index as i32);
+    if n % 2 == 1 {
+        skip_start * (n - 1) + skip_dist * i
+    } else {
+        let dy = skip_start * n + skip_dist * i;
+        if index >= inputs / 2 {
+            dy + skip_lower_even
+        } else {
+            dy
+        }
+    }
+}
+
+/// Union-find over grid points.
+struct Nets {
+    parent: HashMap<Point, Point>,
+}
+
+impl Nets {
+    fn find(&mut self, p: Point) -> Point {
+        let parent = *self.parent.entry(p).or_insert(p);
+        if parent == p {
+            return p;
+        }
+        let root = self.find(parent);
+        self.parent.insert(p, root);
+        root
+    }
+
+    fn union(&mut self, a: Point, b: Point) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent.insert(ra, rb);
+        }
+    }
+}
+
+/// Parse a `.circ` file and lower its main circuit.
+pub fn compile(src: &str) -> Result<Circuit> {
+    let doc = roxmltree::Document::parse(src)?;
+    let project = doc.root_element();
+    // Logisim 2.x gates default to five inputs, Evolution's to two.
+    let legacy = project
+        .attribute("source")
+        .is_some_and(|v| v.starts_with("2."));
+    let circuits: Vec<_> = project
+        .children()
+        .filter(|n| n.has_tag_name("circuit"))
+        .collect();
+    let main = project
+        .children()
+        .find(|n| n.has_tag_name("main"))
+        .and_then(|n| n.attribute("name"));
+    let circuit = match main {
+        Some(name) => circuits
+            .iter()
+            .find(|c| c.attribute("name") == Some(name))
+            .ok_or_else(|| anyhow::anyhow!("main circuit `{}` not found", name))?,
+        None => circuits
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("no circuit found"))?,
+    };
+    let circuit_names: Vec<&str> = circuits
+        .iter()
+        .filter_map(|c| c.attribute("name"))
+        .collect();
+
+    let mut ctx = Ctx::new();
+    let mut inputs = Vec::new();
+    let mut wires: Vec<(Point, Point)> = Vec::new();
+    let mut ports: Vec<(Point, Port)> = Vec::new();
+    let mut gates: Vec<GateComp> = Vec::new();
+    for node in circuit.children().filter(|n| n.is_element()) {
+        if node.has_tag_name("wire") {
+            let from = parse_point(node.attribute("from").unwrap_or_default())?;
+            let to = parse_point(node.attribute("to").unwrap_or_default())?;
+            wires.push((from, to));
+            continue;
+        }
+        if !node.has_tag_name("comp") {
+            continue;
+        }
+        let name = node.attribute("name").unwrap_or_default();
+        let loc = parse_point(node.attribute("loc").unwrap_or_default())?;
+        let attrs: HashMap<&str, &str> = node
+            .children()
+            .filter(|a| a.has_tag_name("a"))
+            .filter_map(|a| Some((a.attribute("name")?, a.attribute("val")?)))
+            .collect();
+        let at = format!("{} at ({},{})", name, loc.0, loc.1);
+        if attrs.get("width").is_some_and(|w| *w != "1") {
+            anyhow::bail!("{}: multi-bit components are not supported", at);
+        }
+        let facing = match attrs.get("facing").copied() {
+            None | Some("east") => Facing::East,
+            Some("west") => Facing::West,
+            Some("north") => Facing::North,
+            Some("south") => Facing::South,
+            Some(f) => anyhow::bail!("{}: unknown facing `{}`", at, f),
+        };
+        let label = attrs
+            .get("label")
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| format!("{}_{}_{}", name.to_lowercase(), loc.0, loc.1));
+        match name {
+            "AND Gate" | "OR Gate" | "NAND Gate" | "NOR Gate" | "XOR Gate" | "XNOR Gate" => {
+                let default_inputs = if legacy { 5 } else { 2 };
+                let n: usize = match attrs.get("inputs") {
+                    Some(v) => v.parse()?,
+                    None => default_inputs,
+                };
+                let size: i32 = match attrs.get("size") {
+                    Some(v) => v.parse()?,
+                    None => 50,
+                };
+                let kind = name.trim_end_matches(" Gate").to_string();
+                // XOR-style gates are 10 wider; inverting gates add a bubble.
+                let mut axis = size;
+                if kind.starts_with('X') {
+                    axis += 10;
+                }
+                if kind.starts_with('N') || kind == "XNOR" {
+                    axis += 10;
+                }
+                let comp = gates.len();
+                for index in 0..n {
+                    let negated = attrs.get(format!("negate{}", index).as_str()) == Some(&"true");
+                    let dx = axis + if negated { 10 } else { 0 };
+                    let p = offset(loc, facing, dx, input_dy(n, size, index));
+                    ports.push((
+                        p,
+                        Port::In {
+                            comp,
+                            index,
+                            negated,
+                        },
+                    ));
+                }
+                let output = next_id();
+                ports.push((loc, Port::Out(output.clone())));
+                gates.push(GateComp {
+                    kind,
+                    loc,
+                    inputs: n,
+                    odd: attrs.get("xor") == Some(&"odd"),
+                    output,
+                });
+            }
+            "NOT Gate" | "Buffer" => {
+                let dx = match (name, attrs.get("size")) {
+                    ("Buffer", _) => 20,
+                    (_, Some(v)) => v.parse()?,
+                    (_, None) => 30,
+                };
+                let comp = gates.len();
+                ports.push((
+                    offset(loc, facing, dx, 0),
+                    Port::In {
+                        comp,
+                        index: 0,
+                        negated: false,
+                    },
+                ));
+                let output = next_id();
+                ports.push((loc, Port::Out(output.clone())));
+                gates.push(GateComp {
+                    kind: if name == "Buffer" { "BUF" } else { "NOT" }.into(),
+                    loc,
+                    inputs: 1,
+                    odd: false,
+                    output,
+                });
+            }
+            "Pin" => {
+                let output =
+                    attrs.get("output") == Some(&"true") || attrs.get("type") == Some(&"output");
+                if output {
+                    ports.push((loc, Port::Sink(label)));
+                } else {
+                    if inputs.contains(&label) {
+                        anyhow::bail!("{}: input `{}` is defined twice", at, label);
+                    }
+                    ports.push((loc, Port::Out(ctx.input(&label))));
+                    inputs.push(label);
+                }
+            }
+            "Button" | "Clock" => {
+                if inputs.contains(&label) {
+                    anyhow::bail!("{}: input `{}` is defined twice", at, label);
+                }
+                ports.push((loc, Port::Out(ctx.input(&label))));
+                inputs.push(label);
+            }
+            "LED" => ports.push((loc, Port::Sink(label))),
+            "Constant" => {
+                let high = match attrs.get("value").copied() {
+                    None => true,
+                    Some(v) => i64::from_str_radix(v.trim_start_matches("0x"), 16)? != 0,
+                };
+                let sig = if high {
+                    "CONST_TRUE_SIG"
+                } else {
+                    "CONST_FALSE_SIG"
+                };
+                ports.push((loc, Port::Out(sig.into())));
+            }
+            "Tunnel" => {
+                let Some(l) = attrs.get("label") else {
+                    anyhow::bail!("{}: tunnel without a label", at);
+                };
+                ports.push((loc, Port::Tunnel(l.to_string())));
+            }
+            "Text" | "Probe" | "Pull Resistor" => {}
+            n if circuit_names.contains(&n) => {
+                anyhow::bail!("{}: subcircuits are not supported; flatten the design", at)
+            }
+            n => anyhow::bail!("{}: unsupported component `{}`", at, n),
+        }
+    }
+
+    // Connectivity: wire endpoints, plus any pin or endpoint that lands on
+    // another wire (a T junction).
+    let mut nets = Nets {
+        parent: HashMap::new(),
+    };
+    let mut points: Vec<Point> = ports.iter().map(|(p, _)| *p).collect();
+    for &(a, b) in &wires {
+        nets.union(a, b);
+        points.push(a);
+        points.push(b);
+    }
+    for &p in &points {
+        for &(a, b) in &wires {
+            let on_x = a.0 == b.0 && p.0 == a.0 && p.1 > a.1.min(b.1) && p.1 < a.1.max(b.1);
+            let on_y = a.1 == b.1 && p.1 == a.1 && p.0 > a.0.min(b.0) && p.0 < a.0.max(b.0);
+            if on_x || on_y {
+                nets.union(p, a);
+            }
+        }
+    }
+    let mut tunnels: HashMap<&str, Point> = HashMap::new();
+    for (p, port) in &ports {
+        if let Port::Tunnel(label) = port {
+            match tunnels.get(label.as_str()) {
+                Some(&first) => nets.union(*p, first),
+                None => {
+                    tunnels.insert(label, *p);
+                }
+            }
+        }
+    }
+    let mut driver: HashMap<Point, String> = HashMap::new();
+    for (p, port) in &ports {
+        if let Port::Out(sig) = port {
+            let root = nets.find(*p);
+            if driver.insert(root, sig.clone()).is_some() {
+                anyhow::bail!("the net at ({},{}) has multiple drivers", p.0, p.1);
+            }
+        }
+    }
+
+    // Gate inputs, in pin order; Logisim ignores floating gate inputs.
+    let mut gate_inputs: Vec<Vec<Option<String>>> =
+        gates.iter().map(|g| vec![None; g.inputs]).collect();
+    let mut outputs = Vec::new();
+    for (p, port) in &ports {
+        let sig = driver.get(&nets.find(*p)).cloned();
+        match port {
+            Port::In {
+                comp,
+                index,
+                negated,
+            } => {
+                gate_inputs[*comp][*index] = match sig {
+                    Some(s) if *negated => Some(ctx.emit("NOT", vec![s])),
+                    s => s,
+                }
+            }
+            Port::Sink(label) => {
+                let Some(sig) = sig else {
+                    anyhow::bail!("output `{}` at ({},{}) is not connected", label, p.0, p.1);
+                };
+                outputs.push((ctx.emit("BUF", vec![sig]), label.clone()));
+            }
+            _ => {}
+        }
+    }
+    for (g, ins) in gates.iter().zip(gate_inputs) {
+        let ins: Vec<String> = ins.into_iter().flatten().collect();
+        if ins.is_empty() {
+            anyhow::bail!(
+                "{} gate at ({},{}) has no connected inputs",
+                g.kind,
+                g.loc.0,
+                g.loc.1
+            );
+        }
+        lower_gate(&mut ctx, g, ins);
+    }
+    Ok(ctx.finish(inputs, outputs))
+}
+
+/// Emit a (possibly multi-input) gate driving its reserved output.
+fn lower_gate(ctx: &mut Ctx, g: &GateComp, ins: Vec<String>) {
+    let out = g.output.clone();
+    let invert = g.kind.starts_with('N') || g.kind == "XNOR";
+    if ins.len() == 1 {
+        let kind = if invert || g.kind == "NOT" {
+            "NOT"
+        } else {
+            "BUF"
+        };
+        ctx.emit_into(kind, ins, out);
+        return;
+    }
+    if ins.len() == 2 {
+        ctx.emit_into(&g.kind, ins, out);
+        return;
+    }
+    let base = g.kind.trim_start_matches('N');
+    let value = match base {
+        "AND" => ctx.reduce_balanced(ins, |c, x, y| c.emit("AND", vec![x, y])),
+        "OR" => ctx.reduce_balanced(ins, |c, x, y| c.emit("OR", vec![x, y])),
+        _ if g.odd => ctx.reduce_balanced(ins, |c, x, y| c.xor(x, y)),
+        // Logisim's default XOR: exactly one input high.
+        _ => {
+            let any = ctx.or_all(ins.clone());
+            let mut pairs = Vec::new();
+            for i in 0..ins.len() {
+                for j in i + 1..ins.len() {
+                    pairs.push(ctx.emit("AND", vec![ins[i].clone(), ins[j].clone()]));
+                }
+            }
+            let two = ctx.or_all(pairs);
+            let not_two = ctx.emit("NOT", vec![two]);
+            Some(ctx.emit("AND", vec![any, not_two]))
+        }
+    };
+    let kind = if invert { "NOT" } else { "BUF" };
+    ctx.emit_into(kind, vec![value.unwrap()], out);
+}
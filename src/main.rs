@@ -1,35 +1,202 @@
 use std::fs;
+use std::io;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+/// Set while the schematic is written to stdout (`--stdout`), which moves
+/// progress lines to stderr.
+static STDOUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// `println!` for progress lines, on stderr under `--stdout`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if crate::STDOUT_TAKEN.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+mod anvil;
+mod bedrock;
+mod blif;
+mod cli;
 mod compiler;
+mod datapack;
+mod digital;
+mod dot;
+mod emit;
 mod layout;
+mod legacy;
+mod logisim;
+mod minimize;
+mod netlist;
 mod optimizer;
 mod parser;
+mod partition;
+mod pla;
 mod primitives;
+mod project;
+mod router;
 mod schematic;
 mod semantics;
+mod truthtable;
+mod verilog;
+
+/// Input extensions with their own frontend; anything else is JS.
+const FORMATS: &[&str] = &[
+    "v", "blif", "csv", "pla", "json", "circ", "dig", "dot", "gv",
+];
 
 fn main() -> anyhow::Result<()> {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} input.js out.litematic", args[0]);
-        std::process::exit(2);
-    }
-    let in_path = &args[1];
-    let out_path = &args[2];
-    let code = fs::read_to_string(in_path)?;
+    let opts = match cli::Options::parse(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::usage(&args[0]));
+            std::process::exit(2);
+        }
+    };
+    STDOUT_TAKEN.store(opts.stdout, Ordering::Relaxed);
+    let (code, ext) = match &opts.input {
+        cli::Input::Path(path) => (
+            fs::read_to_string(path)?,
+            Path::new(path).extension().and_then(|e| e.to_str()),
+        ),
+        cli::Input::Stdin => (io::read_to_string(io::stdin())?, None),
+        cli::Input::Inline(expr) => {
+            // Declare the expression's free variables as inputs.
+            let program = parser::parse_and_validate(expr)?;
+            let decls: String = semantics::free_variables(&program)
+                .iter()
+                .map(|v| format!("let {};\n", v))
+                .collect();
+            (decls + expr, None)
+        }
+        cli::Input::Project(path) => {
+            for target in project::load(path)? {
+                status!("Building target {}", target.name);
+                if let Some(dir) = Path::new(&target.output).parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let mut target_opts = opts.clone();
+                target_opts.output = target.output;
+                // The schematic is named after its target.
+                target_opts.name = Some(target.name.clone());
+                target.overrides.apply(&mut target_opts);
+                build(&target.code, target.ext.as_deref(), &target_opts)
+                    .map_err(|e| anyhow::anyhow!("target `{}`: {}", target.name, e))?;
+            }
+            return Ok(());
+        }
+    };
+    build(&code, ext, &opts)
+}
 
-    let program = parser::parse_and_validate(&code)?;
-    // Debug: print parsed program JSON
-    println!(
-        "schemlogica: parsed program = {}",
-        serde_json::to_string_pretty(&program)?
-    );
-    let sem = semantics::analyze(&program)?;
-    let circuit = compiler::compile(&program, &sem)?;
-    let circuit = optimizer::optimize(circuit);
-    let layout = layout::layout_circuit(&circuit);
-    schematic::write_schem(&circuit, &layout, Path::new(out_path))?;
-    println!("Wrote litematic to {}", out_path);
+/// Compile one design and write it to `opts.output`.
+fn build(code: &str, ext: Option<&str>, opts: &cli::Options) -> anyhow::Result<()> {
+    let circuit = match ext {
+        Some("v") => verilog::compile(code)?,
+        Some("blif") => blif::compile(code)?,
+        Some("csv") => truthtable::compile(code)?,
+        Some("pla") => pla::compile(code, opts.pla_matrix)?,
+        Some("json") => netlist::load(code)?,
+        Some("circ") => logisim::compile(code)?,
+        Some("dig") => digital::compile(code)?,
+        Some("dot" | "gv") => dot::compile(code)?,
+        _ => {
+            let program = parser::parse_and_validate(code)?;
+            let sem = semantics::analyze(&program)?;
+            compiler::compile(&program, &sem)?
+        }
+    };
+    let mut circuit = compiler::resolve_feedback(circuit, opts.infer_latches)?;
+    // Inputs whose source picks no hardware get --input-hardware's.
+    if opts.input_hardware != 0 {
+        for g in circuit.gates.iter_mut() {
+            if g.kind == "INPUT" && g.params.is_empty() {
+                g.params = vec![opts.input_hardware];
+            }
+        }
+    }
+    let optimize_opts = optimizer::Options {
+        level: opts.opt_level,
+        nand_nor: opts.nand_nor,
+        goal: opts.goal,
+    };
+    let (circuit, stats) = optimizer::optimize(circuit, &optimize_opts);
+    let layout_opts = layout::Options {
+        placer: opts.placer,
+        layers: opts.layers,
+        max_size: opts.max_size,
+        hierarchy: opts.hierarchy,
+        grid: layout::Grid {
+            spacing_x: opts.spacing.0,
+            spacing_z: opts.spacing.1,
+            layer_height: opts.layer_height,
+            origin: opts.origin,
+        },
+        directives: layout::parse_directives(code)?,
+        aspect: opts.aspect,
+    };
+    let mut layout = layout::layout_circuit(&circuit, &layout_opts)?;
+    let build_opts = schematic::BuildOptions {
+        equalize_delays: opts.equalize_delays,
+        wire_lanes: opts.wire_lanes,
+        instant_wire: opts.instant_wire,
+        tunnels: opts.tunnels,
+        net_order: opts.net_order,
+        allow_shorts: opts.allow_shorts,
+        allow_unwired: opts.allow_unwired,
+        support_block: opts.support_block.clone(),
+        floor_block: opts.floor_block.clone(),
+        color_nets: opts.color_nets,
+        labels: opts.labels,
+        lamps: opts.lamps,
+    };
+    let mut blocks = schematic::build_blocks(&circuit, &layout, &build_opts)?;
+    // Where inputs are left unwired, make room around their gates and
+    // route again, as long as that wires up more of them.
+    for _ in 0..opts.place_retries {
+        if blocks.unwired.is_empty() {
+            break;
+        }
+        let crowded: Vec<&str> = blocks.unwired.iter().map(|(g, _)| g.as_str()).collect();
+        let Some(spread) = layout::spread(&circuit, &layout, &layout_opts, &crowded)? else {
+            break;
+        };
+        status!(
+            "Spreading the gates around {} unwired input(s) and routing again",
+            crowded.len()
+        );
+        let retry = schematic::build_blocks(&circuit, &spread, &build_opts)?;
+        if retry.unwired.len() >= blocks.unwired.len() {
+            break;
+        }
+        (layout, blocks) = (spread, retry);
+    }
+    schematic::check_wired(&blocks, &build_opts)?;
+    if let Some(shell) = &opts.enclose {
+        schematic::enclose(&mut blocks, shell);
+    }
+    let output = Path::new(&opts.output);
+    let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned());
+    let write_opts = schematic::WriteOptions {
+        sponge_version: opts.sponge_version,
+        name: opts.name.clone().or(stem).unwrap_or_default(),
+        author: opts.author.clone(),
+        description: opts.description.clone(),
+        at: opts.at,
+        verify: opts.verify,
+        gzip: opts.gzip,
+        stdout: opts.stdout,
+    };
+    schematic::write_schem(&blocks, Path::new(&opts.output), &write_opts)?;
+    status!("Wrote {}", opts.output);
+    for kind in &opts.emit {
+        emit::emit(kind, &circuit, &layout, &blocks, Path::new(&opts.output))?;
+    }
+    status!("{}", stats.to_string().trim_end());
     Ok(())
 }
@@ -0,0 +1,84 @@
+//! Two-level logic minimization (Quine-McCluskey) over small truth tables.
+
+use std::collections::HashSet;
+
+/// A product term over `n` variables: bits set in `mask` are don't-cares,
+/// the remaining bits must equal `value`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Cube {
+    pub value: u32,
+    pub mask: u32,
+}
+
+impl Cube {
+    fn covers(&self, minterm: u32) -> bool {
+        minterm & !self.mask == self.value
+    }
+
+    /// Number of variables the term constrains.
+    pub fn literals(&self, n: usize) -> u32 {
+        n as u32 - self.mask.count_ones()
+    }
+}
+
+/// Minimal sum-of-products cover of the `on` minterms, free to use the `dc`
+/// minterms. Prime implicants are found exhaustively; the cover takes the
+/// essential primes and then greedily the prime covering the most remaining
+/// minterms.
+pub fn minimize(n: usize, on: &[u32], dc: &[u32]) -> Vec<Cube> {
+    if on.is_empty() {
+        return Vec::new();
+    }
+    let mut current: HashSet<Cube> = on
+        .iter()
+        .chain(dc)
+        .map(|&m| Cube { value: m, mask: 0 })
+        .collect();
+    let mut primes: Vec<Cube> = Vec::new();
+    while !current.is_empty() {
+        let cubes: Vec<Cube> = current.iter().copied().collect();
+        let mut merged = HashSet::new();
+        let mut used = HashSet::new();
+        for (i, a) in cubes.iter().enumerate() {
+            for b in &cubes[i + 1..] {
+                let diff = a.value ^ b.value;
+                if a.mask == b.mask && diff.count_ones() == 1 {
+                    merged.insert(Cube {
+                        value: a.value & !diff,
+                        mask: a.mask | diff,
+                    });
+                    used.insert(*a);
+                    used.insert(*b);
+                }
+            }
+        }
+        primes.extend(cubes.into_iter().filter(|c| !used.contains(c)));
+        current = merged;
+    }
+    primes.sort_by_key(|c| (c.literals(n), c.value, c.mask));
+
+    let mut uncovered: Vec<u32> = on.to_vec();
+    let mut cover = Vec::new();
+    // Essential primes: the only prime covering some minterm.
+    for &m in on {
+        let covering: Vec<&Cube> = primes.iter().filter(|p| p.covers(m)).collect();
+        if let [only] = covering.as_slice() {
+            if !cover.contains(*only) {
+                cover.push(**only);
+            }
+        }
+    }
+    uncovered.retain(|m| !cover.iter().any(|c| c.covers(*m)));
+    while !uncovered.is_empty() {
+        let best = *primes
+            .iter()
+            .max_by_key(|p| {
+                let hits = uncovered.iter().filter(|m| p.covers(**m)).count();
+                (hits, std::cmp::Reverse(p.literals(n)))
+            })
+            .unwrap();
+        uncovered.retain(|m| !best.covers(*m));
+        cover.push(best);
+    }
+    cover
+}
@@ -0,0 +1,129 @@
+//! JSON netlist input: a serialized `Circuit` handed straight to placement
+//! and routing, for tools that do their own synthesis.
+
+use crate::compiler::{reserve_ids, Circuit, Gate};
+use crate::primitives::{GATE_KINDS, INPUT_HARDWARE, WIDE_MAX_INPUTS};
+use anyhow::Result;
+use std::collections::HashSet;
+
+/// Parse and check a JSON circuit.
+pub fn load(src: &str) -> Result<Circuit> {
+    check(serde_json::from_str(src)?)
+}
+
+/// Check a circuit built outside the compiler. Constant signals are
+/// provided when the netlist uses them without declaring their gates, a
+/// missing `inputs` list is taken from the INPUT gates, and outputs without
+/// names are named after their signals.
+pub fn check(mut circuit: Circuit) -> Result<Circuit> {
+
+    let mut ids = HashSet::new();
+    let mut driven = HashSet::new();
+    for g in &circuit.gates {
+        let Some(&(_, arity, params)) = GATE_KINDS.iter().find(|(k, _, _)| *k == g.kind) else {
+            anyhow::bail!("gate `{}` has unknown kind `{}`", g.id, g.kind);
+        };
+        let lever = g.kind == "INPUT" && g.params.is_empty();
+        if g.params.len() != params && !lever {
+            anyhow::bail!(
+                "gate `{}` ({}) needs {} params, got {}",
+                g.id,
+                g.kind,
+                params,
+                g.params.len()
+            );
+        }
+        match (g.kind.as_str(), g.params.first()) {
+            ("INPUT", Some(&h)) if !(0..INPUT_HARDWARE.len() as i64).contains(&h) => anyhow::bail!(
+                "gate `{}`: input hardware must be 0 to {} ({}), got {}",
+                g.id,
+                INPUT_HARDWARE.len() - 1,
+                INPUT_HARDWARE.join(", "),
+                h
+            ),
+            ("CLOCK", Some(&p)) if !(6..=98).contains(&p) || p % 2 != 0 => anyhow::bail!(
+                "gate `{}`: clock period must be an even number of ticks from 6 to 98, got {}",
+                g.id,
+                p
+            ),
+            ("DELAY", Some(&t)) if !(1..=256).contains(&t) => anyhow::bail!(
+                "gate `{}`: delay must be 1 to 256 ticks, got {}",
+                g.id,
+                t
+            ),
+            ("WIDE_AND" | "WIDE_OR", Some(&n)) if !(2..=WIDE_MAX_INPUTS as i64).contains(&n) => {
+                anyhow::bail!(
+                    "gate `{}`: wide gates take 2 to {} inputs, got {}",
+                    g.id,
+                    WIDE_MAX_INPUTS,
+                    n
+                )
+            }
+            _ => {}
+        }
+        let arity = arity.unwrap_or_else(|| g.params.first().map_or(0, |&n| n as usize));
+        if g.inputs.len() != arity {
+            anyhow::bail!(
+                "gate `{}` ({}) needs {} inputs, got {}",
+                g.id,
+                g.kind,
+                arity,
+                g.inputs.len()
+            );
+        }
+        if !ids.insert(g.id.clone()) {
+            anyhow::bail!("gate id `{}` is used twice", g.id);
+        }
+        if !driven.insert(g.output.clone()) {
+            anyhow::bail!("signal `{}` has multiple drivers", g.output);
+        }
+    }
+
+    for (sig, kind) in [
+        ("CONST_TRUE_SIG", "CONST_TRUE"),
+        ("CONST_FALSE_SIG", "CONST_FALSE"),
+    ] {
+        let used = circuit.gates.iter().any(|g| g.inputs.iter().any(|i| i == sig))
+            || circuit.outputs.iter().any(|o| o == sig);
+        if used && driven.insert(sig.to_string()) {
+            circuit.gates.push(Gate {
+                id: format!("g_{}", kind.to_lowercase()),
+                kind: kind.into(),
+                inputs: vec![],
+                output: sig.into(),
+                params: vec![],
+                block: String::new(),
+            });
+        }
+    }
+
+    for g in &circuit.gates {
+        if let Some(i) = g.inputs.iter().find(|i| !driven.contains(*i)) {
+            anyhow::bail!("gate `{}` reads `{}`, which nothing drives", g.id, i);
+        }
+    }
+    if let Some(o) = circuit.outputs.iter().find(|o| !driven.contains(*o)) {
+        anyhow::bail!("output `{}` is not driven by any gate", o);
+    }
+    if let Some(d) = circuit.dont_care.iter().find(|d| !driven.contains(*d)) {
+        anyhow::bail!("don't-care `{}` is not driven by any gate", d);
+    }
+    reserve_ids(
+        circuit
+            .gates
+            .iter()
+            .flat_map(|g| [g.id.as_str(), g.output.as_str()]),
+    );
+    if circuit.inputs.is_empty() {
+        circuit.inputs = circuit
+            .gates
+            .iter()
+            .filter(|g| g.kind == "INPUT")
+            .map(|g| g.id.strip_prefix("in_").unwrap_or(&g.id).to_string())
+            .collect();
+    }
+    if circuit.output_names.len() != circuit.outputs.len() {
+        circuit.output_names = circuit.outputs.clone();
+    }
+    Ok(circuit)
+}
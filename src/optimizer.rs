@@ -1,13 +1,1441 @@
-use crate::compiler::Circuit;
+use crate::compiler::{next_id, Circuit, Ctx, Gate, SEQUENTIAL_KINDS};
+use crate::layout::{GATE_SPACING_X, GATE_SPACING_Z};
+use crate::minimize::{minimize, Cube};
+use crate::primitives::{cost, primitive_for};
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 
-// Simple optimizer: dead gate elimination & identity simplifications & const folding
-pub fn optimize(mut circuit: Circuit) -> Circuit {
-    // dead-gate elimination: find gates reachable from outputs
-    let mut producers = std::collections::HashMap::new();
+/// What the optimizer minimizes first: floor area or ticks on the critical
+/// path.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    Area,
+    Speed,
+}
+
+impl Goal {
+    pub fn parse(name: &str) -> Result<Goal> {
+        match name {
+            "area" => Ok(Goal::Area),
+            "speed" => Ok(Goal::Speed),
+            _ => anyhow::bail!("Unknown optimization goal `{}` (expected area or speed)", name),
+        }
+    }
+}
+
+/// Highest optimization level, `-O3`.
+pub const MAX_LEVEL: u8 = 3;
+
+pub struct Options {
+    /// `-O` level: 0 keeps the compiler's netlist as is, 1 folds constants
+    /// and removes dead gates, 2 adds gate fusion, mapping, sharing and
+    /// fanout buffering, 3 adds two-level minimization.
+    pub level: u8,
+    /// Rewrite the logic into NAND and NOR gates.
+    pub nand_nor: bool,
+    pub goal: Goal,
+}
+
+/// What optimization did to a circuit.
+pub struct Stats {
+    /// Every pass that ran, with the logic gates before and after it.
+    pub passes: Vec<(&'static str, usize, usize)>,
+    /// Logic depth before and after.
+    pub depth: (usize, usize),
+    /// Critical path in ticks before and after.
+    pub delay: (usize, usize),
+    /// Blocks the gates' primitives take before and after, without wiring.
+    pub blocks: (usize, usize),
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Optimization:")?;
+        for (name, before, after) in &self.passes {
+            let change = *after as isize - *before as isize;
+            writeln!(f, "  {:<20} {:>5} -> {:<5} gates ({:+})", name, before, after, change)?;
+        }
+        writeln!(f, "  logic depth          {:>5} -> {}", self.depth.0, self.depth.1)?;
+        writeln!(f, "  critical path        {:>5} -> {} ticks", self.delay.0, self.delay.1)?;
+        writeln!(f, "  gate blocks (est.)   {:>5} -> {}", self.blocks.0, self.blocks.1)
+    }
+}
+
+/// Gates other than inputs and constants.
+pub fn logic_gates(circuit: &Circuit) -> usize {
+    circuit
+        .gates
+        .iter()
+        .filter(|g| !matches!(g.kind.as_str(), "INPUT" | "CONST_TRUE" | "CONST_FALSE"))
+        .count()
+}
+
+/// Blocks the primitives of the circuit's gates take.
+fn gate_blocks(circuit: &Circuit) -> usize {
+    circuit
+        .gates
+        .iter()
+        .map(|g| primitive_for(&g.kind, &g.params).blocks.len())
+        .sum()
+}
+
+// Simple optimizer: const folding & identity simplifications & dead gate elimination,
+// with more passes at higher levels.
+// With `nand_nor`, the logic is also rewritten into NAND and NOR gates.
+pub fn optimize(circuit: Circuit, opts: &Options) -> (Circuit, Stats) {
+    let (depth_before, delay_before) = (depth(&circuit), delay(&circuit));
+    let blocks_before = gate_blocks(&circuit);
+    let mut passes = Vec::new();
+    let mut run = |name: &'static str, circuit: Circuit, pass: &dyn Fn(Circuit) -> Circuit| {
+        let before = logic_gates(&circuit);
+        let circuit = pass(circuit);
+        passes.push((name, before, logic_gates(&circuit)));
+        circuit
+    };
+    let mut circuit = circuit;
+    if opts.level > 0 {
+        circuit = run("constant folding", circuit, &fold_constants);
+        if opts.level >= 3 {
+            circuit = run("cone minimization", circuit, &|c| minimize_cones(c, opts.goal));
+        }
+        // Their logic is left for dead-gate removal.
+        circuit.dont_care.clear();
+        if opts.level >= 2 {
+            if !opts.nand_nor {
+                circuit = run("mux fusion", circuit, &|c| fuse_muxes(c, opts.goal));
+            }
+            circuit = run("inverter absorption", circuit, &absorb_inverters);
+            circuit = run("chain balancing", circuit, &balance_chains);
+        }
+        if opts.nand_nor {
+            circuit = run("NAND/NOR rewrite", circuit, &to_nand_nor);
+        }
+        if opts.level >= 2 {
+            circuit = run("technology mapping", circuit, &|c| map_technology(c, opts));
+            circuit = run("duplicate merging", circuit, &merge_duplicates);
+        }
+        circuit = run("dead gate removal", circuit, &remove_dead);
+        if opts.level >= 2 {
+            circuit = run("fanout buffering", circuit, &|c| buffer_fanout(c, opts.goal));
+        }
+    }
+    let stats = Stats {
+        passes,
+        depth: (depth_before, depth(&circuit)),
+        delay: (delay_before, delay(&circuit)),
+        blocks: (blocks_before, gate_blocks(&circuit)),
+    };
+    (circuit, stats)
+}
+
+/// Signal known to carry `value`, as provided by the compiler and by
+/// `netlist::check`.
+fn const_signal(value: bool) -> &'static str {
+    if value {
+        "CONST_TRUE_SIG"
+    } else {
+        "CONST_FALSE_SIG"
+    }
+}
+
+/// What a gate reduces to once some of its inputs are known.
+enum Fold {
+    Keep,
+    Const(bool),
+    /// The gate passes another signal through unchanged.
+    Alias(String),
+    /// The gate becomes a simpler one on the same output.
+    Rewrite(&'static str, Vec<String>),
+}
+
+fn fold_gate(g: &Gate, consts: &HashMap<String, bool>) -> Fold {
+    let known: Vec<Option<bool>> = g.inputs.iter().map(|i| consts.get(i).copied()).collect();
+    let rest: Vec<String> = g
+        .inputs
+        .iter()
+        .zip(&known)
+        .filter(|(_, k)| k.is_none())
+        .map(|(i, _)| i.clone())
+        .collect();
+    let any = |v: bool| known.contains(&Some(v));
+    // AND-like and OR-like gates: a dominating input fixes the output,
+    // neutral inputs drop out.
+    let (dominant, invert) = match g.kind.as_str() {
+        "AND" | "WIDE_AND" => (false, false),
+        "NAND" => (false, true),
+        "OR" | "WIDE_OR" => (true, false),
+        "NOR" => (true, true),
+        // One driver per constant: the others alias it, and routing fans it
+        // out to every reader.
+        "CONST_TRUE" | "CONST_FALSE" => {
+            let sig = const_signal(g.kind == "CONST_TRUE");
+            return if g.output == sig {
+                Fold::Keep
+            } else {
+                Fold::Alias(sig.into())
+            };
+        }
+        "BUF" | "DELAY" => {
+            return match known[0] {
+                Some(v) => Fold::Const(v),
+                None => Fold::Keep,
+            }
+        }
+        "NOT" => {
+            return match known[0] {
+                Some(v) => Fold::Const(!v),
+                None => Fold::Keep,
+            }
+        }
+        "XOR" | "XNOR" => {
+            let flip = g.kind == "XNOR";
+            return match (known[0], known[1]) {
+                (Some(a), Some(b)) => Fold::Const((a != b) != flip),
+                (Some(v), None) | (None, Some(v)) if v != flip => Fold::Rewrite("NOT", rest),
+                (Some(_), None) | (None, Some(_)) => Fold::Alias(rest[0].clone()),
+                (None, None) => Fold::Keep,
+            };
+        }
+        "MUX" => {
+            return match known[0] {
+                Some(select) => {
+                    let pick = if select { 1 } else { 2 };
+                    match known[pick] {
+                        Some(v) => Fold::Const(v),
+                        None => Fold::Alias(g.inputs[pick].clone()),
+                    }
+                }
+                None => Fold::Keep,
+            }
+        }
+        // A constant never makes an edge.
+        "RISING" | "FALLING" => {
+            return match known[0] {
+                Some(_) => Fold::Const(false),
+                None => Fold::Keep,
+            }
+        }
+        // Flip-flops and latches start low and stay low if never set.
+        "DFF" if known[0] == Some(false) => return Fold::Const(false),
+        "SRLATCH" if known[0] == Some(false) => return Fold::Const(false),
+        _ => return Fold::Keep,
+    };
+    if any(dominant) {
+        return Fold::Const(dominant != invert);
+    }
+    if rest.len() == g.inputs.len() {
+        return Fold::Keep;
+    }
+    match (rest.len(), invert) {
+        (0, _) => Fold::Const(dominant == invert),
+        (1, false) => Fold::Alias(rest[0].clone()),
+        (1, true) => Fold::Rewrite("NOT", rest),
+        _ => Fold::Rewrite(
+            match g.kind.as_str() {
+                "WIDE_AND" => "WIDE_AND",
+                "WIDE_OR" => "WIDE_OR",
+                // Two-input gates have at most one input left.
+                _ => unreachable!(),
+            },
+            rest,
+        ),
+    }
+}
+
+/// Identities that hold whatever the inputs carry: `x AND x` is `x`,
+/// `x AND NOT x` is false, `x OR NOT x` is true, and absorption,
+/// `x OR (x AND y)` and `x AND (x OR y)` are both `x`.
+fn fold_identity(g: &Gate, drivers: &HashMap<&str, &Gate>) -> Fold {
+    let [a, b] = g.inputs.as_slice() else {
+        return Fold::Keep;
+    };
+    let negation = |x: &String, y: &String| {
+        drivers
+            .get(y.as_str())
+            .is_some_and(|d| d.kind == "NOT" && d.inputs[0] == *x)
+    };
+    let opposite = negation(a, b) || negation(b, a);
+    let (dominant, invert) = match g.kind.as_str() {
+        "AND" => (false, false),
+        "NAND" => (false, true),
+        "OR" => (true, false),
+        "NOR" => (true, true),
+        "XOR" | "XNOR" => {
+            let flip = g.kind == "XNOR";
+            return if a == b {
+                Fold::Const(flip)
+            } else if opposite {
+                Fold::Const(!flip)
+            } else {
+                Fold::Keep
+            };
+        }
+        _ => return Fold::Keep,
+    };
+    let same = |x: &String| {
+        if invert {
+            Fold::Rewrite("NOT", vec![x.clone()])
+        } else {
+            Fold::Alias(x.clone())
+        }
+    };
+    if a == b {
+        return same(a);
+    }
+    if opposite {
+        return Fold::Const(dominant != invert);
+    }
+    // Absorption: the other input is the opposite gate over this one.
+    let other = if dominant { "AND" } else { "OR" };
+    let absorbs = |x: &String, y: &String| {
+        drivers
+            .get(y.as_str())
+            .is_some_and(|d| d.kind == other && d.inputs.contains(x))
+    };
+    if absorbs(a, b) {
+        same(a)
+    } else if absorbs(b, a) {
+        same(b)
+    } else {
+        Fold::Keep
+    }
+}
+
+/// Propagate constants through the netlist: gates with a known output
+/// become the constant, and gates with neutral inputs become simpler ones
+/// (`x AND true` is `x`, `x NAND true` is `NOT x`). Consumers and outputs
+/// are rewired to the surviving signals; dead gates go afterwards.
+fn fold_constants(mut circuit: Circuit) -> Circuit {
+    loop {
+        let consts: HashMap<String, bool> = circuit
+            .gates
+            .iter()
+            .filter_map(|g| match g.kind.as_str() {
+                "CONST_TRUE" => Some((g.output.clone(), true)),
+                "CONST_FALSE" => Some((g.output.clone(), false)),
+                _ => None,
+            })
+            .collect();
+        let drivers: HashMap<&str, &Gate> = circuit
+            .gates
+            .iter()
+            .map(|g| (g.output.as_str(), g))
+            .collect();
+        let folds: Vec<Fold> = circuit
+            .gates
+            .iter()
+            .map(|g| match fold_gate(g, &consts) {
+                Fold::Keep => fold_identity(g, &drivers),
+                fold => fold,
+            })
+            .collect();
+        let mut subst: HashMap<String, String> = HashMap::new();
+        let mut changed = false;
+        for (g, fold) in circuit.gates.iter_mut().zip(folds) {
+            match fold {
+                Fold::Keep => {}
+                Fold::Const(v) => {
+                    subst.insert(g.output.clone(), const_signal(v).to_string());
+                }
+                Fold::Alias(sig) if sig != g.output => {
+                    subst.insert(g.output.clone(), sig);
+                }
+                Fold::Alias(_) => {}
+                Fold::Rewrite(kind, inputs) => {
+                    if kind.starts_with("WIDE_") {
+                        g.params = vec![inputs.len() as i64];
+                    } else {
+                        g.params.clear();
+                    }
+                    g.kind = kind.to_string();
+                    g.inputs = inputs;
+                    changed = true;
+                }
+            }
+        }
+        if subst.is_empty() && !changed {
+            break;
+        }
+        let resolve = |sig: &String| {
+            let mut sig = sig;
+            // Bounded in case aliases form a loop.
+            for _ in 0..subst.len() {
+                match subst.get(sig) {
+                    Some(next) => sig = next,
+                    None => break,
+                }
+            }
+            sig.clone()
+        };
+        circuit.gates.retain(|g| !subst.contains_key(&g.output));
+        for g in &mut circuit.gates {
+            for input in &mut g.inputs {
+                *input = resolve(input);
+            }
+        }
+        for out in circuit.outputs.iter_mut().chain(&mut circuit.dont_care) {
+            *out = resolve(out);
+        }
+    }
+
+    add_constants(&mut circuit);
+    circuit
+}
+
+/// Add the constant drivers that folding or minimization pointed consumers
+/// at when the netlist never drove them.
+fn add_constants(circuit: &mut Circuit) {
+    for (sig, id, kind) in [
+        ("CONST_TRUE_SIG", "g_const_true", "CONST_TRUE"),
+        ("CONST_FALSE_SIG", "g_const_false", "CONST_FALSE"),
+    ] {
+        let used = circuit.outputs.iter().any(|o| o == sig)
+            || circuit
+                .gates
+                .iter()
+                .any(|g| g.inputs.iter().any(|i| i == sig));
+        if used && !circuit.gates.iter().any(|g| g.output == sig) {
+            circuit.gates.push(Gate {
+                id: id.into(),
+                kind: kind.into(),
+                inputs: vec![],
+                output: sig.into(),
+                params: vec![],
+                block: String::new(),
+            });
+        }
+    }
+}
+
+/// Combinational gates whose cones are re-minimized.
+const LOGIC: &[&str] = &["AND", "OR", "NAND", "NOR", "NOT", "XOR", "XNOR"];
+
+/// Most leaves a cone may have for its truth table to be re-minimized.
+const CONE_INPUTS: usize = 6;
+
+/// A cone of logic: the gates feeding one root, up to its leaf signals.
+struct Cone {
+    /// Gates in evaluation order, children before parents.
+    gates: Vec<usize>,
+    /// Gates only the cone reads, which the new logic replaces.
+    owned: Vec<usize>,
+    leaves: Vec<String>,
+}
+
+/// Collect the cone under `root`. It grows through gates read only by the
+/// cone and, since inverters are cheap to redo, through any NOT; a `wide`
+/// cone grows through shared logic too, which stays for its other readers.
+/// Gives up past `CONE_INPUTS` leaves.
+fn collect_cone(
+    circuit: &Circuit,
+    root: usize,
+    producer: &HashMap<&str, usize>,
+    interior: &HashSet<usize>,
+    wide: bool,
+) -> Option<Cone> {
+    let mut cone = Cone {
+        gates: Vec::new(),
+        owned: Vec::new(),
+        leaves: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    // (gate, owned, inputs visited)
+    let mut stack = vec![(root, true, false)];
+    while let Some((i, owned, visited)) = stack.pop() {
+        if visited {
+            cone.gates.push(i);
+            if owned {
+                cone.owned.push(i);
+            }
+            continue;
+        }
+        if !seen.insert(i) {
+            continue;
+        }
+        stack.push((i, owned, true));
+        for input in &circuit.gates[i].inputs {
+            let inner = producer.get(input.as_str()).copied().filter(|&p| {
+                LOGIC.contains(&circuit.gates[p].kind.as_str())
+                    && (wide || interior.contains(&p) || circuit.gates[p].kind == "NOT")
+            });
+            match inner {
+                Some(p) => stack.push((p, owned && interior.contains(&p), false)),
+                None if !cone.leaves.contains(input) => {
+                    cone.leaves.push(input.clone());
+                    if cone.leaves.len() > CONE_INPUTS {
+                        return None;
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+    Some(cone)
+}
+
+/// Gates needed to build a sum of products over `n` leaves.
+fn sop_cost(n: usize, cubes: &[Cube]) -> usize {
+    let inverted = (0..n)
+        .filter(|&b| cubes.iter().any(|c| c.mask >> b & 1 == 0 && c.value >> b & 1 == 0))
+        .count();
+    let ands: usize = cubes
+        .iter()
+        .map(|c| (c.literals(n) as usize).saturating_sub(1))
+        .sum();
+    inverted + ands + cubes.len().saturating_sub(1)
+}
+
+/// Output of a `LOGIC` gate of `kind` for the given input values.
+fn eval_logic(kind: &str, ins: &[bool]) -> bool {
+    match kind {
+        "AND" => ins.iter().all(|&v| v),
+        "OR" => ins.iter().any(|&v| v),
+        "NAND" => !ins.iter().all(|&v| v),
+        "NOR" => !ins.iter().any(|&v| v),
+        "NOT" => !ins[0],
+        "XOR" => ins[0] != ins[1],
+        _ => ins[0] == ins[1],
+    }
+}
+
+/// Value of `sig` given the signals in `value`, through logic gates only;
+/// `None` if it depends on anything else. Settled signals are added to
+/// `value`.
+fn eval_over<'a>(
+    circuit: &'a Circuit,
+    producer: &HashMap<&str, usize>,
+    value: &mut HashMap<&'a str, bool>,
+    sig: &'a str,
+) -> Option<bool> {
+    if let Some(&v) = value.get(sig) {
+        return Some(v);
+    }
+    let g = &circuit.gates[*producer.get(sig)?];
+    if !LOGIC.contains(&g.kind.as_str()) {
+        return None;
+    }
+    let ins = g
+        .inputs
+        .iter()
+        .map(|i| eval_over(circuit, producer, value, i))
+        .collect::<Option<Vec<bool>>>()?;
+    let v = eval_logic(&g.kind, &ins);
+    value.insert(sig, v);
+    Some(v)
+}
+
+/// Ticks a sum of products over `leaves` arriving at the given ticks takes
+/// to settle: inverted literals, balanced AND trees, then an OR tree.
+fn sop_ticks(leaves: &[usize], cubes: &[Cube], invert: bool) -> usize {
+    let tree = |n: usize| n.max(1).next_power_of_two().trailing_zeros() as usize;
+    let (not, and, or) = (
+        cost("NOT", &[]).delay as usize,
+        cost("AND", &[]).delay as usize,
+        cost("OR", &[]).delay as usize,
+    );
+    let settled = cubes
+        .iter()
+        .map(|c| {
+            let lits = (0..leaves.len()).filter(|&b| c.mask >> b & 1 == 0);
+            let ready = lits
+                .clone()
+                .map(|b| leaves[b] + if c.value >> b & 1 == 0 { not } else { 0 })
+                .max()
+                .unwrap_or(0);
+            ready + and * tree(lits.count())
+        })
+        .max()
+        .unwrap_or(0);
+    settled + or * tree(cubes.len()) + if invert { not } else { 0 }
+}
+
+/// Minimal cover of the cone's function over its leaves: the sum of
+/// products of its ones, or of its zeros when inverting that is cheaper,
+/// with the gates it takes. Leaf values that raise a don't-care signal
+/// computable from the leaves may take either output.
+fn cover_cone(
+    circuit: &Circuit,
+    root: usize,
+    cone: &Cone,
+    producer: &HashMap<&str, usize>,
+) -> (Vec<Cube>, bool, usize) {
+    let n = cone.leaves.len();
+    let (mut on, mut off, mut dc) = (Vec::new(), Vec::new(), Vec::new());
+    for m in 0..1u32 << n {
+        let mut value: HashMap<&str, bool> = cone
+            .leaves
+            .iter()
+            .enumerate()
+            .map(|(b, leaf)| (leaf.as_str(), m >> b & 1 == 1))
+            .collect();
+        for &i in &cone.gates {
+            let g = &circuit.gates[i];
+            let ins: Vec<bool> = g.inputs.iter().map(|s| value[s.as_str()]).collect();
+            value.insert(g.output.as_str(), eval_logic(&g.kind, &ins));
+        }
+        let root_value = value[circuit.gates[root].output.as_str()];
+        let impossible = circuit
+            .dont_care
+            .iter()
+            .any(|d| eval_over(circuit, producer, &mut value, d) == Some(true));
+        if impossible {
+            dc.push(m);
+        } else if root_value {
+            on.push(m);
+        } else {
+            off.push(m);
+        }
+    }
+    let (ones, zeros) = (minimize(n, &on, &dc), minimize(n, &off, &dc));
+    // The zeros' cover needs one more gate to invert it.
+    if sop_cost(n, &zeros) + 1 < sop_cost(n, &ones) {
+        let cost = sop_cost(n, &zeros) + 1;
+        (zeros, true, cost)
+    } else {
+        let cost = sop_cost(n, &ones);
+        (ones, false, cost)
+    }
+}
+
+/// Two-level re-synthesis of small cones: the truth table of each cone of
+/// logic with at most `CONE_INPUTS` leaves is minimized with
+/// Quine-McCluskey, and the cone is replaced by the sum of products (or
+/// the inverted sum of products of its zeros) when that takes fewer gates.
+/// Signals read outside a cone keep their own gate, so sharing survives.
+/// Declared don't-cares widen the covers.
+/// Under `Goal::Speed` a cone is only replaced if it settles no later.
+fn minimize_cones(mut circuit: Circuit, goal: Goal) -> Circuit {
+    let arrival: HashMap<String, usize> = levels(&circuit, ticks)
+        .into_iter()
+        .map(|(s, l)| (s.to_string(), l))
+        .collect();
+    let at = |sig: &String| arrival.get(sig).copied().unwrap_or(0);
+    let producer: HashMap<&str, usize> = circuit
+        .gates
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (g.output.as_str(), i))
+        .collect();
+    let mut readers: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, g) in circuit.gates.iter().enumerate() {
+        for input in &g.inputs {
+            readers.entry(input.as_str()).or_default().push(i);
+        }
+    }
+    let outputs: HashSet<&str> = circuit.outputs.iter().map(String::as_str).collect();
+    let is_logic = |i: usize| LOGIC.contains(&circuit.gates[i].kind.as_str());
+    // Logic gates whose only reader is one logic gate belong to its cone.
+    let interior: HashSet<usize> = (0..circuit.gates.len())
+        .filter(|&i| {
+            let out = circuit.gates[i].output.as_str();
+            is_logic(i)
+                && !outputs.contains(out)
+                && matches!(readers.get(out).map(Vec::as_slice), Some(&[r]) if is_logic(r))
+        })
+        .collect();
+
+    let mut ctx = Ctx::new();
+    let mut dropped: HashSet<usize> = HashSet::new();
+    let mut subst: HashMap<String, String> = HashMap::new();
+    for root in 0..circuit.gates.len() {
+        // Don't-care conditions only inform the other cones.
+        if !is_logic(root)
+            || interior.contains(&root)
+            || circuit.dont_care.contains(&circuit.gates[root].output)
+        {
+            continue;
+        }
+        // Keep whichever cone saves the most gates.
+        let best = [true, false]
+            .into_iter()
+            .filter_map(|wide| collect_cone(&circuit, root, &producer, &interior, wide))
+            .map(|cone| {
+                let (cubes, invert, cost) = cover_cone(&circuit, root, &cone, &producer);
+                (cone.owned.len() as isize - cost as isize, cone, cubes, invert)
+            })
+            .filter(|(saved, cone, cubes, invert)| {
+                let leaves: Vec<usize> = cone.leaves.iter().map(at).collect();
+                *saved > 0
+                    && (goal == Goal::Area
+                        || sop_ticks(&leaves, cubes, *invert) <= at(&circuit.gates[root].output))
+            })
+            .max_by_key(|(saved, ..)| *saved);
+        let Some((_, cone, cubes, invert)) = best else {
+            continue;
+        };
+        let mut inverted: HashMap<usize, String> = HashMap::new();
+        let mut products = Vec::new();
+        for cube in &cubes {
+            let mut lits = Vec::new();
+            for (bit, leaf) in cone.leaves.iter().enumerate() {
+                if cube.mask >> bit & 1 == 1 {
+                    continue;
+                }
+                if cube.value >> bit & 1 == 1 {
+                    lits.push(leaf.clone());
+                } else {
+                    let not = inverted
+                        .entry(bit)
+                        .or_insert_with(|| ctx.emit("NOT", vec![leaf.clone()]));
+                    lits.push(not.clone());
+                }
+            }
+            let product = ctx
+                .reduce_balanced(lits, |c, x, y| c.emit("AND", vec![x, y]))
+                .unwrap_or_else(|| const_signal(true).into());
+            products.push(product);
+        }
+        let mut sop = ctx.or_all(products);
+        if invert {
+            sop = ctx.emit("NOT", vec![sop]);
+        }
+        subst.insert(circuit.gates[root].output.clone(), sop);
+        dropped.extend(cone.owned);
+    }
+    if subst.is_empty() {
+        return circuit;
+    }
+
+    let mut i = 0;
+    circuit.gates.retain(|_| {
+        i += 1;
+        !dropped.contains(&(i - 1))
+    });
+    // The new logic brings its own constant drivers; the netlist's stay.
+    let fresh = ctx.finish(vec![], vec![]).gates;
+    circuit
+        .gates
+        .extend(fresh.into_iter().filter(|g| !g.kind.starts_with("CONST_")));
+    // A cone may reduce to one of its leaves, itself a replaced root.
+    let resolve = |sig: &String| {
+        let mut sig = sig;
+        for _ in 0..subst.len() {
+            match subst.get(sig) {
+                Some(next) => sig = next,
+                None => break,
+            }
+        }
+        sig.clone()
+    };
+    for g in &mut circuit.gates {
+        for input in &mut g.inputs {
+            *input = resolve(input);
+        }
+    }
+    for out in &mut circuit.outputs {
+        *out = resolve(out);
+    }
+    add_constants(&mut circuit);
+    circuit
+}
+
+/// Redstone ticks a signal takes through `g`.
+fn ticks(g: &Gate) -> usize {
+    cost(&g.kind, &g.params).delay as usize
+}
+
+/// Logic level of every signal: the summed `weight` of the gates on the
+/// longest path back to an input, a constant or a state element.
+fn levels(circuit: &Circuit, weight: fn(&Gate) -> usize) -> HashMap<&str, usize> {
+    let drivers: HashMap<&str, &Gate> = circuit
+        .gates
+        .iter()
+        .map(|g| (g.output.as_str(), g))
+        .collect();
+    let mut memo: HashMap<&str, usize> = HashMap::new();
+    // Iterative post-order walk; a signal met again while open is a loop
+    // and counts as a source.
+    let mut open: HashSet<&str> = HashSet::new();
+    for root in circuit.gates.iter().map(|g| g.output.as_str()) {
+        let mut stack = vec![(root, false)];
+        while let Some((sig, done)) = stack.pop() {
+            if memo.contains_key(sig) {
+                continue;
+            }
+            let Some(g) = drivers.get(sig) else {
+                memo.insert(sig, 0);
+                continue;
+            };
+            let source = g.inputs.is_empty() || SEQUENTIAL_KINDS.contains(&g.kind.as_str());
+            if source {
+                memo.insert(sig, 0);
+            } else if done {
+                open.remove(sig);
+                let below = g
+                    .inputs
+                    .iter()
+                    .map(|i| memo.get(i.as_str()).copied().unwrap_or(0))
+                    .max()
+                    .unwrap_or(0);
+                memo.insert(sig, below + weight(g));
+            } else if open.insert(sig) {
+                stack.push((sig, true));
+                for i in &g.inputs {
+                    if !open.contains(i.as_str()) {
+                        stack.push((i, false));
+                    }
+                }
+            }
+        }
+    }
+    memo
+}
+
+/// Gates on the longest path between state or inputs and the outputs or
+/// the next state, i.e. the slowest settling chain.
+pub fn depth(circuit: &Circuit) -> usize {
+    critical_path(circuit, |_| 1)
+}
+
+/// Redstone ticks the slowest settling chain takes. A clocked element must
+/// not sample its input before then.
+pub fn delay(circuit: &Circuit) -> usize {
+    critical_path(circuit, ticks)
+}
+
+fn critical_path(circuit: &Circuit, weight: fn(&Gate) -> usize) -> usize {
+    let level = levels(circuit, weight);
+    let at = |sig: &String| level.get(sig.as_str()).copied().unwrap_or(0);
+    circuit
+        .gates
+        .iter()
+        .map(|g| {
+            // State elements end a path at their inputs.
+            if SEQUENTIAL_KINDS.contains(&g.kind.as_str()) {
+                g.inputs.iter().map(at).max().unwrap_or(0)
+            } else {
+                at(&g.output)
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Depth balancing: a chain of ANDs or ORs (`a && b && c && d` nests to
+/// the left) is rebuilt as a tree that joins the earliest signals first,
+/// so late inputs pass through as few ticks as possible. A NAND or NOR
+/// topping the chain stays on top. Only gates the chain alone reads are
+/// rebuilt, and only when the result settles sooner.
+fn balance_chains(mut circuit: Circuit) -> Circuit {
+    let base = |kind: &str| match kind {
+        "AND" | "NAND" => Some("AND"),
+        "OR" | "NOR" => Some("OR"),
+        _ => None,
+    };
+    let mut readers: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, g) in circuit.gates.iter().enumerate() {
+        for input in &g.inputs {
+            readers.entry(input.clone()).or_default().push(i);
+        }
+    }
+    let outputs: HashSet<String> = circuit.outputs.iter().cloned().collect();
+    // Inner links of a chain: plain ANDs or ORs read only by a gate of the
+    // same family.
+    let links: HashSet<usize> = (0..circuit.gates.len())
+        .filter(|&i| {
+            let g = &circuit.gates[i];
+            let family = base(&g.kind);
+            family == Some(g.kind.as_str())
+                && !outputs.contains(&g.output)
+                && matches!(readers.get(&g.output).map(Vec::as_slice),
+                    Some(&[r]) if base(&circuit.gates[r].kind) == family)
+        })
+        .collect();
+    let producer: HashMap<String, usize> = circuit
+        .gates
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (g.output.clone(), i))
+        .collect();
+    let mut level: HashMap<String, usize> = levels(&circuit, ticks)
+        .into_iter()
+        .map(|(s, l)| (s.to_string(), l))
+        .collect();
+
+    // Roots in level order, so chains below are rebuilt first.
+    let mut roots: Vec<usize> = (0..circuit.gates.len())
+        .filter(|&i| base(&circuit.gates[i].kind).is_some() && !links.contains(&i))
+        .collect();
+    roots.sort_by_key(|&i| level[&circuit.gates[i].output]);
+
+    let mut dropped: HashSet<usize> = HashSet::new();
+    let mut added: Vec<Gate> = Vec::new();
+    for root in roots {
+        let family = base(&circuit.gates[root].kind).unwrap();
+        let mut leaves = Vec::new();
+        let mut inner = Vec::new();
+        let mut stack: Vec<String> = circuit.gates[root].inputs.clone();
+        while let Some(sig) = stack.pop() {
+            match producer.get(&sig) {
+                Some(&p) if links.contains(&p) => {
+                    inner.push(p);
+                    stack.extend(circuit.gates[p].inputs.iter().cloned());
+                }
+                _ => leaves.push(sig),
+            }
+        }
+        if inner.is_empty() {
+            continue;
+        }
+        // Join the two earliest signals until two are left for the root.
+        let mut heap: BinaryHeap<Reverse<(usize, usize, String)>> = leaves
+            .into_iter()
+            .enumerate()
+            .map(|(n, sig)| Reverse((level.get(&sig).copied().unwrap_or(0), n, sig)))
+            .collect();
+        let mut built = Vec::new();
+        let mut n = heap.len();
+        let pop = |heap: &mut BinaryHeap<Reverse<(usize, usize, String)>>| {
+            let Reverse((l, _, sig)) = heap.pop().unwrap();
+            (l, sig)
+        };
+        let link = cost(family, &[]).delay as usize;
+        while heap.len() > 2 {
+            let ((la, a), (lb, b)) = (pop(&mut heap), pop(&mut heap));
+            let out = next_id();
+            built.push((la.max(lb) + link, gate(family, vec![a, b], out.clone())));
+            heap.push(Reverse((la.max(lb) + link, n, out)));
+            n += 1;
+        }
+        let ((la, a), (lb, b)) = (pop(&mut heap), pop(&mut heap));
+        let root_level = la.max(lb) + ticks(&circuit.gates[root]);
+        if root_level >= level[&circuit.gates[root].output] {
+            continue;
+        }
+        level.insert(circuit.gates[root].output.clone(), root_level);
+        for (l, g) in built {
+            level.insert(g.output.clone(), l);
+            added.push(g);
+        }
+        dropped.extend(inner);
+        circuit.gates[root].inputs = vec![a, b];
+    }
+    let mut i = 0;
+    circuit.gates.retain(|_| {
+        i += 1;
+        !dropped.contains(&(i - 1))
+    });
+    circuit.gates.extend(added);
+    circuit
+}
+
+/// Multiplexer recognition: `(t AND c) OR (NOT t AND a)`, as the compiler
+/// builds `t ? c : a`, becomes one MUX gate when the OR alone reads both
+/// ANDs. The inverter stays for its other readers, if any. Under
+/// `Goal::Speed` only muxes that settle no later are fused.
+fn fuse_muxes(mut circuit: Circuit, goal: Goal) -> Circuit {
+    let arrival: HashMap<String, usize> = levels(&circuit, ticks)
+        .into_iter()
+        .map(|(s, l)| (s.to_string(), l))
+        .collect();
+    let at = |sig: &String| arrival.get(sig).copied().unwrap_or(0);
+    let mux = cost("MUX", &[]).delay as usize;
+    let producer: HashMap<String, usize> = circuit
+        .gates
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (g.output.clone(), i))
+        .collect();
+    let mut readers: HashMap<&str, usize> = HashMap::new();
+    for sig in circuit
+        .gates
+        .iter()
+        .flat_map(|g| &g.inputs)
+        .chain(&circuit.outputs)
+    {
+        *readers.entry(sig.as_str()).or_default() += 1;
+    }
+    let gates = &circuit.gates;
+    // An AND read by nothing but the OR, so by no other OR either.
+    let term = |sig: &String| {
+        producer
+            .get(sig)
+            .copied()
+            .filter(|&i| gates[i].kind == "AND" && readers.get(sig.as_str()) == Some(&1))
+    };
+    let negation = |sig: &String| {
+        producer
+            .get(sig)
+            .map(|&i| &gates[i])
+            .filter(|g| g.kind == "NOT")
+            .map(|g| g.inputs[0].clone())
+    };
+    // `t ? c : a` when `on` is `t AND c` and `off` is `NOT t AND a`.
+    let select = |on: &[String], off: &[String]| {
+        for (t, c) in [(&on[0], &on[1]), (&on[1], &on[0])] {
+            for (n, a) in [(&off[0], &off[1]), (&off[1], &off[0])] {
+                if negation(n).as_ref() == Some(t) {
+                    return Some(vec![t.clone(), c.clone(), a.clone()]);
+                }
+            }
+        }
+        None
+    };
+    let mut fused: Vec<(usize, Vec<String>)> = Vec::new();
+    let mut dropped: HashSet<usize> = HashSet::new();
+    for (i, g) in gates.iter().enumerate() {
+        if g.kind != "OR" {
+            continue;
+        }
+        let (Some(x), Some(y)) = (term(&g.inputs[0]), term(&g.inputs[1])) else {
+            continue;
+        };
+        let (on, off) = (&gates[x].inputs, &gates[y].inputs);
+        let Some(inputs) = select(on, off).or_else(|| select(off, on)) else {
+            continue;
+        };
+        let settles = inputs.iter().map(at).max().unwrap_or(0) + mux;
+        if goal == Goal::Area || settles <= at(&g.output) {
+            dropped.extend([x, y]);
+            fused.push((i, inputs));
+        }
+    }
+    for (i, inputs) in fused {
+        circuit.gates[i].kind = "MUX".into();
+        circuit.gates[i].inputs = inputs;
+    }
+    let mut i = 0;
+    circuit.gates.retain(|_| {
+        i += 1;
+        !dropped.contains(&(i - 1))
+    });
+    circuit
+}
+
+/// Inverter cleanup: `NOT(NOT x)` is `x`, and an inverter that is the only
+/// reader of an AND or OR turns it into a NAND or NOR, so negated
+/// comparisons stop stacking torches. Inverters on both inputs move through
+/// the gate by De Morgan, `NOT a AND NOT b` being `a NOR b`. Repeats until
+/// chains are gone.
+fn absorb_inverters(mut circuit: Circuit) -> Circuit {
+    loop {
+        let producer: HashMap<String, usize> = circuit
+            .gates
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (g.output.clone(), i))
+            .collect();
+        let mut readers: HashMap<&str, usize> = HashMap::new();
+        for sig in circuit
+            .gates
+            .iter()
+            .flat_map(|g| &g.inputs)
+            .chain(&circuit.outputs)
+        {
+            *readers.entry(sig.as_str()).or_default() += 1;
+        }
+        let mut subst: HashMap<String, String> = HashMap::new();
+        let mut absorbed: Vec<(usize, usize)> = Vec::new();
+        // Gates changed this round, whose old form must not be built on.
+        let mut touched: HashSet<usize> = HashSet::new();
+        let mut fused: Vec<(usize, Vec<String>)> = Vec::new();
+        for (i, g) in circuit.gates.iter().enumerate() {
+            if matches!(g.kind.as_str(), "AND" | "OR" | "NAND" | "NOR") && !touched.contains(&i) {
+                let nots: Option<Vec<usize>> = g
+                    .inputs
+                    .iter()
+                    .map(|sig| {
+                        producer
+                            .get(sig)
+                            .copied()
+                            .filter(|&p| circuit.gates[p].kind == "NOT" && !touched.contains(&p))
+                    })
+                    .collect();
+                if let Some(nots) = nots {
+                    let inputs = nots
+                        .iter()
+                        .map(|&p| circuit.gates[p].inputs[0].clone())
+                        .collect();
+                    fused.push((i, inputs));
+                    touched.insert(i);
+                    touched.extend(nots);
+                }
+                continue;
+            }
+            if g.kind != "NOT" {
+                continue;
+            }
+            let Some(&p) = producer.get(&g.inputs[0]) else {
+                continue;
+            };
+            if touched.contains(&p) || touched.contains(&i) {
+                continue;
+            }
+            let inner = &circuit.gates[p];
+            match inner.kind.as_str() {
+                "NOT" => {
+                    subst.insert(g.output.clone(), inner.inputs[0].clone());
+                }
+                "AND" | "OR" if readers[inner.output.as_str()] == 1 => absorbed.push((i, p)),
+                _ => continue,
+            }
+            touched.insert(i);
+            touched.insert(p);
+        }
+        if subst.is_empty() && absorbed.is_empty() && fused.is_empty() {
+            return circuit;
+        }
+        for (i, inputs) in fused {
+            let g = &mut circuit.gates[i];
+            g.kind = dual(&g.kind).into();
+            g.inputs = inputs;
+        }
+        for &(i, p) in &absorbed {
+            let inner = &circuit.gates[p];
+            let kind = if inner.kind == "AND" { "NAND" } else { "NOR" };
+            let inputs = inner.inputs.clone();
+            let g = &mut circuit.gates[i];
+            g.kind = kind.into();
+            g.inputs = inputs;
+        }
+        let dropped: HashSet<usize> = absorbed.iter().map(|&(_, p)| p).collect();
+        let mut i = 0;
+        circuit.gates.retain(|g| {
+            i += 1;
+            !dropped.contains(&(i - 1)) && !subst.contains_key(&g.output)
+        });
+        let resolve = |sig: &String| {
+            let mut sig = sig;
+            for _ in 0..subst.len() {
+                match subst.get(sig) {
+                    Some(next) => sig = next,
+                    None => break,
+                }
+            }
+            sig.clone()
+        };
+        for g in &mut circuit.gates {
+            for input in &mut g.inputs {
+                *input = resolve(input);
+            }
+        }
+        for out in &mut circuit.outputs {
+            *out = resolve(out);
+        }
+    }
+}
+
+fn gate(kind: &str, inputs: Vec<String>, output: String) -> Gate {
+    Gate {
+        id: next_id(),
+        kind: kind.into(),
+        inputs,
+        output,
+        params: vec![],
+        block: String::new(),
+    }
+}
+
+/// NAND/NOR-only synthesis: AND, OR, XOR and XNOR are rewritten with De
+/// Morgan's laws into NAND and NOR, whose torch primitives are smaller and
+/// faster than the composed AND and OR. NOT counts as a one-input NAND and
+/// keeps its single torch. Complements are shared, so `a AND b` becomes
+/// `NOT a NOR NOT b` when both inverted signals already exist, and a
+/// `NOT` of a rewritten gate reads the NAND or NOR inside it.
+fn to_nand_nor(circuit: Circuit) -> Circuit {
+    // Known complement of a signal, in both directions.
+    let mut inverse: HashMap<String, String> = HashMap::new();
+    for g in &circuit.gates {
+        if g.kind == "NOT" {
+            inverse
+                .entry(g.inputs[0].clone())
+                .or_insert_with(|| g.output.clone());
+            inverse
+                .entry(g.output.clone())
+                .or_insert_with(|| g.inputs[0].clone());
+        }
+    }
+    let mut gates = Vec::with_capacity(circuit.gates.len());
+    let mut nots = Vec::new();
+    for g in circuit.gates {
+        let kind = g.kind.as_str();
+        let (inner, outer) = match kind {
+            "AND" | "OR" => {
+                let (a, b) = (&g.inputs[0], &g.inputs[1]);
+                if let (Some(na), Some(nb)) = (inverse.get(a), inverse.get(b)) {
+                    let dual = if kind == "AND" { "NOR" } else { "NAND" };
+                    gates.push(Gate {
+                        kind: dual.into(),
+                        inputs: vec![na.clone(), nb.clone()],
+                        ..g
+                    });
+                    continue;
+                }
+                // The inverted gate, then a torch to restore the polarity.
+                (if kind == "AND" { "NAND" } else { "NOR" }, true)
+            }
+            // Four NANDs make an XOR, four NORs an XNOR.
+            "XOR" => ("NAND", false),
+            "XNOR" => ("NOR", false),
+            "NOT" => {
+                nots.push(g);
+                continue;
+            }
+            _ => {
+                gates.push(g);
+                continue;
+            }
+        };
+        let (a, b) = (g.inputs[0].clone(), g.inputs[1].clone());
+        let t = next_id();
+        gates.push(gate(inner, vec![a.clone(), b.clone()], t.clone()));
+        if outer {
+            inverse.insert(g.output.clone(), t.clone());
+            inverse.insert(t.clone(), g.output.clone());
+            gates.push(Gate {
+                kind: "NOT".into(),
+                inputs: vec![t],
+                ..g
+            });
+        } else {
+            let (u, v) = (next_id(), next_id());
+            gates.push(gate(inner, vec![a, t.clone()], u.clone()));
+            gates.push(gate(inner, vec![b, t], v.clone()));
+            gates.push(Gate {
+                kind: inner.into(),
+                inputs: vec![u, v],
+                ..g
+            });
+        }
+    }
+    // An inverter on a rewritten AND or OR reads the NAND or NOR directly.
+    let mut subst: HashMap<String, String> = HashMap::new();
+    for g in nots {
+        match inverse.get(&g.inputs[0]) {
+            Some(inner) if *inner != g.output => {
+                subst.insert(g.output, inner.clone());
+            }
+            _ => gates.push(g),
+        }
+    }
+    let resolve = |sig: &String| {
+        let mut sig = sig;
+        for _ in 0..subst.len() {
+            match subst.get(sig) {
+                Some(next) => sig = next,
+                None => break,
+            }
+        }
+        sig.clone()
+    };
+    for g in &mut gates {
+        for input in &mut g.inputs {
+            *input = resolve(input);
+        }
+    }
+    Circuit {
+        gates,
+        outputs: circuit.outputs.iter().map(resolve).collect(),
+        ..circuit
+    }
+}
+
+/// Floor the layout leaves around every gate for wiring, which outweighs
+/// the differences between primitives.
+const WIRING_AREA: i32 = GATE_SPACING_X * GATE_SPACING_Z;
+
+/// The same function built another way: `AND(a, b)` is `NOR(NOT a, NOT b)`,
+/// and `NOT(NAND(a, b))`.
+fn dual(kind: &str) -> &'static str {
+    match kind {
+        "AND" => "NOR",
+        "OR" => "NAND",
+        "NAND" => "OR",
+        _ => "AND",
+    }
+}
+
+fn inverse(kind: &str) -> &'static str {
+    match kind {
+        "AND" => "NAND",
+        "OR" => "NOR",
+        "NAND" => "AND",
+        _ => "OR",
+    }
+}
+
+/// Technology mapping: every two-input AND, OR, NAND and NOR picks the
+/// cheapest of its native primitive, the dual primitive on inputs whose
+/// complements the netlist already has, and the inverted primitive behind
+/// a NOT. Costs come from `primitives::cost`; `Goal::Area` ranks by floor
+/// area and then by arrival tick, `Goal::Speed` the other way round. Gates
+/// are visited inputs first so arrival times build on earlier choices.
+fn map_technology(mut circuit: Circuit, opts: &Options) -> Circuit {
+    let allowed = |kind: &str| !opts.nand_nor || matches!(kind, "NAND" | "NOR" | "NOT");
+    let area = |kind: &str| cost(kind, &[]).area + WIRING_AREA;
+    let delay = |kind: &str| cost(kind, &[]).delay;
+
+    let mut complement: HashMap<String, String> = HashMap::new();
+    for g in circuit.gates.iter().filter(|g| g.kind == "NOT") {
+        complement
+            .entry(g.inputs[0].clone())
+            .or_insert_with(|| g.output.clone());
+        complement
+            .entry(g.output.clone())
+            .or_insert_with(|| g.inputs[0].clone());
+    }
+
+    // Visit order: state elements and sources start paths, so loops
+    // through them do not hold anything back.
+    let source = |g: &Gate| g.inputs.is_empty() || matches!(g.kind.as_str(), "DFF" | "SRLATCH");
+    let producer: HashMap<&str, usize> = circuit
+        .gates
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (g.output.as_str(), i))
+        .collect();
+    let mut pending = vec![0; circuit.gates.len()];
+    let mut readers: Vec<Vec<usize>> = vec![Vec::new(); circuit.gates.len()];
+    for (i, g) in circuit.gates.iter().enumerate() {
+        if source(g) {
+            continue;
+        }
+        for input in &g.inputs {
+            if let Some(&p) = producer.get(input.as_str()) {
+                pending[i] += 1;
+                readers[p].push(i);
+            }
+        }
+    }
+    let mut queue: VecDeque<usize> = (0..circuit.gates.len()).filter(|&i| pending[i] == 0).collect();
+    let mut order = Vec::with_capacity(circuit.gates.len());
+    while let Some(i) = queue.pop_front() {
+        order.push(i);
+        for &r in &readers[i] {
+            pending[r] -= 1;
+            if pending[r] == 0 {
+                queue.push_back(r);
+            }
+        }
+    }
+
+    let mut arrival: HashMap<String, i32> = HashMap::new();
+    let mut added = Vec::new();
+    for i in order {
+        let g = &circuit.gates[i];
+        let at = |sig: &String| arrival.get(sig).copied().unwrap_or(0);
+        let ready = g.inputs.iter().map(at).max().unwrap_or(0);
+        let kind = match g.kind.as_str() {
+            "AND" => "AND",
+            "OR" => "OR",
+            "NAND" => "NAND",
+            "NOR" => "NOR",
+            other => {
+                let d = if source(g) { 0 } else { cost(other, &g.params).delay };
+                arrival.insert(g.output.clone(), ready + d);
+                continue;
+            }
+        };
+        // (kinds from input to output, inputs, area, arrival)
+        let mut options = vec![(vec![kind], g.inputs.clone(), area(kind), ready + delay(kind))];
+        let flipped: Option<Vec<String>> = g
+            .inputs
+            .iter()
+            .map(|i| complement.get(i).cloned())
+            .collect();
+        if let Some(flipped) = flipped {
+            let k = dual(kind);
+            // A complement not reached yet is an inverter on the input.
+            let ready = g
+                .inputs
+                .iter()
+                .zip(&flipped)
+                .map(|(i, c)| arrival.get(c).copied().unwrap_or(at(i) + delay("NOT")))
+                .max()
+                .unwrap_or(0);
+            options.push((vec![k], flipped, area(k), ready + delay(k)));
+        }
+        let k = inverse(kind);
+        options.push((
+            vec![k, "NOT"],
+            g.inputs.clone(),
+            area(k) + area("NOT"),
+            ready + delay(k) + delay("NOT"),
+        ));
+        let (kinds, inputs, _, arrives) = options
+            .into_iter()
+            .filter(|(kinds, ..)| kinds.iter().all(|k| allowed(k)))
+            .min_by_key(|&(_, _, area, arrives)| match opts.goal {
+                Goal::Area => (area, arrives),
+                Goal::Speed => (arrives, area),
+            })
+            .unwrap_or((vec![kind], g.inputs.clone(), 0, ready + delay(kind)));
+        arrival.insert(g.output.clone(), arrives);
+        let g = &mut circuit.gates[i];
+        match kinds.as_slice() {
+            [k] => {
+                g.kind = k.to_string();
+                g.inputs = inputs;
+            }
+            [k, _] => {
+                let inner = next_id();
+                added.push(gate(k, inputs, inner.clone()));
+                g.kind = "NOT".into();
+                g.inputs = vec![inner];
+            }
+            _ => unreachable!(),
+        }
+    }
+    circuit.gates.extend(added);
+    circuit
+}
+
+/// Gate kinds whose inputs can be reordered.
+const COMMUTATIVE: &[&str] = &[
+    "AND", "OR", "NAND", "NOR", "XOR", "XNOR", "WIDE_AND", "WIDE_OR",
+];
+
+/// Common subexpression elimination: gates of the same kind and params on
+/// the same inputs compute the same signal, so consumers of the later ones
+/// are rewired to the first. Repeats until merges stop exposing new
+/// duplicates. Inputs (levers) and clocks are physical sources and stay,
+/// as do gates driving an output, so every output keeps its own signal.
+fn merge_duplicates(mut circuit: Circuit) -> Circuit {
+    let outputs: HashSet<String> = circuit.outputs.iter().cloned().collect();
+    loop {
+        let mut seen: HashMap<(String, Vec<i64>, Vec<String>), String> = HashMap::new();
+        let mut subst: HashMap<String, String> = HashMap::new();
+        for g in &circuit.gates {
+            if matches!(g.kind.as_str(), "INPUT" | "CLOCK") {
+                continue;
+            }
+            let mut inputs = g.inputs.clone();
+            if COMMUTATIVE.contains(&g.kind.as_str()) {
+                inputs.sort();
+            }
+            let key = (g.kind.clone(), g.params.clone(), inputs);
+            match seen.get(&key) {
+                Some(first) if !outputs.contains(&g.output) => {
+                    subst.insert(g.output.clone(), first.clone());
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(key, g.output.clone());
+                }
+            }
+        }
+        if subst.is_empty() {
+            return circuit;
+        }
+        circuit.gates.retain(|g| !subst.contains_key(&g.output));
+        for g in &mut circuit.gates {
+            for input in &mut g.inputs {
+                if let Some(first) = subst.get(input) {
+                    *input = first.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Dead-gate elimination: keep only gates reachable from the outputs.
+fn remove_dead(mut circuit: Circuit) -> Circuit {
+    let mut producers = HashMap::new();
     for g in &circuit.gates {
         producers.insert(g.output.clone(), g.id.clone());
     }
-    let mut reachable = std::collections::HashSet::new();
+    let mut reachable = HashSet::new();
     // Start reachability from the output signals directly (compile now resolves outputs to signals)
     let mut stack: Vec<String> = circuit.outputs.clone();
     while let Some(sig) = stack.pop() {
@@ -23,7 +1451,117 @@ pub fn optimize(mut circuit: Circuit) -> Circuit {
             }
         }
     }
-    circuit.gates.retain(|g| reachable.contains(&g.output));
-    // TODO: more optimizations
+    // Declared inputs are physical levers, so keep them even when unused.
+    circuit
+        .gates
+        .retain(|g| g.kind == "INPUT" || reachable.contains(&g.output));
+    circuit
+}
+
+/// Ticks from each signal through its slowest reader to an output or a
+/// state element. Gates are taken from the last logic level down.
+fn tails(circuit: &Circuit, level: &HashMap<String, usize>) -> HashMap<String, usize> {
+    let mut order: Vec<&Gate> = circuit.gates.iter().collect();
+    order.sort_by_key(|g| Reverse(level.get(&g.output).copied().unwrap_or(0)));
+    let mut tail: HashMap<String, usize> = HashMap::new();
+    for g in order {
+        if SEQUENTIAL_KINDS.contains(&g.kind.as_str()) {
+            continue;
+        }
+        let through = ticks(g) + tail.get(&g.output).copied().unwrap_or(0);
+        for input in &g.inputs {
+            let t = tail.entry(input.clone()).or_default();
+            *t = (*t).max(through);
+        }
+    }
+    tail
+}
+
+/// Most gate inputs one signal drives before it is split through buffers.
+const MAX_FANOUT: usize = 4;
+
+/// Fanout buffering: a signal read by more than `MAX_FANOUT` gate inputs
+/// feeds them through a tree of BUFs (repeaters), each driving at most
+/// `MAX_FANOUT`, so no wire has to carry one signal to many distant inputs.
+/// Every reader sits behind the same number of buffers, which keeps clock
+/// edges aligned. Readers are grouped by logic level so each buffer serves
+/// gates laid out near each other. Under `Goal::Speed`, readers on the
+/// critical path of a signal no state element reads stay on the signal
+/// itself, and only the others go through the tree.
+fn buffer_fanout(mut circuit: Circuit, goal: Goal) -> Circuit {
+    let level: HashMap<String, usize> = levels(&circuit, |_| 1)
+        .into_iter()
+        .map(|(s, l)| (s.to_string(), l))
+        .collect();
+    let arrival: HashMap<String, usize> = levels(&circuit, ticks)
+        .into_iter()
+        .map(|(s, l)| (s.to_string(), l))
+        .collect();
+    let tail = tails(&circuit, &level);
+    let critical = delay(&circuit);
+    // Ticks from a reader's input to the end of its path; none for state.
+    let through: Vec<Option<usize>> = circuit
+        .gates
+        .iter()
+        .map(|g| {
+            let state = SEQUENTIAL_KINDS.contains(&g.kind.as_str());
+            (!state).then(|| ticks(g) + tail.get(&g.output).copied().unwrap_or(0))
+        })
+        .collect();
+    // Ticks left before reader `i` of `sig` would lengthen the critical path.
+    let slack = |sig: &String, i: usize| {
+        let after = arrival.get(sig).copied().unwrap_or(0) + through[i].unwrap_or(0);
+        critical.saturating_sub(after)
+    };
+    let mut sinks: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+    for (i, g) in circuit.gates.iter().enumerate() {
+        for (pos, input) in g.inputs.iter().enumerate() {
+            sinks.entry(input.clone()).or_default().push((i, pos));
+        }
+    }
+    let constants: HashSet<String> = circuit
+        .gates
+        .iter()
+        .filter(|g| g.kind.starts_with("CONST_"))
+        .map(|g| g.output.clone())
+        .collect();
+    let mut crowded: Vec<(String, Vec<(usize, usize)>)> = sinks
+        .into_iter()
+        .filter(|(sig, targets)| targets.len() > MAX_FANOUT && !constants.contains(sig))
+        .collect();
+    // Deterministic gate numbering.
+    crowded.sort();
+    for (sig, mut targets) in crowded {
+        let mut direct = 0;
+        if goal == Goal::Speed && targets.iter().all(|&(i, _)| through[i].is_some()) {
+            // The tree below the signal is this many buffers deep.
+            let mut depth = 0;
+            let mut width = targets.len();
+            while width > MAX_FANOUT {
+                width = width.div_ceil(MAX_FANOUT);
+                depth += 1;
+            }
+            targets.sort_by_key(|&(i, pos)| (slack(&sig, i), i, pos));
+            direct = targets
+                .iter()
+                .take(MAX_FANOUT - 1)
+                .take_while(|&&(i, _)| slack(&sig, i) < depth)
+                .count();
+            targets.drain(..direct);
+        }
+        targets.sort_by_key(|&(i, pos)| (level.get(&circuit.gates[i].output), i, pos));
+        while targets.len() > MAX_FANOUT - direct {
+            let mut next = Vec::new();
+            for chunk in targets.chunks(MAX_FANOUT) {
+                let out = next_id();
+                for &(i, pos) in chunk {
+                    circuit.gates[i].inputs[pos] = out.clone();
+                }
+                next.push((circuit.gates.len(), 0));
+                circuit.gates.push(gate("BUF", vec![sig.clone()], out));
+            }
+            targets = next;
+        }
+    }
     circuit
 }
@@ -29,11 +29,22 @@ fn expr_to_json<'a>(expr: &Expression<'a>) -> anyhow::Result<Value> {
             let right = expr_to_json(&boxed.right)?;
             Ok(json!({"type":"LogicalExpression","operator": op, "left": left, "right": right}))
         }
+        Expression::NumericLiteral(boxed) => Ok(json!({"type":"Literal","value": boxed.value })),
+        Expression::StringLiteral(boxed) => {
+            Ok(json!({"type":"Literal","value": boxed.value.as_str() }))
+        }
         Expression::BinaryExpression(boxed) => {
+            // Arithmetic operators are only meaningful on compile-time integers
+            // (e.g. `repeat` indices); the compiler rejects them on signals.
             let op = match boxed.operator {
                 BinaryOperator::Equality => "==",
                 BinaryOperator::Inequality => "!=",
-                _ => anyhow::bail!("Only == and != supported in binary expressions"),
+                BinaryOperator::Addition => "+",
+                BinaryOperator::Subtraction => "-",
+                BinaryOperator::Multiplication => "*",
+                BinaryOperator::Division => "/",
+                BinaryOperator::Remainder => "%",
+                _ => anyhow::bail!("Unsupported binary operator"),
             };
             let left = expr_to_json(&boxed.left)?;
             let right = expr_to_json(&boxed.right)?;
@@ -48,6 +59,52 @@ fn expr_to_json<'a>(expr: &Expression<'a>) -> anyhow::Result<Value> {
             )
         }
         Expression::ParenthesizedExpression(boxed) => expr_to_json(&boxed.expression),
+        Expression::ArrayExpression(boxed) => {
+            let mut elements = Vec::new();
+            for el in &boxed.elements {
+                match el.as_expression() {
+                    Some(e) => elements.push(expr_to_json(e)?),
+                    None => anyhow::bail!("Spread and holes not supported in arrays"),
+                }
+            }
+            Ok(json!({"type":"ArrayExpression","elements": elements}))
+        }
+        Expression::ComputedMemberExpression(boxed) => {
+            let object = expr_to_json(&boxed.object)?;
+            let property = expr_to_json(&boxed.expression)?;
+            Ok(json!({"type":"MemberExpression","object": object, "property": property}))
+        }
+        Expression::CallExpression(boxed) => {
+            let callee = match &boxed.callee {
+                Expression::Identifier(id) => id.name.as_str().to_string(),
+                _ => anyhow::bail!("Only calls to named builtins are supported"),
+            };
+            let mut args = Vec::new();
+            for arg in &boxed.arguments {
+                match arg.as_expression() {
+                    Some(e) => args.push(expr_to_json(e)?),
+                    None => anyhow::bail!("Spread arguments not supported"),
+                }
+            }
+            Ok(json!({"type":"CallExpression","callee": callee, "arguments": args}))
+        }
+        Expression::ArrowFunctionExpression(boxed) => {
+            if !boxed.expression {
+                anyhow::bail!("Only expression-bodied arrow functions are supported");
+            }
+            let mut params = Vec::new();
+            for p in &boxed.params.items {
+                match &p.pattern {
+                    BindingPattern::BindingIdentifier(bi) => params.push(bi.name.as_str().to_string()),
+                    _ => anyhow::bail!("Destructuring not supported"),
+                }
+            }
+            let body = match boxed.body.statements.first() {
+                Some(Statement::ExpressionStatement(es)) => expr_to_json(&es.expression)?,
+                _ => anyhow::bail!("Arrow function body must be an expression"),
+            };
+            Ok(json!({"type":"ArrowFunctionExpression","params": params, "body": body}))
+        }
         Expression::AssignmentExpression(boxed) => {
             if boxed.operator != AssignmentOperator::Assign {
                 anyhow::bail!("Only = assignment supported");
@@ -67,6 +124,26 @@ fn expr_to_json<'a>(expr: &Expression<'a>) -> anyhow::Result<Value> {
     }
 }
 
+/// Bit width from a `boolean` or `uN` annotation, e.g. `let a: u4;`.
+fn annotation_width(ann: &TSTypeAnnotation) -> anyhow::Result<u32> {
+    match &ann.type_annotation {
+        TSType::TSBooleanKeyword(_) => Ok(1),
+        TSType::TSTypeReference(r) => {
+            let TSTypeName::IdentifierReference(id) = &r.type_name else {
+                anyhow::bail!("Unsupported type annotation");
+            };
+            match id.name.strip_prefix('u').and_then(|n| n.parse().ok()) {
+                Some(w @ 1..=64) if r.type_arguments.is_none() => Ok(w),
+                _ => anyhow::bail!(
+                    "Unsupported type `{}`; use `boolean` or `u1` to `u64`",
+                    id.name
+                ),
+            }
+        }
+        _ => anyhow::bail!("Unsupported type annotation; use `boolean` or `uN`"),
+    }
+}
+
 fn stmt_to_json<'a>(stmt: &Statement<'a>) -> anyhow::Result<Value> {
     match stmt {
         Statement::VariableDeclaration(vd) => {
@@ -77,12 +154,14 @@ fn stmt_to_json<'a>(stmt: &Statement<'a>) -> anyhow::Result<Value> {
             for d in &vd.declarations {
                 if let BindingPattern::BindingIdentifier(bi) = &d.id {
                     let name = bi.name.as_str().to_string();
+                    let mut decl = json!({"type":"VariableDeclarator","id": {"type":"Identifier","name": name}});
                     if let Some(init_expr) = &d.init {
-                        let init = expr_to_json(init_expr)?;
-                        decls.push(json!({"type":"VariableDeclarator","id": {"type":"Identifier","name": name}, "init": init}));
-                    } else {
-                        decls.push(json!({"type":"VariableDeclarator","id": {"type":"Identifier","name": name}}));
+                        decl["init"] = expr_to_json(init_expr)?;
+                    }
+                    if let Some(ann) = &d.type_annotation {
+                        decl["width"] = json!(annotation_width(ann)?);
                     }
+                    decls.push(decl);
                 } else {
                     anyhow::bail!("Destructuring not supported");
                 }
@@ -93,13 +172,34 @@ fn stmt_to_json<'a>(stmt: &Statement<'a>) -> anyhow::Result<Value> {
             let expr = expr_to_json(&es.expression)?;
             Ok(json!({"type":"ExpressionStatement","expression": expr}))
         }
+        Statement::SwitchStatement(sw) => {
+            let discriminant = expr_to_json(&sw.discriminant)?;
+            let mut cases = Vec::new();
+            for case in &sw.cases {
+                let test = match &case.test {
+                    Some(t) => expr_to_json(t)?,
+                    None => Value::Null,
+                };
+                let mut body = Vec::new();
+                for s in &case.consequent {
+                    match s {
+                        Statement::BreakStatement(_) => {}
+                        Statement::ExpressionStatement(_) => body.push(stmt_to_json(s)?),
+                        _ => anyhow::bail!("Only assignments and `break` allowed in switch cases"),
+                    }
+                }
+                cases.push(json!({"test": test, "consequent": body}));
+            }
+            Ok(json!({"type":"SwitchStatement","discriminant": discriminant, "cases": cases}))
+        }
         _ => anyhow::bail!("Unsupported top-level statement"),
     }
 }
 
 pub fn parse_and_validate(code: &str) -> Result<Value> {
     let alloc = Allocator::default();
-    let parser = Parser::new(&alloc, code, SourceType::default());
+    // TypeScript, for width annotations on declarations.
+    let parser = Parser::new(&alloc, code, SourceType::ts());
     let ret = parser.parse();
     if ret.panicked || !ret.errors.is_empty() {
         anyhow::bail!("Parse errors: {:?}", ret.errors);
@@ -0,0 +1,196 @@
+//! Min-cut graph partitioning (Fiduccia-Mattheyses) for placement.
+
+/// A hypergraph: cells with a weight each, and nets joining sets of cells.
+pub struct Graph {
+    pub weight: Vec<i32>,
+    pub nets: Vec<Vec<usize>>,
+    /// Where each cell wants to be along the axis being split, if it is
+    /// tied to something already placed.
+    pub anchor: Vec<Option<f64>>,
+}
+
+/// Cells stop being split once a part holds this many.
+const LEAF_CELLS: usize = 2;
+/// FM passes per bisection, at most; most converge in two or three.
+const MAX_PASSES: usize = 8;
+
+/// Order every cell by recursive min-cut bisection: each split keeps as
+/// few nets cut as it can while balancing the two halves' weight, and the
+/// half whose anchors lie further along goes second. Cells of a tightly
+/// connected cluster end up next to each other in the order.
+pub fn bisection_order(graph: &Graph) -> Vec<usize> {
+    let mut nets_of = vec![Vec::new(); graph.weight.len()];
+    for (n, net) in graph.nets.iter().enumerate() {
+        for &c in net {
+            nets_of[c].push(n);
+        }
+    }
+    let mut order = Vec::with_capacity(graph.weight.len());
+    let mut side = vec![0u8; graph.weight.len()];
+    let mut member = vec![false; graph.weight.len()];
+    split(
+        graph,
+        &nets_of,
+        (0..graph.weight.len()).collect(),
+        &mut side,
+        &mut member,
+        &mut order,
+    );
+    order
+}
+
+fn split(
+    graph: &Graph,
+    nets_of: &[Vec<usize>],
+    cells: Vec<usize>,
+    side: &mut [u8],
+    member: &mut [bool],
+    order: &mut Vec<usize>,
+) {
+    if cells.len() <= LEAF_CELLS {
+        order.extend(cells);
+        return;
+    }
+    for &c in &cells {
+        member[c] = true;
+    }
+    bisect(graph, nets_of, &cells, side, member);
+    for &c in &cells {
+        member[c] = false;
+    }
+    let (mut first, mut second): (Vec<usize>, Vec<usize>) =
+        cells.iter().partition(|&&c| side[c] == 0);
+    if first.is_empty() || second.is_empty() {
+        order.extend(cells);
+        return;
+    }
+    let mean = |part: &[usize]| {
+        let anchors: Vec<f64> = part.iter().filter_map(|&c| graph.anchor[c]).collect();
+        (!anchors.is_empty()).then(|| anchors.iter().sum::<f64>() / anchors.len() as f64)
+    };
+    if let (Some(a), Some(b)) = (mean(&first), mean(&second)) {
+        if a > b {
+            std::mem::swap(&mut first, &mut second);
+        }
+    }
+    split(graph, nets_of, first, side, member, order);
+    split(graph, nets_of, second, side, member, order);
+}
+
+/// Split `cells` into sides 0 and 1, written to `side`. Starts from the
+/// cells' order cut in half by weight, then runs FM passes: move the free
+/// cell with the best gain that keeps the halves balanced, lock it, and
+/// keep the prefix of moves that left the fewest nets cut.
+fn bisect(
+    graph: &Graph,
+    nets_of: &[Vec<usize>],
+    cells: &[usize],
+    side: &mut [u8],
+    member: &[bool],
+) {
+    let total: i32 = cells.iter().map(|&c| graph.weight[c]).sum();
+    let heaviest = cells.iter().map(|&c| graph.weight[c]).max().unwrap_or(0);
+    let limit = total / 2 + heaviest;
+    let mut load = [0, 0];
+    for &c in cells {
+        side[c] = u8::from(load[0] >= total / 2);
+        load[side[c] as usize] += graph.weight[c];
+    }
+
+    // Nets with at least two pins among these cells, and their pin counts
+    // on each side.
+    let mut nets: Vec<usize> = cells
+        .iter()
+        .flat_map(|&c| nets_of[c].iter().copied())
+        .collect();
+    nets.sort_unstable();
+    nets.dedup();
+    let pins = |n: usize| graph.nets[n].iter().filter(|&&c| member[c]);
+    nets.retain(|&n| pins(n).count() >= 2);
+    let mut count = vec![[0i32; 2]; graph.nets.len()];
+    let mut live = vec![false; graph.nets.len()];
+    for &n in &nets {
+        live[n] = true;
+    }
+
+    for _ in 0..MAX_PASSES {
+        for &n in &nets {
+            count[n] = [0, 0];
+            for &c in pins(n) {
+                count[n][side[c] as usize] += 1;
+            }
+        }
+        let mut gain = vec![0i32; graph.weight.len()];
+        for &c in cells {
+            let (from, to) = (side[c] as usize, 1 - side[c] as usize);
+            for &n in nets_of[c].iter().filter(|&&n| live[n]) {
+                if count[n][from] == 1 {
+                    gain[c] += 1;
+                }
+                if count[n][to] == 0 {
+                    gain[c] -= 1;
+                }
+            }
+        }
+
+        let mut locked = vec![false; graph.weight.len()];
+        let mut moves = Vec::new();
+        let (mut change, mut best_change, mut best_len) = (0, 0, 0);
+        loop {
+            let pick = cells
+                .iter()
+                .copied()
+                .filter(|&c| !locked[c])
+                .filter(|&c| load[1 - side[c] as usize] + graph.weight[c] <= limit)
+                .max_by_key(|&c| (gain[c], std::cmp::Reverse(c)));
+            let Some(c) = pick else {
+                break;
+            };
+            let (from, to) = (side[c] as usize, 1 - side[c] as usize);
+            change -= gain[c];
+            locked[c] = true;
+            for &n in nets_of[c].iter().filter(|&&n| live[n]) {
+                // Gains of the net's free cells before and after the move,
+                // per the usual FM bookkeeping.
+                if count[n][to] == 0 {
+                    for &o in pins(n).filter(|&&o| !locked[o]) {
+                        gain[o] += 1;
+                    }
+                } else if count[n][to] == 1 {
+                    for &o in pins(n).filter(|&&o| !locked[o] && side[o] as usize == to) {
+                        gain[o] -= 1;
+                    }
+                }
+                count[n][from] -= 1;
+                count[n][to] += 1;
+                if count[n][from] == 0 {
+                    for &o in pins(n).filter(|&&o| !locked[o]) {
+                        gain[o] -= 1;
+                    }
+                } else if count[n][from] == 1 {
+                    for &o in pins(n).filter(|&&o| !locked[o] && side[o] as usize == from) {
+                        gain[o] += 1;
+                    }
+                }
+            }
+            side[c] = to as u8;
+            load[from] -= graph.weight[c];
+            load[to] += graph.weight[c];
+            moves.push(c);
+            if change < best_change {
+                best_change = change;
+                best_len = moves.len();
+            }
+        }
+        // Undo the moves past the best prefix.
+        for &c in &moves[best_len..] {
+            let from = side[c] as usize;
+            side[c] = 1 - side[c];
+            load[from] -= graph.weight[c];
+            load[1 - from] += graph.weight[c];
+        }
+        if best_len == 0 {
+            break;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Espresso PLA frontend. A `.pla` file is a two-level cover: each row is a
+//! product term over the inputs and marks the outputs it feeds, so the
+//! circuit is an AND plane of shared terms followed by an OR plane.
+//!
+//! Types `f`, `fd`, `fr` and `fdr` are accepted; only the on-set rows are
+//! built, since the don't-care and off-set rows leave those free.
+
+use crate::compiler::{Circuit, Ctx};
+use crate::primitives::WIDE_MAX_INPUTS;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Parse and lower a PLA. With `matrix`, each product term and each output
+/// sum becomes a single wide row instead of a tree of 2-input gates.
+pub fn compile(src: &str, matrix: bool) -> Result<Circuit> {
+    let mut n_in = None;
+    let mut n_out = None;
+    let mut in_names: Option<Vec<String>> = None;
+    let mut out_names: Option<Vec<String>> = None;
+    let mut rows: Vec<(usize, String)> = Vec::new();
+    for (n, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let at = |msg: String| anyhow::anyhow!("line {}: {}", n + 1, msg);
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap();
+        let count = |w: Option<&str>| -> Result<usize> {
+            w.and_then(|w| w.parse().ok())
+                .ok_or_else(|| at(format!("`{}` needs a count", first)))
+        };
+        match first {
+            ".i" => n_in = Some(count(words.next())?),
+            ".o" => n_out = Some(count(words.next())?),
+            ".ilb" => in_names = Some(words.map(String::from).collect()),
+            ".ob" => out_names = Some(words.map(String::from).collect()),
+            ".p" => {
+                count(words.next())?;
+            }
+            ".type" => match words.next() {
+                Some("f" | "fd" | "fr" | "fdr") => {}
+                Some(t) => return Err(at(format!("unsupported PLA type `{}`", t))),
+                None => return Err(at("`.type` needs a value".into())),
+            },
+            ".e" | ".end" => break,
+            d if d.starts_with('.') => {
+                return Err(at(format!("unsupported directive `{}`", d)));
+            }
+            _ => rows.push((n + 1, line.chars().filter(|c| !c.is_whitespace()).collect())),
+        }
+    }
+    let (Some(n_in), Some(n_out)) = (n_in, n_out) else {
+        anyhow::bail!("a PLA needs `.i` and `.o`");
+    };
+    let in_names = in_names.unwrap_or_else(|| (0..n_in).map(|i| format!("in{}", i)).collect());
+    let out_names = out_names.unwrap_or_else(|| (0..n_out).map(|i| format!("out{}", i)).collect());
+    if in_names.len() != n_in || out_names.len() != n_out {
+        anyhow::bail!("`.ilb`/`.ob` name counts must match `.i` and `.o`");
+    }
+
+    let mut ctx = Ctx::new();
+    let levers: Vec<String> = in_names.iter().map(|name| ctx.input(name)).collect();
+    let mut inverted: HashMap<usize, String> = HashMap::new();
+    let mut terms: HashMap<String, String> = HashMap::new();
+    let mut sums: Vec<Vec<String>> = vec![Vec::new(); n_out];
+    for (line, row) in rows {
+        let at = |msg: String| anyhow::anyhow!("line {}: {}", line, msg);
+        if row.chars().count() != n_in + n_out {
+            return Err(at(format!(
+                "expected {} input and {} output columns",
+                n_in, n_out
+            )));
+        }
+        let (ins, outs) = row.split_at(n_in);
+        let mut feeds = Vec::new();
+        for (o, c) in outs.chars().enumerate() {
+            match c {
+                '1' | '4' => feeds.push(o),
+                '0' | '-' | '2' | '~' | '3' => {}
+                c => return Err(at(format!("invalid output column `{}`", c))),
+            }
+        }
+        if feeds.is_empty() {
+            continue;
+        }
+        // Rows with the same input plane share one product term.
+        let term = match terms.get(ins) {
+            Some(sig) => sig.clone(),
+            None => {
+                let mut lits = Vec::new();
+                for (bit, c) in ins.chars().enumerate() {
+                    match c {
+                        '1' => lits.push(levers[bit].clone()),
+                        '0' => {
+                            let lever = levers[bit].clone();
+                            let not = inverted
+                                .entry(bit)
+                                .or_insert_with(|| ctx.emit("NOT", vec![lever]));
+                            lits.push(not.clone());
+                        }
+                        '-' | '2' => {}
+                        c => return Err(at(format!("invalid input column `{}`", c))),
+                    }
+                }
+                let sig = if matrix {
+                    wide(&mut ctx, "WIDE_AND", lits)
+                } else {
+                    ctx.reduce_balanced(lits, |c, x, y| c.emit("AND", vec![x, y]))
+                }
+                .unwrap_or_else(|| "CONST_TRUE_SIG".into());
+                terms.insert(ins.to_string(), sig.clone());
+                sig
+            }
+        };
+        for o in feeds {
+            if !sums[o].contains(&term) {
+                sums[o].push(term.clone());
+            }
+        }
+    }
+
+    let mut out_sigs = Vec::new();
+    for (products, name) in sums.into_iter().zip(out_names) {
+        let sop = if matrix {
+            wide(&mut ctx, "WIDE_OR", products).unwrap_or_else(|| "CONST_FALSE_SIG".into())
+        } else {
+            ctx.or_all(products)
+        };
+        out_sigs.push((ctx.emit("BUF", vec![sop]), name));
+    }
+    Ok(ctx.finish(in_names, out_sigs))
+}
+
+/// Combine `sigs` with wide `kind` rows, chaining rows when there are more
+/// signals than one row takes.
+fn wide(ctx: &mut Ctx, kind: &str, mut sigs: Vec<String>) -> Option<String> {
+    while sigs.len() > 1 {
+        sigs = sigs
+            .chunks(WIDE_MAX_INPUTS)
+            .map(|chunk| match chunk {
+                [one] => one.clone(),
+                _ => ctx.emit_with_params(kind, chunk.to_vec(), vec![chunk.len() as i64]),
+            })
+            .collect();
+    }
+    sigs.pop()
+}
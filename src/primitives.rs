@@ -1,5 +1,7 @@
 use serde::Serialize;
 
+type Port = (i32, i32, i32);
+
 #[derive(Serialize, Clone)]
 pub struct BlockPlaque {
     pub x: i32,
@@ -7,6 +9,9 @@ pub struct BlockPlaque {
     pub z: i32,
     pub name: String,
     pub properties: Option<Vec<(String, String)>>,
+    /// Needs a scheduled block tick when pasted, so self-running circuits
+    /// start without an external update.
+    pub scheduled: bool,
 }
 
 #[derive(Serialize)]
@@ -29,13 +34,17 @@ fn make_block(x: i32, y: i32, z: i32, name: &str, props: Option<Vec<(&str, &str)
         z,
         name: name.into(),
         properties: props.map(|v| v.into_iter().map(|(k, v)| (k.into(), v.into())).collect()),
+        scheduled: false,
     }
 }
 
+/// The solid floor under every gate, unless the build picks another.
+pub const FLOOR_BLOCK: &str = "minecraft:sandstone";
+
 fn make_floor(blocks: &mut Vec<BlockPlaque>, size_x: i32, size_z: i32) {
     for x in 0..size_x {
         for z in 0..size_z {
-            blocks.push(make_block(x, 0, z, "minecraft:sandstone", None));
+            blocks.push(make_block(x, 0, z, FLOOR_BLOCK, None));
         }
     }
 }
@@ -48,7 +57,7 @@ fn place_nand(
     dx: i32,
     dy: i32,
     dz: i32,
-) -> ((i32, i32, i32), (i32, i32, i32), (i32, i32, i32)) {
+) -> (Port, Port, Port) {
     // Standard NAND: Inputs -> Blocks w/ Torches -> Wire
     // Input A (dx, dy+1, dz)
     // Input B (dx, dy+1, dz+2)
@@ -105,21 +114,21 @@ fn place_or(
     dx: i32,
     dy: i32,
     dz: i32,
-) -> ((i32, i32, i32), (i32, i32, i32), (i32, i32, i32)) {
+) -> (Port, Port, Port) {
     // OR: Inputs -> Repeaters -> Wire Merge
     blocks.push(make_block(
         dx,
         dy + 1,
         dz,
         "minecraft:repeater",
-        Some(vec![("facing", "east")]),
+        Some(vec![("facing", "west")]),
     ));
     blocks.push(make_block(
         dx,
         dy + 1,
         dz + 2,
         "minecraft:repeater",
-        Some(vec![("facing", "east")]),
+        Some(vec![("facing", "west")]),
     ));
 
     blocks.push(make_block(
@@ -151,9 +160,141 @@ fn place_or(
     )
 }
 
+fn place_and(blocks: &mut Vec<BlockPlaque>, dx: i32, dy: i32, dz: i32) -> (Port, Port, Port) {
+    // AND: NAND followed by an inverter
+    let (a, b, (nx, ny, nz)) = place_nand(blocks, dx, dy, dz); // Output at Y=2
+                                                               // Drop signal to Y=1 for Inverter?
+    blocks.push(make_block(
+        nx + 1,
+        ny - 1,
+        nz,
+        "minecraft:cobblestone",
+        None,
+    )); // Block
+    blocks.push(make_block(nx + 1, ny, nz, "minecraft:redstone_wire", None)); // Wire on top
+    blocks.push(make_block(
+        nx + 2,
+        ny - 1,
+        nz,
+        "minecraft:redstone_torch",
+        Some(vec![("facing", "east"), ("lit", "true")]),
+    )); // Torch side
+    (a, b, (nx + 2, ny - 1, nz))
+}
+
 // --- Gate Implementations ---
 
-pub fn primitive_for(kind: &str) -> Primitive {
+/// Most inputs a WIDE_AND or WIDE_OR row takes; the shared wire line must
+/// stay within signal strength 15.
+pub const WIDE_MAX_INPUTS: usize = 8;
+
+/// What an INPUT is worked by, indexed by its parameter: a lever, a stone
+/// button that stays on for a second, or a stone pressure plate. Without
+/// the parameter it is a lever.
+pub const INPUT_HARDWARE: &[&str] = &["lever", "button", "plate"];
+
+/// The INPUT parameter for hardware named like `button`.
+pub fn input_hardware(name: &str) -> anyhow::Result<i64> {
+    match INPUT_HARDWARE.iter().position(|h| *h == name) {
+        Some(i) => Ok(i as i64),
+        None => anyhow::bail!(
+            "input hardware must be one of {}, got `{}`",
+            INPUT_HARDWARE.join(", "),
+            name
+        ),
+    }
+}
+
+/// Gate kinds that have a primitive, with their input and parameter counts.
+/// Wide gates take as many inputs as their first parameter says (`None`).
+/// An INPUT's parameter may be left out.
+pub const GATE_KINDS: &[(&str, Option<usize>, usize)] = &[
+    ("CONST_TRUE", Some(0), 0),
+    ("CONST_FALSE", Some(0), 0),
+    ("INPUT", Some(0), 1),
+    ("BUF", Some(1), 0),
+    ("NOT", Some(1), 0),
+    ("OR", Some(2), 0),
+    ("NOR", Some(2), 0),
+    ("NAND", Some(2), 0),
+    ("AND", Some(2), 0),
+    ("XOR", Some(2), 0),
+    ("MUX", Some(3), 0),
+    ("XNOR", Some(2), 0),
+    ("RISING", Some(1), 0),
+    ("FALLING", Some(1), 0),
+    ("DFF", Some(2), 0),
+    ("SRLATCH", Some(2), 0),
+    ("CLOCK", Some(0), 1),
+    ("DELAY", Some(1), 1),
+    ("WIDE_AND", None, 1),
+    ("WIDE_OR", None, 1),
+];
+
+/// What a primitive costs: the floor it covers and the redstone ticks a
+/// signal takes through it.
+#[derive(Clone, Copy)]
+pub struct Cost {
+    pub area: i32,
+    pub delay: i32,
+}
+
+pub fn cost(kind: &str, params: &[i64]) -> Cost {
+    let prim = primitive_for(kind, params);
+    // Repeaters and torches each hold a signal for a tick, wire is free.
+    // XOR and XNOR are built as an OR and a NAND into an AND (and a NOT).
+    let delay = match kind {
+        "BUF" | "OR" | "NAND" | "WIDE_OR" => 1,
+        "NOT" | "NOR" | "AND" | "WIDE_AND" => 2,
+        "XOR" => 3,
+        "XNOR" => 5,
+        // Select repeater, torch, side repeater and comparator.
+        "MUX" => 4,
+        "DELAY" => params.first().copied().unwrap_or(1).max(1) as i32,
+        _ => 0,
+    };
+    Cost {
+        area: prim.size_x * prim.size_z,
+        delay,
+    }
+}
+
+/// Signal strength at a primitive's output port while it is on, at its
+/// weakest. A torch or block at the port counts as 16, one more than the
+/// wire beside it gets.
+pub fn output_strength(kind: &str, params: &[i64]) -> i32 {
+    match kind {
+        // Wire straight after a repeater or a lever's block.
+        "INPUT" | "BUF" | "DFF" | "DELAY" => 15,
+        // Wire one block past the torches.
+        "NAND" | "SRLATCH" | "CLOCK" => 14,
+        // Wire down the merge from an outer input.
+        "OR" => 13,
+        "WIDE_OR" => {
+            let n = params.first().copied().unwrap_or(2);
+            15 - n.clamp(2, WIDE_MAX_INPUTS as i64) as i32
+        }
+        // The else row's repeater, down its row and the merge.
+        "MUX" => 9,
+        _ => 16,
+    }
+}
+
+/// Signal strength a wire must still have at a primitive's input ports
+/// for the wire inside to carry it on to a repeater or block.
+pub fn input_strength(kind: &str) -> i32 {
+    match kind {
+        "BUF" => 2,
+        // The then row runs two wires to its repeater, and the clock two
+        // to the block of the !CLK torch.
+        "MUX" | "DFF" => 3,
+        // The delayed row is three wires down the split.
+        "RISING" | "FALLING" => 4,
+        _ => 1,
+    }
+}
+
+pub fn primitive_for(kind: &str, params: &[i64]) -> Primitive {
     let mut blocks = Vec::new();
 
     match kind {
@@ -186,16 +327,35 @@ pub fn primitive_for(kind: &str) -> Primitive {
             }
         }
         "INPUT" => {
-            let (sx, sy, sz) = (1, 2, 1);
+            // Lever, button or plate on a block; the powered block drives
+            // the wire beside it.
+            let (sx, sy, sz) = (2, 2, 1);
             make_floor(&mut blocks, sx, sz);
             blocks.push(make_block(0, 1, 0, "minecraft:cobblestone", None));
-            blocks.push(make_block(
-                0,
-                2,
-                0,
-                "minecraft:lever",
-                Some(vec![("face", "floor"), ("powered", "false")]),
-            ));
+            blocks.push(match params.first().copied().unwrap_or(0) {
+                1 => make_block(
+                    0,
+                    2,
+                    0,
+                    "minecraft:stone_button",
+                    Some(vec![("face", "floor"), ("powered", "false")]),
+                ),
+                2 => make_block(
+                    0,
+                    2,
+                    0,
+                    "minecraft:stone_pressure_plate",
+                    Some(vec![("powered", "false")]),
+                ),
+                _ => make_block(
+                    0,
+                    2,
+                    0,
+                    "minecraft:lever",
+                    Some(vec![("face", "floor"), ("powered", "false")]),
+                ),
+            });
+            blocks.push(make_block(1, 1, 0, "minecraft:redstone_wire", None));
             Primitive {
                 name: kind.into(),
                 size_x: sx,
@@ -203,7 +363,7 @@ pub fn primitive_for(kind: &str) -> Primitive {
                 size_z: sz,
                 blocks,
                 input_ports: vec![],
-                output_port: (0, 1, 0),
+                output_port: (1, 1, 0),
             }
         }
         "BUF" => {
@@ -215,7 +375,7 @@ pub fn primitive_for(kind: &str) -> Primitive {
                 1,
                 0,
                 "minecraft:repeater",
-                Some(vec![("facing", "east"), ("delay", "1")]),
+                Some(vec![("facing", "west"), ("delay", "1")]),
             ));
             Primitive {
                 name: kind.into(),
@@ -235,7 +395,7 @@ pub fn primitive_for(kind: &str) -> Primitive {
                 1,
                 0,
                 "minecraft:repeater",
-                Some(vec![("facing", "east")]),
+                Some(vec![("facing", "west")]),
             ));
             blocks.push(make_block(1, 1, 0, "minecraft:cobblestone", None));
             blocks.push(make_block(
@@ -297,7 +457,7 @@ pub fn primitive_for(kind: &str) -> Primitive {
         "NAND" => {
             let (sx, sy, sz) = (3, 3, 3);
             make_floor(&mut blocks, sx, sz);
-            let ((ix1, iy1, iz1), (ix2, iy2, iz2), (ox, oy, oz)) = place_nand(&mut blocks, 0, 0, 0);
+            let (_, _, (ox, oy, oz)) = place_nand(&mut blocks, 0, 0, 0);
             // Note: NAND inputs are blocks. We route input ports to hit them.
             // Port A -> (-1, 1, 0) leads to Block(0, 1, 0)
             Primitive {
@@ -314,32 +474,399 @@ pub fn primitive_for(kind: &str) -> Primitive {
             // Optimized: NAND + NOT
             let (sx, sy, sz) = (5, 3, 3);
             make_floor(&mut blocks, sx, sz);
-            let (_, _, (nx, ny, nz)) = place_nand(&mut blocks, 0, 0, 0); // Output at Y=2
-                                                                         // Drop signal to Y=1 for Inverter?
+            let (_, _, out) = place_and(&mut blocks, 0, 0, 0);
+
+            Primitive {
+                name: kind.into(),
+                size_x: sx,
+                size_y: sy,
+                size_z: sz,
+                blocks,
+                input_ports: vec![(-1, 1, 0), (-1, 1, 2)],
+                output_port: out,
+            }
+        }
+        "MUX" => {
+            // Inputs: select, then, else. Two subtract-mode comparators pass
+            // their data only while their side is off: the select's torch
+            // inverse holds the then row's side, the select itself holds
+            // the else row's side. The rows merge onto a wire at x=5.
+            let (sx, sy, sz) = (6, 2, 5);
+            make_floor(&mut blocks, sx, sz);
+            let comparator = || Some(vec![("facing", "west"), ("mode", "subtract")]);
+            let facing = |dir| Some(vec![("facing", dir)]);
+            // Then row, z=0.
+            blocks.push(make_block(0, 1, 0, "minecraft:redstone_wire", None));
+            blocks.push(make_block(1, 1, 0, "minecraft:redstone_wire", None));
+            blocks.push(make_block(2, 1, 0, "minecraft:repeater", facing("west")));
+            blocks.push(make_block(3, 1, 0, "minecraft:comparator", comparator()));
+            blocks.push(make_block(4, 1, 0, "minecraft:redstone_wire", None));
+            // Select, z=2: straight to the else side, inverted to the then side.
+            blocks.push(make_block(0, 1, 2, "minecraft:redstone_wire", None));
+            blocks.push(make_block(0, 1, 3, "minecraft:repeater", facing("north")));
+            blocks.push(make_block(1, 1, 2, "minecraft:repeater", facing("west")));
+            blocks.push(make_block(2, 1, 2, "minecraft:cobblestone", None));
             blocks.push(make_block(
-                nx + 1,
-                ny - 1,
-                nz,
-                "minecraft:cobblestone",
-                None,
-            )); // Block
-            blocks.push(make_block(nx + 1, ny, nz, "minecraft:redstone_wire", None)); // Wire on top
+                3,
+                1,
+                2,
+                "minecraft:redstone_wall_torch",
+                Some(vec![("facing", "east"), ("lit", "true")]),
+            ));
+            blocks.push(make_block(3, 1, 1, "minecraft:repeater", facing("south")));
+            // Else row, z=4. Its data comes off the port at any strength, so
+            // a repeater restores it.
+            blocks.push(make_block(0, 1, 4, "minecraft:comparator", comparator()));
+            blocks.push(make_block(1, 1, 4, "minecraft:repeater", facing("west")));
+            for x in 2..5 {
+                blocks.push(make_block(x, 1, 4, "minecraft:redstone_wire", None));
+            }
+            // Merge.
+            for z in 0..5 {
+                blocks.push(make_block(5, 1, z, "minecraft:redstone_wire", None));
+            }
+            Primitive {
+                name: kind.into(),
+                size_x: sx,
+                size_y: sy,
+                size_z: sz,
+                blocks,
+                input_ports: vec![(-1, 1, 2), (-1, 1, 0), (-1, 1, 4)],
+                output_port: (6, 1, 2),
+            }
+        }
+        "WIDE_AND" | "WIDE_OR" => {
+            // Diode-matrix rows for PLA planes: one input every other block
+            // along Z, merged onto a single wire line running beside them.
+            let n = params.first().copied().unwrap_or(2).clamp(2, WIDE_MAX_INPUTS as i64) as i32;
+            let line = 2 * n - 1;
+            let input_ports = (0..n).map(|i| (-1, 1, 2 * i)).collect();
+            if kind == "WIDE_OR" {
+                // Repeaters act as diodes onto the shared line.
+                let (sx, sy, sz) = (2, 2, line);
+                make_floor(&mut blocks, sx, sz);
+                for i in 0..n {
+                    blocks.push(make_block(
+                        0,
+                        1,
+                        2 * i,
+                        "minecraft:repeater",
+                        Some(vec![("facing", "west")]),
+                    ));
+                }
+                for z in 0..line {
+                    blocks.push(make_block(1, 1, z, "minecraft:redstone_wire", None));
+                }
+                Primitive {
+                    name: kind.into(),
+                    size_x: sx,
+                    size_y: sy,
+                    size_z: sz,
+                    blocks,
+                    input_ports,
+                    output_port: (2, 1, n - 1),
+                }
+            } else {
+                // Each input inverts through a torch onto the line (a wired
+                // NAND); the line's end block and torch invert it back.
+                let (sx, sy, sz) = (3, 2, line + 2);
+                make_floor(&mut blocks, sx, sz);
+                for i in 0..n {
+                    blocks.push(make_block(0, 1, 2 * i, "minecraft:cobblestone", None));
+                    blocks.push(make_block(
+                        1,
+                        1,
+                        2 * i,
+                        "minecraft:redstone_wall_torch",
+                        Some(vec![("facing", "east"), ("lit", "true")]),
+                    ));
+                }
+                for z in 0..line {
+                    blocks.push(make_block(2, 1, z, "minecraft:redstone_wire", None));
+                }
+                blocks.push(make_block(2, 1, line, "minecraft:cobblestone", None));
+                blocks.push(make_block(
+                    2,
+                    1,
+                    line + 1,
+                    "minecraft:redstone_wall_torch",
+                    Some(vec![("facing", "south"), ("lit", "true")]),
+                ));
+                Primitive {
+                    name: kind.into(),
+                    size_x: sx,
+                    size_y: sy,
+                    size_z: sz,
+                    blocks,
+                    input_ports,
+                    output_port: (2, 1, line + 1),
+                }
+            }
+        }
+        "RISING" | "FALLING" => {
+            // Edge detector: AND of the input and the inverted, delayed input
+            // (rising), or of the inverted input and the delayed input
+            // (falling). The output pulses for roughly the delay difference.
+            let (sx, sy, sz) = (9, 3, 3);
+            make_floor(&mut blocks, sx, sz);
+            // Split the input onto rows z=0 (direct) and z=2 (delayed)
+            blocks.push(make_block(0, 1, 0, "minecraft:redstone_wire", None));
+            blocks.push(make_block(0, 1, 1, "minecraft:redstone_wire", None));
+            blocks.push(make_block(0, 1, 2, "minecraft:redstone_wire", None));
+            let (inverted_row, delay) = if kind == "RISING" { (2, "2") } else { (0, "4") };
+            for z in [0, 2] {
+                let d = if z == 2 { delay } else { "1" };
+                blocks.push(make_block(
+                    1,
+                    1,
+                    z,
+                    "minecraft:repeater",
+                    Some(vec![("facing", "west"), ("delay", d)]),
+                ));
+                if z == inverted_row {
+                    blocks.push(make_block(2, 1, z, "minecraft:cobblestone", None));
+                    blocks.push(make_block(
+                        3,
+                        1,
+                        z,
+                        "minecraft:redstone_wall_torch",
+                        Some(vec![("facing", "east"), ("lit", "true")]),
+                    ));
+                } else {
+                    blocks.push(make_block(2, 1, z, "minecraft:redstone_wire", None));
+                    blocks.push(make_block(3, 1, z, "minecraft:redstone_wire", None));
+                }
+                blocks.push(make_block(
+                    4,
+                    1,
+                    z,
+                    "minecraft:repeater",
+                    Some(vec![("facing", "west")]),
+                ));
+            }
+            let (_, _, out) = place_and(&mut blocks, 5, 0, 0);
+            Primitive {
+                name: kind.into(),
+                size_x: sx,
+                size_y: sy,
+                size_z: sz,
+                blocks,
+                input_ports: vec![(-1, 1, 0)],
+                output_port: out,
+            }
+        }
+        "DFF" => {
+            // Master-slave repeater-lock flip-flop, rising-edge triggered.
+            // Row z=3 carries data through two repeaters: the master (0,1,3)
+            // is locked while CLK is high, the slave (2,1,3) while CLK is low.
+            // CLK enters on row z=0, whose dust runs straight into the block
+            // carrying the wall torch that derives !CLK for the slave.
+            let (sx, sy, sz) = (4, 2, 4);
+            make_floor(&mut blocks, sx, sz);
+            // Clock distribution
+            blocks.push(make_block(0, 1, 0, "minecraft:redstone_wire", None));
+            blocks.push(make_block(1, 1, 0, "minecraft:redstone_wire", None));
+            blocks.push(make_block(2, 1, 0, "minecraft:cobblestone", None));
+            blocks.push(make_block(
+                0,
+                1,
+                1,
+                "minecraft:repeater",
+                Some(vec![("facing", "north")]),
+            ));
             blocks.push(make_block(
-                nx + 2,
-                ny - 1,
-                nz,
+                0,
+                1,
+                2,
+                "minecraft:repeater",
+                Some(vec![("facing", "north")]),
+            )); // Locks master while CLK is high
+            blocks.push(make_block(
+                2,
+                1,
+                1,
+                "minecraft:redstone_wall_torch",
+                Some(vec![("facing", "south"), ("lit", "true")]),
+            )); // !CLK
+            blocks.push(make_block(
+                2,
+                1,
+                2,
+                "minecraft:repeater",
+                Some(vec![("facing", "north")]),
+            )); // Locks slave while CLK is low
+            // Data path
+            blocks.push(make_block(
+                0,
+                1,
+                3,
+                "minecraft:repeater",
+                Some(vec![("facing", "west")]),
+            )); // Master
+            blocks.push(make_block(1, 1, 3, "minecraft:redstone_wire", None));
+            blocks.push(make_block(
+                2,
+                1,
+                3,
+                "minecraft:repeater",
+                Some(vec![("facing", "west")]),
+            )); // Slave
+            blocks.push(make_block(3, 1, 3, "minecraft:redstone_wire", None));
+            Primitive {
+                name: kind.into(),
+                size_x: sx,
+                size_y: sy,
+                size_z: sz,
+                blocks,
+                // D, CLK
+                input_ports: vec![(-1, 1, 3), (-1, 1, 0)],
+                output_port: (3, 1, 3),
+            }
+        }
+        "SRLATCH" => {
+            // Crossed-torch RS NOR latch. Block X (S side) carries torch !Q,
+            // block Y (R side) carries torch Q; each torch's dust climbs onto
+            // glass and drops into the other block off cobblestone, since dust
+            // only reads a step down when the upper dust sits on a conductor.
+            let (sx, sy, sz) = (6, 3, 4);
+            make_floor(&mut blocks, sx, sz);
+            // S -> X
+            blocks.push(make_block(
+                0,
+                1,
+                0,
+                "minecraft:repeater",
+                Some(vec![("facing", "west")]),
+            ));
+            blocks.push(make_block(1, 1, 0, "minecraft:cobblestone", None)); // X
+            blocks.push(make_block(
+                1,
+                2,
+                0,
                 "minecraft:redstone_torch",
-                Some(vec![("facing", "east"), ("lit", "true")]),
-            )); // Torch side
-
+                Some(vec![("lit", "true")]),
+            )); // !Q
+            // R -> Y (enters from the south)
+            blocks.push(make_block(
+                3,
+                1,
+                3,
+                "minecraft:repeater",
+                Some(vec![("facing", "south")]),
+            ));
+            blocks.push(make_block(3, 1, 2, "minecraft:cobblestone", None)); // Y
+            blocks.push(make_block(
+                3,
+                2,
+                2,
+                "minecraft:redstone_torch",
+                Some(vec![("lit", "false")]),
+            )); // Q
+            // !Q -> Y
+            for (x, z, under) in [(2, 0, "minecraft:glass"), (3, 0, "minecraft:cobblestone")] {
+                blocks.push(make_block(x, 1, z, under, None));
+                blocks.push(make_block(x, 2, z, "minecraft:redstone_wire", None));
+            }
+            blocks.push(make_block(3, 1, 1, "minecraft:redstone_wire", None));
+            // Q -> X
+            for (x, z, under) in [(2, 2, "minecraft:glass"), (1, 2, "minecraft:cobblestone")] {
+                blocks.push(make_block(x, 1, z, under, None));
+                blocks.push(make_block(x, 2, z, "minecraft:redstone_wire", None));
+            }
+            blocks.push(make_block(1, 1, 1, "minecraft:redstone_wire", None));
+            // Q -> output
+            blocks.push(make_block(4, 1, 2, "minecraft:cobblestone", None));
+            blocks.push(make_block(4, 2, 2, "minecraft:redstone_wire", None));
+            blocks.push(make_block(5, 1, 2, "minecraft:redstone_wire", None));
             Primitive {
                 name: kind.into(),
                 size_x: sx,
                 size_y: sy,
                 size_z: sz,
                 blocks,
-                input_ports: vec![(-1, 1, 0), (-1, 1, 2)],
-                output_port: (nx + 2, ny - 1, nz),
+                // S, R
+                input_ports: vec![(-1, 1, 0), (3, 1, 4)],
+                output_port: (5, 1, 2),
+            }
+        }
+        "CLOCK" => {
+            // Torch on block B feeds a south-running repeater chain that loops
+            // back into B, so each half period is 1 torch tick plus the chain.
+            // The torch starts unlit with a scheduled tick to kick it off.
+            let period = params.first().copied().unwrap_or(6) as i32;
+            let mut chain = period / 2 - 1;
+            let n = (chain + 3) / 4;
+            let (sx, sy, sz) = (4, 2, n + 2);
+            make_floor(&mut blocks, sx, sz);
+            blocks.push(make_block(0, 1, 0, "minecraft:cobblestone", None)); // B
+            let mut torch = make_block(
+                1,
+                1,
+                0,
+                "minecraft:redstone_wall_torch",
+                Some(vec![("facing", "east"), ("lit", "false")]),
+            );
+            torch.scheduled = true;
+            blocks.push(torch);
+            blocks.push(make_block(2, 1, 0, "minecraft:redstone_wire", None));
+            blocks.push(make_block(3, 1, 0, "minecraft:redstone_wire", None)); // Output
+            for i in 0..n {
+                // Spread the delay across the chain, 1-4 ticks per repeater.
+                let delay = (chain - (n - 1 - i)).min(4);
+                chain -= delay;
+                let delay = delay.to_string();
+                blocks.push(make_block(
+                    2,
+                    1,
+                    1 + i,
+                    "minecraft:repeater",
+                    Some(vec![("facing", "north"), ("delay", delay.as_str())]),
+                ));
+            }
+            // Return path back up into B
+            for (x, z) in [(2, n + 1), (1, n + 1), (0, n + 1)] {
+                blocks.push(make_block(x, 1, z, "minecraft:redstone_wire", None));
+            }
+            for z in 1..=n {
+                blocks.push(make_block(0, 1, z, "minecraft:redstone_wire", None));
+            }
+            Primitive {
+                name: kind.into(),
+                size_x: sx,
+                size_y: sy,
+                size_z: sz,
+                blocks,
+                input_ports: vec![],
+                output_port: (3, 1, 0),
+            }
+        }
+        "DELAY" => {
+            // Repeater chain totalling `ticks`, 1-4 ticks per repeater.
+            let mut ticks = params.first().copied().unwrap_or(1).max(1) as i32;
+            let n = (ticks + 3) / 4;
+            let (sx, sy, sz) = (n + 1, 2, 1);
+            make_floor(&mut blocks, sx, sz);
+            for i in 0..n {
+                let delay = (ticks - (n - 1 - i)).min(4);
+                ticks -= delay;
+                let delay = delay.to_string();
+                blocks.push(make_block(
+                    i,
+                    1,
+                    0,
+                    "minecraft:repeater",
+                    Some(vec![("facing", "west"), ("delay", delay.as_str())]),
+                ));
+            }
+            blocks.push(make_block(n, 1, 0, "minecraft:redstone_wire", None));
+            Primitive {
+                name: kind.into(),
+                size_x: sx,
+                size_y: sy,
+                size_z: sz,
+                blocks,
+                input_ports: vec![(-1, 1, 0)],
+                output_port: (n, 1, 0),
             }
         }
         "XOR" => {
@@ -347,7 +874,7 @@ pub fn primitive_for(kind: &str) -> Primitive {
             // Implementation: OR Gate || NAND Gate -> AND Gate
             // Stacked or Planar? Planar is easier to visualize.
             // Shared Inputs split to OR and NAND sections.
-            let (sx, sy, sz) = (6, 3, 5);
+            let (sx, _sy, sz) = (6, 3, 5);
             make_floor(&mut blocks, sx, sz);
 
             // Inputs: (-1, 1, 1), (-1, 1, 3)
@@ -367,14 +894,14 @@ pub fn primitive_for(kind: &str) -> Primitive {
                 1,
                 1,
                 "minecraft:repeater",
-                Some(vec![("facing", "east")]),
+                Some(vec![("facing", "west")]),
             ));
             blocks.push(make_block(
                 1,
                 1,
                 3,
                 "minecraft:repeater",
-                Some(vec![("facing", "east")]),
+                Some(vec![("facing", "west")]),
             ));
             blocks.push(make_block(2, 1, 1, "minecraft:redstone_wire", None));
             blocks.push(make_block(2, 1, 2, "minecraft:redstone_wire", None)); // Merge OR
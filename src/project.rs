@@ -0,0 +1,496 @@
+//! Project manifests: `schemlogica.json` or `schemlogica.toml` listing
+//! several targets built in one invocation.
+//!
+//! ```toml
+//! libraries = ["lib/gates.js"]
+//!
+//! [[targets]]
+//! name = "adder"
+//! sources = ["adder.js"]
+//! output = "build/adder.litematic"
+//! infer_latches = true
+//! ```
+//!
+//! Paths are relative to the manifest. A target's sources are joined in
+//! order after the libraries written in the same language, so libraries
+//! can hold shared helpers. Formats without a notion of several files
+//! (truth tables, netlists, schematics from other tools) take one source.
+
+use crate::cli::Options;
+use crate::layout::Placer;
+use crate::optimizer::Goal;
+use crate::schematic::{parse_block, NetOrder};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    libraries: Vec<String>,
+    targets: Vec<TargetSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetSpec {
+    name: String,
+    sources: Vec<String>,
+    /// Defaults to `<name>.litematic` next to the manifest.
+    output: Option<String>,
+    infer_latches: Option<bool>,
+    pla_matrix: Option<bool>,
+    nand_nor: Option<bool>,
+    optimize: Option<String>,
+    opt_level: Option<u8>,
+    placer: Option<String>,
+    layers: Option<usize>,
+    max_size: Option<String>,
+    hierarchy: Option<bool>,
+    spacing: Option<String>,
+    layer_height: Option<i32>,
+    origin: Option<String>,
+    aspect: Option<String>,
+    equalize_delays: Option<bool>,
+    wire_lanes: Option<usize>,
+    instant_wire: Option<usize>,
+    tunnels: Option<bool>,
+    net_order: Option<String>,
+    allow_shorts: Option<bool>,
+    allow_unwired: Option<bool>,
+    place_retries: Option<usize>,
+    support_block: Option<String>,
+    floor_block: Option<String>,
+    color_nets: Option<bool>,
+    labels: Option<bool>,
+    lamps: Option<bool>,
+    input_hardware: Option<String>,
+    enclose: Option<String>,
+    author: Option<String>,
+    description: Option<String>,
+}
+
+/// One target, with its sources read and joined.
+pub struct Target {
+    pub name: String,
+    pub code: String,
+    /// Source extension, which picks the frontend.
+    pub ext: Option<String>,
+    pub output: String,
+    /// Overrides of the command-line options, when the target sets them.
+    pub overrides: TargetOverrides,
+}
+
+/// The options a target sets for itself.
+pub struct TargetOverrides {
+    pub infer_latches: Option<bool>,
+    pub pla_matrix: Option<bool>,
+    pub nand_nor: Option<bool>,
+    pub goal: Option<Goal>,
+    pub opt_level: Option<u8>,
+    pub placer: Option<Placer>,
+    pub layers: Option<usize>,
+    pub max_size: Option<(i32, i32, i32)>,
+    pub hierarchy: Option<bool>,
+    pub spacing: Option<(i32, i32)>,
+    pub layer_height: Option<i32>,
+    pub origin: Option<(i32, i32, i32)>,
+    pub aspect: Option<f64>,
+    pub equalize_delays: Option<bool>,
+    pub wire_lanes: Option<usize>,
+    pub instant_wire: Option<usize>,
+    pub tunnels: Option<bool>,
+    pub net_order: Option<NetOrder>,
+    pub allow_shorts: Option<bool>,
+    pub allow_unwired: Option<bool>,
+    pub place_retries: Option<usize>,
+    pub support_block: Option<String>,
+    pub floor_block: Option<String>,
+    pub color_nets: Option<bool>,
+    pub labels: Option<bool>,
+    pub lamps: Option<bool>,
+    pub input_hardware: Option<i64>,
+    pub enclose: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+}
+
+impl TargetOverrides {
+    /// Replace the options in `opts` that this target sets.
+    pub fn apply(&self, opts: &mut Options) {
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(if let Some(value) = &self.$field {
+                    opts.$field = value.clone();
+                })*
+            };
+        }
+        set!(
+            infer_latches,
+            pla_matrix,
+            nand_nor,
+            goal,
+            opt_level,
+            placer,
+            layers,
+            hierarchy,
+            spacing,
+            layer_height,
+            origin,
+            equalize_delays,
+            wire_lanes,
+            tunnels,
+            net_order,
+            allow_shorts,
+            allow_unwired,
+            place_retries,
+            support_block,
+            floor_block,
+            color_nets,
+            labels,
+            lamps,
+            input_hardware,
+            author,
+            description
+        );
+        opts.max_size = self.max_size.or(opts.max_size);
+        opts.aspect = self.aspect.or(opts.aspect);
+        opts.instant_wire = self.instant_wire.or(opts.instant_wire);
+        opts.enclose = self.enclose.clone().or_else(|| opts.enclose.take());
+    }
+}
+
+/// Formats whose files can be concatenated into one design.
+const JOINABLE: &[&str] = &["js", "ts", "v", "blif"];
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_string())
+}
+
+/// Read a manifest and the sources of every target.
+pub fn load(path: &str) -> Result<Vec<Target>> {
+    let text = fs::read_to_string(path)?;
+    let value = match extension(path).as_deref() {
+        Some("toml") => parse_toml(&text)?,
+        _ => serde_json::from_str(&text)?,
+    };
+    let manifest: Manifest = serde_json::from_value(value)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let resolve = |p: &str| dir.join(p).to_string_lossy().into_owned();
+
+    let mut targets = Vec::new();
+    for spec in manifest.targets {
+        let ext = match spec.sources.first() {
+            Some(first) => extension(first),
+            None => anyhow::bail!("target `{}` has no sources", spec.name),
+        };
+        if spec.sources.iter().any(|s| extension(s) != ext) {
+            anyhow::bail!("target `{}` mixes source formats", spec.name);
+        }
+        // JS sources may carry any extension but the other known ones.
+        let joinable = match ext.as_deref() {
+            Some(e) => JOINABLE.contains(&e) || !crate::FORMATS.contains(&e),
+            None => true,
+        };
+        if !joinable && spec.sources.len() > 1 {
+            anyhow::bail!(
+                "target `{}`: .{} sources cannot be combined; list one",
+                spec.name,
+                ext.unwrap_or_default()
+            );
+        }
+        let goal = match &spec.optimize {
+            Some(name) => Some(
+                Goal::parse(name).map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        let placer = match &spec.placer {
+            Some(name) => Some(
+                Placer::parse(name).map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        let net_order = match &spec.net_order {
+            Some(name) => Some(
+                NetOrder::parse(name)
+                    .map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        let block = |id: &Option<String>| match id {
+            Some(id) => parse_block(id)
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e)),
+            None => Ok(None),
+        };
+        let (support_block, floor_block) = (block(&spec.support_block)?, block(&spec.floor_block)?);
+        let enclose = block(&spec.enclose)?;
+        let input_hardware = match &spec.input_hardware {
+            Some(name) => Some(
+                crate::primitives::input_hardware(name)
+                    .map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        let max_size = match &spec.max_size {
+            Some(size) => Some(
+                crate::layout::parse_size(size)
+                    .map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        let spacing = match &spec.spacing {
+            Some(text) => Some(
+                crate::layout::parse_spacing(text)
+                    .map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        let origin = match &spec.origin {
+            Some(text) => Some(
+                crate::layout::parse_origin(text)
+                    .map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        let aspect = match &spec.aspect {
+            Some(text) => Some(
+                crate::layout::parse_aspect(text)
+                    .map_err(|e| anyhow::anyhow!("target `{}`: {}", spec.name, e))?,
+            ),
+            None => None,
+        };
+        if spec
+            .layer_height
+            .is_some_and(|h| h < crate::layout::MIN_LAYER_HEIGHT)
+        {
+            anyhow::bail!(
+                "target `{}`: layer_height must be at least {}",
+                spec.name,
+                crate::layout::MIN_LAYER_HEIGHT
+            );
+        }
+        if spec.layers == Some(0) {
+            anyhow::bail!("target `{}`: layers must be at least 1", spec.name);
+        }
+        if spec.opt_level.is_some_and(|l| l > crate::optimizer::MAX_LEVEL) {
+            anyhow::bail!("target `{}`: opt_level must be 0 to 3", spec.name);
+        }
+        let mut code = String::new();
+        let libraries = manifest.libraries.iter().filter(|l| extension(l) == ext);
+        for file in libraries.chain(&spec.sources) {
+            let text = fs::read_to_string(resolve(file))
+                .map_err(|e| anyhow::anyhow!("target `{}`: {}: {}", spec.name, file, e))?;
+            code.push_str(&text);
+            if !code.ends_with('\n') {
+                code.push('\n');
+            }
+        }
+        let output = spec
+            .output
+            .unwrap_or_else(|| format!("{}.litematic", spec.name));
+        targets.push(Target {
+            name: spec.name,
+            code,
+            ext,
+            output: resolve(&output),
+            overrides: TargetOverrides {
+                infer_latches: spec.infer_latches,
+                pla_matrix: spec.pla_matrix,
+                nand_nor: spec.nand_nor,
+                goal,
+                opt_level: spec.opt_level,
+                placer,
+                layers: spec.layers,
+                max_size,
+                hierarchy: spec.hierarchy,
+                spacing,
+                layer_height: spec.layer_height,
+                origin,
+                aspect,
+                equalize_delays: spec.equalize_delays,
+                wire_lanes: spec.wire_lanes,
+                instant_wire: spec.instant_wire,
+                tunnels: spec.tunnels,
+                net_order,
+                allow_shorts: spec.allow_shorts,
+                allow_unwired: spec.allow_unwired,
+                place_retries: spec.place_retries,
+                support_block,
+                floor_block,
+                color_nets: spec.color_nets,
+                labels: spec.labels,
+                lamps: spec.lamps,
+                input_hardware,
+                enclose,
+                author: spec.author,
+                description: spec.description,
+            },
+        });
+    }
+    Ok(targets)
+}
+
+/// Parse the TOML subset manifests need: tables, arrays of tables, and
+/// string, boolean, integer and array values.
+fn parse_toml(text: &str) -> Result<Value> {
+    let mut root = Map::new();
+    // Path of the table that keys go into: a top-level key, and whether it
+    // names the last element of an array of tables.
+    let mut table: Option<(String, bool)> = None;
+    let mut lines = text.lines().enumerate();
+    while let Some((n, line)) = lines.next() {
+        let at = |msg: &str| anyhow::anyhow!("line {}: {}", n + 1, msg);
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            let name = name.trim().to_string();
+            let entry = root
+                .entry(name.clone())
+                .or_insert_with(|| Value::Array(Vec::new()));
+            let Some(items) = entry.as_array_mut() else {
+                return Err(at("redefined as an array of tables"));
+            };
+            items.push(Value::Object(Map::new()));
+            table = Some((name, true));
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.trim().to_string();
+            root.entry(name.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            table = Some((name, false));
+            continue;
+        }
+        let Some((key, _)) = line.split_once('=') else {
+            return Err(at("expected `key = value`"));
+        };
+        let key = key.trim().trim_matches('"').to_string();
+        // Arrays may span lines until their brackets balance.
+        while depth(&line) > 0 {
+            let Some((_, next)) = lines.next() else {
+                return Err(at("unterminated array"));
+            };
+            line.push(' ');
+            line.push_str(strip_comment(next).trim());
+        }
+        let raw = line.split_once('=').unwrap().1.trim();
+        let (value, rest) = parse_value(raw).map_err(|e| at(&e))?;
+        if !rest.trim().is_empty() {
+            return Err(at("trailing characters after value"));
+        }
+        let target = match &table {
+            None => &mut root,
+            Some((name, is_array)) => {
+                let slot = root.get_mut(name).unwrap();
+                let slot = if *is_array {
+                    slot.as_array_mut().unwrap().last_mut().unwrap()
+                } else {
+                    slot
+                };
+                slot.as_object_mut()
+                    .ok_or_else(|| at("keys under a non-table"))?
+            }
+        };
+        if target.insert(key.clone(), value).is_some() {
+            return Err(at(&format!("`{}` is set twice", key)));
+        }
+    }
+    Ok(Value::Object(root))
+}
+
+/// The line without a trailing `#` comment outside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Unclosed `[` brackets on the value side of a line, outside strings.
+fn depth(line: &str) -> i32 {
+    let value = line.split_once('=').map_or("", |(_, v)| v);
+    let mut quote = None;
+    let mut depth = 0;
+    for c in value.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+/// Parse one value from the start of `s`, returning it and the rest.
+fn parse_value(s: &str) -> std::result::Result<(Value, &str), String> {
+    let s = s.trim_start();
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(out), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(c @ ('"' | '\\')) => out.push(c),
+                    _ => return Err("unsupported escape in string".into()),
+                },
+                c => out.push(c),
+            }
+        }
+        return Err("unterminated string".into());
+    }
+    if let Some(rest) = s.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((Value::String(rest[..end].into()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return Err("expected `,` or `]` in array".into()),
+            }
+        }
+    }
+    let end = s
+        .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+        .unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        w => match w.replace('_', "").parse::<i64>() {
+            Ok(i) => Value::from(i),
+            Err(_) => return Err(format!("unsupported value `{}`", w)),
+        },
+    };
+    Ok((value, rest))
+}
@@ -0,0 +1,1189 @@
+//! Wire routing between placed gates.
+//!
+//! Nets are routed one after another on a 3D grid of dust positions. A
+//! wire is redstone dust on a glass block. Each step moves it one block
+//! sideways, and a via step also climbs or drops a level, like a
+//! staircase, so a wire can rise over another and come back down, or
+//! over a gate on a bridge two blocks above its top. Dust only reads dust
+//! a level above it off a block that conducts, so a wire climbs on glass
+//! but drops off a solid block, kept clear of the gates' blocks beside it
+//! and of torches under it. Where tunnels are allowed, it can also drop
+//! under a gate, beneath the solid floor that keeps the wire apart from
+//! the gate's parts.
+//! Dust of different nets is never placed where the two would connect:
+//! side by side, or a level apart diagonally, but across a bridge.
+//! A wire into a sink beside a gate block comes in straight from the far
+//! side, so its last dust points into the block and powers it.
+//!
+//! To climb three or four levels, a wire can instead take a torch ladder:
+//! a repeater runs it straight into a block with a torch on top, a block
+//! over that and a second torch over that, which lights dust beside the
+//! top torch, or beside one more block over it, two blocks on from the
+//! repeater. Nothing but the wire's own ends may sit beside the column.
+//!
+//! A wire can cross another net's wire that runs straight across its way
+//! on a one-block bridge, from two blocks before it to two blocks past:
+//! it climbs on glass, passes over a solid block laid on the other wire's
+//! dust and drops off a solid block beside it. The block over the crossed
+//! dust keeps it from the bridge's dust a level up on either side. Bridges
+//! only go over wires already laid down, so negotiation leaves them out.
+//!
+//! Above the ground, wire lanes are levels set aside for long nets: each
+//! long net gets a lane of its own to run along as cheaply as on the
+//! ground, and pays a little more for every flat step anywhere else, so
+//! nets that climb out of the crowd spread over the lanes instead of
+//! meeting on the same level.
+//!
+//! The bits of a bus are routed one right after the other, each paying a
+//! little for every flat step that does not run alongside the bit before
+//! it, so a bus keeps together as parallel wires in bit order.
+//!
+//! Routing starts out global: the area is cut into square tiles, and each
+//! net in turn gets the cheapest tree of tiles joining its ports, where a
+//! tile costs more the more nets already cross it. The detailed search for
+//! the net's wires then keeps to that tree and the tiles around it, which
+//! keeps every search small however large the circuit; only a sink that
+//! cannot be reached that way is searched for over the whole area.
+//!
+//! Detailed routing negotiates congestion first, PathFinder style: every
+//! net is routed over and over, free to share cells with others at a price
+//! that rises each pass and stays high where sharing keeps happening,
+//! until the nets settle on wires that keep out of each other's way. Those
+//! are then laid down for real in order, and whatever still collides is
+//! routed again around what is already there, ripping up nets in its way
+//! if it must.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+
+/// A block position in world coordinates.
+pub(crate) type Pos = (i32, i32, i32);
+
+/// Room around the gates for wires to go around the edge.
+const MARGIN: i32 = 4;
+/// Levels above the highest port a wire may climb to.
+const HEADROOM: i32 = 2;
+/// Cost of a via step, against 1 for a flat one on the ground or the
+/// net's own lane.
+const VIA_COST: u32 = 3;
+/// Cost of a flat step on any other level.
+const OFF_LANE_COST: u32 = 2;
+/// Cost of a torch ladder step, no more than the blocks it covers so the
+/// search's distance estimate holds.
+const LADDER_COST: u32 = 6;
+/// Levels a torch ladder climbs.
+const LADDER_RISES: [i32; 2] = [3, 4];
+/// Cost of a bridge step over another net's wire, four blocks on: a via
+/// for each of its hops.
+const BRIDGE_COST: u32 = 4 * VIA_COST;
+/// Source-to-sink span (X plus Z) from which a net gets a lane.
+const LANE_SPAN: i32 = 24;
+/// Cost a bus bit adds to a flat step that strays from the bit before it.
+const BUS_STRAY_COST: u32 = 1;
+/// Side of a global routing tile, in blocks.
+const TILE: i32 = 8;
+/// Tiles on every side of a net's global route its wires may also use.
+const CORRIDOR: i32 = 1;
+/// Nets a tile takes before crossing it costs one more step.
+const TILE_CAPACITY: u32 = 4;
+/// Cost of a step through another net's wire when looking for nets to rip
+/// up, more for each time that net has been ripped up already.
+const RIP_COST: u32 = 20;
+/// Rip-ups allowed per net routed before failing sinks are given up on.
+const RIP_BUDGET: usize = 4;
+/// Most negotiation passes; most circuits settle in two or three.
+const NEGOTIATE_PASSES: usize = 8;
+/// Cost of sharing a cell with another net in the first pass, and how
+/// much it grows each pass after.
+const PRESENT_COST: u32 = 1;
+const PRESENT_GROWTH: u32 = 2;
+/// Searches for one sink before giving up on laying a path to it.
+const CONNECT_TRIES: usize = 4;
+/// Cost a cell adds to sharing it for each pass it ended up shared in.
+const HISTORY_COST: u32 = 2;
+
+const DIRS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// A placed gate as the router sees it: its box and the blocks its
+/// primitive fills.
+pub(crate) struct Footprint {
+    pub at: Pos,
+    pub size: Pos,
+    pub blocks: Vec<Pos>,
+}
+
+/// A signal to wire up: the port driving it and the input ports it feeds.
+pub(crate) struct Net {
+    /// Footprint of the gate driving the net.
+    pub driver: usize,
+    pub source: Pos,
+    pub sinks: Vec<Pos>,
+    /// The net of the bus bit before this one, which it runs alongside.
+    pub follows: Option<usize>,
+}
+
+/// A routed net as a tree of dust positions rooted at its source: each
+/// cell after the first is fed from the cell at its `parent` index, which
+/// comes before it.
+pub(crate) struct Route {
+    pub cells: Vec<Pos>,
+    pub parent: Vec<Option<usize>>,
+}
+
+pub(crate) struct Routing {
+    pub routes: Vec<Route>,
+    /// Sinks left unconnected: (net, sink) indices.
+    pub failed: Vec<(usize, usize)>,
+    /// The last cell of each net's exit run, where its wire has just left
+    /// the driving gate.
+    pub terminals: Vec<Pos>,
+}
+
+/// What holds a grid cell.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Free,
+    /// A block of a gate's primitive.
+    Block(usize),
+    /// The rest of a gate's box, and the level just above it.
+    Body(usize),
+    /// A net's dust.
+    Dust(usize),
+    /// The block under a net's dust.
+    Support(usize),
+    /// Air a net's via needs above its lower dust to connect.
+    Clear(usize),
+    /// The column of a net's torch ladder, and the air over it.
+    Ladder(usize),
+}
+
+/// Nets in the order `ranked`, but each bus bit right after the bit it
+/// follows, wherever `ranked` has it.
+pub(crate) fn bus_order(nets: &[Net], ranked: &[usize]) -> Vec<usize> {
+    let mut next = vec![None; nets.len()];
+    for (n, net) in nets.iter().enumerate() {
+        if let Some(m) = net.follows {
+            next[m] = Some(n);
+        }
+    }
+    let mut order = Vec::with_capacity(nets.len());
+    for &n in ranked.iter().filter(|&&n| nets[n].follows.is_none()) {
+        let mut at = Some(n);
+        while let Some(m) = at {
+            order.push(m);
+            at = next[m];
+        }
+    }
+    order
+}
+
+/// The cells running alongside a wire, two blocks to either side, where
+/// the next bit of its bus goes.
+fn beside(route: &Route) -> HashSet<Pos> {
+    let mut cells = HashSet::new();
+    for &(x, y, z) in &route.cells {
+        cells.extend([(x + 2, y, z), (x - 2, y, z), (x, y, z + 2), (x, y, z - 2)]);
+    }
+    cells
+}
+
+/// The cheapest run of tiles from any tile of `tree` to `goal`, within
+/// `width` by `depth` tiles, where `cost` prices entering a tile.
+fn tile_path(
+    tree: &HashSet<(i32, i32)>,
+    goal: (i32, i32),
+    (width, depth): (i32, i32),
+    cost: impl Fn((i32, i32)) -> u32,
+) -> Vec<(i32, i32)> {
+    let h = |(x, z): (i32, i32)| ((x - goal.0).abs() + (z - goal.1).abs()) as u32;
+    let mut open = BinaryHeap::new();
+    let mut best = HashMap::new();
+    for &t in tree {
+        best.insert(t, (0, None));
+        open.push(Reverse((h(t), 0, t)));
+    }
+    while let Some(Reverse((_, g, t))) = open.pop() {
+        if t == goal {
+            let mut path = vec![t];
+            let mut at = t;
+            while let Some(&(_, Some(prev))) = best.get(&at) {
+                path.push(prev);
+                at = prev;
+            }
+            return path;
+        }
+        if g > best[&t].0 {
+            continue;
+        }
+        for (dx, dz) in DIRS {
+            let u = (t.0 + dx, t.1 + dz);
+            if !(0..width).contains(&u.0) || !(0..depth).contains(&u.1) {
+                continue;
+            }
+            let cost = g + cost(u);
+            if best.get(&u).is_none_or(|&(b, _)| cost < b) {
+                best.insert(u, (cost, Some(t)));
+                open.push(Reverse((cost + h(u), cost, u)));
+            }
+        }
+    }
+    Vec::new()
+}
+
+/// Route every net, in `order`. A sink that cannot be reached otherwise
+/// may rip up the nets in its way, which go back on the queue to be routed
+/// again, until every sink is connected or RIP_BUDGET rip-ups per net have
+/// been spent. `area` bounds the wires in X and Z, `ground` is the lowest
+/// level dust may go, unless a port or the `tunnel` level beneath the
+/// gates is lower, and `lanes` are the levels long nets are spread over.
+pub(crate) fn route(
+    footprints: &[Footprint],
+    nets: &[Net],
+    area: Option<(i32, i32, i32, i32)>,
+    ground: i32,
+    lanes: &[i32],
+    tunnel: Option<i32>,
+    order: &[usize],
+) -> Routing {
+    let mut grid = Grid::new(footprints, nets, area, ground, lanes, tunnel);
+    grid.plan(nets, order);
+    let negotiated = negotiate(&mut grid, nets, order);
+    let mut routes: Vec<Route> = (0..nets.len()).map(|n| grid.trunk(n, &nets[n])).collect();
+    for &n in order {
+        if !grid.commit(n, &mut routes[n], &negotiated[n]) {
+            grid.release(n);
+            routes[n] = grid.trunk(n, &nets[n]);
+        }
+    }
+
+    let mut rips = vec![0; nets.len()];
+    let mut budget = RIP_BUDGET * nets.len();
+    let mut queue: VecDeque<usize> = order.iter().copied().collect();
+    while let Some(n) = queue.pop_front() {
+        for &sink in &nets[n].sinks {
+            if routes[n].cells.contains(&sink) {
+                continue;
+            }
+            if grid.connect(n, &mut routes[n], sink, |g, p, q| g.clear_cost(n, p, q)) {
+                continue;
+            }
+            // Let the net out of its corridor before ripping anything up.
+            if grid.corridors[n].take().is_some()
+                && grid.connect(n, &mut routes[n], sink, |g, p, q| g.clear_cost(n, p, q))
+            {
+                continue;
+            }
+            if budget == 0 {
+                continue;
+            }
+            let cost = |p, q| grid.rip_cost(n, p, q, &rips);
+            let Some(path) = grid.search(&routes[n].starts(), sink, cost) else {
+                continue;
+            };
+            for m in grid.blockers(n, &path) {
+                grid.release(m);
+                routes[m] = grid.trunk(m, &nets[m]);
+                rips[m] += 1;
+                budget = budget.saturating_sub(1);
+                queue.push_back(m);
+            }
+            grid.connect(n, &mut routes[n], sink, |g, p, q| g.clear_cost(n, p, q));
+        }
+    }
+    let failed = nets
+        .iter()
+        .enumerate()
+        .flat_map(|(n, net)| {
+            let cells = &routes[n].cells;
+            (0..net.sinks.len())
+                .filter(move |&s| !cells.contains(&net.sinks[s]))
+                .map(move |s| (n, s))
+        })
+        .collect();
+    let terminals = nets
+        .iter()
+        .zip(&grid.exits)
+        .map(|(net, exit)| exit.last().copied().unwrap_or(net.source))
+        .collect();
+    Routing {
+        routes,
+        failed,
+        terminals,
+    }
+}
+
+/// Route every net again and again with other nets' wires as a cost
+/// rather than an obstacle, until no two share or crowd a cell, and return
+/// the last pass's trees. Each net's tree sits in the grid only while that
+/// net is being routed, so only what is fixed and the net's own wire are
+/// ever in its way.
+fn negotiate(grid: &mut Grid, nets: &[Net], order: &[usize]) -> Vec<Route> {
+    let mut load = Load {
+        dust: vec![0; grid.cells.len()],
+        solid: vec![0; grid.cells.len()],
+        air: vec![0; grid.cells.len()],
+        history: vec![0; grid.cells.len()],
+    };
+    let mut routes: Vec<Route> = (0..nets.len()).map(|n| grid.trunk(n, &nets[n])).collect();
+    let mut present = PRESENT_COST;
+    for _ in 0..NEGOTIATE_PASSES {
+        for &n in order {
+            let net = &nets[n];
+            grid.carry(&mut load, &routes[n], -1);
+            let mut route = grid.trunk(n, net);
+            let beside = net.follows.map(|m| beside(&routes[m]));
+            let stray = |p: Pos, q: Pos| match &beside {
+                Some(cells) if p.1 == q.1 && !cells.contains(&q) => BUS_STRAY_COST,
+                _ => 0,
+            };
+            for &sink in &net.sinks {
+                if route.cells.contains(&sink) {
+                    continue;
+                }
+                let cost =
+                    |g: &Grid, p, q| Some(g.shared_cost(&load, n, p, q, present)? + stray(p, q));
+                grid.connect(n, &mut route, sink, cost);
+            }
+            grid.release(n);
+            grid.carry(&mut load, &route, 1);
+            routes[n] = route;
+        }
+        let shared = grid.shared(&load, &routes);
+        if shared.is_empty() {
+            break;
+        }
+        for i in shared {
+            load.history[i] += HISTORY_COST;
+        }
+        present = present.saturating_mul(PRESENT_GROWTH);
+    }
+    routes
+}
+
+/// How many routed nets use each cell during negotiation, as dust or a
+/// ladder's column, as the block under dust and as a via's air, and the
+/// history cost of sharing it.
+struct Load {
+    dust: Vec<u16>,
+    solid: Vec<u16>,
+    air: Vec<u16>,
+    history: Vec<u32>,
+}
+
+impl Route {
+    /// Add a path leaving from one of the tree's cells.
+    fn extend(&mut self, path: &[Pos]) {
+        let mut from = self.cells.iter().position(|&c| c == path[0]);
+        for &q in &path[1..] {
+            self.cells.push(q);
+            self.parent.push(from);
+            from = Some(self.cells.len() - 1);
+        }
+    }
+
+    /// Cells a new branch may leave from: all but the repeaters running
+    /// into torch ladders, which must have nothing else beside them.
+    fn starts(&self) -> Vec<Pos> {
+        let mut feeds = vec![false; self.cells.len()];
+        for (p, q) in self.steps() {
+            if ladder(p, q).is_some() {
+                feeds[self.cells.iter().position(|&c| c == p).unwrap()] = true;
+            }
+        }
+        let cells = self.cells.iter().zip(feeds);
+        cells
+            .filter(|&(_, feeds)| !feeds)
+            .map(|(&c, _)| c)
+            .collect()
+    }
+
+    /// Each routed step of the tree: the cell fed and the one feeding it.
+    fn steps(&self) -> impl Iterator<Item = (Pos, Pos)> + '_ {
+        (0..self.cells.len()).filter_map(|i| Some((self.cells[self.parent[i]?], self.cells[i])))
+    }
+}
+
+/// The bottom block and rise of the torch ladder a step from `p` to `q`
+/// climbs, if it is a ladder step.
+pub(crate) fn ladder(p: Pos, q: Pos) -> Option<(Pos, i32)> {
+    let rise = q.1 - p.1;
+    (rise > 1).then(|| (((p.0 + q.0) / 2, p.1, (p.2 + q.2) / 2), rise))
+}
+
+/// The cells a torch ladder holds: its blocks and torches, and the air
+/// over them.
+fn column((x, y, z): Pos, rise: i32) -> impl Iterator<Item = Pos> {
+    (y..=y + rise + 1).map(move |y| (x, y, z))
+}
+
+/// The air a via from `p` to `q` needs: above the lower of the two.
+fn via_air(p: Pos, q: Pos) -> Pos {
+    if q.1 > p.1 {
+        (p.0, q.1, p.2)
+    } else {
+        (q.0, p.1, q.2)
+    }
+}
+
+/// The dust a bridge step from `p` to `q` lays on its way over the wire
+/// between them, if it is a bridge step: a level up on glass, two up on a
+/// block over the crossed dust, then a level up on a block it drops off.
+fn bridge(p: Pos, q: Pos) -> Option<[Pos; 3]> {
+    let (dx, dz) = (q.0 - p.0, q.2 - p.2);
+    if q.1 != p.1 || dx.abs() + dz.abs() != 4 || dx != 0 && dz != 0 {
+        return None;
+    }
+    let at = |k: i32, up: i32| (p.0 + k * dx / 4, p.1 + up, p.2 + k * dz / 4);
+    Some([at(1, 1), at(2, 2), at(3, 1)])
+}
+
+/// A lane for each net that spans at least LANE_SPAN: the longest nets
+/// first, each to the lane where it overlaps the boxes of the nets
+/// already there the least.
+fn assign_lanes(nets: &[Net], lanes: &[i32]) -> Vec<Option<i32>> {
+    let boxes: Vec<(i32, i32, i32, i32)> = nets
+        .iter()
+        .map(|net| {
+            let ports = std::iter::once(&net.source).chain(&net.sinks);
+            ports.fold((i32::MAX, i32::MAX, i32::MIN, i32::MIN), |b, &(x, _, z)| {
+                (b.0.min(x), b.1.min(z), b.2.max(x), b.3.max(z))
+            })
+        })
+        .collect();
+    let span = |n: usize| boxes[n].2 - boxes[n].0 + boxes[n].3 - boxes[n].1;
+    let mut order: Vec<usize> = (0..nets.len()).filter(|&n| span(n) >= LANE_SPAN).collect();
+    order.sort_by_key(|&n| Reverse(span(n)));
+    let mut assigned = vec![None; nets.len()];
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); lanes.len()];
+    for n in order {
+        let overlap = |m: &usize| {
+            let (a, b) = (boxes[n], boxes[*m]);
+            let dx = (a.2.min(b.2) - a.0.max(b.0) + 1).max(0);
+            let dz = (a.3.min(b.3) - a.1.max(b.1) + 1).max(0);
+            dx * dz
+        };
+        let best = (0..lanes.len()).min_by_key(|&l| members[l].iter().map(overlap).sum::<i32>());
+        if let Some(l) = best {
+            members[l].push(n);
+            assigned[n] = Some(lanes[l]);
+        }
+    }
+    assigned
+}
+
+/// The top level of each gate's body: a level clear above its box and
+/// every block of its primitive, so dust bridging over it on glass cannot
+/// connect to the gate.
+fn body_tops(footprints: &[Footprint]) -> impl Iterator<Item = i32> + '_ {
+    footprints.iter().map(|f| {
+        let blocks = f.blocks.iter().map(|b| b.1 + 1);
+        blocks.fold(f.at.1 + f.size.1, i32::max)
+    })
+}
+
+struct Grid {
+    /// Low corner and size of the cell array, one block wider than the
+    /// routable box on every side.
+    min: Pos,
+    size: Pos,
+    cells: Vec<Cell>,
+    /// Inclusive bounds of where dust may go.
+    lo: Pos,
+    hi: Pos,
+    /// Cells held for a net from the start, which rip-up leaves alone.
+    fixed: Vec<bool>,
+    /// Each net's run from its source out of its driver's box.
+    exits: Vec<Vec<Pos>>,
+    /// Cells each net's routed wires hold.
+    claimed: Vec<Vec<Pos>>,
+    /// Levels each net runs along at the plain cost: the ground, its
+    /// ports' levels and its lane.
+    levels: Vec<Vec<i32>>,
+    /// Tiles each net's wires keep to, from global routing, until the net
+    /// is let out of its corridor.
+    corridors: Vec<Option<HashSet<(i32, i32)>>>,
+    /// The way from each sink's cell into the gate block beside it, which
+    /// its dust only points into if the wire comes in from the far side.
+    feeds: Vec<Option<(i32, i32)>>,
+}
+
+impl Grid {
+    fn new(
+        footprints: &[Footprint],
+        nets: &[Net],
+        area: Option<(i32, i32, i32, i32)>,
+        ground: i32,
+        lanes: &[i32],
+        tunnel: Option<i32>,
+    ) -> Grid {
+        let ports = nets
+            .iter()
+            .flat_map(|net| std::iter::once(&net.source).chain(&net.sinks));
+        let (mut lo, mut hi) = ((i32::MAX, ground, i32::MAX), (i32::MIN, ground, i32::MIN));
+        for &(x, y, z) in ports {
+            lo = (lo.0.min(x), lo.1.min(y), lo.2.min(z));
+            hi = (hi.0.max(x), hi.1.max(y), hi.2.max(z));
+        }
+        for (f, top) in footprints.iter().zip(body_tops(footprints)) {
+            lo = (lo.0.min(f.at.0), lo.1, lo.2.min(f.at.2));
+            hi = (
+                hi.0.max(f.at.0 + f.size.0),
+                hi.1.max(top + 2),
+                hi.2.max(f.at.2 + f.size.2),
+            );
+        }
+        if lo.0 > hi.0 {
+            (lo, hi) = ((0, ground, 0), (0, ground, 0));
+        }
+        hi.1 = lanes.iter().fold(hi.1, |top, &y| top.max(y));
+        lo.1 = tunnel.map_or(lo.1, |y| lo.1.min(y));
+        lo = (lo.0 - MARGIN, lo.1, lo.2 - MARGIN);
+        hi = (hi.0 + MARGIN, hi.1 + HEADROOM, hi.2 + MARGIN);
+        // Under --max-size, wires stay inside the allowed footprint too.
+        if let Some((ax, az, bx, bz)) = area {
+            lo = (lo.0.max(ax), lo.1, lo.2.max(az));
+            hi = (hi.0.min(bx), hi.1, hi.2.min(bz));
+        }
+        let min = (lo.0 - 1, lo.1 - 1, lo.2 - 1);
+        let size = (hi.0 - lo.0 + 3, hi.1 - lo.1 + 3, hi.2 - lo.2 + 3);
+        let mut grid = Grid {
+            min,
+            size,
+            cells: vec![Cell::Free; (size.0 * size.1 * size.2).max(0) as usize],
+            lo,
+            hi,
+            fixed: vec![false; (size.0 * size.1 * size.2).max(0) as usize],
+            exits: Vec::with_capacity(nets.len()),
+            claimed: vec![Vec::new(); nets.len()],
+            levels: Vec::with_capacity(nets.len()),
+            corridors: vec![None; nets.len()],
+            feeds: vec![None; (size.0 * size.1 * size.2).max(0) as usize],
+        };
+        for (net, lane) in nets.iter().zip(assign_lanes(nets, lanes)) {
+            let ports = std::iter::once(&net.source).chain(&net.sinks);
+            let mut levels: Vec<i32> = ports.map(|p| p.1).chain([ground]).chain(lane).collect();
+            levels.sort_unstable();
+            levels.dedup();
+            grid.levels.push(levels);
+        }
+
+        for (g, (f, top)) in footprints.iter().zip(body_tops(footprints)).enumerate() {
+            for x in f.at.0..=f.at.0 + f.size.0 {
+                for z in f.at.2..=f.at.2 + f.size.2 {
+                    for y in f.at.1..=top {
+                        grid.set((x, y, z), Cell::Body(g));
+                    }
+                }
+            }
+            for &b in &f.blocks {
+                grid.set(b, Cell::Block(g));
+            }
+        }
+        // Outputs leave their gate eastward, straight through its box; the
+        // run is the net's from the start so no other wire crowds it.
+        for net in nets {
+            let f = &footprints[net.driver];
+            let exit = (net.source.0 + 1..=f.at.0 + f.size.0 + 1)
+                .map(|x| (x, net.source.1, net.source.2))
+                .take_while(|&p| !matches!(grid.at(p), Cell::Block(_)))
+                .collect();
+            grid.exits.push(exit);
+        }
+        for &(x, y, z) in nets.iter().flat_map(|net| &net.sinks) {
+            let into = DIRS
+                .into_iter()
+                .find(|&(dx, dz)| matches!(grid.at((x + dx, y, z + dz)), Cell::Block(_)));
+            if let (Some(d), Some(i)) = (into, grid.index((x, y, z))) {
+                grid.feeds[i] = Some(d);
+            }
+        }
+        for (n, net) in nets.iter().enumerate() {
+            let ports = std::iter::once(&net.source).chain(&net.sinks);
+            for &cell in ports.chain(&grid.exits[n].clone()) {
+                grid.fix(cell, Cell::Dust(n));
+                let below = (cell.0, cell.1 - 1, cell.2);
+                if grid.at(below) == Cell::Free {
+                    grid.fix(below, Cell::Support(n));
+                }
+            }
+        }
+        grid
+    }
+
+    /// The global routing tile holding `p`.
+    fn tile(&self, p: Pos) -> (i32, i32) {
+        (
+            (p.0 - self.lo.0).div_euclid(TILE),
+            (p.2 - self.lo.2).div_euclid(TILE),
+        )
+    }
+
+    /// Global routing: give each net, in `order`, a tree of tiles joining
+    /// its ports' tiles, and the tiles around that tree as its corridor.
+    fn plan(&mut self, nets: &[Net], order: &[usize]) {
+        let tiles = self.tile(self.hi);
+        let (width, depth) = (tiles.0 + 1, tiles.1 + 1);
+        let mut crossings = vec![0; (width * depth) as usize];
+        for &n in order {
+            let net = &nets[n];
+            let mut tree = HashSet::from([self.tile(net.source)]);
+            for &sink in &net.sinks {
+                let path = tile_path(&tree, self.tile(sink), (width, depth), |(x, z)| {
+                    1 + crossings[(z * width + x) as usize] / TILE_CAPACITY
+                });
+                tree.extend(path);
+            }
+            for &(x, z) in &tree {
+                crossings[(z * width + x) as usize] += 1;
+            }
+            let mut corridor = HashSet::new();
+            for &(x, z) in &tree {
+                for dx in -CORRIDOR..=CORRIDOR {
+                    for dz in -CORRIDOR..=CORRIDOR {
+                        corridor.insert((x + dx, z + dz));
+                    }
+                }
+            }
+            self.corridors[n] = Some(corridor);
+        }
+    }
+
+    fn fix(&mut self, p: Pos, cell: Cell) {
+        if let Some(i) = self.index(p) {
+            self.cells[i] = cell;
+            self.fixed[i] = true;
+        }
+    }
+
+    /// A net's tree before routing: its source and exit run.
+    fn trunk(&self, n: usize, net: &Net) -> Route {
+        let mut route = Route {
+            cells: vec![net.source],
+            parent: vec![None],
+        };
+        for &cell in &self.exits[n] {
+            route.parent.push(Some(route.cells.len() - 1));
+            route.cells.push(cell);
+        }
+        route
+    }
+
+    /// Rip up a net's routed wires.
+    fn release(&mut self, n: usize) {
+        for p in std::mem::take(&mut self.claimed[n]) {
+            self.set(p, Cell::Free);
+        }
+    }
+
+    /// The net whose routed wire holds a cell, if one does; wires held
+    /// from the start are not anyone's to rip up.
+    fn routed(&self, p: Pos) -> Option<usize> {
+        let i = self.index(p)?;
+        match self.cells[i] {
+            Cell::Dust(m) | Cell::Support(m) | Cell::Clear(m) | Cell::Ladder(m)
+                if !self.fixed[i] =>
+            {
+                Some(m)
+            }
+            _ => None,
+        }
+    }
+
+    fn index(&self, (x, y, z): Pos) -> Option<usize> {
+        let (x, y, z) = (x - self.min.0, y - self.min.1, z - self.min.2);
+        if x < 0 || y < 0 || z < 0 || x >= self.size.0 || y >= self.size.1 || z >= self.size.2 {
+            return None;
+        }
+        Some(((y * self.size.2 + z) * self.size.0 + x) as usize)
+    }
+
+    fn at(&self, p: Pos) -> Cell {
+        self.index(p).map_or(Cell::Free, |i| self.cells[i])
+    }
+
+    fn set(&mut self, p: Pos, cell: Cell) {
+        if let Some(i) = self.index(p) {
+            self.cells[i] = cell;
+        }
+    }
+
+    /// The way from a sink at `p` into its gate's block, if it has one.
+    fn feed(&self, p: Pos) -> Option<(i32, i32)> {
+        self.index(p).and_then(|i| self.feeds[i])
+    }
+
+    /// Whether `p` is where dust comes into a sink from.
+    fn approach(&self, p: Pos) -> bool {
+        DIRS.into_iter()
+            .any(|(dx, dz)| self.feed((p.0 + dx, p.1, p.2 + dz)) == Some((dx, dz)))
+    }
+
+    fn routable(&self, p: Pos) -> bool {
+        (self.lo.0..=self.hi.0).contains(&p.0)
+            && (self.lo.1..=self.hi.1).contains(&p.1)
+            && (self.lo.2..=self.hi.2).contains(&p.2)
+    }
+
+    /// Whether net `n` may step from dust at `p` to new dust at `q`, if
+    /// the routed nets pushed onto `blockers` were ripped up.
+    fn step(&self, n: usize, p: Pos, q: Pos, blockers: &mut Vec<usize>) -> bool {
+        let Some(hops) = bridge(p, q) else {
+            return self.hop(n, p, q, blockers, None);
+        };
+        // A bridge crosses another net's dust running straight across its
+        // way, which it must not rip up, and whose dust beside the bridge's
+        // first and last hops is cut off from them by the block over it.
+        let crossed = ((p.0 + q.0) / 2, p.1, (p.2 + q.2) / 2);
+        let Cell::Dust(m) = self.at(crossed) else {
+            return false;
+        };
+        let (dx, dz) = ((q.0 - p.0) / 4, (q.2 - p.2) / 4);
+        let across = [(dz, dx), (-dz, -dx)];
+        let straight = across
+            .iter()
+            .all(|&(ex, ez)| self.at((crossed.0 + ex, crossed.1, crossed.2 + ez)) == Cell::Dust(m));
+        if m == n || !straight {
+            return false;
+        }
+        let mut from = p;
+        for to in hops.into_iter().chain([q]) {
+            if !self.hop(n, from, to, blockers, Some(crossed)) {
+                return false;
+            }
+            from = to;
+        }
+        true
+    }
+
+    /// Whether net `n` may step one block from dust at `p` to new dust at
+    /// `q`, as `step`, where a bridge's hops may pass beside the dust it
+    /// has `crossed`.
+    fn hop(
+        &self,
+        n: usize,
+        p: Pos,
+        q: Pos,
+        blockers: &mut Vec<usize>,
+        crossed: Option<Pos>,
+    ) -> bool {
+        if !self.routable(q) {
+            return false;
+        }
+        if !self.corridors[n]
+            .as_ref()
+            .is_none_or(|c| c.contains(&self.tile(q)))
+        {
+            return false;
+        }
+        // Anything in the way that a rip-up cannot clear rules the step out.
+        let mut clear = |p: Pos, ok: bool| -> bool {
+            if ok {
+                return true;
+            }
+            match self.routed(p) {
+                Some(m) if m != n => {
+                    blockers.push(m);
+                    true
+                }
+                _ => false,
+            }
+        };
+        // The cell dust comes into a sink from, and the two over it where
+        // a via down into it goes, are kept for that dust.
+        let over_approach = |c: Pos, mut dys: RangeInclusive<i32>| {
+            dys.any(|dy| self.approach((c.0, c.1 - dy, c.2)))
+        };
+        // A ladder's column and the cells beside it must be its own; only
+        // the repeater at its foot and the dust at its top may touch it.
+        if let Some((c, rise)) = ladder(p, q) {
+            for cell in column(c, rise) {
+                if over_approach(cell, 0..=2) || !clear(cell, self.at(cell) == Cell::Free) {
+                    return false;
+                }
+                if cell.1 > c.1 + rise {
+                    continue;
+                }
+                for (dx, dz) in DIRS {
+                    let r = (cell.0 + dx, cell.1, cell.2 + dz);
+                    let end = cell.1 == c.1 || cell.1 == c.1 + rise;
+                    let ok = match self.at(r) {
+                        Cell::Dust(m) => m == n && end,
+                        Cell::Block(_) => false,
+                        _ => !over_approach(r, 0..=0),
+                    };
+                    if !clear(r, ok) {
+                        return false;
+                    }
+                }
+            }
+        }
+        // A via connects only with air above the lower of its two dusts.
+        else if q.1 != p.1 {
+            let above = via_air(p, q);
+            let at = self.at(above);
+            if !clear(above, matches!(at, Cell::Free) || at == Cell::Clear(n)) {
+                return false;
+            }
+            // Dust dropping a level sits on a block that conducts: one no
+            // gate block touches, with no torch under it to power it.
+            if q.1 < p.1 {
+                let (x, y, z) = (p.0, p.1 - 1, p.2);
+                let gate = |(dx, dz)| matches!(self.at((x + dx, y, z + dz)), Cell::Block(_));
+                if DIRS.into_iter().any(gate) {
+                    return false;
+                }
+                let under = (x, y - 1, z);
+                let ok = !matches!(self.at(under), Cell::Block(_) | Cell::Ladder(_));
+                if !clear(under, ok) {
+                    return false;
+                }
+            }
+        }
+        // Dust into a sink comes from the side away from its gate, and no
+        // other dust of the net sits beside it, so it points straight in.
+        let from_behind = |sink: Pos, p: Pos| {
+            self.feed(sink)
+                .is_none_or(|(dx, dz)| (p.0, p.2) == (sink.0 - dx, sink.2 - dz))
+        };
+        if !from_behind(q, p) || over_approach(q, 1..=2) {
+            return false;
+        }
+        match self.at(q) {
+            Cell::Dust(m) if m == n => return true,
+            at => {
+                if !clear(q, at == Cell::Free) {
+                    return false;
+                }
+            }
+        }
+        let below = (q.0, q.1 - 1, q.2);
+        if !clear(below, self.at(below) == Cell::Free) {
+            return false;
+        }
+        for (dx, dz) in DIRS {
+            for dy in -1..=1 {
+                let r = (q.0 + dx, q.1 + dy, q.2 + dz);
+                let ok = match self.at(r) {
+                    Cell::Dust(m) => m == n && from_behind(r, q) || crossed == Some(r),
+                    Cell::Block(_) | Cell::Ladder(_) => dy != 0,
+                    _ => true,
+                };
+                if !clear(r, ok) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Cost of a step's own length for net `n`.
+    fn length_cost(&self, n: usize, p: Pos, q: Pos) -> u32 {
+        if ladder(p, q).is_some() {
+            LADDER_COST
+        } else if bridge(p, q).is_some() {
+            BRIDGE_COST
+        } else if p.1 != q.1 {
+            VIA_COST
+        } else if self.levels[n].contains(&q.1) {
+            1
+        } else {
+            OFF_LANE_COST
+        }
+    }
+
+    /// Cost of a step that is clear of everything.
+    fn clear_cost(&self, n: usize, p: Pos, q: Pos) -> Option<u32> {
+        let mut blockers = Vec::new();
+        let clear = self.step(n, p, q, &mut blockers) && blockers.is_empty();
+        clear.then(|| self.length_cost(n, p, q))
+    }
+
+    /// Cost of a step that may go through routed nets to rip up, more for
+    /// each time a net in the way has been ripped up already.
+    fn rip_cost(&self, n: usize, p: Pos, q: Pos, rips: &[u32]) -> Option<u32> {
+        let mut blockers = Vec::new();
+        if !self.step(n, p, q, &mut blockers) {
+            return None;
+        }
+        blockers.sort_unstable();
+        blockers.dedup();
+        let rip: u32 = blockers.iter().map(|&m| RIP_COST * (1 + rips[m])).sum();
+        Some(self.length_cost(n, p, q) + rip)
+    }
+
+    /// The routed nets a path goes through or crowds.
+    fn blockers(&self, n: usize, path: &[Pos]) -> Vec<usize> {
+        let mut blockers = Vec::new();
+        for pair in path.windows(2) {
+            self.step(n, pair[0], pair[1], &mut blockers);
+        }
+        blockers.sort_unstable();
+        blockers.dedup();
+        blockers
+    }
+
+    /// Cost of a step during negotiation: each use of a cell the step
+    /// needs by another net costs that cell's history plus one, times
+    /// `present`.
+    fn shared_cost(&self, load: &Load, n: usize, p: Pos, q: Pos, present: u32) -> Option<u32> {
+        // Bridges only go over wires already laid down.
+        if bridge(p, q).is_some() || !self.step(n, p, q, &mut Vec::new()) {
+            return None;
+        }
+        let mut shared = 0;
+        let mut charge = |c: Pos, uses: fn(&Load, usize) -> u32| {
+            if let Some(i) = self.index(c) {
+                shared += uses(load, i) * (1 + load.history[i]);
+            }
+        };
+        charge(q, |l, i| (l.dust[i] + l.solid[i] + l.air[i]) as u32);
+        charge((q.0, q.1 - 1, q.2), |l, i| (l.dust[i] + l.air[i]) as u32);
+        if let Some((c, rise)) = ladder(p, q) {
+            for cell in column(c, rise) {
+                charge(cell, |l, i| (l.dust[i] + l.solid[i] + l.air[i]) as u32);
+                if cell.1 <= c.1 + rise {
+                    for (dx, dz) in DIRS {
+                        charge((cell.0 + dx, cell.1, cell.2 + dz), |l, i| l.dust[i] as u32);
+                    }
+                }
+            }
+        } else if q.1 != p.1 {
+            charge(via_air(p, q), |l, i| (l.dust[i] + l.solid[i]) as u32);
+        }
+        for (dx, dz) in DIRS {
+            for dy in -1..=1 {
+                charge((q.0 + dx, q.1 + dy, q.2 + dz), |l, i| l.dust[i] as u32);
+            }
+        }
+        Some(self.length_cost(n, p, q) + shared.saturating_mul(present))
+    }
+
+    /// Add (`delta` 1) or take away (-1) a route's routed cells from the
+    /// negotiation load.
+    fn carry(&self, load: &mut Load, route: &Route, delta: i16) {
+        let add = |uses: &mut Vec<u16>, c: Pos| {
+            if let Some(i) = self.index(c).filter(|&i| !self.fixed[i]) {
+                uses[i] = uses[i].wrapping_add_signed(delta);
+            }
+        };
+        for (p, q) in route.steps() {
+            add(&mut load.dust, q);
+            add(&mut load.solid, (q.0, q.1 - 1, q.2));
+            if let Some((c, rise)) = ladder(p, q) {
+                for cell in column(c, rise) {
+                    add(&mut load.dust, cell);
+                }
+            } else if q.1 != p.1 {
+                add(&mut load.air, via_air(p, q));
+            }
+        }
+    }
+
+    /// Cells where routed nets collide or crowd each other, by index.
+    fn shared(&self, load: &Load, routes: &[Route]) -> Vec<usize> {
+        let mut shared = Vec::new();
+        for route in routes {
+            let mut own: HashSet<Pos> = route.cells.iter().copied().collect();
+            for (p, q) in route.steps() {
+                if let Some((c, rise)) = ladder(p, q) {
+                    own.extend(column(c, rise));
+                }
+            }
+            let mut check = |c: Pos, over: &dyn Fn(usize) -> bool| {
+                if let Some(i) = self.index(c).filter(|&i| !self.fixed[i] && over(i)) {
+                    shared.push(i);
+                }
+            };
+            for (p, q) in route.steps() {
+                check(q, &|i| load.dust[i] + load.solid[i] + load.air[i] > 1);
+                check((q.0, q.1 - 1, q.2), &|i| load.dust[i] + load.air[i] > 0);
+                if let Some((c, rise)) = ladder(p, q) {
+                    for cell in column(c, rise) {
+                        check(cell, &|i| load.dust[i] + load.solid[i] + load.air[i] > 1);
+                        if cell.1 <= c.1 + rise {
+                            for (dx, dz) in DIRS {
+                                let r = (cell.0 + dx, cell.1, cell.2 + dz);
+                                let mine = own.contains(&r) as u16;
+                                check(r, &|i| load.dust[i] > mine);
+                            }
+                        }
+                    }
+                } else if q.1 != p.1 {
+                    check(via_air(p, q), &|i| load.dust[i] + load.solid[i] > 0);
+                }
+                for (dx, dz) in DIRS {
+                    for dy in -1..=1 {
+                        let r = (q.0 + dx, q.1 + dy, q.2 + dz);
+                        let mine = own.contains(&r) as u16;
+                        check(r, &|i| load.dust[i] > mine);
+                    }
+                }
+            }
+        }
+        shared.sort_unstable();
+        shared.dedup();
+        shared
+    }
+
+    /// Lay a negotiated tree down for net `n`, step by step, as long as
+    /// each step is clear; false if one is not.
+    fn commit(&mut self, n: usize, route: &mut Route, tree: &Route) -> bool {
+        for i in route.cells.len()..tree.cells.len() {
+            let Some(parent) = tree.parent[i] else {
+                return false;
+            };
+            let (p, q) = (tree.cells[parent], tree.cells[i]);
+            if self.clear_cost(n, p, q).is_none() {
+                return false;
+            }
+            self.claim(n, route, &[p, q]);
+        }
+        true
+    }
+
+    /// The cheapest path from any cell of a tree to `sink`, starting with
+    /// the tree cell it leaves from, where `cost` prices each step and
+    /// rules out those it gives no price.
+    fn search(
+        &self,
+        tree: &[Pos],
+        sink: Pos,
+        mut cost: impl FnMut(Pos, Pos) -> Option<u32>,
+    ) -> Option<Vec<Pos>> {
+        let h =
+            |p: Pos| ((p.0 - sink.0).abs() + (p.1 - sink.1).abs() + (p.2 - sink.2).abs()) as u32;
+        let mut open = BinaryHeap::new();
+        let mut best: HashMap<Pos, (u32, Option<Pos>)> = HashMap::new();
+        for &p in tree {
+            best.insert(p, (0, None));
+            open.push(Reverse((h(p), 0, p)));
+        }
+        while let Some(Reverse((_, g, p))) = open.pop() {
+            if p == sink {
+                let mut path = vec![p];
+                let mut at = p;
+                while let Some(&(_, Some(prev))) = best.get(&at) {
+                    path.push(prev);
+                    at = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let (b, from) = best[&p];
+            if g > b {
+                continue;
+            }
+            // A torch ladder goes on straight from a flat step into it.
+            let flat = |f: &Pos| f.1 == p.1 && (p.0 - f.0).abs() + (p.2 - f.2).abs() == 1;
+            let ladders = from.filter(flat).into_iter().flat_map(|f| {
+                let (dx, dz) = (p.0 - f.0, p.2 - f.2);
+                LADDER_RISES.map(|rise| (p.0 + 2 * dx, p.1 + rise, p.2 + 2 * dz))
+            });
+            // A block on, flat or a level up or down, or over a bridge.
+            let reach = [(1, 0), (1, 1), (1, -1), (4, 0)];
+            let steps = DIRS
+                .iter()
+                .flat_map(|&(dx, dz)| reach.map(|(k, dy)| (p.0 + k * dx, p.1 + dy, p.2 + k * dz)));
+            for q in steps.chain(ladders) {
+                let Some(step) = cost(p, q) else {
+                    continue;
+                };
+                let cost = g.saturating_add(step);
+                if best.get(&q).is_none_or(|&(b, _)| cost < b) {
+                    best.insert(q, (cost, Some(p)));
+                    open.push(Reverse((cost.saturating_add(h(q)), cost, q)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Search for a path from net `n`'s tree to `sink` priced by `cost`
+    /// and lay it down step by step. A path can get in its own way, as a
+    /// staircase turning back under itself does, which the search cannot
+    /// see; then the path is taken back up and searched for again without
+    /// the step it got stuck at.
+    fn connect(
+        &mut self,
+        n: usize,
+        route: &mut Route,
+        sink: Pos,
+        cost: impl Fn(&Self, Pos, Pos) -> Option<u32>,
+    ) -> bool {
+        let (mark, len) = (self.claimed[n].len(), route.cells.len());
+        let mut banned = HashSet::new();
+        for _ in 0..CONNECT_TRIES {
+            let allowed = |p, q| {
+                if banned.contains(&(p, q)) {
+                    None
+                } else {
+                    cost(self, p, q)
+                }
+            };
+            let Some(path) = self.search(&route.starts(), sink, allowed) else {
+                // Start over from the tree as it was, if a path laid part
+                // of the way shut the sink off.
+                if route.cells.len() == len {
+                    break;
+                }
+                self.unclaim(n, route, mark, len);
+                continue;
+            };
+            let mut stuck = None;
+            for pair in path.windows(2) {
+                if self.clear_cost(n, pair[0], pair[1]).is_none() {
+                    stuck = Some((pair[0], pair[1]));
+                    break;
+                }
+                self.claim(n, route, pair);
+            }
+            let Some(step) = stuck else {
+                return true;
+            };
+            banned.insert(step);
+        }
+        self.unclaim(n, route, mark, len);
+        false
+    }
+
+    /// Take back what net `n` claimed since it held `mark` cells and its
+    /// tree had `len`.
+    fn unclaim(&mut self, n: usize, route: &mut Route, mark: usize, len: usize) {
+        for p in self.claimed[n].split_off(mark) {
+            self.set(p, Cell::Free);
+        }
+        route.cells.truncate(len);
+        route.parent.truncate(len);
+    }
+
+    /// Mark a found path as net `n`'s and add it to the net's tree, with
+    /// each bridge on it as the hops it is made of.
+    fn claim(&mut self, n: usize, route: &mut Route, path: &[Pos]) {
+        let mut hops = vec![path[0]];
+        for pair in path.windows(2) {
+            hops.extend(bridge(pair[0], pair[1]).into_iter().flatten());
+            hops.push(pair[1]);
+        }
+        let path = &hops[..];
+        let mut hold = |cell: Pos, as_cell: Cell| {
+            if self.at(cell) == Cell::Free {
+                self.set(cell, as_cell);
+                self.claimed[n].push(cell);
+            }
+        };
+        for pair in path.windows(2) {
+            let (p, q) = (pair[0], pair[1]);
+            if let Some((c, rise)) = ladder(p, q) {
+                for cell in column(c, rise) {
+                    hold(cell, Cell::Ladder(n));
+                }
+            } else if q.1 != p.1 {
+                hold(via_air(p, q), Cell::Clear(n));
+            }
+            hold(q, Cell::Dust(n));
+            hold((q.0, q.1 - 1, q.2), Cell::Support(n));
+        }
+        route.extend(path);
+    }
+}
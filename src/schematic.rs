@@ -1,269 +1,354 @@
-use crate::compiler::Circuit;
+use crate::anvil;
+use crate::bedrock;
+use crate::compiler::{Circuit, Gate, SEQUENTIAL_KINDS};
+use crate::datapack;
 use crate::layout::Layout;
-use crate::primitives::primitive_for;
+use crate::legacy;
+use crate::primitives::{cost, input_strength, output_strength, primitive_for, FLOOR_BLOCK};
+use crate::router::{self, Footprint, Net, Route};
 use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use nbt::{Map, Value};
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 // Routing constants
 const REDSTONE_SIGNAL_LIMIT: i32 = 15;
-const REPEATER_THRESHOLD: i32 = 14;
+/// Strength to spare at which a repeater still goes in beside one on the
+/// previous bit of a bus.
+const REPEATER_ALIGN_SLACK: i32 = 4;
+/// Powered rails in one instant-wire segment: a rail passes its power on
+/// to the next eight in line.
+const RAIL_RUN: usize = 9;
+/// Delay of a torch ladder's two torches.
+const LADDER_TICKS: i32 = 2;
 const WIRE_LANE_START_Y: i32 = 4;
 const WIRE_Y_SPACING: i32 = 2; // Vertical spacing between wire lanes
 
-pub fn write_schem(_circuit: &Circuit, _layout: &Layout, path: &Path) -> Result<()> {
-    let mut root_map = Map::new();
-    root_map.insert("SubVersion".to_string(), Value::Int(1));
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
-
-    let mut metadata = Map::new();
-    metadata.insert("Name".to_string(), Value::String("Unnamed".to_string()));
-    metadata.insert(
-        "Author".to_string(),
-        Value::String("schemlogica".to_string()),
-    );
-    metadata.insert("TimeCreated".to_string(), Value::Long(now));
-    metadata.insert("TimeModified".to_string(), Value::Long(now));
+/// Data version written into every output format.
+const DATA_VERSION: i32 = 4671;
+
+/// A placed block in world coordinates: (x, y, z, name, properties).
+type PlacedBlock = (i32, i32, i32, String, Option<Vec<(String, String)>>);
+
+/// A built circuit: every block in world coordinates, before it is
+/// written out in some file format.
+pub struct Blocks {
+    pub placed: Vec<PlacedBlock>,
+    /// Blocks of self-running primitives (e.g. clocks) that need a pending
+    /// tick so they start as soon as the schematic is pasted.
+    pub scheduled: Vec<(i32, i32, i32, String)>,
+    /// Routing problems worth reporting, also printed as they happen.
+    pub warnings: Vec<String>,
+    /// Each gate input the router could not wire up: the gate's id and the
+    /// warning about it.
+    pub unwired: Vec<(String, String)>,
+    /// Signs among the placed blocks, and the lines written on each.
+    pub signs: Vec<(i32, i32, i32, Vec<String>)>,
+}
 
-    let mut region = Map::new();
-    region.insert("Name".to_string(), Value::String("Unnamed".to_string()));
+/// Settings for the output writers.
+pub struct WriteOptions {
+    /// Sponge schematic version for `.schem` files: 2 or 3.
+    pub sponge_version: u8,
+    /// Name, author and description written into the schematic's
+    /// metadata, where the format has room for them.
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    /// World position of the circuit's low corner, for world outputs.
+    pub at: Option<(i32, i32, i32)>,
+    /// Read a `.litematic` back after writing it and check it holds the
+    /// placed blocks.
+    pub verify: bool,
+    /// Gzip level (0 to 9) of the NBT outputs, or `None` to leave them
+    /// uncompressed.
+    pub gzip: Option<u32>,
+    /// Write the NBT to stdout instead of the output file, which still
+    /// picks the format.
+    pub stdout: bool,
+}
 
-    let mut placed: Vec<(i32, i32, i32, String, Option<Vec<(String, String)>>)> = Vec::new();
-    let mut pos_map: HashMap<String, (i32, i32, i32)> = HashMap::new();
+/// Which nets the router takes first.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NetOrder {
+    /// In the order of the gates driving them.
+    Gates,
+    /// Smallest bounding box first, while short nets still have room.
+    Size,
+    /// On the longest gate-delay path first.
+    Critical,
+    /// Most sinks first.
+    Fanout,
+}
 
-    // Map layout positions
-    for (id, lx, ly, lz) in &_layout.positions {
-        pos_map.insert(id.clone(), (*lx, *ly, *lz));
+impl NetOrder {
+    pub fn parse(name: &str) -> Result<NetOrder> {
+        match name {
+            "gates" => Ok(NetOrder::Gates),
+            "size" => Ok(NetOrder::Size),
+            "critical" => Ok(NetOrder::Critical),
+            "fanout" => Ok(NetOrder::Fanout),
+            _ => anyhow::bail!(
+                "Unknown net order `{}` (expected gates, size, critical or fanout)",
+                name
+            ),
+        }
     }
+}
 
-    // Place primitives
-    // Helper functions that operate on the placed vector without capturing it
-    fn place_wire_fn(
-        placed: &mut Vec<(i32, i32, i32, String, Option<Vec<(String, String)>>)>,
-        x: i32,
-        y: i32,
-        z: i32,
-        dist: &mut i32,
-        facing: &str,
-    ) {
-        placed.push((x, y - 1, z, "minecraft:glass".to_string(), None)); // Support
-        *dist += 1;
-        if *dist >= REPEATER_THRESHOLD {
-            *dist = 0;
-            placed.push((
-                x,
-                y,
-                z,
-                "minecraft:repeater".to_string(),
-                Some(vec![("facing".to_string(), facing.to_string())]),
-            ));
-        } else {
-            placed.push((x, y, z, "minecraft:redstone_wire".to_string(), None));
-        }
+/// Concrete under each net's wires for `--color-nets`, taken in turn, in
+/// an order that keeps neighbouring nets easy to tell apart.
+const NET_COLORS: [&str; 16] = [
+    "minecraft:red_concrete",
+    "minecraft:blue_concrete",
+    "minecraft:lime_concrete",
+    "minecraft:yellow_concrete",
+    "minecraft:magenta_concrete",
+    "minecraft:cyan_concrete",
+    "minecraft:orange_concrete",
+    "minecraft:purple_concrete",
+    "minecraft:green_concrete",
+    "minecraft:pink_concrete",
+    "minecraft:light_blue_concrete",
+    "minecraft:brown_concrete",
+    "minecraft:white_concrete",
+    "minecraft:gray_concrete",
+    "minecraft:black_concrete",
+    "minecraft:light_gray_concrete",
+];
+
+/// Settings for building the blocks.
+pub struct BuildOptions {
+    /// Pad each gate's early inputs with repeater delay so they arrive
+    /// together, from `--equalize-delays`.
+    pub equalize_delays: bool,
+    /// Wire lanes above the ground for long nets, from `--wire-lanes`.
+    pub wire_lanes: usize,
+    /// Route length past which a net carries its level over instant-wire
+    /// segments, from `--instant-wire`.
+    pub instant_wire: Option<usize>,
+    /// Let wires pass beneath the gates, from `--tunnels`.
+    pub tunnels: bool,
+    /// Which nets are routed first, from `--net-order`.
+    pub net_order: NetOrder,
+    /// Write a build whose wires short anyway, from `--allow-shorts`.
+    pub allow_shorts: bool,
+    /// Leave inputs unwired instead of failing, from `--allow-unwired`.
+    pub allow_unwired: bool,
+    /// Block under the wires, from `--support-block`.
+    pub support_block: String,
+    /// Block under the gates and where wires drop, from `--floor-block`.
+    pub floor_block: String,
+    /// Put each net's wires on concrete of its own colour, from
+    /// `--color-nets`.
+    pub color_nets: bool,
+    /// Label the levers and outputs with signs, unless `--no-labels`.
+    pub labels: bool,
+    /// Light a redstone lamp with each output, unless `--no-lamps`.
+    pub lamps: bool,
+}
+
+/// Whether a block conducts redstone power: a solid, opaque one, not glass
+/// or a component.
+fn conducts(name: &str) -> bool {
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    let clear = [
+        "air",
+        "glass",
+        "slab",
+        "stairs",
+        "leaves",
+        "glowstone",
+        "sea_lantern",
+        "redstone_wire",
+        "redstone_block",
+        "torch",
+        "repeater",
+        "comparator",
+        "lever",
+        "button",
+        "pressure_plate",
+        "sign",
+        "door",
+        "piston",
+        "observer",
+        "hopper",
+        "slime",
+        "honey",
+        "carpet",
+        "fence",
+        "lantern",
+    ];
+    name != "ice" && !clear.iter().any(|c| name.contains(c))
+}
+
+/// Parse a block id for `--support-block` or `--floor-block`, e.g.
+/// `white_concrete`; an id without a namespace is Minecraft's.
+pub fn parse_block(text: &str) -> Result<String> {
+    let valid = |s: &str| {
+        let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-./".contains(c);
+        !s.is_empty() && s.chars().all(allowed)
+    };
+    match text.split_once(':') {
+        Some((space, name)) if valid(space) && valid(name) => Ok(text.to_string()),
+        None if valid(text) => Ok(format!("minecraft:{}", text)),
+        _ => anyhow::bail!("Bad block id `{}` (expected e.g. white_concrete)", text),
     }
+}
 
-    fn build_stairs_fn(
-        placed: &mut Vec<(i32, i32, i32, String, Option<Vec<(String, String)>>)>,
-        x: i32,
-        y_start: i32,
-        y_end: i32,
-        z_start: i32,
-    ) -> i32 {
-        let mut cy = y_start;
-        let mut cz = z_start;
-        let dy = if y_end > y_start { 1 } else { -1 };
+/// Output formats, chosen by the output file's extension.
+pub fn write_schem(blocks: &Blocks, path: &Path, opts: &WriteOptions) -> Result<()> {
+    // A directory holding `level.dat` is a world save.
+    if path.join("level.dat").is_file() {
+        let Some(at) = opts.at else {
+            anyhow::bail!("writing into a world needs --at X,Y,Z");
+        };
+        return anvil::write(blocks, path, at);
+    }
+    if opts.at.is_some() {
+        anyhow::bail!("--at only applies when the output is a world directory");
+    }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("schem") => write_sponge(blocks, path, opts),
+        Some("nbt") => write_structure(blocks, path, opts),
+        Some("schematic") => write_legacy(blocks, path, opts),
+        Some("mcfunction") => write_mcfunction(blocks, path),
+        Some("zip") => datapack::write(blocks, path),
+        Some("mcstructure") => bedrock::write(blocks, path),
+        _ => write_litematic(blocks, path, opts),
+    }
+}
 
-        while cy != y_end {
-            // To move 1 Y, we must move 1 horizontally (Z).
-            // Step 1: Wire at current
-            placed.push((x, cy - 1, cz, "minecraft:glass".to_string(), None));
-            placed.push((x, cy, cz, "minecraft:redstone_wire".to_string(), None));
+/// Place every primitive and route the nets between them.
+pub fn build_blocks(_circuit: &Circuit, _layout: &Layout, opts: &BuildOptions) -> Result<Blocks> {
+    let mut placed: Vec<PlacedBlock> = Vec::new();
+    let mut pos_map: HashMap<String, (i32, i32, i32)> = HashMap::new();
 
-            // Step 2: Move Z and Y
-            cy += dy;
-            cz += 1; // Always move Z+ to avoid self-collision
-        }
-        // Final placement at target height
-        placed.push((x, cy - 1, cz, "minecraft:glass".to_string(), None));
-        placed.push((x, cy, cz, "minecraft:redstone_wire".to_string(), None));
-        cz
+    // Map layout positions
+    for (id, lx, ly, lz) in &_layout.positions {
+        pos_map.insert(id.clone(), (*lx, *ly, *lz));
     }
 
+    // Place primitives
+    let mut scheduled: Vec<(i32, i32, i32, String)> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let mut footprints: Vec<Footprint> = Vec::new();
+    let mut footprint_of: HashMap<&str, usize> = HashMap::new();
+    let mut signs = Vec::new();
+    let labels = if opts.labels {
+        io_labels(_circuit)
+    } else {
+        HashMap::new()
+    };
     for g in &_circuit.gates {
         if let Some(&(gx, gy, gz)) = pos_map.get(&g.id) {
-            let prim = primitive_for(&g.kind);
+            let prim = primitive_for(&g.kind, &g.params);
+            let mut cells = Vec::new();
             for b in prim.blocks.iter() {
                 let ax = gx + b.x;
                 let ay = gy + b.y;
                 let az = gz + b.z;
-                placed.push((ax, ay, az, b.name.clone(), b.properties.clone()));
+                let name = if b.name == FLOOR_BLOCK {
+                    opts.floor_block.clone()
+                } else {
+                    b.name.clone()
+                };
+                placed.push((ax, ay, az, name, b.properties.clone()));
+                cells.push((ax, ay, az));
+                if b.scheduled {
+                    scheduled.push((ax, ay, az, b.name.clone()));
+                }
             }
+            // The sign is part of the gate, so no wire takes its place.
+            if let Some(lines) = labels.get(g.id.as_str()) {
+                let (x, y, z) = if g.kind == "INPUT" {
+                    (gx, gy + 1, gz - 1)
+                } else {
+                    (gx + prim.output_port.0, gy + 1, gz + prim.size_z)
+                };
+                let inside = _layout.area.is_none_or(|(ax, az, bx, bz)| {
+                    (ax..=bx).contains(&x) && (az..=bz).contains(&z)
+                });
+                if inside {
+                    if g.kind == "INPUT" {
+                        let props = vec![("facing".to_string(), "north".to_string())];
+                        placed.push((x, y, z, "minecraft:oak_wall_sign".into(), Some(props)));
+                    } else {
+                        let props = vec![("rotation".to_string(), "0".to_string())];
+                        placed.push((x, y, z, "minecraft:oak_sign".into(), Some(props)));
+                        placed.push((x, y - 1, z, opts.floor_block.clone(), None));
+                        cells.push((x, y - 1, z));
+                    }
+                    cells.push((x, y, z));
+                    signs.push((x, y, z, lines.clone()));
+                }
+            }
+            footprint_of.insert(&g.id, footprints.len());
+            footprints.push(Footprint {
+                at: (gx, gy, gz),
+                size: (prim.size_x, prim.size_y, prim.size_z),
+                blocks: cells,
+            });
         }
     }
 
     // Routing
     let mut signal_output_pos: HashMap<String, (i32, i32, i32)> = HashMap::new();
     let mut signal_source_gate: HashMap<String, String> = HashMap::new();
+    let mut signal_strength: HashMap<String, i32> = HashMap::new();
     for g in &_circuit.gates {
         if let Some(&(gx, gy, gz)) = pos_map.get(&g.id) {
-            let prim = primitive_for(&g.kind);
+            let prim = primitive_for(&g.kind, &g.params);
             let (ox, oy, oz) = prim.output_port;
             signal_output_pos.insert(g.output.clone(), (gx + ox, gy + oy, gz + oz));
             signal_source_gate.insert(g.output.clone(), g.id.clone());
+            signal_strength.insert(g.output.clone(), output_strength(&g.kind, &g.params));
         }
     }
 
-    // --- Flat Routing Strategy ---
-    // Use A* pathfinding to route wires on the ground (Y=1) around obstacles.
-    // 1. Mark all gate blocks as obstacles.
-    // 2. Route wires sequentially using A*.
-    // 3. Mark placed wires as new obstacles.
-
-    // Grid management
-    let mut grid_obstacles: std::collections::HashSet<(i32, i32)> =
-        std::collections::HashSet::new();
-
-    // Mark gates as obstacles
+    // Collect one net per driven signal, each feeding the input ports
+    // that read it, in gate order.
+    let mut nets: Vec<Net> = Vec::new();
+    let mut net_of: HashMap<&str, usize> = HashMap::new();
+    // Signal name of each net, and the gate of each of its sinks.
+    let mut net_names: Vec<&str> = Vec::new();
+    let mut sink_gates: Vec<Vec<&str>> = Vec::new();
+    // Signal strength leaving each net's driver, and what each sink needs.
+    let mut net_strengths: Vec<i32> = Vec::new();
+    let mut sink_strengths: Vec<Vec<i32>> = Vec::new();
+    // Each gate's routed inputs: (input, net, sink) indices.
+    let mut gate_sinks: HashMap<&str, Vec<(usize, usize, usize)>> = HashMap::new();
     for g in &_circuit.gates {
         if let Some(&(gx, gy, gz)) = pos_map.get(&g.id) {
-            let prim = primitive_for(&g.kind);
-            // Mark the footprint. previously we added a 1-block negative padding
-            // around primitives which caused ports to be embedded inside obstacles.
-            // Reduce padding to 0 to give ports more room (helps routing).
-            let pad_x_before = 0; // was -1
-            let pad_z_before = 0; // was -1
-            for x in pad_x_before..=prim.size_x {
-                for z in pad_z_before..=prim.size_z {
-                    grid_obstacles.insert((gx + x, gz + z));
-                }
-            }
-        }
-    }
-
-    #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
-    struct Point {
-        x: i32,
-        z: i32,
-    }
-
-    impl Point {
-        fn dist(&self, other: &Point) -> i32 {
-            (self.x - other.x).abs() + (self.z - other.z).abs()
-        }
-    }
-
-    // A* Pathfinding
-    fn find_path(
-        start: Point,
-        end: Point,
-        obstacles: &std::collections::HashSet<(i32, i32)>,
-    ) -> Option<Vec<Point>> {
-        use std::cmp::Reverse;
-        use std::collections::BinaryHeap;
-
-        // Priority queue holds (cost+heuristic, cost, point)
-        let mut open_set = BinaryHeap::new();
-        open_set.push(Reverse((0, 0, start)));
-
-        let mut came_from: HashMap<Point, Point> = HashMap::new();
-        let mut g_score: HashMap<Point, i32> = HashMap::new();
-        g_score.insert(start, 0);
-
-        let mut close_set = std::collections::HashSet::new();
-
-        // Safety Break (don't search forever)
-        let max_steps = 10000;
-        let mut steps = 0;
-
-        while let Some(Reverse((_, current_g, current))) = open_set.pop() {
-            steps += 1;
-            // if steps > max_steps { return None; } // remove limit for reliable outputs
-
-            if current == end {
-                // Reconstruct path
-                let mut path = vec![current];
-                let mut curr = current;
-                while let Some(&prev) = came_from.get(&curr) {
-                    path.push(prev);
-                    curr = prev;
-                }
-                path.reverse();
-                return Some(path);
-            }
-
-            close_set.insert(current);
-
-            // Neighbors (4 directions)
-            let neighbors = [
-                Point {
-                    x: current.x + 1,
-                    z: current.z,
-                },
-                Point {
-                    x: current.x - 1,
-                    z: current.z,
-                },
-                Point {
-                    x: current.x,
-                    z: current.z + 1,
-                },
-                Point {
-                    x: current.x,
-                    z: current.z - 1,
-                },
-            ];
-
-            for &next in &neighbors {
-                if close_set.contains(&next) {
-                    continue;
-                }
-
-                // Check obstacles (except for end point, which might be "in" a gate port)
-                if next != end && obstacles.contains(&(next.x, next.z)) {
-                    continue;
-                }
-
-                let tentative_g = current_g + 1;
-
-                if tentative_g < *g_score.get(&next).unwrap_or(&i32::MAX) {
-                    came_from.insert(next, current);
-                    g_score.insert(next, tentative_g);
-                    let f_score = tentative_g + next.dist(&end);
-                    open_set.push(Reverse((f_score, tentative_g, next)));
-                }
-            }
-        }
-        None
-    }
-
-    // Collect signals
-    struct Connection {
-        src: Point,
-        dst: Point,
-        src_y: i32,
-        dst_y: i32,
-    }
-    let mut connections = Vec::new();
-
-    for g in &_circuit.gates {
-        if let Some(&(gx, gy, gz)) = pos_map.get(&g.id) {
-            let prim = primitive_for(&g.kind);
+            let prim = primitive_for(&g.kind, &g.params);
             for (i_idx, in_port) in prim.input_ports.iter().enumerate() {
                 if let Some(src_sig) = g.inputs.get(i_idx) {
                     if let Some(&(sx, sy, sz)) = signal_output_pos.get(src_sig) {
                         let (ix, iy, iz) = (gx + in_port.0, gy + in_port.1, gz + in_port.2);
-                        connections.push(Connection {
-                            src: Point { x: sx, z: sz },
-                            dst: Point { x: ix, z: iz },
-                            src_y: sy,
-                            dst_y: iy,
+                        let n = *net_of.entry(src_sig).or_insert_with(|| {
+                            nets.push(Net {
+                                driver: footprint_of[signal_source_gate[src_sig].as_str()],
+                                source: (sx, sy, sz),
+                                sinks: Vec::new(),
+                                follows: None,
+                            });
+                            net_names.push(src_sig);
+                            sink_gates.push(Vec::new());
+                            net_strengths.push(signal_strength[src_sig]);
+                            sink_strengths.push(Vec::new());
+                            nets.len() - 1
                         });
+                        nets[n].sinks.push((ix, iy, iz));
+                        sink_gates[n].push(&g.id);
+                        sink_strengths[n].push(input_strength(&g.kind));
+                        let s = nets[n].sinks.len() - 1;
+                        gate_sinks.entry(&g.id).or_default().push((i_idx, n, s));
 
                         // Diagnostic: if the Manhattan distance is large, print details
                         let manhattan = (sx - ix).abs() + (sz - iz).abs();
@@ -272,8 +357,10 @@ pub fn write_schem(_circuit: &Circuit, _layout: &Layout, path: &Path) -> Result<
                                 .get(src_sig)
                                 .cloned()
                                 .unwrap_or("<unknown>".to_string());
-                            eprintln!("Long connection (distance {}) for signal '{}' from gate '{}' @ ({},{}) to gate '{}' @ ({},{})",
+                            let warning = format!("Long connection (distance {}) for signal '{}' from gate '{}' @ ({},{}) to gate '{}' @ ({},{})",
                                 manhattan, src_sig, src_gate, sx, sz, g.id, ix, iz);
+                            eprintln!("{}", warning);
+                            warnings.push(warning);
                         }
                     }
                 }
@@ -281,426 +368,1054 @@ pub fn write_schem(_circuit: &Circuit, _layout: &Layout, path: &Path) -> Result<
         }
     }
 
-    // Sort connections by length (heuristic) to route short ones first?
-    // Or maybe route long ones first?
-    // Let's just route in order.
+    // An output nothing else reads still gets a net, for its exit run to
+    // end in a lamp.
+    if opts.lamps {
+        for out in &_circuit.outputs {
+            if net_of.contains_key(out.as_str()) {
+                continue;
+            }
+            let Some(&source) = signal_output_pos.get(out) else {
+                continue;
+            };
+            net_of.insert(out, nets.len());
+            nets.push(Net {
+                driver: footprint_of[signal_source_gate[out].as_str()],
+                source,
+                sinks: Vec::new(),
+                follows: None,
+            });
+            net_names.push(out);
+            sink_gates.push(Vec::new());
+            net_strengths.push(signal_strength[out]);
+            sink_strengths.push(Vec::new());
+        }
+    }
 
-    for conn in connections {
-        // Clear obstacles at start/end to ensure connectivity
-        // (Sometimes ports are inside the "block footprint" padding)
-        // Actually, find_path already allows end point.
+    // Each bit of a bus runs alongside the bit before it.
+    let mut buses: HashMap<&str, Vec<(usize, usize)>> = HashMap::new();
+    for (n, name) in net_names.iter().enumerate() {
+        if let Some((base, bit)) = bus_bit(name) {
+            buses.entry(base).or_default().push((bit, n));
+        }
+    }
+    for bits in buses.values_mut() {
+        bits.sort();
+        for pair in bits.windows(2) {
+            nets[pair[1].1].follows = Some(pair[0].1);
+        }
+    }
 
-        // Allow start/end positions to be considered free even if they lie inside
-        // the padded gate footprints. Clone the obstacle set and clear the endpoints
-        // so A* can start or finish inside what was marked as an obstacle.
-        let mut local_obs = grid_obstacles.clone();
-        local_obs.remove(&(conn.src.x, conn.src.z));
-        local_obs.remove(&(conn.dst.x, conn.dst.z));
+    // Wires run on the ground (Y=1 above the layout's origin) and climb
+    // over each other, up to ports on upper layers or up to the wire lanes
+    // of long nets, on glass vias. Under --tunnels they may also drop to
+    // Y=-1, beneath the gates' floors.
+    let ground = _layout.origin.1 + 1;
+    let lanes: Vec<i32> = (0..opts.wire_lanes as i32)
+        .map(|k| _layout.origin.1 + WIRE_LANE_START_Y + k * WIRE_Y_SPACING)
+        .collect();
+    let tunnel = opts.tunnels.then_some(_layout.origin.1 - 1);
+    let ranked = rank_nets(opts.net_order, _circuit, &nets, &net_names);
+    let order = router::bus_order(&nets, &ranked);
+    let area = _layout.area;
+    let routing = router::route(&footprints, &nets, area, ground, &lanes, tunnel, &order);
+    let unwired: Vec<(String, String)> = routing
+        .failed
+        .iter()
+        .map(|&(n, s)| {
+            let (x, y, z) = nets[n].sinks[s];
+            let gate = sink_gates[n][s];
+            let warning = format!(
+                "No path found for signal '{}' to gate '{}' @ ({},{},{})",
+                net_names[n], gate, x, y, z
+            );
+            (gate.to_string(), warning)
+        })
+        .collect();
+    // Without --allow-unwired, check_wired fails with these instead.
+    for (_, warning) in &unwired {
+        if opts.allow_unwired {
+            eprintln!("Warning: {}", warning);
+        }
+        warnings.push(warning.clone());
+    }
+    let mut laid: Vec<Option<Laid>> = (0..nets.len()).map(|_| None).collect();
+    for &n in &order {
+        // A bus bit puts its repeaters in line with the bit before it where
+        // it can, so the bus keeps its timing together.
+        let mut aligned = HashSet::new();
+        if let Some(m) = nets[n].follows {
+            for &b in &laid[m].as_ref().unwrap().blocks {
+                let (x, y, z, name, _) = &placed[b];
+                if name == "minecraft:repeater" {
+                    let (x, y, z) = (*x, *y, *z);
+                    aligned.extend([(x + 2, y, z), (x - 2, y, z), (x, y, z + 2), (x, y, z - 2)]);
+                }
+            }
+        }
+        let route = &routing.routes[n];
+        let (mut sinks, mut needs) = (nets[n].sinks.clone(), sink_strengths[n].clone());
+        // Dust lights the block under it whichever way it runs, so a lamp
+        // in place of the support under the end of an output's exit run
+        // shows the output. That dust is kept like a sink's.
+        let terminal = routing.terminals[n];
+        let output = _circuit.outputs.iter().any(|o| o == net_names[n]);
+        if opts.lamps && output && terminal != nets[n].source {
+            let (x, y, z) = terminal;
+            placed.push((x, y - 1, z, "minecraft:redstone_lamp".to_string(), None));
+            sinks.push(terminal);
+            needs.push(1);
+        }
+        let support = if opts.color_nets {
+            NET_COLORS[n % NET_COLORS.len()]
+        } else {
+            &opts.support_block
+        };
+        // Dust a level down only reads dust dropping to it off a block that
+        // conducts, so that dust goes on the floor block if the support is
+        // clear.
+        let drop = if conducts(support) {
+            support
+        } else {
+            &opts.floor_block
+        };
+        let mut l = place_route(
+            &mut placed,
+            route,
+            &sinks,
+            net_strengths[n],
+            &needs,
+            &aligned,
+            (support, drop),
+        );
+        if opts.instant_wire.is_some_and(|n| route.cells.len() > n) {
+            lay_instant_wire(&mut placed, route, &mut l);
+        }
+        if !l.strong {
+            let warning = format!(
+                "Signal '{}' runs out of strength before every gate it feeds",
+                net_names[n]
+            );
+            eprintln!("Warning: {}", warning);
+            warnings.push(warning);
+        }
+        laid[n] = Some(l);
+    }
+    let laid: Vec<Laid> = laid.into_iter().flatten().collect();
+
+    if opts.equalize_delays {
+        let routes = &routing.routes;
+        let short = equalize_delays(_circuit, &nets, routes, &laid, &gate_sinks, &mut placed);
+        for (gate, ticks) in short {
+            let warning = format!(
+                "Inputs of gate '{}' still arrive up to {} ticks apart; their wires have no room for more repeaters",
+                gate, ticks
+            );
+            eprintln!("Warning: {}", warning);
+            warnings.push(warning);
+        }
+    }
 
-        if let Some(path) = find_path(conn.src, conn.dst, &local_obs) {
-            // Place path
-            let mut signal_dist = 0;
+    // POST-PROCESSING: Calculate redstone wire connections
+    // Redstone wire needs north/south/east/west properties to connect properly,
+    // worked out the way the game does so they hold when it updates them.
+    fn calculate_redstone_connections(placed: &mut [PlacedBlock]) {
+        // The first block placed at a position is the one built.
+        let mut block_map: HashMap<(i32, i32, i32), usize> = HashMap::new();
+        for (idx, (x, y, z, _, _)) in placed.iter().enumerate() {
+            block_map.entry((*x, *y, *z)).or_insert(idx);
+        }
+        let name_at = |placed: &[PlacedBlock], p: (i32, i32, i32)| {
+            block_map.get(&p).map(|&i| placed[i].3.clone())
+        };
+        let is_wire = |name: &Option<String>| name.as_deref() == Some("minecraft:redstone_wire");
+
+        // Whether wire joins a block beside it along the X or Z axis:
+        // repeaters only at their ends, other signal sources on any side.
+        fn joins((_, _, _, name, props): &PlacedBlock, along_x: bool) -> bool {
+            if name.contains("repeater") {
+                let facing = props.iter().flatten().find(|(k, _)| k == "facing");
+                return match facing.map(|(_, v)| v.as_str()) {
+                    Some("east" | "west") => along_x,
+                    _ => !along_x,
+                };
+            }
+            let sources = [
+                "redstone_wire",
+                "redstone_torch",
+                "redstone_wall_torch",
+                "redstone_block",
+                "comparator",
+                "lever",
+                "_button",
+                "_pressure_plate",
+            ];
+            sources.iter().any(|s| name.contains(s))
+        }
 
-            for (idx, p) in path.iter().enumerate() {
-                // Determine direction for repeaters
-                let facing = if idx + 1 < path.len() {
-                    let next = path[idx + 1];
-                    if next.x > p.x {
-                        "east"
-                    } else if next.x < p.x {
-                        "west"
-                    } else if next.z > p.z {
-                        "south"
-                    } else {
-                        "north"
-                    }
+        for idx in 0..placed.len() {
+            if placed[idx].3 != "minecraft:redstone_wire" {
+                continue;
+            }
+            let (x, y, z, _, _) = placed[idx];
+            let covered = name_at(placed, (x, y + 1, z)).is_some_and(|b| conducts(&b));
+            let mut sides = Vec::new();
+            for (dir, dx, dz) in [
+                ("north", 0, -1),
+                ("south", 0, 1),
+                ("east", 1, 0),
+                ("west", -1, 0),
+            ] {
+                let side_pos = (x + dx, y, z + dz);
+                let side = block_map.get(&side_pos).map(|&i| &placed[i]);
+                // Dust climbs onto a block beside it that holds dust, unless
+                // a conductor over this dust cuts it off; it drops down past
+                // anything that does not conduct.
+                let holds_dust = side.is_some_and(|b| conducts(&b.3) || b.3.contains("glass"));
+                let up = is_wire(&name_at(placed, (x + dx, y + 1, z + dz)));
+                let down = is_wire(&name_at(placed, (x + dx, y - 1, z + dz)));
+                let side_conducts = side.is_some_and(|b| conducts(&b.3));
+                let connection = if !covered && holds_dust && up {
+                    Some("up")
+                } else if side.is_some_and(|b| joins(b, dx != 0)) || !side_conducts && down {
+                    Some("side")
                 } else {
-                    "north" // default
+                    None
                 };
+                sides.push((dir, connection));
+            }
+            // Dust joined on one axis only runs straight along it, and dust
+            // joined nowhere is a cross.
+            let joined = |d: &str| sides.iter().any(|&(s, c)| s == d && c.is_some());
+            let north_south = joined("north") || joined("south");
+            let east_west = joined("east") || joined("west");
+            let connections = sides
+                .iter()
+                .map(|&(dir, connection)| {
+                    let across = match dir {
+                        "north" | "south" => !east_west,
+                        _ => !north_south,
+                    };
+                    let value = connection.unwrap_or(if across { "side" } else { "none" });
+                    (dir.to_string(), value.to_string())
+                })
+                .collect();
+            placed[idx].4 = Some(connections);
+        }
+    }
 
-                // Add to obstacles for future wires
-                grid_obstacles.insert((p.x, p.z));
-
-                // Place wire or repeater
-                // Don't place on top of start/end if they are higher up?
-                // Logic:
-                // If this is the START point:
-                //   If src_y > 1, we need to bridge down.
-                //   The path[0] is at (src_x, src_z) at Y=1.
-                //   We need to ensure connection from (src_x, src_y, src_z) to (src_x, 1, src_z).
-
-                let is_start = idx == 0;
-                let is_end = idx == path.len() - 1;
-
-                place_wire_fn(&mut placed, p.x, 1, p.z, &mut signal_dist, facing);
-
-                // Handle vertical transitions at endpoints
-                if is_start && conn.src_y > 1 {
-                    // Vertical drop from src_y to 1
-                    let mut cy = conn.src_y;
-                    while cy > 1 {
-                        placed.push((p.x, cy - 1, p.z, "minecraft:glass".to_string(), None));
-                        placed.push((p.x, cy, p.z, "minecraft:redstone_wire".to_string(), None));
-                        cy -= 1;
-                    }
-                }
+    // Apply redstone wire connections
+    calculate_redstone_connections(&mut placed);
 
-                if is_end && conn.dst_y > 1 {
-                    // Vertical rise from 1 to dst_y
-                    let mut cy = 1;
-                    while cy < conn.dst_y {
-                        placed.push((p.x, cy, p.z, "minecraft:glass".to_string(), None)); // Step support
-                        placed.push((
-                            p.x,
-                            cy + 1,
-                            p.z,
-                            "minecraft:redstone_wire".to_string(),
-                            None,
-                        ));
-                        cy += 1;
-                    }
-                }
-            }
-        } else {
-            // Retry with a relaxed obstacle set: clear a 1-block neighborhood around
-            // start and end. This lets the router carve a short tunnel through padding
-            // when ports are only slightly embedded in obstacles.
-            let mut relaxed = grid_obstacles.clone();
-            for dx in -1..=1 {
-                for dz in -1..=1 {
-                    relaxed.remove(&(conn.src.x + dx, conn.src.z + dz));
-                    relaxed.remove(&(conn.dst.x + dx, conn.dst.z + dz));
-                }
-            }
+    let dead: Vec<String> = find_dead_drops(&routing.routes, &placed)
+        .into_iter()
+        .map(|(n, (x, y, z))| {
+            let name = net_names[n];
+            format!("Signal '{}' drops a level at ({},{},{})", name, x, y, z)
+        })
+        .collect();
+    if !dead.is_empty() {
+        anyhow::bail!(
+            "Wires drop a level off blocks that do not conduct, which cuts the signal off (--floor-block must be solid):\n{}",
+            dead.join("\n")
+        );
+    }
 
-            if let Some(path) = find_path(conn.src, conn.dst, &relaxed) {
-                let mut signal_dist = 0;
-                for (idx, p) in path.iter().enumerate() {
-                    let facing = if idx + 1 < path.len() {
-                        let next = path[idx + 1];
-                        if next.x > p.x {
-                            "east"
-                        } else if next.x < p.x {
-                            "west"
-                        } else if next.z > p.z {
-                            "south"
-                        } else {
-                            "north"
-                        }
-                    } else {
-                        "north"
-                    };
+    // Each gate's blocks carry the signals of its ports, its own output's
+    // first.
+    let mut gate_nets: HashMap<(i32, i32, i32), Vec<usize>> = HashMap::new();
+    for g in &_circuit.gates {
+        let Some(&f) = footprint_of.get(g.id.as_str()) else {
+            continue;
+        };
+        let output = net_of.get(g.output.as_str()).copied();
+        let inputs = gate_sinks.get(g.id.as_str()).into_iter().flatten();
+        let mut ports: Vec<usize> = output.into_iter().collect();
+        ports.extend(inputs.map(|&(_, n, _)| n));
+        ports.dedup();
+        if ports.is_empty() {
+            continue;
+        }
+        for &cell in &footprints[f].blocks {
+            gate_nets.insert(cell, ports.clone());
+        }
+    }
+    let shorts: Vec<String> = find_shorts(&routing.routes, &gate_nets, &placed)
+        .into_iter()
+        .map(|(n, m, (x, y, z))| {
+            let (a, b) = (net_names[n], net_names[m]);
+            format!("Signals '{}' and '{}' short at ({},{},{})", a, b, x, y, z)
+        })
+        .collect();
+    if !shorts.is_empty() && !opts.allow_shorts {
+        anyhow::bail!(
+            "Wires of different signals connect (--allow-shorts writes the build anyway):\n{}",
+            shorts.join("\n")
+        );
+    }
+    for warning in shorts {
+        eprintln!("Warning: {}", warning);
+        warnings.push(warning);
+    }
+    Ok(Blocks {
+        placed,
+        scheduled,
+        warnings,
+        unwired,
+        signs,
+    })
+}
 
-                    // Mark and place
-                    grid_obstacles.insert((p.x, p.z));
-                    place_wire_fn(&mut placed, p.x, 1, p.z, &mut signal_dist, facing);
-                }
-            } else {
-                // Final fallback: emit debug info and try a straight Manhattan carve
-                eprintln!(
-                    "Warning: No path found for connection {:?} -> {:?}",
-                    conn.src, conn.dst
-                );
+/// The lines of the sign beside each INPUT lever and each gate driving an
+/// output, by gate id: the input's name, or the names of the outputs.
+fn io_labels(circuit: &Circuit) -> HashMap<&str, Vec<String>> {
+    let mut labels: HashMap<&str, Vec<String>> = HashMap::new();
+    for g in circuit.gates.iter().filter(|g| g.kind == "INPUT") {
+        let name = g.id.strip_prefix("in_").unwrap_or(&g.id);
+        labels.insert(&g.id, vec![name.to_string()]);
+    }
+    let driver: HashMap<&str, &Gate> = circuit
+        .gates
+        .iter()
+        .map(|g| (g.output.as_str(), g))
+        .collect();
+    for (i, out) in circuit.outputs.iter().enumerate() {
+        // An input passed straight through keeps its lever's sign.
+        let Some(g) = driver.get(out.as_str()).filter(|g| g.kind != "INPUT") else {
+            continue;
+        };
+        let name = circuit.output_names.get(i).unwrap_or(out);
+        let lines = labels.entry(&g.id).or_default();
+        if !lines.contains(name) {
+            lines.push(name.clone());
+        }
+    }
+    labels
+}
 
-                // Debug: print nearby obstacles
-                let r = 3;
-                eprintln!("Nearby obstacles around src:");
-                for dz in -r..=r {
-                    let mut line = String::new();
-                    for dx in -r..=r {
-                        let x = conn.src.x + dx;
-                        let z = conn.src.z + dz;
-                        line.push(if grid_obstacles.contains(&(x, z)) {
-                            '#'
-                        } else {
-                            '.'
-                        });
-                    }
-                    eprintln!("{}", line);
-                }
+/// Fail if any gate input was left unwired, unless `--allow-unwired`.
+pub fn check_wired(blocks: &Blocks, opts: &BuildOptions) -> Result<()> {
+    if blocks.unwired.is_empty() || opts.allow_unwired {
+        return Ok(());
+    }
+    let unwired: Vec<&str> = blocks.unwired.iter().map(|(_, w)| w.as_str()).collect();
+    let mut fixes = vec!["more room between gates with --spacing"];
+    if !opts.tunnels {
+        fixes.push("--tunnels");
+    }
+    fixes.push("more --wire-lanes");
+    fixes.push("--allow-unwired to write it anyway");
+    anyhow::bail!(
+        "{} input(s) could not be wired up:\n{}\nTry {}",
+        unwired.len(),
+        unwired.join("\n"),
+        fixes.join(", or ")
+    );
+}
 
-                eprintln!("Nearby obstacles around dst:");
-                for dz in -r..=r {
-                    let mut line = String::new();
-                    for dx in -r..=r {
-                        let x = conn.dst.x + dx;
-                        let z = conn.dst.z + dz;
-                        line.push(if grid_obstacles.contains(&(x, z)) {
-                            '#'
-                        } else {
-                            '.'
-                        });
-                    }
-                    eprintln!("{}", line);
+/// Air between the circuit and the walls of `--enclose`, to walk around in.
+const ENCLOSURE_MARGIN: i32 = 2;
+/// Air layers between the circuit's top and the enclosure's ceiling.
+const ENCLOSURE_HEADROOM: i32 = 2;
+/// Blocks between the glowstone lights in the enclosure's ceiling.
+const LIGHT_SPACING: i32 = 6;
+
+/// Wrap the circuit in a hollow room of `shell` blocks for `--enclose`: a
+/// floor under everything, walls with room to walk around the gates, a
+/// ceiling lit by glowstone, and an oak door in the middle of the front
+/// wall, on the levers' side.
+pub fn enclose(blocks: &mut Blocks, shell: &str) {
+    let ((min_x, min_y, min_z), (width, height, length)) = bounds(&blocks.placed);
+    let (lo_x, lo_z) = (min_x - ENCLOSURE_MARGIN - 1, min_z - ENCLOSURE_MARGIN - 1);
+    let (hi_x, hi_z) = (
+        min_x + width + ENCLOSURE_MARGIN,
+        min_z + length + ENCLOSURE_MARGIN,
+    );
+    let (floor, ceiling) = (min_y - 1, min_y + height + ENCLOSURE_HEADROOM);
+    let door_x = (lo_x + hi_x) / 2;
+    for y in floor..=ceiling {
+        for z in lo_z..=hi_z {
+            for x in lo_x..=hi_x {
+                let wall = x == lo_x || x == hi_x || z == lo_z || z == hi_z;
+                if !wall && y != floor && y != ceiling {
+                    continue;
                 }
+                let light = |d: i32| d % LIGHT_SPACING == LIGHT_SPACING / 2;
+                let block = if wall && z == lo_z && x == door_x && y > floor && y <= floor + 2 {
+                    let half = if y == floor + 1 { "lower" } else { "upper" };
+                    let props = [("facing", "south"), ("half", half), ("hinge", "left")];
+                    let props = props.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+                    ("minecraft:oak_door".to_string(), Some(props.collect()))
+                } else if !wall && y == ceiling && light(x - lo_x) && light(z - lo_z) {
+                    ("minecraft:glowstone".to_string(), None)
+                } else {
+                    (shell.to_string(), None)
+                };
+                blocks.placed.push((x, y, z, block.0, block.1));
+            }
+        }
+    }
+}
 
-                // Try straight Manhattan carve: go along X then Z
-                let mut carve = Vec::new();
-                let mut cx = conn.src.x;
-                let mut cz = conn.src.z;
-                while cx != conn.dst.x {
-                    if conn.dst.x > cx {
-                        cx += 1
-                    } else {
-                        cx -= 1
-                    }
-                    carve.push(Point { x: cx, z: cz });
+/// Places where a wire drops a level off dust on a block that does not
+/// conduct, which the dust below cannot read, as (net, position of the
+/// upper dust). The first block placed at a position is the one built.
+fn find_dead_drops(routes: &[Route], placed: &[PlacedBlock]) -> Vec<(usize, (i32, i32, i32))> {
+    let mut built: HashMap<(i32, i32, i32), &str> = HashMap::new();
+    for (x, y, z, name, _) in placed {
+        built.entry((*x, *y, *z)).or_insert(name);
+    }
+    let mut dead = Vec::new();
+    for (n, route) in routes.iter().enumerate() {
+        for (i, &(_, y, _)) in route.cells.iter().enumerate() {
+            let Some(p) = route.parent[i] else {
+                continue;
+            };
+            let upper = route.cells[p];
+            let under = (upper.0, upper.1 - 1, upper.2);
+            if y < upper.1 && !built.get(&under).is_some_and(|b| conducts(b)) {
+                dead.push((n, upper));
+            }
+        }
+    }
+    dead.sort_unstable();
+    dead.dedup();
+    dead
+}
+
+/// Electrical rule check: places where the wires of two nets connect, as
+/// (net, net, position), each pair once. Dust connects to dust beside it,
+/// and to dust a level up or down diagonally unless a block that conducts
+/// sits over the lower one; a repeater connects at its ends and a torch on
+/// any side. Routed wires are checked against each other and against the
+/// blocks of the gates in `gates`, which may touch the nets of the gate's
+/// ports and short with the first of them otherwise. Where two blocks go
+/// at one position, the first placed is the one built, and they short.
+fn find_shorts(
+    routes: &[Route],
+    gates: &HashMap<(i32, i32, i32), Vec<usize>>,
+    placed: &[PlacedBlock],
+) -> Vec<(usize, usize, (i32, i32, i32))> {
+    let mut built: HashMap<(i32, i32, i32), usize> = HashMap::new();
+    for (i, (x, y, z, _, _)) in placed.iter().enumerate() {
+        built.entry((*x, *y, *z)).or_insert(i);
+    }
+    let mut owner: HashMap<(i32, i32, i32), usize> = HashMap::new();
+    let mut shorts: Vec<(usize, usize, (i32, i32, i32))> = Vec::new();
+    for (n, route) in routes.iter().enumerate() {
+        for &cell in &route.cells {
+            match owner.insert(cell, n) {
+                Some(m) if m != n => shorts.push((m.min(n), m.max(n), cell)),
+                _ => {}
+            }
+            match gates.get(&cell) {
+                Some(ports) if !ports.contains(&n) => {
+                    shorts.push((ports[0].min(n), ports[0].max(n), cell))
                 }
-                while cz != conn.dst.z {
-                    if conn.dst.z > cz {
-                        cz += 1
-                    } else {
-                        cz -= 1
-                    }
-                    carve.push(Point { x: cx, z: cz });
+                _ => {}
+            }
+        }
+    }
+    let block = |p: &(i32, i32, i32)| built.get(p).map(|&i| &placed[i]);
+    let dust = |b: &PlacedBlock| b.3 == "minecraft:redstone_wire";
+    let wire = |p: &(i32, i32, i32)| {
+        block(p).filter(|b| dust(b) || repeater_delay(b).is_some() || b.3.contains("torch"))
+    };
+    // Whether a wire block joins one beside it along the X or Z axis.
+    let joins = |(_, _, _, name, props): &PlacedBlock, along_x: bool| {
+        if name != "minecraft:repeater" {
+            return true;
+        }
+        let facing = props.iter().flatten().find(|(k, _)| k == "facing");
+        matches!(facing.map(|(_, v)| v.as_str()), Some("east" | "west")) == along_x
+    };
+    // Blocks that conduct over dust cut its diagonal connections upward.
+    let cuts = |p: &(i32, i32, i32)| block(p).is_some_and(|b| conducts(&b.3));
+    for (&p, &n) in &owner {
+        let Some(a) = wire(&p) else {
+            continue;
+        };
+        for (dx, dz) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let side = (p.0 + dx, p.1, p.2 + dz);
+            let up = (p.0 + dx, p.1 + 1, p.2 + dz);
+            let down = (p.0 + dx, p.1 - 1, p.2 + dz);
+            let along_x = dx != 0;
+            let mut touch = |q: (i32, i32, i32), joined: bool| {
+                if !joined {
+                    return;
                 }
-
-                if !carve.is_empty() {
-                    let mut signal_dist = 0;
-                    for (idx, p) in carve.iter().enumerate() {
-                        let facing = if idx + 1 < carve.len() {
-                            let next = carve[idx + 1];
-                            if next.x > p.x {
-                                "east"
-                            } else if next.x < p.x {
-                                "west"
-                            } else if next.z > p.z {
-                                "south"
-                            } else {
-                                "north"
-                            }
-                        } else {
-                            "north"
-                        };
-
-                        // Remove obstacle and place
-                        grid_obstacles.remove(&(p.x, p.z));
-                        grid_obstacles.insert((p.x, p.z));
-                        place_wire_fn(&mut placed, p.x, 1, p.z, &mut signal_dist, facing);
+                match (owner.get(&q), gates.get(&q)) {
+                    (Some(&m), _) if m != n => shorts.push((n.min(m), n.max(m), p)),
+                    (None, Some(ports)) if !ports.contains(&n) => {
+                        shorts.push((n.min(ports[0]), n.max(ports[0]), p))
                     }
+                    _ => {}
                 }
+            };
+            if let Some(b) = wire(&side) {
+                touch(side, joins(a, along_x) && joins(b, along_x));
+            }
+            if let Some(b) = wire(&up) {
+                touch(up, dust(a) && dust(b) && !cuts(&(p.0, p.1 + 1, p.2)));
+            }
+            if let Some(b) = wire(&down) {
+                touch(down, dust(a) && dust(b) && !cuts(&side));
             }
         }
     }
+    shorts.sort_unstable();
+    shorts.dedup_by_key(|s| (s.0, s.1));
+    shorts
+}
 
-    // POST-PROCESSING: Calculate redstone wire connections
-    // Redstone wire needs north/south/east/west properties to connect properly
-    fn calculate_redstone_connections(
-        placed: &mut Vec<(i32, i32, i32, String, Option<Vec<(String, String)>>)>,
-    ) {
-        // Build a map of block positions for quick lookup
-        let mut block_map: HashMap<(i32, i32, i32), usize> = HashMap::new();
-        for (idx, (x, y, z, _, _)) in placed.iter().enumerate() {
-            block_map.insert((*x, *y, *z), idx);
+/// Lay a routed net down as dust on `support` blocks, or on `drop` where
+/// the wire drops a level, starting at `strength` at its source. A
+/// repeater replaces the dust where the wire runs straight and flat and
+/// the signal would otherwise run out before the next sink's `needs` or
+/// the next place a repeater could go. False if the signal runs out
+/// anyway, on a wire that has nowhere to put one. At `aligned` cells a
+/// repeater goes in a little early, if one is due soon. Where the route
+/// climbs a torch ladder, its column goes in, a repeater runs into it and
+/// the dust at its top starts at full strength.
+fn place_route(
+    placed: &mut Vec<PlacedBlock>,
+    route: &Route,
+    sinks: &[(i32, i32, i32)],
+    strength: i32,
+    needs: &[i32],
+    aligned: &HashSet<(i32, i32, i32)>,
+    (support, drop): (&str, &str),
+) -> Laid {
+    let mut children = vec![Vec::new(); route.cells.len()];
+    for (i, parent) in route.parent.iter().enumerate() {
+        if let Some(p) = *parent {
+            children[p].push(i);
+        }
+    }
+    let climbs: Vec<_> = (0..route.cells.len())
+        .map(|i| router::ladder(route.cells[route.parent[i]?], route.cells[i]))
+        .collect();
+    // Where a repeater could go, facing back toward the cell feeding it as
+    // a repeater's `facing` names its input side; not on a sink, which
+    // must point into its gate.
+    let repeatable: Vec<Option<&str>> = (0..route.cells.len())
+        .map(|i| {
+            let back = facing(route.cells[i], route.cells[route.parent[i]?])?;
+            if sinks.contains(&route.cells[i]) {
+                return None;
+            }
+            match children[i][..] {
+                [] => Some(back),
+                [c] if facing(route.cells[c], route.cells[i]) == Some(back) => Some(back),
+                [c] if climbs[c].is_some() => Some(back),
+                _ => None,
+            }
+        })
+        .collect();
+    // Strength the dust at each cell needs for the sinks below it, counting
+    // on repeaters wherever they could go. Children come after parents.
+    let mut need = vec![1; route.cells.len()];
+    for i in (0..route.cells.len()).rev() {
+        for (sink, &n) in sinks.iter().zip(needs) {
+            if *sink == route.cells[i] {
+                need[i] = need[i].max(n);
+            }
+        }
+        for &c in &children[i] {
+            // A repeater only needs the dust behind it lit, and a ladder
+            // lights its top afresh.
+            let n = match (repeatable[c], climbs[c]) {
+                (None, None) => need[c] + 1,
+                _ => 1,
+            };
+            need[i] = need[i].max(n);
         }
+    }
+    let mut strong = true;
+    let mut at = vec![0; route.cells.len()];
+    let mut blocks = Vec::new();
+    for (i, &(x, y, z)) in route.cells.iter().enumerate() {
+        // Blocks and torches up the ladder, each torch turning the signal
+        // over, so the top one carries it the right way up.
+        if let Some(((cx, cy, cz), rise)) = climbs[i] {
+            for dy in 0..=rise {
+                let block = if dy % 2 == 0 {
+                    ("minecraft:cobblestone", None)
+                } else {
+                    let lit = if dy % 4 == 1 { "true" } else { "false" };
+                    let props = vec![("lit".to_string(), lit.to_string())];
+                    ("minecraft:redstone_torch", Some(props))
+                };
+                placed.push((cx, cy + dy, cz, block.0.to_string(), block.1));
+            }
+        }
+        let drops = children[i].iter().any(|&c| route.cells[c].1 < y);
+        let under = if drops { drop } else { support };
+        placed.push((x, y - 1, z, under.to_string(), None));
+        blocks.push(placed.len());
+        let s = match (route.parent[i], climbs[i]) {
+            (Some(_), Some(_)) => REDSTONE_SIGNAL_LIMIT,
+            (Some(p), None) => at[p] - 1,
+            (None, _) => strength,
+        };
+        let into_ladder = children[i].iter().any(|&c| climbs[c].is_some());
+        match repeatable[i] {
+            Some(facing)
+                if s < need[i]
+                    || into_ladder
+                    || aligned.contains(&(x, y, z)) && s < need[i] + REPEATER_ALIGN_SLACK =>
+            {
+                at[i] = REDSTONE_SIGNAL_LIMIT + 1;
+                placed.push((
+                    x,
+                    y,
+                    z,
+                    "minecraft:repeater".to_string(),
+                    Some(vec![("facing".to_string(), facing.to_string())]),
+                ));
+            }
+            _ => {
+                strong &= s >= need[i];
+                at[i] = s;
+                placed.push((x, y, z, "minecraft:redstone_wire".to_string(), None));
+            }
+        }
+    }
+    Laid {
+        blocks,
+        repeatable,
+        strong,
+    }
+}
 
-        // Check if a block can connect to redstone wire
-        fn can_connect(name: &str) -> bool {
-            name.contains("redstone")
-                || name.contains("repeater")
-                || name.contains("comparator")
-                || name.contains("torch")
-                || name == "minecraft:cobblestone"
-                || name == "minecraft:sandstone"
+/// Swap straight, flat stretches of a laid route for instant-wire
+/// segments: a repeater powers a run of powered rails, which pass the
+/// level along in the same tick; an observer watching the last rail
+/// toggles a copper bulb at each edge, so the bulb follows the level, and
+/// a comparator reading the bulb drives the wire on at full strength. A
+/// segment's comparator can power the next segment's rails.
+fn lay_instant_wire(placed: &mut [PlacedBlock], route: &Route, laid: &mut Laid) {
+    let mut children = vec![Vec::new(); route.cells.len()];
+    for (i, parent) in route.parent.iter().enumerate() {
+        if let Some(p) = *parent {
+            children[p].push(i);
+        }
+    }
+    // Cells in a row that could each take a repeater facing the same way,
+    // ending at each cell. Children come after parents.
+    let segment = RAIL_RUN + 4;
+    let mut run = vec![0; route.cells.len()];
+    let mut ends = Vec::new();
+    for i in 0..route.cells.len() {
+        if laid.repeatable[i].is_none() {
+            continue;
+        }
+        run[i] = match route.parent[i] {
+            Some(p) if laid.repeatable[p] == laid.repeatable[i] => run[p] + 1,
+            _ => 1,
+        };
+        // The comparator at the end needs a wire to drive, and can itself
+        // start the next segment.
+        if run[i] >= segment && !children[i].is_empty() {
+            ends.push(i);
+            run[i] = 1;
+        }
+    }
+    for end in ends {
+        let facing = laid.repeatable[end].unwrap();
+        let mut cells = vec![end];
+        while cells.len() < segment {
+            cells.push(route.parent[cells[cells.len() - 1]].unwrap());
         }
+        cells.reverse();
+        let shape = match facing {
+            "east" | "west" => "east_west",
+            _ => "north_south",
+        };
+        for (k, &i) in cells.iter().enumerate() {
+            let (name, props) = match k {
+                // Keep a repeater, or the comparator of the segment before.
+                0 if placed[laid.blocks[i]].3 != "minecraft:redstone_wire" => continue,
+                0 => ("repeater", vec![("facing", facing)]),
+                k if k <= RAIL_RUN => ("powered_rail", vec![("shape", shape)]),
+                // An observer faces the block it watches and signals from
+                // its back.
+                k if k == RAIL_RUN + 1 => ("observer", vec![("facing", facing)]),
+                k if k == RAIL_RUN + 2 => ("waxed_copper_bulb", vec![]),
+                _ => ("comparator", vec![("facing", facing)]),
+            };
+            let block = &mut placed[laid.blocks[i]];
+            block.3 = format!("minecraft:{}", name);
+            block.4 = (!props.is_empty()).then(|| {
+                props
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect()
+            });
+            // Delay can only be padded on the repeater.
+            if k > 0 {
+                laid.repeatable[i] = None;
+            }
+        }
+    }
+}
 
-        // Update each redstone wire block
-        for idx in 0..placed.len() {
-            if placed[idx].3 == "minecraft:redstone_wire" {
-                let (x, y, z, _, _) = placed[idx];
-                let mut connections = Vec::new();
-
-                // Check all four horizontal directions
-                // North (-Z)
-                let north_pos = (x, y, z - 1);
-                let north_up = (x, y + 1, z - 1);
-                let north_down = (x, y - 1, z - 1);
-
-                if let Some(&north_idx) = block_map.get(&north_pos) {
-                    if can_connect(&placed[north_idx].3) {
-                        connections.push(("north".to_string(), "side".to_string()));
-                    }
-                } else if let Some(&north_up_idx) = block_map.get(&north_up) {
-                    if can_connect(&placed[north_up_idx].3) {
-                        connections.push(("north".to_string(), "up".to_string()));
-                    }
-                } else if let Some(&north_down_idx) = block_map.get(&north_down) {
-                    if can_connect(&placed[north_down_idx].3) {
-                        connections.push(("north".to_string(), "side".to_string()));
-                    }
-                } else {
-                    connections.push(("north".to_string(), "none".to_string()));
-                }
+/// The bus and bit index of a signal named like `bus[3]`.
+fn bus_bit(name: &str) -> Option<(&str, usize)> {
+    let (base, rest) = name.rsplit_once('[')?;
+    Some((base, rest.strip_suffix(']')?.parse().ok()?))
+}
 
-                // South (+Z)
-                let south_pos = (x, y, z + 1);
-                let south_up = (x, y + 1, z + 1);
-                let south_down = (x, y - 1, z + 1);
+/// A route as laid down by `place_route`.
+struct Laid {
+    /// Index in the placed blocks of each cell's dust or repeater.
+    blocks: Vec<usize>,
+    /// The `facing` of a repeater each cell could take, where it could.
+    repeatable: Vec<Option<&'static str>>,
+    /// Whether the signal holds out to every sink.
+    strong: bool,
+}
 
-                if let Some(&south_idx) = block_map.get(&south_pos) {
-                    if can_connect(&placed[south_idx].3) {
-                        connections.push(("south".to_string(), "side".to_string()));
-                    }
-                } else if let Some(&south_up_idx) = block_map.get(&south_up) {
-                    if can_connect(&placed[south_up_idx].3) {
-                        connections.push(("south".to_string(), "up".to_string()));
-                    }
-                } else if let Some(&south_down_idx) = block_map.get(&south_down) {
-                    if can_connect(&placed[south_down_idx].3) {
-                        connections.push(("south".to_string(), "side".to_string()));
-                    }
-                } else {
-                    connections.push(("south".to_string(), "none".to_string()));
-                }
+/// Nets in the order `strategy` routes them; ties keep gate order.
+fn rank_nets(strategy: NetOrder, circuit: &Circuit, nets: &[Net], names: &[&str]) -> Vec<usize> {
+    let mut ranked: Vec<usize> = (0..nets.len()).collect();
+    match strategy {
+        NetOrder::Gates => {}
+        NetOrder::Size => ranked.sort_by_key(|&n| {
+            let ports = std::iter::once(&nets[n].source).chain(&nets[n].sinks);
+            let b = ports.fold((i32::MAX, i32::MAX, i32::MIN, i32::MIN), |b, &(x, _, z)| {
+                (b.0.min(x), b.1.min(z), b.2.max(x), b.3.max(z))
+            });
+            b.2 - b.0 + b.3 - b.1
+        }),
+        NetOrder::Critical => {
+            let through = path_delays(circuit);
+            ranked.sort_by_key(|&n| Reverse(through.get(names[n]).copied().unwrap_or(0)));
+        }
+        NetOrder::Fanout => ranked.sort_by_key(|&n| Reverse(nets[n].sinks.len())),
+    }
+    ranked
+}
 
-                // East (+X)
-                let east_pos = (x + 1, y, z);
-                let east_up = (x + 1, y + 1, z);
-                let east_down = (x + 1, y - 1, z);
+/// Whether a gate's output starts a timing path: it reads nothing, or
+/// holds state.
+fn starts_path(g: &Gate) -> bool {
+    g.inputs.is_empty() || SEQUENTIAL_KINDS.contains(&g.kind.as_str())
+}
 
-                if let Some(&east_idx) = block_map.get(&east_pos) {
-                    if can_connect(&placed[east_idx].3) {
-                        connections.push(("east".to_string(), "side".to_string()));
-                    }
-                } else if let Some(&east_up_idx) = block_map.get(&east_up) {
-                    if can_connect(&placed[east_up_idx].3) {
-                        connections.push(("east".to_string(), "up".to_string()));
-                    }
-                } else if let Some(&east_down_idx) = block_map.get(&east_down) {
-                    if can_connect(&placed[east_down_idx].3) {
-                        connections.push(("east".to_string(), "side".to_string()));
-                    }
-                } else {
-                    connections.push(("east".to_string(), "none".to_string()));
-                }
+/// Gate indices in dependency order, each after the gates driving it,
+/// leaving out any loop. Gates starting a path wait for nothing.
+fn settle_order(circuit: &Circuit) -> Vec<usize> {
+    let driver: HashSet<&str> = circuit.gates.iter().map(|g| g.output.as_str()).collect();
+    let driven = |i: &&String| driver.contains(i.as_str());
+    let mut waiting: Vec<usize> = circuit
+        .gates
+        .iter()
+        .map(|g| g.inputs.iter().filter(driven).count())
+        .collect();
+    let mut readers: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, g) in circuit.gates.iter().enumerate() {
+        if starts_path(g) {
+            waiting[i] = 0;
+        } else {
+            for input in &g.inputs {
+                readers.entry(input).or_default().push(i);
+            }
+        }
+    }
+    let mut ready: Vec<usize> = (0..waiting.len()).filter(|&i| waiting[i] == 0).collect();
+    let mut order = Vec::with_capacity(waiting.len());
+    while let Some(i) = ready.pop() {
+        let output = circuit.gates[i].output.as_str();
+        for &r in readers.get(output).into_iter().flatten() {
+            waiting[r] -= 1;
+            if waiting[r] == 0 {
+                ready.push(r);
+            }
+        }
+        order.push(i);
+    }
+    order
+}
 
-                // West (-X)
-                let west_pos = (x - 1, y, z);
-                let west_up = (x - 1, y + 1, z);
-                let west_down = (x - 1, y - 1, z);
+/// Gate delay along the longest path through each signal, from where the
+/// path starts to a state element or an output.
+fn path_delays(circuit: &Circuit) -> HashMap<&str, i32> {
+    let order = settle_order(circuit);
+    let mut arrival: HashMap<&str, i32> = HashMap::new();
+    for &i in &order {
+        let g = &circuit.gates[i];
+        let latest = if starts_path(g) {
+            0
+        } else {
+            let at = |s: &String| arrival.get(s.as_str()).copied().unwrap_or(0);
+            g.inputs.iter().map(at).max().unwrap_or(0) + cost(&g.kind, &g.params).delay
+        };
+        arrival.insert(&g.output, latest);
+    }
+    // Delay still ahead of each signal, back from the path ends.
+    let mut ahead: HashMap<&str, i32> = HashMap::new();
+    for &i in order.iter().rev() {
+        let g = &circuit.gates[i];
+        if starts_path(g) {
+            continue;
+        }
+        let after = ahead.get(g.output.as_str()).copied().unwrap_or(0);
+        for input in &g.inputs {
+            let t = ahead.entry(input).or_insert(0);
+            *t = (*t).max(after + cost(&g.kind, &g.params).delay);
+        }
+    }
+    arrival
+        .into_iter()
+        .map(|(s, t)| (s, t + ahead.get(s).copied().unwrap_or(0)))
+        .collect()
+}
 
-                if let Some(&west_idx) = block_map.get(&west_pos) {
-                    if can_connect(&placed[west_idx].3) {
-                        connections.push(("west".to_string(), "side".to_string()));
-                    }
-                } else if let Some(&west_up_idx) = block_map.get(&west_up) {
-                    if can_connect(&placed[west_up_idx].3) {
-                        connections.push(("west".to_string(), "up".to_string()));
-                    }
-                } else if let Some(&west_down_idx) = block_map.get(&west_down) {
-                    if can_connect(&placed[west_down_idx].3) {
-                        connections.push(("west".to_string(), "side".to_string()));
-                    }
-                } else {
-                    connections.push(("west".to_string(), "none".to_string()));
-                }
+/// Pad the inputs of each combinational gate that arrive before its
+/// latest one with repeater delay, on the part of the wire that feeds
+/// that input alone, so reconvergent paths settle together and the gate
+/// does not glitch. A signal arrives after the gate delays and routed
+/// repeater ticks back to an input, a constant or a state element.
+/// Returns the gates left unbalanced, with the ticks still between their
+/// inputs.
+fn equalize_delays<'a>(
+    circuit: &'a Circuit,
+    nets: &[Net],
+    routes: &[Route],
+    laid: &[Laid],
+    gate_sinks: &HashMap<&str, Vec<(usize, usize, usize)>>,
+    placed: &mut [PlacedBlock],
+) -> Vec<(&'a str, i32)> {
+    let mut arrival: HashMap<&str, i32> = HashMap::new();
+    let mut short = Vec::new();
+    for i in settle_order(circuit) {
+        let g = &circuit.gates[i];
+        if starts_path(g) {
+            arrival.insert(&g.output, 0);
+            continue;
+        }
+        let at = |input: usize| arrival.get(g.inputs[input].as_str()).copied().unwrap_or(0);
+        let sinks = gate_sinks.get(g.id.as_str()).map_or(&[][..], |s| &s[..]);
+        let mut latest = (0..g.inputs.len()).map(at).max().unwrap_or(0);
+        let mut ticks = Vec::new();
+        for &(input, n, s) in sinks {
+            let Some(cell) = routes[n].cells.iter().position(|&c| c == nets[n].sinks[s]) else {
+                continue;
+            };
+            let t = at(input) + wire_ticks(&routes[n], &laid[n], cell, placed);
+            latest = latest.max(t);
+            ticks.push((n, cell, t));
+        }
+        let mut apart = 0;
+        for (n, cell, t) in ticks {
+            if t < latest {
+                let left = pad_delay(&nets[n], &routes[n], &laid[n], cell, latest - t, placed);
+                apart = apart.max(left);
+            }
+        }
+        if apart > 0 {
+            short.push((g.id.as_str(), apart));
+        }
+        arrival.insert(&g.output, latest + cost(&g.kind, &g.params).delay);
+    }
+    short
+}
 
-                // Update the block with connection properties
-                placed[idx].4 = Some(connections);
+/// Repeater and torch ladder ticks from a route's source to one of its
+/// cells, counting a tick each for the observer and comparator of an
+/// instant-wire segment.
+fn wire_ticks(route: &Route, laid: &Laid, cell: usize, placed: &[PlacedBlock]) -> i32 {
+    let mut ticks = 0;
+    let mut at = Some(cell);
+    while let Some(i) = at {
+        let block = &placed[laid.blocks[i]];
+        ticks += repeater_delay(block).unwrap_or(0);
+        if matches!(
+            block.3.as_str(),
+            "minecraft:observer" | "minecraft:comparator"
+        ) {
+            ticks += 1;
+        }
+        at = route.parent[i];
+        if let Some(p) = at {
+            if router::ladder(route.cells[p], route.cells[i]).is_some() {
+                ticks += LADDER_TICKS;
             }
         }
     }
+    ticks
+}
 
-    // Apply redstone wire connections
-    calculate_redstone_connections(&mut placed);
+/// The delay a placed repeater holds a signal for, if it is one.
+fn repeater_delay((_, _, _, name, props): &PlacedBlock) -> Option<i32> {
+    if name != "minecraft:repeater" {
+        return None;
+    }
+    let delay = props.iter().flatten().find(|(k, _)| k == "delay");
+    Some(delay.and_then(|(_, v)| v.parse().ok()).unwrap_or(1))
+}
+
+/// Add up to `ticks` of delay to the wire leading to sink `cell` alone,
+/// first by slowing its repeaters and then by putting more in. Returns
+/// the ticks it found no room for.
+fn pad_delay(
+    net: &Net,
+    route: &Route,
+    laid: &Laid,
+    cell: usize,
+    mut ticks: i32,
+    placed: &mut [PlacedBlock],
+) -> i32 {
+    // Sinks each cell feeds; children come after parents.
+    let mut feeds = vec![0; route.cells.len()];
+    for i in (0..route.cells.len()).rev() {
+        if net.sinks.contains(&route.cells[i]) {
+            feeds[i] += 1;
+        }
+        if let Some(p) = route.parent[i] {
+            feeds[p] += feeds[i];
+        }
+    }
+    let mut own = Vec::new();
+    let mut at = Some(cell);
+    while let Some(i) = at.filter(|&i| feeds[i] == 1) {
+        own.push(i);
+        at = route.parent[i];
+    }
+    own.sort_by_key(|&i| repeater_delay(&placed[laid.blocks[i]]).is_none());
+    for i in own {
+        let Some(facing) = laid.repeatable[i] else {
+            continue;
+        };
+        if ticks == 0 {
+            break;
+        }
+        let block = &mut placed[laid.blocks[i]];
+        let delay = repeater_delay(block).unwrap_or(0);
+        let added = ticks.min(4 - delay);
+        ticks -= added;
+        block.3 = "minecraft:repeater".to_string();
+        block.4 = Some(vec![
+            ("facing".to_string(), facing.to_string()),
+            ("delay".to_string(), (delay + added).to_string()),
+        ]);
+    }
+    ticks
+}
 
-    // Bounds calculation
+/// Direction of a flat one-block step, as a block state's `facing`: a
+/// repeater's input side is the direction from it to the cell feeding it.
+fn facing(from: (i32, i32, i32), to: (i32, i32, i32)) -> Option<&'static str> {
+    if from.1 != to.1 {
+        return None;
+    }
+    match (to.0 - from.0, to.2 - from.2) {
+        (1, 0) => Some("east"),
+        (-1, 0) => Some("west"),
+        (0, 1) => Some("south"),
+        (0, -1) => Some("north"),
+        _ => None,
+    }
+}
+
+/// Bounding box of the placed blocks: `(min, size)`.
+pub(crate) fn bounds(placed: &[PlacedBlock]) -> ((i32, i32, i32), (i32, i32, i32)) {
     let (min_x, min_y, min_z, max_x, max_y, max_z) = if placed.is_empty() {
         (0, 0, 0, 0, 0, 0)
     } else {
-        let (mut mx, mut my, mut mz, mut Mx, mut My, mut Mz) =
+        let (mut min_x, mut min_y, mut min_z, mut max_x, mut max_y, mut max_z) =
             (i32::MAX, i32::MAX, i32::MAX, i32::MIN, i32::MIN, i32::MIN);
-        for (x, y, z, _, _) in &placed {
-            if *x < mx {
-                mx = *x
+        for (x, y, z, _, _) in placed {
+            if *x < min_x {
+                min_x = *x
             }
-            if *y < my {
-                my = *y
+            if *y < min_y {
+                min_y = *y
             }
-            if *z < mz {
-                mz = *z
+            if *z < min_z {
+                min_z = *z
             }
-            if *x > Mx {
-                Mx = *x
+            if *x > max_x {
+                max_x = *x
             }
-            if *y > My {
-                My = *y
+            if *y > max_y {
+                max_y = *y
             }
-            if *z > Mz {
-                Mz = *z
+            if *z > max_z {
+                max_z = *z
             }
         }
-        (mx, my, mz, Mx, My, Mz)
+        (min_x, min_y, min_z, max_x, max_y, max_z)
     };
 
-    let width = max_x - min_x + 1;
-    let height = max_y - min_y + 1;
-    let length = max_z - min_z + 1;
+    (
+        (min_x, min_y, min_z),
+        (max_x - min_x + 1, max_y - min_y + 1, max_z - min_z + 1),
+    )
+}
 
-    // Palette Building
-    fn canonical_key(name: &str, props: &Option<Vec<(String, String)>>) -> String {
-        let mut key = name.to_string();
-        if let Some(p) = props {
-            let mut ps = p.clone();
-            ps.sort_by(|a, b| a.0.cmp(&b.0));
-            for (k, v) in ps {
-                key.push_str(&format!("|{}={}", k, v));
-            }
-        }
-        key
+/// Palette key of a block state, e.g. `minecraft:repeater[delay=1,facing=east]`.
+fn block_state(name: &str, props: &Option<Vec<(String, String)>>) -> String {
+    let mut key = name.to_string();
+    if let Some(p) = props.as_ref().filter(|p| !p.is_empty()) {
+        let mut ps = p.clone();
+        ps.sort_by(|a, b| a.0.cmp(&b.0));
+        let list: Vec<String> = ps.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        key.push_str(&format!("[{}]", list.join(",")));
     }
+    key
+}
 
-    let mut palette_keys = vec![("minecraft:air".to_string(), None)];
-    let mut palette_index = HashMap::new();
-    palette_index.insert(canonical_key("minecraft:air", &None), 0usize);
+/// A sign's block entity data, less its id and position: up to four lines
+/// on the front in black, and nothing on the back.
+pub(crate) fn sign_data(lines: &[String]) -> Map<String, Value> {
+    let side = |lines: &[String]| {
+        let mut messages: Vec<Value> = lines.iter().take(4).cloned().map(Value::String).collect();
+        messages.resize(4, Value::String(String::new()));
+        let mut text = Map::new();
+        text.insert("messages".into(), Value::List(messages));
+        text.insert("color".into(), Value::String("black".into()));
+        text.insert("has_glowing_text".into(), Value::Byte(0));
+        Value::Compound(text)
+    };
+    let mut data = Map::new();
+    data.insert("front_text".into(), side(lines));
+    data.insert("back_text".into(), side(&[]));
+    data.insert("is_waxed".into(), Value::Byte(0));
+    data
+}
 
-    for (_, _, _, name, props) in &placed {
-        let key = canonical_key(name, props);
-        if let std::collections::hash_map::Entry::Vacant(e) = palette_index.entry(key) {
-            let idx = palette_keys.len();
-            e.insert(idx);
-            palette_keys.push((name.clone(), props.clone()));
-        }
-    }
+fn write_litematic(blocks: &Blocks, path: &Path, opts: &WriteOptions) -> Result<()> {
+    let ((min_x, min_y, min_z), _) = bounds(&blocks.placed);
+    let grid = Grid::new(blocks);
+    let (width, height, length) = grid.size;
 
-    // BlockStates
-    let mut indices: Vec<u32> = Vec::with_capacity((width * height * length) as usize);
-    for y in 0..height {
-        for z in 0..length {
-            for x in 0..width {
-                let (wx, wy, wz) = (min_x + x, min_y + y, min_z + z);
-                let mut found = 0;
-                for (bx, by, bz, name, props) in &placed {
-                    if *bx == wx && *by == wy && *bz == wz {
-                        let key = canonical_key(name, props);
-                        found = *palette_index.get(&key).unwrap_or(&0) as u32;
-                        break;
-                    }
-                }
-                indices.push(found);
-            }
-        }
-    }
+    let mut root_map = Map::new();
+    root_map.insert("SubVersion".to_string(), Value::Int(1));
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
 
-    // Bit Packing
-    let bits = ((palette_keys.len() as f64).log2().ceil() as usize).max(2);
-    let mut longs = Vec::new();
-    let _mask = (1u128 << bits) - 1;
-    let mut acc = 0u128;
-    let mut acc_bits = 0;
+    let mut metadata = Map::new();
+    metadata.insert("Name".to_string(), Value::String(opts.name.clone()));
+    metadata.insert("Author".to_string(), Value::String(opts.author.clone()));
+    metadata.insert(
+        "Description".to_string(),
+        Value::String(opts.description.clone()),
+    );
+    metadata.insert("TimeCreated".to_string(), Value::Long(now));
+    metadata.insert("TimeModified".to_string(), Value::Long(now));
 
-    for idx in indices {
-        acc |= (idx as u128) << acc_bits;
-        acc_bits += bits;
-        while acc_bits >= 64 {
-            longs.push((acc & 0xFFFF_FFFF_FFFF_FFFF) as i64);
-            acc >>= 64;
-            acc_bits -= 64;
-        }
-    }
-    if acc_bits > 0 {
-        longs.push(acc as i64);
-    }
+    let mut region = Map::new();
+    region.insert("Name".to_string(), Value::String(opts.name.clone()));
+
+    // Blocks other than air, once each however many went to a position.
+    let solid = grid.cells.iter().filter(|&&i| i != 0).count();
+
+    // Bit Packing
+    let longs = pack_bits(&grid.cells, palette_bits(grid.palette.len()));
 
     // Region Construction
     let mut pos_tag = Map::new();
@@ -716,12 +1431,13 @@ pub fn write_schem(_circuit: &Circuit, _layout: &Layout, path: &Path) -> Result<
     region.insert("Size".into(), Value::Compound(size_tag));
 
     let mut pal_list = Vec::new();
-    for (name, props) in palette_keys {
+    for state in &grid.palette {
+        let (name, props) = parse_state(state);
         let mut entry = Map::new();
         entry.insert("Name".into(), Value::String(name));
-        if let Some(p) = props {
+        if !props.is_empty() {
             let mut pm = Map::new();
-            for (k, v) in p {
+            for (k, v) in props {
                 pm.insert(k, Value::String(v));
             }
             entry.insert("Properties".into(), Value::Compound(pm));
@@ -730,16 +1446,38 @@ pub fn write_schem(_circuit: &Circuit, _layout: &Layout, path: &Path) -> Result<
     }
     region.insert("BlockStatePalette".into(), Value::List(pal_list));
     region.insert("BlockStates".into(), Value::LongArray(longs));
-    region.insert("PendingBlockTicks".into(), Value::List(vec![]));
-    region.insert("TileEntities".into(), Value::List(vec![]));
+    let mut ticks = Vec::new();
+    for (x, y, z, name) in blocks.scheduled.clone() {
+        let mut tick = Map::new();
+        tick.insert("Block".into(), Value::String(name));
+        tick.insert("Priority".into(), Value::Int(0));
+        tick.insert("SubTick".into(), Value::Long(0));
+        tick.insert("Time".into(), Value::Int(1));
+        tick.insert("x".into(), Value::Int(x - min_x));
+        tick.insert("y".into(), Value::Int(y - min_y));
+        tick.insert("z".into(), Value::Int(z - min_z));
+        ticks.push(Value::Compound(tick));
+    }
+    region.insert("PendingBlockTicks".into(), Value::List(ticks));
+    let mut tile_entities = Vec::new();
+    for (x, y, z, lines) in &grid.signs {
+        let mut entity = sign_data(lines);
+        entity.insert("id".into(), Value::String("minecraft:sign".into()));
+        entity.insert("x".into(), Value::Int(*x));
+        entity.insert("y".into(), Value::Int(*y));
+        entity.insert("z".into(), Value::Int(*z));
+        tile_entities.push(Value::Compound(entity));
+    }
+    region.insert("TileEntities".into(), Value::List(tile_entities));
     region.insert("Entities".into(), Value::List(vec![]));
 
     let mut regions = Map::new();
-    regions.insert("Unnamed".into(), Value::Compound(region));
+    regions.insert(opts.name.clone(), Value::Compound(region));
     root_map.insert("Regions".into(), Value::Compound(regions));
 
     // Metadata
-    metadata.insert("TotalBlocks".into(), Value::Int(placed.len() as i32));
+    metadata.insert("RegionCount".into(), Value::Int(1));
+    metadata.insert("TotalBlocks".into(), Value::Int(solid as i32));
     metadata.insert("TotalVolume".into(), Value::Int(width * height * length));
     let mut enc = Map::new();
     enc.insert("x".into(), Value::Int(width));
@@ -747,7 +1485,7 @@ pub fn write_schem(_circuit: &Circuit, _layout: &Layout, path: &Path) -> Result<
     enc.insert("z".into(), Value::Int(length));
     metadata.insert("EnclosingSize".into(), Value::Compound(enc));
     root_map.insert("Metadata".into(), Value::Compound(metadata));
-    root_map.insert("MinecraftDataVersion".into(), Value::Int(4671)); // 1.16.5
+    root_map.insert("MinecraftDataVersion".into(), Value::Int(DATA_VERSION));
     root_map.insert("Version".into(), Value::Int(7));
 
     let mut blob = nbt::Blob::new();
@@ -755,7 +1493,550 @@ pub fn write_schem(_circuit: &Circuit, _layout: &Layout, path: &Path) -> Result<
         blob.insert(k, v)?;
     }
 
-    let file = File::create(path)?;
-    blob.to_gzip_writer(&mut std::io::BufWriter::new(file))?;
+    write_nbt(&blob, path, opts)?;
+    if opts.verify {
+        verify_litematic(blocks, path, opts)?;
+    }
+    Ok(())
+}
+
+/// Writes an NBT output gzipped at `opts.gzip`'s level or uncompressed, to
+/// `path` or to stdout.
+fn write_nbt(blob: &nbt::Blob, path: &Path, opts: &WriteOptions) -> Result<()> {
+    let out: Box<dyn Write> = if opts.stdout {
+        Box::new(std::io::stdout().lock())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    let mut out = std::io::BufWriter::new(out);
+    match opts.gzip {
+        Some(level) => {
+            let mut gz = GzEncoder::new(&mut out, Compression::new(level));
+            blob.to_writer(&mut gz)?;
+            gz.finish()?;
+        }
+        None => blob.to_writer(&mut out)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Bits per palette index in a litematic: enough for the last index, and
+/// never fewer than 2.
+fn palette_bits(len: usize) -> usize {
+    (usize::BITS - len.saturating_sub(1).leading_zeros()).max(2) as usize
+}
+
+/// Packs palette indices into longs as Litematica's bit array does: entry
+/// `i` starts at bit `i * bits`, and one that does not fit in the rest of
+/// a long carries on at the bottom of the next.
+fn pack_bits(indices: &[u32], bits: usize) -> Vec<i64> {
+    let mut longs = vec![0u64; (indices.len() * bits).div_ceil(64)];
+    for (i, &idx) in indices.iter().enumerate() {
+        let (word, offset) = (i * bits / 64, i * bits % 64);
+        longs[word] |= (idx as u64) << offset;
+        if offset + bits > 64 {
+            longs[word + 1] |= (idx as u64) >> (64 - offset);
+        }
+    }
+    longs.into_iter().map(|l| l as i64).collect()
+}
+
+/// The first `count` entries of a bit array written by `pack_bits`.
+fn unpack_bits(longs: &[i64], bits: usize, count: usize) -> Vec<u32> {
+    let mask = (1u64 << bits) - 1;
+    (0..count)
+        .map(|i| {
+            let (word, offset) = (i * bits / 64, i * bits % 64);
+            let mut idx = longs[word] as u64 >> offset;
+            if offset + bits > 64 {
+                idx |= (longs[word + 1] as u64) << (64 - offset);
+            }
+            (idx & mask) as u32
+        })
+        .collect()
+}
+
+/// Reads a written litematic back, as `read_litematic` does, and compares
+/// its region with the placed blocks: the first placed at each position,
+/// and air everywhere else in the bounding box.
+fn verify_litematic(blocks: &Blocks, path: &Path, opts: &WriteOptions) -> Result<()> {
+    let mut file = std::io::BufReader::new(File::open(path)?);
+    let blob = if opts.gzip.is_some() {
+        nbt::Blob::from_gzip_reader(&mut file)?
+    } else {
+        nbt::Blob::from_reader(&mut file)?
+    };
+    let Some(Value::Compound(regions)) = blob.get("Regions") else {
+        anyhow::bail!("{}: no Regions read back", path.display());
+    };
+    let Some(Value::Compound(region)) = regions.values().next() else {
+        anyhow::bail!("{}: no region read back", path.display());
+    };
+    let corner = |tag: &str| -> Result<(i32, i32, i32)> {
+        let Some(Value::Compound(c)) = region.get(tag) else {
+            anyhow::bail!("{}: no {} read back", path.display(), tag);
+        };
+        match (c.get("x"), c.get("y"), c.get("z")) {
+            (Some(Value::Int(x)), Some(Value::Int(y)), Some(Value::Int(z))) => Ok((*x, *y, *z)),
+            _ => anyhow::bail!("{}: {} read back without x, y and z", path.display(), tag),
+        }
+    };
+    let origin = corner("Position")?;
+    let size = corner("Size")?;
+    if (origin, size) != bounds(&blocks.placed) {
+        anyhow::bail!(
+            "{}: region read back at {:?} sized {:?}, not the placed blocks' {:?}",
+            path.display(),
+            origin,
+            size,
+            bounds(&blocks.placed)
+        );
+    }
+
+    let mut palette = Vec::new();
+    if let Some(Value::List(entries)) = region.get("BlockStatePalette") {
+        for entry in entries {
+            let Value::Compound(entry) = entry else {
+                anyhow::bail!("{}: palette entry is not a compound", path.display());
+            };
+            let Some(Value::String(name)) = entry.get("Name") else {
+                anyhow::bail!("{}: palette entry has no Name", path.display());
+            };
+            let mut props = Vec::new();
+            if let Some(Value::Compound(p)) = entry.get("Properties") {
+                for (k, v) in p {
+                    if let Value::String(v) = v {
+                        props.push((k.clone(), v.clone()));
+                    }
+                }
+            }
+            palette.push(block_state(name, &Some(props)));
+        }
+    }
+    let Some(Value::LongArray(longs)) = region.get("BlockStates") else {
+        anyhow::bail!("{}: no BlockStates read back", path.display());
+    };
+    let volume = (size.0 * size.1 * size.2) as usize;
+    let bits = palette_bits(palette.len());
+    if longs.len() != (volume * bits).div_ceil(64) {
+        anyhow::bail!(
+            "{}: {} longs of BlockStates read back, expected {} for {} blocks at {} bits",
+            path.display(),
+            longs.len(),
+            (volume * bits).div_ceil(64),
+            volume,
+            bits
+        );
+    }
+
+    let mut expected = vec![None; volume];
+    for (x, y, z, name, props) in &blocks.placed {
+        let (x, y, z) = (x - origin.0, y - origin.1, z - origin.2);
+        let cell = &mut expected[((y * size.2 + z) * size.0 + x) as usize];
+        if cell.is_none() {
+            *cell = Some(block_state(name, props));
+        }
+    }
+    let mut wrong = Vec::new();
+    for (i, idx) in unpack_bits(longs, bits, volume).into_iter().enumerate() {
+        let want = expected[i].as_deref().unwrap_or("minecraft:air");
+        let got = palette
+            .get(idx as usize)
+            .map_or("(past the palette)", String::as_str);
+        if got != want {
+            let i = i as i32;
+            let (x, y, z) = (i % size.0, i / size.0 / size.2, i / size.0 % size.2);
+            wrong.push(format!(
+                "{},{},{}: {} instead of {}",
+                origin.0 + x,
+                origin.1 + y,
+                origin.2 + z,
+                got,
+                want
+            ));
+        }
+    }
+    if let Some(first) = wrong.first() {
+        anyhow::bail!(
+            "{}: {} blocks read back wrong, first at {}",
+            path.display(),
+            wrong.len(),
+            first
+        );
+    }
+    Ok(())
+}
+
+/// The blocks as a dense box: a palette of block states (air first) and a
+/// palette index per cell, in Y, then Z, then X order.
+pub struct Grid {
+    pub size: (i32, i32, i32),
+    pub palette: Vec<String>,
+    pub cells: Vec<u32>,
+    /// The signs, at positions relative to the low corner.
+    pub signs: Vec<(i32, i32, i32, Vec<String>)>,
+}
+
+impl Grid {
+    /// The first block placed at a position wins, as in the litematic.
+    pub fn new(blocks: &Blocks) -> Grid {
+        let (origin, size) = bounds(&blocks.placed);
+        let mut at: HashMap<(i32, i32, i32), String> = HashMap::new();
+        for (x, y, z, name, props) in &blocks.placed {
+            at.entry((*x, *y, *z))
+                .or_insert_with(|| block_state(name, props));
+        }
+        let mut palette = vec!["minecraft:air".to_string()];
+        let mut index: HashMap<String, u32> = HashMap::new();
+        index.insert(palette[0].clone(), 0);
+        let mut cells = Vec::with_capacity((size.0 * size.1 * size.2) as usize);
+        for y in 0..size.1 {
+            for z in 0..size.2 {
+                for x in 0..size.0 {
+                    let idx = match at.get(&(origin.0 + x, origin.1 + y, origin.2 + z)) {
+                        Some(state) => *index.entry(state.clone()).or_insert_with(|| {
+                            palette.push(state.clone());
+                            palette.len() as u32 - 1
+                        }),
+                        None => 0,
+                    };
+                    cells.push(idx);
+                }
+            }
+        }
+        let signs = blocks
+            .signs
+            .iter()
+            .map(|(x, y, z, lines)| (x - origin.0, y - origin.1, z - origin.2, lines.clone()))
+            .collect();
+        Grid {
+            size,
+            palette,
+            cells,
+            signs,
+        }
+    }
+}
+
+/// Sponge schematic (`.schem`), as read by WorldEdit and FAWE. Version 2
+/// keeps the palette and block data at the top level; version 3 nests them
+/// in a `Blocks` compound under a `Schematic` root.
+fn write_sponge(blocks: &Blocks, path: &Path, opts: &WriteOptions) -> Result<()> {
+    let version = opts.sponge_version;
+    let grid = Grid::new(blocks);
+    let (width, height, length) = grid.size;
+    if width > u16::MAX as i32 || height > u16::MAX as i32 || length > u16::MAX as i32 {
+        anyhow::bail!("structure is too large for a Sponge schematic");
+    }
+
+    // Palette indices are stored as varints.
+    let mut data: Vec<i8> = Vec::with_capacity(grid.cells.len());
+    for &idx in &grid.cells {
+        let mut v = idx;
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                data.push(byte as i8);
+                break;
+            }
+            data.push((byte | 0x80) as i8);
+        }
+    }
+    let palette: Map<String, Value> = grid
+        .palette
+        .iter()
+        .enumerate()
+        .map(|(i, state)| (state.clone(), Value::Int(i as i32)))
+        .collect();
+
+    // Version 3 moves the block entity's data into a `Data` compound.
+    let block_entities: Vec<Value> = grid
+        .signs
+        .iter()
+        .map(|(x, y, z, lines)| {
+            let mut entity = Map::new();
+            entity.insert("Pos".into(), Value::IntArray(vec![*x, *y, *z]));
+            entity.insert("Id".into(), Value::String("minecraft:sign".into()));
+            if version >= 3 {
+                entity.insert("Data".into(), Value::Compound(sign_data(lines)));
+            } else {
+                entity.extend(sign_data(lines));
+            }
+            Value::Compound(entity)
+        })
+        .collect();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let mut metadata = Map::new();
+    metadata.insert("Name".into(), Value::String(opts.name.clone()));
+    metadata.insert("Author".into(), Value::String(opts.author.clone()));
+    metadata.insert("Date".into(), Value::Long(now));
+
+    let mut schematic = Map::new();
+    schematic.insert("Version".into(), Value::Int(version as i32));
+    schematic.insert("DataVersion".into(), Value::Int(DATA_VERSION));
+    schematic.insert("Metadata".into(), Value::Compound(metadata));
+    schematic.insert("Width".into(), Value::Short(width as u16 as i16));
+    schematic.insert("Height".into(), Value::Short(height as u16 as i16));
+    schematic.insert("Length".into(), Value::Short(length as u16 as i16));
+    schematic.insert("Offset".into(), Value::IntArray(vec![0, 0, 0]));
+    let blob = if version >= 3 {
+        let mut block_container = Map::new();
+        block_container.insert("Palette".into(), Value::Compound(palette));
+        block_container.insert("Data".into(), Value::ByteArray(data));
+        block_container.insert("BlockEntities".into(), Value::List(block_entities));
+        schematic.insert("Blocks".into(), Value::Compound(block_container));
+        let mut blob = nbt::Blob::new();
+        blob.insert("Schematic", Value::Compound(schematic))?;
+        blob
+    } else {
+        schematic.insert("PaletteMax".into(), Value::Int(grid.palette.len() as i32));
+        schematic.insert("Palette".into(), Value::Compound(palette));
+        schematic.insert("BlockData".into(), Value::ByteArray(data));
+        schematic.insert("BlockEntities".into(), Value::List(block_entities));
+        let mut blob = nbt::Blob::named("Schematic");
+        for (k, v) in schematic {
+            blob.insert(k, v)?;
+        }
+        blob
+    };
+
+    write_nbt(&blob, path, opts)
+}
+
+/// Largest structure a vanilla structure block saves or loads, per axis.
+const STRUCTURE_LIMIT: i32 = 48;
+
+/// Split a palette key back into its name and properties.
+pub(crate) fn parse_state(state: &str) -> (String, Vec<(String, String)>) {
+    match state.split_once('[') {
+        Some((name, props)) => (
+            name.to_string(),
+            props
+                .trim_end_matches(']')
+                .split(',')
+                .filter_map(|kv| kv.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        ),
+        None => (state.to_string(), Vec::new()),
+    }
+}
+
+/// Vanilla structure-block file (`.nbt`). A circuit larger than 48 blocks
+/// along any axis is split into pieces named `<stem>_x<X>_y<Y>_z<Z>.nbt`,
+/// after each piece's offset from the first.
+fn write_structure(blocks: &Blocks, path: &Path, opts: &WriteOptions) -> Result<()> {
+    let grid = Grid::new(blocks);
+    let (width, height, length) = grid.size;
+    let palette: Vec<Value> = grid
+        .palette
+        .iter()
+        .map(|state| {
+            let (name, props) = parse_state(state);
+            let mut entry = Map::new();
+            entry.insert("Name".into(), Value::String(name));
+            if !props.is_empty() {
+                let props = props
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect();
+                entry.insert("Properties".into(), Value::Compound(props));
+            }
+            Value::Compound(entry)
+        })
+        .collect();
+
+    let sign_at: HashMap<(i32, i32, i32), &Vec<String>> = grid
+        .signs
+        .iter()
+        .map(|(x, y, z, lines)| ((*x, *y, *z), lines))
+        .collect();
+
+    let split = width > STRUCTURE_LIMIT || height > STRUCTURE_LIMIT || length > STRUCTURE_LIMIT;
+    if split && opts.stdout {
+        anyhow::bail!(
+            "{}x{}x{} is too big for one structure file, so it cannot go to --stdout",
+            width,
+            height,
+            length
+        );
+    }
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("circuit");
+    for py in (0..height).step_by(STRUCTURE_LIMIT as usize) {
+        for pz in (0..length).step_by(STRUCTURE_LIMIT as usize) {
+            for px in (0..width).step_by(STRUCTURE_LIMIT as usize) {
+                let size = (
+                    (width - px).min(STRUCTURE_LIMIT),
+                    (height - py).min(STRUCTURE_LIMIT),
+                    (length - pz).min(STRUCTURE_LIMIT),
+                );
+                let mut block_list = Vec::new();
+                for y in 0..size.1 {
+                    for z in 0..size.2 {
+                        for x in 0..size.0 {
+                            let cell = ((py + y) * length + pz + z) * width + px + x;
+                            let mut block = Map::new();
+                            block.insert(
+                                "state".into(),
+                                Value::Int(grid.cells[cell as usize] as i32),
+                            );
+                            block.insert(
+                                "pos".into(),
+                                Value::List(vec![Value::Int(x), Value::Int(y), Value::Int(z)]),
+                            );
+                            if let Some(lines) = sign_at.get(&(px + x, py + y, pz + z)) {
+                                let mut data = sign_data(lines);
+                                data.insert("id".into(), Value::String("minecraft:sign".into()));
+                                block.insert("nbt".into(), Value::Compound(data));
+                            }
+                            block_list.push(Value::Compound(block));
+                        }
+                    }
+                }
+                let mut blob = nbt::Blob::new();
+                blob.insert("DataVersion", Value::Int(DATA_VERSION))?;
+                blob.insert(
+                    "size",
+                    Value::List(vec![
+                        Value::Int(size.0),
+                        Value::Int(size.1),
+                        Value::Int(size.2),
+                    ]),
+                )?;
+                blob.insert("palette", Value::List(palette.clone()))?;
+                blob.insert("blocks", Value::List(block_list))?;
+                blob.insert("entities", Value::List(vec![]))?;
+
+                let piece = if split {
+                    path.with_file_name(format!("{}_x{}_y{}_z{}.nbt", stem, px, py, pz))
+                } else {
+                    path.to_path_buf()
+                };
+                write_nbt(&blob, &piece, opts)?;
+                if split {
+                    status!("Wrote structure piece {}", piece.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// MCEdit/WorldEdit schematic (`.schematic`) with the numeric block IDs
+/// of 1.12 and earlier, for legacy servers.
+fn write_legacy(blocks: &Blocks, path: &Path, opts: &WriteOptions) -> Result<()> {
+    let grid = Grid::new(blocks);
+    let (width, height, length) = grid.size;
+    if width > i16::MAX as i32 || height > i16::MAX as i32 || length > i16::MAX as i32 {
+        anyhow::bail!("structure is too large for a legacy schematic");
+    }
+    let ids = grid
+        .palette
+        .iter()
+        .map(|state| {
+            let (name, props) = parse_state(state);
+            legacy::legacy_id(&name, &props)
+                .ok_or_else(|| anyhow::anyhow!("`{}` has no pre-1.13 block ID", state))
+        })
+        .collect::<Result<Vec<(u8, u8)>>>()?;
+
+    let mut blob = nbt::Blob::named("Schematic");
+    blob.insert("Width", Value::Short(width as i16))?;
+    blob.insert("Height", Value::Short(height as i16))?;
+    blob.insert("Length", Value::Short(length as i16))?;
+    blob.insert("Materials", Value::String("Alpha".into()))?;
+    blob.insert(
+        "Blocks",
+        Value::ByteArray(grid.cells.iter().map(|&c| ids[c as usize].0 as i8).collect()),
+    )?;
+    blob.insert(
+        "Data",
+        Value::ByteArray(grid.cells.iter().map(|&c| ids[c as usize].1 as i8).collect()),
+    )?;
+    blob.insert("Entities", Value::List(vec![]))?;
+    // Signs before 1.13 hold each line as a JSON text component.
+    let tile_entities = grid
+        .signs
+        .iter()
+        .map(|(x, y, z, lines)| {
+            let mut entity = Map::new();
+            entity.insert("id".into(), Value::String("Sign".into()));
+            entity.insert("x".into(), Value::Int(*x));
+            entity.insert("y".into(), Value::Int(*y));
+            entity.insert("z".into(), Value::Int(*z));
+            for i in 0..4 {
+                let line = lines.get(i).map_or("", String::as_str);
+                let text = serde_json::json!({ "text": line }).to_string();
+                entity.insert(format!("Text{}", i + 1), Value::String(text));
+            }
+            Value::Compound(entity)
+        })
+        .collect();
+    blob.insert("TileEntities", Value::List(tile_entities))?;
+
+    write_nbt(&blob, path, opts)
+}
+
+/// `setblock` commands that build the structure relative to the command's
+/// position, with the circuit's low corner one block east of it.
+pub fn setblock_commands(blocks: &Blocks) -> Vec<String> {
+    let grid = Grid::new(blocks);
+    let (width, height, length) = grid.size;
+    let sign_at: HashMap<(i32, i32, i32), &Vec<String>> = grid
+        .signs
+        .iter()
+        .map(|(x, y, z, lines)| ((*x, *y, *z), lines))
+        .collect();
+    let mut commands = Vec::new();
+    // Bottom up, so attached blocks like torches find their support.
+    for y in 0..height {
+        for z in 0..length {
+            for x in 0..width {
+                let cell = grid.cells[((y * length + z) * width + x) as usize];
+                if cell != 0 {
+                    let mut command = format!(
+                        "setblock ~{} ~{} ~{} {}",
+                        x + 1,
+                        y,
+                        z,
+                        grid.palette[cell as usize]
+                    );
+                    if let Some(lines) = sign_at.get(&(x, y, z)) {
+                        command.push_str(&sign_snbt(lines));
+                    }
+                    commands.push(command);
+                }
+            }
+        }
+    }
+    commands
+}
+
+/// A sign's front lines as the SNBT that follows its block state in a
+/// `setblock` command.
+fn sign_snbt(lines: &[String]) -> String {
+    let mut messages: Vec<String> = lines
+        .iter()
+        .take(4)
+        .map(|l| serde_json::Value::String(l.clone()).to_string())
+        .collect();
+    messages.resize(4, "\"\"".into());
+    format!("{{front_text:{{messages:[{}]}}}}", messages.join(","))
+}
+
+/// A `.mcfunction` file that builds the circuit where it is run.
+fn write_mcfunction(blocks: &Blocks, path: &Path) -> Result<()> {
+    let mut text = String::from("# Generated by schemlogica. Run from the circuit's west corner.\n");
+    for cmd in setblock_commands(blocks) {
+        text.push_str(&cmd);
+        text.push('\n');
+    }
+    std::fs::write(path, text)?;
     Ok(())
 }
@@ -1,12 +1,15 @@
 use anyhow::Result;
 use serde_json::Value;
+use std::collections::HashMap;
 
+/// What checking a program finds out about it.
 pub struct Semantics {
-    pub vars: Vec<String>,
+    /// Declared bit widths from `let a: u4;` annotations.
+    pub widths: HashMap<String, usize>,
 }
 
 pub fn analyze(program: &Value) -> Result<Semantics> {
-    let mut vars = Vec::new();
+    let mut widths = HashMap::new();
     if let Some(body) = program.get("body").and_then(|b| b.as_array()) {
         for stmt in body {
             if let Some(t) = stmt.get("type").and_then(|s| s.as_str()) {
@@ -22,7 +25,9 @@ pub fn analyze(program: &Value) -> Result<Semantics> {
                             if let Some(id) = d.get("id") {
                                 if id.get("type").and_then(|s| s.as_str()) == Some("Identifier") {
                                     if let Some(name) = id.get("name").and_then(|n| n.as_str()) {
-                                        vars.push(name.to_string());
+                                        if let Some(w) = d.get("width").and_then(|w| w.as_u64()) {
+                                            widths.insert(name.to_string(), w as usize);
+                                        }
                                     }
                                 } else {
                                     anyhow::bail!("Destructuring not supported");
@@ -34,5 +39,45 @@ pub fn analyze(program: &Value) -> Result<Semantics> {
             }
         }
     }
-    Ok(Semantics { vars })
+    Ok(Semantics { widths })
+}
+
+/// Identifiers the program reads but never declares or assigns, in order of
+/// first use. Inline `-e` expressions treat these as inputs.
+pub fn free_variables(program: &Value) -> Vec<String> {
+    fn walk(v: &Value, bound: &mut Vec<String>, out: &mut Vec<String>) {
+        match v.get("type").and_then(|t| t.as_str()) {
+            Some("Identifier") => {
+                let name = v.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+                if !bound.iter().any(|b| b == name) && !out.iter().any(|o| o == name) {
+                    out.push(name.to_string());
+                }
+            }
+            Some("AssignmentExpression") => walk(&v["right"], bound, out),
+            Some("VariableDeclarator") => walk(&v["init"], bound, out),
+            Some("ArrowFunctionExpression") => {
+                let params = v["params"].as_array().cloned().unwrap_or_default();
+                let depth = bound.len();
+                bound.extend(params.iter().filter_map(|p| p.as_str().map(String::from)));
+                walk(&v["body"], bound, out);
+                bound.truncate(depth);
+            }
+            _ => match v {
+                Value::Array(items) => items.iter().for_each(|i| walk(i, bound, out)),
+                Value::Object(fields) => fields.values().for_each(|f| walk(f, bound, out)),
+                _ => {}
+            },
+        }
+    }
+
+    let mut defined = Vec::new();
+    for stmt in program["body"].as_array().into_iter().flatten() {
+        for d in stmt["declarations"].as_array().into_iter().flatten() {
+            defined.extend(d["id"]["name"].as_str().map(String::from));
+        }
+        defined.extend(stmt["expression"]["left"]["name"].as_str().map(String::from));
+    }
+    let mut out = Vec::new();
+    walk(program, &mut defined, &mut out);
+    out
 }
@@ -0,0 +1,174 @@
+//! Truth-table frontend. Reads a CSV table and synthesizes a minimized
+//! sum-of-products circuit for each output column.
+//!
+//! The header names the columns. An empty header cell separates inputs
+//! from outputs; without one, the last column is the only output. Input
+//! cells are `0`, `1` or `-` (either); output cells are `0`, `1` or `x`
+//! (don't care). Input combinations with no row drive the outputs low.
+
+use crate::compiler::{Circuit, Ctx};
+use crate::minimize::{minimize, Cube};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Limit on input columns; the table has 2^n rows.
+const MAX_INPUTS: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    Zero,
+    One,
+    DontCare,
+}
+
+/// Parse and lower a CSV truth table.
+pub fn compile(src: &str) -> Result<Circuit> {
+    let mut rows = src
+        .lines()
+        .enumerate()
+        .map(|(n, l)| (n + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(n, l)| (n, l.split(',').map(str::trim).collect::<Vec<_>>()));
+    let Some((_, header)) = rows.next() else {
+        anyhow::bail!("empty truth table");
+    };
+    let (inputs, outputs, skip) = match header.iter().position(|c| c.is_empty()) {
+        Some(i) => (&header[..i], &header[i + 1..], Some(i)),
+        None if header.len() >= 2 => (
+            &header[..header.len() - 1],
+            &header[header.len() - 1..],
+            None,
+        ),
+        None => anyhow::bail!("a truth table needs input and output columns"),
+    };
+    if inputs.is_empty() || outputs.is_empty() {
+        anyhow::bail!("a truth table needs input and output columns");
+    }
+    if inputs.len() > MAX_INPUTS {
+        anyhow::bail!(
+            "{} input columns is too many; at most {} are supported",
+            inputs.len(),
+            MAX_INPUTS
+        );
+    }
+    if let Some(dup) = header
+        .iter()
+        .enumerate()
+        .find(|(i, c)| !c.is_empty() && header[..*i].contains(c))
+    {
+        anyhow::bail!("column `{}` appears twice", dup.1);
+    }
+
+    // Per output: the value at each input combination, if the table says.
+    let n = inputs.len();
+    let mut table: Vec<HashMap<u32, Cell>> = vec![HashMap::new(); outputs.len()];
+    for (line, cells) in rows {
+        let at = |msg: String| anyhow::anyhow!("line {}: {}", line, msg);
+        if cells.len() != header.len() {
+            return Err(at(format!(
+                "expected {} cells, found {}",
+                header.len(),
+                cells.len()
+            )));
+        }
+        let cells: Vec<&str> = cells
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != skip)
+            .map(|(_, c)| *c)
+            .collect();
+        let (ins, outs) = cells.split_at(n);
+        let mut fixed = 0u32;
+        let mut free = 0u32;
+        for (bit, cell) in ins.iter().enumerate() {
+            match *cell {
+                "0" => {}
+                "1" => fixed |= 1 << bit,
+                "-" | "x" | "X" => free |= 1 << bit,
+                c => return Err(at(format!("invalid input cell `{}`", c))),
+            }
+        }
+        let outs: Vec<Cell> = outs
+            .iter()
+            .map(|c| match *c {
+                "0" => Ok(Cell::Zero),
+                "1" => Ok(Cell::One),
+                "x" | "X" | "-" | "?" => Ok(Cell::DontCare),
+                c => Err(at(format!("invalid output cell `{}`", c))),
+            })
+            .collect::<Result<_>>()?;
+        // Enumerate every combination the `-` inputs stand for.
+        let mut sub = free;
+        loop {
+            let minterm = fixed | sub;
+            for (o, cell) in outs.iter().enumerate() {
+                if let Some(prev) = table[o].insert(minterm, *cell) {
+                    if prev != *cell {
+                        let bits: String = (0..n)
+                            .map(|b| if minterm >> b & 1 == 1 { '1' } else { '0' })
+                            .collect();
+                        return Err(at(format!(
+                            "conflicting values for `{}` at inputs {}",
+                            outputs[o], bits
+                        )));
+                    }
+                }
+            }
+            if sub == 0 {
+                break;
+            }
+            sub = (sub - 1) & free;
+        }
+    }
+
+    let mut ctx = Ctx::new();
+    let levers: Vec<String> = inputs.iter().map(|name| ctx.input(name)).collect();
+    let mut inverted: HashMap<usize, String> = HashMap::new();
+    let mut terms: HashMap<Cube, String> = HashMap::new();
+    let mut out_sigs = Vec::new();
+    for (column, name) in table.iter().zip(outputs) {
+        let mut on: Vec<u32> = column
+            .iter()
+            .filter(|(_, c)| **c == Cell::One)
+            .map(|(m, _)| *m)
+            .collect();
+        let mut dc: Vec<u32> = column
+            .iter()
+            .filter(|(_, c)| **c == Cell::DontCare)
+            .map(|(m, _)| *m)
+            .collect();
+        // Sorted so the greedy cover is deterministic.
+        on.sort();
+        dc.sort();
+        let mut products = Vec::new();
+        for cube in minimize(n, &on, &dc) {
+            // Products are shared between outputs.
+            if let Some(sig) = terms.get(&cube) {
+                products.push(sig.clone());
+                continue;
+            }
+            let mut lits = Vec::new();
+            for (bit, lever) in levers.iter().enumerate() {
+                if cube.mask >> bit & 1 == 1 {
+                    continue;
+                }
+                if cube.value >> bit & 1 == 1 {
+                    lits.push(lever.clone());
+                } else {
+                    let not = inverted
+                        .entry(bit)
+                        .or_insert_with(|| ctx.emit("NOT", vec![lever.clone()]));
+                    lits.push(not.clone());
+                }
+            }
+            let sig = ctx
+                .reduce_balanced(lits, |c, x, y| c.emit("AND", vec![x, y]))
+                .unwrap_or_else(|| "CONST_TRUE_SIG".into());
+            terms.insert(cube, sig.clone());
+            products.push(sig);
+        }
+        let sop = ctx.or_all(products);
+        out_sigs.push((ctx.emit("BUF", vec![sop]), name.to_string()));
+    }
+    Ok(ctx.finish(inputs.iter().map(|s| s.to_string()).collect(), out_sigs))
+}
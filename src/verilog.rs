@@ -0,0 +1,1606 @@
+//! Verilog subset frontend. Parses structural and simple behavioral Verilog
+//! (modules and instances, `assign`, gate primitives, `always @(*)` and
+//! `always @(posedge clk)`) and lowers it into the same `Circuit` as the JS
+//! compiler.
+//!
+//! Nets are unsigned and operands are zero-extended to a common width. Reads
+//! in a clocked block see the registered value, as with nonblocking `<=`.
+
+use crate::compiler::{next_id, Bus, Circuit, Ctx};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+// ---------------------------------------------------------------------------
+// Lexer
+
+#[derive(Clone, PartialEq, Debug)]
+enum Tok {
+    Ident(String),
+    Number(String),
+    Sym(&'static str),
+    Eof,
+}
+
+impl std::fmt::Display for Tok {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Tok::Ident(s) | Tok::Number(s) => write!(f, "`{}`", s),
+            Tok::Sym(s) => write!(f, "`{}`", s),
+            Tok::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+/// Longest symbols first so `<=` wins over `<`.
+const SYMBOLS: &[&str] = &[
+    "===", "!==", "~&", "~|", "~^", "^~", "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "(", ")",
+    "[", "]", "{", "}", ";", ",", ":", ".", "#", "@", "=", "?", "+", "-", "*", "/", "%", "&", "|",
+    "^", "~", "!", "<", ">",
+];
+
+fn lex(src: &str) -> Result<Vec<(Tok, usize)>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut toks = Vec::new();
+    let (mut i, mut line) = (0, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if chars[i..].starts_with(&['/', '/']) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if chars[i..].starts_with(&['/', '*'])
+            || (chars[i..].starts_with(&['(', '*']) && chars.get(i + 2) != Some(&')'))
+        {
+            // Block comments and `(* attributes *)` (but not `@(*)`) are
+            // both skipped.
+            let close = if c == '/' { '/' } else { ')' };
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&close)) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c == '`' {
+            anyhow::bail!("line {}: compiler directives are not supported", line);
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "_$".contains(chars[i])) {
+                i += 1;
+            }
+            toks.push((Tok::Ident(chars[start..i].iter().collect()), line));
+        } else if c.is_ascii_digit() || c == '\'' {
+            // Sized literals like 4'b1010 may have spaces around the quote.
+            let mut text = String::new();
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+                text.push(chars[i]);
+                i += 1;
+            }
+            let mut j = i;
+            while j < chars.len() && chars[j].is_whitespace() && chars[j] != '\n' {
+                j += 1;
+            }
+            if chars.get(j) == Some(&'\'') {
+                i = j + 1;
+                text.push('\'');
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || "_?".contains(chars[i]))
+                {
+                    text.push(chars[i]);
+                    i += 1;
+                    // Allow a space between the base and the digits.
+                    if text.len() >= 2 && "bBoOdDhH".contains(chars[i - 1]) {
+                        while i < chars.len() && chars[i] == ' ' {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            toks.push((Tok::Number(text), line));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+            let Some(sym) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) else {
+                anyhow::bail!("line {}: unexpected character `{}`", line, c);
+            };
+            toks.push((Tok::Sym(sym), line));
+            i += sym.len();
+        }
+    }
+    toks.push((Tok::Eof, line));
+    Ok(toks)
+}
+
+// ---------------------------------------------------------------------------
+// AST
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Ident(String),
+    Number { width: Option<usize>, value: u64 },
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Box<Expr>, Box<Expr>),
+    Concat(Vec<Expr>),
+    Repeat(Box<Expr>, Vec<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Block(Vec<Stmt>),
+    Assign(Expr, Expr),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    Case(Expr, Vec<(Vec<Expr>, Stmt)>, Option<Box<Stmt>>),
+    Empty,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Dir {
+    Input,
+    Output,
+}
+
+#[derive(Debug)]
+struct NetDecl {
+    name: String,
+    dir: Option<Dir>,
+    range: Option<(Expr, Expr)>,
+    line: usize,
+}
+
+/// Clock edge of a clocked `always` block.
+#[derive(Debug)]
+struct Edge {
+    negedge: bool,
+    clock: Expr,
+}
+
+#[derive(Debug)]
+enum Item {
+    Assign(Expr, Expr, usize),
+    Always(Option<Edge>, Stmt, usize),
+    /// Built-in gate primitive: output first, then inputs.
+    Gate(String, Vec<Expr>, usize),
+    Instance {
+        module: String,
+        name: String,
+        params: Vec<(Option<String>, Expr)>,
+        ports: Vec<(Option<String>, Option<Expr>)>,
+        line: usize,
+    },
+}
+
+#[derive(Debug)]
+struct Module {
+    name: String,
+    ports: Vec<String>,
+    /// Parameters in declaration order; `true` marks a `localparam`.
+    params: Vec<(String, Expr, bool)>,
+    nets: Vec<NetDecl>,
+    items: Vec<Item>,
+}
+
+const GATE_PRIMITIVES: &[&str] = &["and", "or", "nand", "nor", "xor", "xnor", "not", "buf"];
+
+// ---------------------------------------------------------------------------
+// Parser
+
+struct Parser {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.pos].0
+    }
+
+    fn line(&self) -> usize {
+        self.toks[self.pos].1
+    }
+
+    fn next(&mut self) -> Tok {
+        let t = self.toks[self.pos].0.clone();
+        if t != Tok::Eof {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn is_sym(&self, s: &str) -> bool {
+        matches!(self.peek(), Tok::Sym(t) if *t == s)
+    }
+
+    fn is_word(&self, w: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(t) if t == w)
+    }
+
+    fn eat_sym(&mut self, s: &str) -> bool {
+        let found = self.is_sym(s);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_word(&mut self, w: &str) -> bool {
+        let found = self.is_word(w);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_sym(&mut self, s: &str) -> Result<()> {
+        if !self.eat_sym(s) {
+            anyhow::bail!(
+                "line {}: expected `{}`, found {}",
+                self.line(),
+                s,
+                self.peek()
+            );
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.next() {
+            Tok::Ident(s) => Ok(s),
+            t => anyhow::bail!("line {}: expected an identifier, found {}", self.line(), t),
+        }
+    }
+
+    fn modules(&mut self) -> Result<Vec<Module>> {
+        let mut modules = Vec::new();
+        while *self.peek() != Tok::Eof {
+            if !(self.eat_word("module") || self.eat_word("macromodule")) {
+                anyhow::bail!(
+                    "line {}: expected `module`, found {}",
+                    self.line(),
+                    self.peek()
+                );
+            }
+            modules.push(self.module()?);
+        }
+        Ok(modules)
+    }
+
+    fn module(&mut self) -> Result<Module> {
+        let mut m = Module {
+            name: self.ident()?,
+            ports: Vec::new(),
+            params: Vec::new(),
+            nets: Vec::new(),
+            items: Vec::new(),
+        };
+        if self.eat_sym("#") {
+            self.expect_sym("(")?;
+            while !self.eat_sym(")") {
+                self.eat_word("parameter");
+                let name = self.ident()?;
+                self.expect_sym("=")?;
+                m.params.push((name, self.expr()?, false));
+                if !self.is_sym(")") {
+                    self.expect_sym(",")?;
+                }
+            }
+        }
+        if self.eat_sym("(") {
+            let mut dir = None;
+            let mut range = None;
+            while !self.eat_sym(")") {
+                let line = self.line();
+                // ANSI style: `input [3:0] a, b, output y`.
+                if let Some(d) = self.direction()? {
+                    dir = Some(d);
+                    self.net_kind()?;
+                    range = self.range()?;
+                }
+                let name = self.ident()?;
+                if dir.is_some() {
+                    m.nets.push(NetDecl {
+                        name: name.clone(),
+                        dir,
+                        range: range.clone(),
+                        line,
+                    });
+                }
+                m.ports.push(name);
+                if !self.is_sym(")") {
+                    self.expect_sym(",")?;
+                }
+            }
+        }
+        self.expect_sym(";")?;
+        while !self.eat_word("endmodule") {
+            self.module_item(&mut m)?;
+        }
+        Ok(m)
+    }
+
+    fn direction(&mut self) -> Result<Option<Dir>> {
+        if self.eat_word("input") {
+            Ok(Some(Dir::Input))
+        } else if self.eat_word("output") {
+            Ok(Some(Dir::Output))
+        } else if self.is_word("inout") {
+            anyhow::bail!("line {}: inout ports are not supported", self.line())
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Skip an optional net kind; everything is an unsigned bit vector.
+    fn net_kind(&mut self) -> Result<()> {
+        for kind in ["wire", "reg", "logic"] {
+            if self.eat_word(kind) {
+                break;
+            }
+        }
+        if self.is_word("signed") {
+            anyhow::bail!("line {}: signed nets are not supported", self.line());
+        }
+        Ok(())
+    }
+
+    fn range(&mut self) -> Result<Option<(Expr, Expr)>> {
+        if !self.eat_sym("[") {
+            return Ok(None);
+        }
+        let msb = self.expr()?;
+        self.expect_sym(":")?;
+        let lsb = self.expr()?;
+        self.expect_sym("]")?;
+        Ok(Some((msb, lsb)))
+    }
+
+    fn module_item(&mut self, m: &mut Module) -> Result<()> {
+        let line = self.line();
+        let word = match self.peek() {
+            Tok::Ident(w) => w.clone(),
+            t => anyhow::bail!("line {}: expected a module item, found {}", line, t),
+        };
+        match word.as_str() {
+            "input" | "output" | "inout" | "wire" | "reg" | "logic" => {
+                let dir = self.direction()?;
+                if dir.is_none() {
+                    self.next();
+                }
+                self.net_kind()?;
+                let range = self.range()?;
+                loop {
+                    let name = self.ident()?;
+                    if self.is_sym("[") {
+                        anyhow::bail!("line {}: memories (arrays of nets) are not supported", line);
+                    }
+                    if self.eat_sym("=") {
+                        let rhs = self.expr()?;
+                        m.items
+                            .push(Item::Assign(Expr::Ident(name.clone()), rhs, line));
+                    }
+                    m.nets.push(NetDecl {
+                        name,
+                        dir,
+                        range: range.clone(),
+                        line,
+                    });
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")
+            }
+            "parameter" | "localparam" => {
+                self.next();
+                let local = word == "localparam";
+                loop {
+                    let name = self.ident()?;
+                    self.expect_sym("=")?;
+                    m.params.push((name, self.expr()?, local));
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")
+            }
+            "assign" => {
+                self.next();
+                loop {
+                    let lhs = self.expr()?;
+                    self.expect_sym("=")?;
+                    let rhs = self.expr()?;
+                    m.items.push(Item::Assign(lhs, rhs, line));
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")
+            }
+            "always" | "always_comb" | "always_ff" | "always_latch" => {
+                self.next();
+                if word == "always_latch" {
+                    anyhow::bail!("line {}: always_latch is not supported", line);
+                }
+                let edge = if word == "always_comb" {
+                    None
+                } else {
+                    self.sensitivity()?
+                };
+                if word == "always_ff" && edge.is_none() {
+                    anyhow::bail!("line {}: always_ff needs a clock edge", line);
+                }
+                let body = self.stmt()?;
+                m.items.push(Item::Always(edge, body, line));
+                Ok(())
+            }
+            w if GATE_PRIMITIVES.contains(&w) => {
+                self.next();
+                loop {
+                    if let Tok::Ident(_) = self.peek() {
+                        self.next();
+                    }
+                    self.expect_sym("(")?;
+                    let args = self.expr_list(")")?;
+                    m.items.push(Item::Gate(word.clone(), args, line));
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")
+            }
+            "initial" | "function" | "task" | "generate" | "genvar" | "integer" | "specify" => {
+                anyhow::bail!("line {}: `{}` is not supported", line, word)
+            }
+            _ => self.instance(m),
+        }
+    }
+
+    /// Parse `@(*)`, `@*`, `@(a or b)` or `@(posedge clk)`. Returns the
+    /// clock edge for clocked blocks.
+    fn sensitivity(&mut self) -> Result<Option<Edge>> {
+        let line = self.line();
+        self.expect_sym("@")?;
+        if self.eat_sym("*") {
+            return Ok(None);
+        }
+        self.expect_sym("(")?;
+        let mut edges = Vec::new();
+        let mut levels = false;
+        loop {
+            if self.eat_sym("*") {
+                levels = true;
+            } else if self.is_word("posedge") || self.is_word("negedge") {
+                let negedge = self.next() == Tok::Ident("negedge".into());
+                edges.push(Edge {
+                    negedge,
+                    clock: self.expr()?,
+                });
+            } else {
+                self.expr()?;
+                levels = true;
+            }
+            if !(self.eat_sym(",") || self.eat_word("or")) {
+                break;
+            }
+        }
+        self.expect_sym(")")?;
+        match (edges.len(), levels) {
+            (0, _) => Ok(None),
+            (1, false) => Ok(edges.pop()),
+            _ => anyhow::bail!(
+                "line {}: only a single clock edge is supported; use a synchronous reset",
+                line
+            ),
+        }
+    }
+
+    fn stmt(&mut self) -> Result<Stmt> {
+        let line = self.line();
+        if self.eat_sym(";") {
+            return Ok(Stmt::Empty);
+        }
+        if self.eat_word("begin") {
+            if self.eat_sym(":") {
+                self.ident()?;
+            }
+            let mut body = Vec::new();
+            while !self.eat_word("end") {
+                body.push(self.stmt()?);
+            }
+            return Ok(Stmt::Block(body));
+        }
+        if self.eat_word("if") {
+            self.expect_sym("(")?;
+            let cond = self.expr()?;
+            self.expect_sym(")")?;
+            let then = Box::new(self.stmt()?);
+            let other = if self.eat_word("else") {
+                Some(Box::new(self.stmt()?))
+            } else {
+                None
+            };
+            return Ok(Stmt::If(cond, then, other));
+        }
+        if self.is_word("case") || self.is_word("casez") || self.is_word("casex") {
+            if !self.eat_word("case") {
+                anyhow::bail!("line {}: casez/casex are not supported", line);
+            }
+            self.expect_sym("(")?;
+            let subject = self.expr()?;
+            self.expect_sym(")")?;
+            let mut arms = Vec::new();
+            let mut default = None;
+            while !self.eat_word("endcase") {
+                if self.eat_word("default") {
+                    self.eat_sym(":");
+                    default = Some(Box::new(self.stmt()?));
+                } else {
+                    let labels = self.expr_list(":")?;
+                    arms.push((labels, self.stmt()?));
+                }
+            }
+            return Ok(Stmt::Case(subject, arms, default));
+        }
+        let lhs = self.expr_lvalue()?;
+        if !(self.eat_sym("=") || self.eat_sym("<=")) {
+            anyhow::bail!("line {}: expected a statement, found {}", line, self.peek());
+        }
+        let rhs = self.expr()?;
+        self.expect_sym(";")?;
+        Ok(Stmt::Assign(lhs, rhs))
+    }
+
+    /// An assignment target; parsed without binary operators so that `<=`
+    /// is not taken for a comparison.
+    fn expr_lvalue(&mut self) -> Result<Expr> {
+        self.postfix()
+    }
+
+    fn instance(&mut self, m: &mut Module) -> Result<()> {
+        let line = self.line();
+        let module = self.ident()?;
+        let mut params = Vec::new();
+        if self.eat_sym("#") {
+            self.expect_sym("(")?;
+            for (name, value) in self.connections()? {
+                let Some(value) = value else {
+                    anyhow::bail!("line {}: empty parameter override", line);
+                };
+                params.push((name, value));
+            }
+        }
+        loop {
+            let name = self.ident()?;
+            self.expect_sym("(")?;
+            let ports = self.connections()?;
+            m.items.push(Item::Instance {
+                module: module.clone(),
+                name,
+                params: params.clone(),
+                ports,
+                line,
+            });
+            if !self.eat_sym(",") {
+                break;
+            }
+        }
+        self.expect_sym(";")
+    }
+
+    /// Positional `(a, b)` or named `(.x(a), .y())` connections, after the
+    /// opening parenthesis.
+    fn connections(&mut self) -> Result<Vec<(Option<String>, Option<Expr>)>> {
+        let mut conns = Vec::new();
+        while !self.eat_sym(")") {
+            if self.eat_sym(".") {
+                let name = self.ident()?;
+                self.expect_sym("(")?;
+                let expr = if self.eat_sym(")") {
+                    None
+                } else {
+                    let e = self.expr()?;
+                    self.expect_sym(")")?;
+                    Some(e)
+                };
+                conns.push((Some(name), expr));
+            } else {
+                conns.push((None, Some(self.expr()?)));
+            }
+            if !self.is_sym(")") {
+                self.expect_sym(",")?;
+            }
+        }
+        Ok(conns)
+    }
+
+    /// Comma-separated expressions up to and including `end`.
+    fn expr_list(&mut self, end: &str) -> Result<Vec<Expr>> {
+        let mut list = vec![self.expr()?];
+        while self.eat_sym(",") {
+            list.push(self.expr()?);
+        }
+        self.expect_sym(end)?;
+        Ok(list)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let cond = self.binary(0)?;
+        if !self.eat_sym("?") {
+            return Ok(cond);
+        }
+        let then = self.expr()?;
+        self.expect_sym(":")?;
+        let other = self.expr()?;
+        Ok(Expr::Cond(Box::new(cond), Box::new(then), Box::new(other)))
+    }
+
+    /// Precedence climbing over the binary operators, loosest first.
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["|", "~|"],
+            &["^", "~^", "^~"],
+            &["&", "~&"],
+            &["==", "!=", "===", "!=="],
+            &["<", "<=", ">", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Tok::Sym(s) if LEVELS[level].contains(s) => *s,
+                _ => return Ok(lhs),
+            };
+            if op == "===" || op == "!==" {
+                anyhow::bail!("line {}: `{}` is not supported; use `==`", self.line(), op);
+            }
+            self.next();
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        for op in ["!", "~&", "~|", "~^", "^~", "~", "&", "|", "^", "-", "+"] {
+            if self.eat_sym(op) {
+                let arg = self.unary()?;
+                return Ok(if op == "+" {
+                    arg
+                } else {
+                    Expr::Unary(op, Box::new(arg))
+                });
+            }
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut e = self.primary()?;
+        while self.eat_sym("[") {
+            let hi = self.expr()?;
+            if self.eat_sym(":") {
+                let lo = self.expr()?;
+                e = Expr::Slice(Box::new(e), Box::new(hi), Box::new(lo));
+            } else {
+                e = Expr::Index(Box::new(e), Box::new(hi));
+            }
+            self.expect_sym("]")?;
+        }
+        Ok(e)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let line = self.line();
+        match self.next() {
+            Tok::Ident(name) => Ok(Expr::Ident(name)),
+            Tok::Number(text) => number(&text).map_err(|e| anyhow::anyhow!("line {}: {}", line, e)),
+            Tok::Sym("(") => {
+                let e = self.expr()?;
+                self.expect_sym(")")?;
+                Ok(e)
+            }
+            Tok::Sym("{") => {
+                let first = self.expr()?;
+                if self.eat_sym("{") {
+                    let parts = self.expr_list("}")?;
+                    self.expect_sym("}")?;
+                    return Ok(Expr::Repeat(Box::new(first), parts));
+                }
+                let mut parts = vec![first];
+                while self.eat_sym(",") {
+                    parts.push(self.expr()?);
+                }
+                self.expect_sym("}")?;
+                Ok(Expr::Concat(parts))
+            }
+            t => anyhow::bail!("line {}: expected an expression, found {}", line, t),
+        }
+    }
+}
+
+/// Parse a literal such as `12`, `4'b1010`, `8'hff` or `'d3`.
+fn number(text: &str) -> Result<Expr> {
+    let text = text.replace('_', "");
+    let Some((size, rest)) = text.split_once('\'') else {
+        let value = text
+            .parse()
+            .map_err(|_| anyhow::anyhow!("number `{}` is too large", text))?;
+        return Ok(Expr::Number { width: None, value });
+    };
+    let width = match size {
+        "" => None,
+        s => match s.parse::<usize>() {
+            Ok(w) if (1..=64).contains(&w) => Some(w),
+            _ => anyhow::bail!("literal width must be 1 to 64 bits, got `{}`", s),
+        },
+    };
+    let mut rest = rest.chars();
+    let radix = match rest.next().map(|c| c.to_ascii_lowercase()) {
+        Some('b') => 2,
+        Some('o') => 8,
+        Some('d') => 10,
+        Some('h') => 16,
+        _ => anyhow::bail!("invalid base in literal `{}`", text),
+    };
+    let digits: String = rest.collect();
+    if digits.chars().any(|c| "xXzZ?".contains(c)) {
+        anyhow::bail!("x/z digits are not supported in `{}`", text);
+    }
+    let value = u64::from_str_radix(&digits, radix)
+        .map_err(|_| anyhow::anyhow!("invalid literal `{}`", text))?;
+    let value = match width {
+        Some(w) if w < 64 => value & ((1 << w) - 1),
+        _ => value,
+    };
+    Ok(Expr::Number { width, value })
+}
+
+// ---------------------------------------------------------------------------
+// Elaboration
+
+/// A declared net: one reserved signal per bit, LSB first.
+struct Net {
+    bits: Bus,
+    lsb: i64,
+    dir: Option<Dir>,
+}
+
+/// Names visible inside one module instance.
+struct Scope {
+    path: String,
+    params: HashMap<String, i64>,
+    nets: HashMap<String, Net>,
+}
+
+/// Values assigned so far inside an `always` block, by reserved bit.
+type Env = HashMap<String, String>;
+
+struct Elab<'a> {
+    ctx: Ctx,
+    modules: HashMap<&'a str, &'a Module>,
+    /// Reserved net bit -> the signal driving it.
+    alias: HashMap<String, String>,
+    /// Net bits driven by some gate or alias, to catch multiple drivers.
+    driven: HashSet<String>,
+    /// Reserved net bit -> hierarchical name, for error messages.
+    names: HashMap<String, String>,
+    depth: usize,
+}
+
+fn bool_sig(b: bool) -> String {
+    if b {
+        "CONST_TRUE_SIG".into()
+    } else {
+        "CONST_FALSE_SIG".into()
+    }
+}
+
+/// Zero-extend or truncate `bus` to `width` bits.
+fn resize(mut bus: Bus, width: usize) -> Bus {
+    bus.resize(width, bool_sig(false));
+    bus
+}
+
+fn bits_needed(value: u64) -> usize {
+    ((u64::BITS - value.leading_zeros()) as usize).max(1)
+}
+
+impl<'a> Elab<'a> {
+    fn drive(&mut self, bit: &str, sig: String) -> Result<()> {
+        if !self.driven.insert(bit.to_string()) {
+            anyhow::bail!("`{}` has multiple drivers", self.names[bit]);
+        }
+        self.alias.insert(bit.to_string(), sig);
+        Ok(())
+    }
+
+    /// Elaborate one instance of `m`, returning its scope so the parent can
+    /// connect its ports.
+    fn module(&mut self, m: &Module, path: &str, overrides: HashMap<String, i64>) -> Result<Scope> {
+        self.depth += 1;
+        if self.depth > 64 {
+            anyhow::bail!("module `{}` instantiates itself", m.name);
+        }
+        let mut scope = Scope {
+            path: path.to_string(),
+            params: HashMap::new(),
+            nets: HashMap::new(),
+        };
+        for (name, value, local) in &m.params {
+            let value = match overrides.get(name) {
+                Some(v) if !local => *v,
+                _ => self.const_eval(value, &scope)?,
+            };
+            scope.params.insert(name.clone(), value);
+        }
+        if let Some(name) = overrides
+            .keys()
+            .find(|n| !m.params.iter().any(|(p, _, l)| p == *n && !l))
+        {
+            anyhow::bail!("module `{}` has no parameter `{}`", m.name, name);
+        }
+        for decl in &m.nets {
+            self.declare(&mut scope, decl)?;
+        }
+        for port in &m.ports {
+            match scope.nets.get(port) {
+                Some(net) if net.dir.is_some() => {}
+                _ => anyhow::bail!("port `{}` of module `{}` has no direction", port, m.name),
+            }
+        }
+        for item in &m.items {
+            self.item(item, &scope)
+                .map_err(|e| anyhow::anyhow!("in module `{}`: {}", m.name, e))?;
+        }
+        self.depth -= 1;
+        Ok(scope)
+    }
+
+    fn declare(&mut self, scope: &mut Scope, decl: &NetDecl) -> Result<()> {
+        let (msb, lsb) = match &decl.range {
+            Some((msb, lsb)) => (self.const_eval(msb, scope)?, self.const_eval(lsb, scope)?),
+            None => (0, 0),
+        };
+        if msb < lsb {
+            anyhow::bail!(
+                "line {}: ascending range [{}:{}] is not supported",
+                decl.line,
+                msb,
+                lsb
+            );
+        }
+        let width = (msb - lsb + 1) as usize;
+        // `output [3:0] y; reg [3:0] y;` declares the same net twice.
+        if let Some(net) = scope.nets.get_mut(&decl.name) {
+            if decl.range.is_some() && net.bits.len() != width {
+                anyhow::bail!(
+                    "line {}: `{}` redeclared with a different width",
+                    decl.line,
+                    decl.name
+                );
+            }
+            if decl.dir.is_some() {
+                if net.dir.is_some() {
+                    anyhow::bail!("line {}: `{}` declared twice", decl.line, decl.name);
+                }
+                net.dir = decl.dir;
+            }
+            return Ok(());
+        }
+        let bits: Bus = (0..width).map(|_| next_id()).collect();
+        for (i, bit) in bits.iter().enumerate() {
+            let name = if decl.range.is_some() {
+                format!("{}{}[{}]", scope.path, decl.name, lsb + i as i64)
+            } else {
+                format!("{}{}", scope.path, decl.name)
+            };
+            self.names.insert(bit.clone(), name);
+        }
+        scope.nets.insert(
+            decl.name.clone(),
+            Net {
+                bits,
+                lsb,
+                dir: decl.dir,
+            },
+        );
+        Ok(())
+    }
+
+    fn item(&mut self, item: &Item, scope: &Scope) -> Result<()> {
+        match item {
+            Item::Assign(lhs, rhs, line) => {
+                let at = |e: anyhow::Error| anyhow::anyhow!("line {}: {}", line, e);
+                let target = self.lvalue(lhs, scope).map_err(at)?;
+                let value = self.expr(rhs, scope, None).map_err(at)?;
+                for (bit, sig) in target.iter().zip(resize(value, target.len())) {
+                    self.drive(bit, sig).map_err(at)?;
+                }
+                Ok(())
+            }
+            Item::Gate(kind, args, line) => {
+                let at = |e: anyhow::Error| anyhow::anyhow!("line {}: {}", line, e);
+                let (out, ins) = args.split_first().unwrap();
+                let target = self.lvalue(out, scope).map_err(at)?;
+                if target.len() != 1 || ins.is_empty() {
+                    return Err(at(anyhow::anyhow!(
+                        "`{}` needs a 1-bit output and inputs",
+                        kind
+                    )));
+                }
+                let mut ins_sig = Vec::new();
+                for e in ins {
+                    let v = self.expr(e, scope, None).map_err(at)?;
+                    if v.len() != 1 {
+                        return Err(at(anyhow::anyhow!("`{}` inputs must be single bits", kind)));
+                    }
+                    ins_sig.push(v[0].clone());
+                }
+                let sig = match kind.as_str() {
+                    "buf" | "not" if ins_sig.len() != 1 => {
+                        return Err(at(anyhow::anyhow!("`{}` takes one input", kind)))
+                    }
+                    "buf" => ins_sig.pop().unwrap(),
+                    "not" => self.ctx.emit("NOT", ins_sig),
+                    _ => {
+                        let joined = self.reduce(kind, ins_sig);
+                        // nand/nor/xnor invert the and/or/xor of their inputs.
+                        if kind.starts_with('n') || kind == "xnor" {
+                            self.ctx.emit("NOT", vec![joined])
+                        } else {
+                            joined
+                        }
+                    }
+                };
+                self.drive(&target[0], sig).map_err(at)
+            }
+            Item::Always(edge, body, line) => self
+                .always(edge.as_ref(), body, scope)
+                .map_err(|e| anyhow::anyhow!("line {}: {}", line, e)),
+            Item::Instance {
+                module,
+                name,
+                params,
+                ports,
+                line,
+            } => self
+                .instance(module, name, params, ports, scope)
+                .map_err(|e| anyhow::anyhow!("line {}: {}", line, e)),
+        }
+    }
+
+    /// Combine single bits with the and/or/xor underlying a gate primitive.
+    fn reduce(&mut self, kind: &str, bits: Vec<String>) -> String {
+        let op = match kind {
+            "and" | "nand" => "AND",
+            "or" | "nor" => "OR",
+            _ => "XOR",
+        };
+        self.ctx
+            .reduce_balanced(bits, |c, x, y| match op {
+                "XOR" => c.xor(x, y),
+                _ => c.emit(op, vec![x, y]),
+            })
+            .unwrap()
+    }
+
+    fn instance(
+        &mut self,
+        module: &str,
+        name: &str,
+        params: &[(Option<String>, Expr)],
+        ports: &[(Option<String>, Option<Expr>)],
+        scope: &Scope,
+    ) -> Result<()> {
+        let Some(&m) = self.modules.get(module) else {
+            anyhow::bail!("unknown module `{}`", module);
+        };
+        let public: Vec<&String> = m.params.iter().filter(|p| !p.2).map(|p| &p.0).collect();
+        let mut overrides = HashMap::new();
+        for (i, (pname, value)) in params.iter().enumerate() {
+            let pname = match pname {
+                Some(n) => n.clone(),
+                None => match public.get(i) {
+                    Some(n) => n.to_string(),
+                    None => anyhow::bail!("too many parameters for module `{}`", module),
+                },
+            };
+            overrides.insert(pname, self.const_eval(value, scope)?);
+        }
+        let entered = self.ctx.enter_block(|_| format!("{}{}", scope.path, name));
+        let child = self.module(m, &format!("{}{}.", scope.path, name), overrides);
+        self.ctx.leave_block(entered);
+        let child = child?;
+        if ports.iter().any(|(n, _)| n.is_none()) && ports.len() > m.ports.len() {
+            anyhow::bail!("too many ports for module `{}`", module);
+        }
+        for (i, (pname, conn)) in ports.iter().enumerate() {
+            let pname = pname.clone().unwrap_or_else(|| m.ports[i].clone());
+            let Some(net) = child.nets.get(&pname).filter(|_| m.ports.contains(&pname)) else {
+                anyhow::bail!("module `{}` has no port `{}`", module, pname);
+            };
+            let Some(conn) = conn else { continue };
+            match net.dir {
+                Some(Dir::Input) => {
+                    let value = resize(self.expr(conn, scope, None)?, net.bits.len());
+                    for (bit, sig) in net.bits.iter().zip(value) {
+                        self.drive(bit, sig)?;
+                    }
+                }
+                _ => {
+                    let target = self.lvalue(conn, scope)?;
+                    let value = resize(net.bits.clone(), target.len());
+                    for (bit, sig) in target.iter().zip(value) {
+                        self.drive(bit, sig)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn always(&mut self, edge: Option<&Edge>, body: &Stmt, scope: &Scope) -> Result<()> {
+        let mut targets = Vec::new();
+        self.targets(body, scope, &mut targets)?;
+        let Some(edge) = edge else {
+            // Combinational: every target must be assigned on every path.
+            let env = self.stmt(body, scope, Env::new(), true)?;
+            for bit in &targets {
+                let Some(sig) = env.get(bit) else {
+                    anyhow::bail!(
+                        "`{}` is not assigned on every path through always @(*), \
+                         which would need a latch",
+                        self.names[bit]
+                    );
+                };
+                self.drive(bit, sig.clone())?;
+            }
+            return Ok(());
+        };
+        let clk = self.expr(&edge.clock, scope, None)?;
+        if clk.len() != 1 {
+            anyhow::bail!("Clock must be a single bit, got {} bits", clk.len());
+        }
+        let clk = if edge.negedge {
+            self.ctx.emit("NOT", clk)
+        } else {
+            clk[0].clone()
+        };
+        // Registers hold their value unless assigned.
+        let hold: Env = targets.iter().map(|b| (b.clone(), b.clone())).collect();
+        let env = self.stmt(body, scope, hold, false)?;
+        for bit in &targets {
+            if !self.driven.insert(bit.clone()) {
+                anyhow::bail!("`{}` has multiple drivers", self.names[bit]);
+            }
+            self.ctx
+                .register(env[bit].clone(), &clk, &None, bit.clone());
+        }
+        Ok(())
+    }
+
+    /// Collect the net bits assigned anywhere in `stmt`, in order.
+    fn targets(&self, stmt: &Stmt, scope: &Scope, out: &mut Vec<String>) -> Result<()> {
+        match stmt {
+            Stmt::Block(body) => {
+                for s in body {
+                    self.targets(s, scope, out)?;
+                }
+            }
+            Stmt::Assign(lhs, _) => {
+                for bit in self.lvalue(lhs, scope)? {
+                    if !out.contains(&bit) {
+                        out.push(bit);
+                    }
+                }
+            }
+            Stmt::If(_, then, other) => {
+                self.targets(then, scope, out)?;
+                if let Some(other) = other {
+                    self.targets(other, scope, out)?;
+                }
+            }
+            Stmt::Case(_, arms, default) => {
+                for (_, s) in arms {
+                    self.targets(s, scope, out)?;
+                }
+                if let Some(d) = default {
+                    self.targets(d, scope, out)?;
+                }
+            }
+            Stmt::Empty => {}
+        }
+        Ok(())
+    }
+
+    /// Execute a procedural statement symbolically. `comb` selects blocking
+    /// reads of values assigned earlier in the block.
+    fn stmt(&mut self, stmt: &Stmt, scope: &Scope, mut env: Env, comb: bool) -> Result<Env> {
+        let read_env = |env: &Env| if comb { Some(env.clone()) } else { None };
+        match stmt {
+            Stmt::Empty => Ok(env),
+            Stmt::Block(body) => {
+                for s in body {
+                    env = self.stmt(s, scope, env, comb)?;
+                }
+                Ok(env)
+            }
+            Stmt::Assign(lhs, rhs) => {
+                let target = self.lvalue(lhs, scope)?;
+                let value = self.expr(rhs, scope, read_env(&env).as_ref())?;
+                let width = target.len();
+                for (bit, sig) in target.into_iter().zip(resize(value, width)) {
+                    env.insert(bit, sig);
+                }
+                Ok(env)
+            }
+            Stmt::If(cond, then, other) => {
+                let c = self.expr(cond, scope, read_env(&env).as_ref())?;
+                let c = self.truthy(c);
+                let t = self.stmt(then, scope, env.clone(), comb)?;
+                let e = match other {
+                    Some(other) => self.stmt(other, scope, env, comb)?,
+                    None => env,
+                };
+                self.merge(c, t, e)
+            }
+            Stmt::Case(subject, arms, default) => {
+                let s = self.expr(subject, scope, read_env(&env).as_ref())?;
+                // Lower to an if/else chain, last arm innermost.
+                let mut result = match default {
+                    Some(d) => self.stmt(d, scope, env.clone(), comb)?,
+                    None => env.clone(),
+                };
+                for (labels, body) in arms.iter().rev() {
+                    let mut hits = Vec::new();
+                    for label in labels {
+                        let l = self.expr(label, scope, read_env(&env).as_ref())?;
+                        let width = s.len().max(l.len());
+                        let ne = self
+                            .ctx
+                            .not_equal(resize(s.clone(), width), resize(l, width))?;
+                        hits.push(self.ctx.emit("NOT", vec![ne]));
+                    }
+                    let hit = self.ctx.or_all(hits);
+                    let taken = self.stmt(body, scope, env.clone(), comb)?;
+                    result = self.merge(hit, taken, result)?;
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    /// Join two branches: bits assigned differently become a MUX on `cond`;
+    /// bits assigned on only one side are dropped.
+    fn merge(&mut self, cond: String, then: Env, other: Env) -> Result<Env> {
+        let mut merged = Env::new();
+        let (mut keys, mut c, mut a) = (Vec::new(), Vec::new(), Vec::new());
+        for (bit, t) in &then {
+            match other.get(bit) {
+                Some(e) if e == t => {
+                    merged.insert(bit.clone(), t.clone());
+                }
+                Some(e) => {
+                    keys.push(bit.clone());
+                    c.push(t.clone());
+                    a.push(e.clone());
+                }
+                None => {}
+            }
+        }
+        if !keys.is_empty() {
+            for (bit, sig) in keys.into_iter().zip(self.ctx.mux(cond, c, a)?) {
+                merged.insert(bit, sig);
+            }
+        }
+        Ok(merged)
+    }
+
+    /// The reserved bits written by an assignment target, LSB first.
+    fn lvalue(&self, e: &Expr, scope: &Scope) -> Result<Bus> {
+        match e {
+            Expr::Ident(name) => Ok(self.net(name, scope)?.bits.clone()),
+            Expr::Index(base, idx) => {
+                let Expr::Ident(name) = &**base else {
+                    anyhow::bail!("invalid assignment target");
+                };
+                let i = self.const_eval(idx, scope)?;
+                Ok(vec![self.select(name, i, i, scope)?.remove(0)])
+            }
+            Expr::Slice(base, hi, lo) => {
+                let Expr::Ident(name) = &**base else {
+                    anyhow::bail!("invalid assignment target");
+                };
+                let (hi, lo) = (self.const_eval(hi, scope)?, self.const_eval(lo, scope)?);
+                self.select(name, hi, lo, scope)
+            }
+            // The first part of a concatenation is the most significant.
+            Expr::Concat(parts) => {
+                let mut bits = Vec::new();
+                for p in parts.iter().rev() {
+                    bits.extend(self.lvalue(p, scope)?);
+                }
+                Ok(bits)
+            }
+            _ => anyhow::bail!("invalid assignment target"),
+        }
+    }
+
+    fn net<'s>(&self, name: &str, scope: &'s Scope) -> Result<&'s Net> {
+        match scope.nets.get(name) {
+            Some(net) => Ok(net),
+            None => anyhow::bail!("`{}` is not declared", name),
+        }
+    }
+
+    /// Bits `hi` down to `lo` of a net, in declared bit numbering.
+    fn select(&self, name: &str, hi: i64, lo: i64, scope: &Scope) -> Result<Bus> {
+        let net = self.net(name, scope)?;
+        let top = net.lsb + net.bits.len() as i64 - 1;
+        if hi < lo || lo < net.lsb || hi > top {
+            anyhow::bail!(
+                "[{}:{}] is out of range for `{}` [{}:{}]",
+                hi,
+                lo,
+                name,
+                top,
+                net.lsb
+            );
+        }
+        Ok(net.bits[(lo - net.lsb) as usize..=(hi - net.lsb) as usize].to_vec())
+    }
+
+    fn const_eval(&self, e: &Expr, scope: &Scope) -> Result<i64> {
+        match e {
+            Expr::Number { value, .. } => Ok(*value as i64),
+            Expr::Ident(name) => match scope.params.get(name) {
+                Some(v) => Ok(*v),
+                None => anyhow::bail!("`{}` is not a constant", name),
+            },
+            Expr::Unary("-", a) => Ok(-self.const_eval(a, scope)?),
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (self.const_eval(l, scope)?, self.const_eval(r, scope)?);
+                match *op {
+                    "+" => Ok(l + r),
+                    "-" => Ok(l - r),
+                    "*" => Ok(l * r),
+                    "/" | "%" if r == 0 => anyhow::bail!("Division by zero"),
+                    "/" => Ok(l / r),
+                    "%" => Ok(l % r),
+                    "<<" => Ok(l << r),
+                    ">>" => Ok(l >> r),
+                    op => anyhow::bail!("operator `{}` is not supported in constants", op),
+                }
+            }
+            _ => anyhow::bail!("expected a constant expression"),
+        }
+    }
+
+    /// Reduce a bus to a single truth value (non-zero).
+    fn truthy(&mut self, bus: Bus) -> String {
+        if bus.len() == 1 {
+            return bus[0].clone();
+        }
+        self.ctx.or_all(bus)
+    }
+
+    /// Lower an expression. `env` holds values assigned earlier in an
+    /// `always @(*)` block, which reads must see instead of the net.
+    fn expr(&mut self, e: &Expr, scope: &Scope, env: Option<&Env>) -> Result<Bus> {
+        match e {
+            Expr::Number { width, value } => Ok(Ctx::const_bus(
+                *value as usize,
+                width.unwrap_or_else(|| bits_needed(*value)),
+            )),
+            Expr::Ident(name) => {
+                if let Some(v) = scope.params.get(name) {
+                    let v = *v as u64;
+                    return Ok(Ctx::const_bus(v as usize, bits_needed(v)));
+                }
+                let bits = self.net(name, scope)?.bits.clone();
+                self.read(bits, env)
+            }
+            Expr::Index(base, idx) => {
+                let i = self
+                    .const_eval(idx, scope)
+                    .map_err(|_| anyhow::anyhow!("bit select index must be a constant"))?;
+                self.slice(base, i, i, scope, env)
+            }
+            Expr::Slice(base, hi, lo) => {
+                let (hi, lo) = (self.const_eval(hi, scope)?, self.const_eval(lo, scope)?);
+                self.slice(base, hi, lo, scope, env)
+            }
+            Expr::Concat(parts) => {
+                let mut bits = Vec::new();
+                for p in parts.iter().rev() {
+                    bits.extend(self.expr(p, scope, env)?);
+                }
+                Ok(bits)
+            }
+            Expr::Repeat(count, parts) => {
+                let n = self.const_eval(count, scope)?;
+                if n < 1 {
+                    anyhow::bail!("replication count must be positive, got {}", n);
+                }
+                let once = self.expr(&Expr::Concat(parts.clone()), scope, env)?;
+                Ok((0..n).flat_map(|_| once.clone()).collect())
+            }
+            Expr::Unary(op, arg) => {
+                let a = self.expr(arg, scope, env)?;
+                self.unary(op, a)
+            }
+            Expr::Binary(op, l, r) => {
+                if ["*", "/", "%"].contains(op) {
+                    let v = self.const_eval(e, scope).map_err(|_| {
+                        anyhow::anyhow!("operator `{}` is only supported on constants", op)
+                    })?;
+                    if v < 0 {
+                        anyhow::bail!("negative constant {}", v);
+                    }
+                    return Ok(Ctx::const_bus(v as usize, bits_needed(v as u64)));
+                }
+                if ["<<", ">>"].contains(op) {
+                    let a = self.expr(l, scope, env)?;
+                    let n = self
+                        .const_eval(r, scope)
+                        .map_err(|_| anyhow::anyhow!("shift amount must be a constant"))?
+                        as usize;
+                    let width = a.len();
+                    let shifted: Bus = if *op == "<<" {
+                        std::iter::repeat_n(bool_sig(false), n).chain(a).collect()
+                    } else {
+                        a.into_iter().skip(n).collect()
+                    };
+                    return Ok(resize(shifted, width));
+                }
+                let a = self.expr(l, scope, env)?;
+                let b = self.expr(r, scope, env)?;
+                self.binary(op, a, b)
+            }
+            Expr::Cond(cond, then, other) => {
+                let c = self.expr(cond, scope, env)?;
+                let c = self.truthy(c);
+                let t = self.expr(then, scope, env)?;
+                let o = self.expr(other, scope, env)?;
+                let width = t.len().max(o.len());
+                self.ctx.mux(c, resize(t, width), resize(o, width))
+            }
+        }
+    }
+
+    fn slice(
+        &mut self,
+        base: &Expr,
+        hi: i64,
+        lo: i64,
+        scope: &Scope,
+        env: Option<&Env>,
+    ) -> Result<Bus> {
+        if let Expr::Ident(name) = base {
+            if !scope.params.contains_key(name) {
+                let bits = self.select(name, hi, lo, scope)?;
+                return self.read(bits, env);
+            }
+        }
+        let bus = self.expr(base, scope, env)?;
+        if hi < lo || lo < 0 || hi as usize >= bus.len() {
+            anyhow::bail!("[{}:{}] is out of range for {} bits", hi, lo, bus.len());
+        }
+        Ok(bus[lo as usize..=hi as usize].to_vec())
+    }
+
+    /// Read net bits, seeing earlier blocking assignments in `env`.
+    fn read(&self, bits: Bus, env: Option<&Env>) -> Result<Bus> {
+        let Some(env) = env else {
+            return Ok(bits);
+        };
+        Ok(bits
+            .into_iter()
+            .map(|b| env.get(&b).cloned().unwrap_or(b))
+            .collect())
+    }
+
+    fn unary(&mut self, op: &str, a: Bus) -> Result<Bus> {
+        Ok(match op {
+            "~" => a
+                .into_iter()
+                .map(|b| self.ctx.emit("NOT", vec![b]))
+                .collect(),
+            "!" => {
+                let t = self.truthy(a);
+                vec![self.ctx.emit("NOT", vec![t])]
+            }
+            "&" | "|" | "^" => vec![self.reduce(&op.replace('&', "and").replace('|', "or"), a)],
+            "~&" | "~|" | "~^" | "^~" => {
+                let kind = match op {
+                    "~&" => "and",
+                    "~|" => "or",
+                    _ => "xor",
+                };
+                let r = self.reduce(kind, a);
+                vec![self.ctx.emit("NOT", vec![r])]
+            }
+            // Two's complement: ~a + 1, at the operand's width.
+            "-" => {
+                let width = a.len();
+                let inv = self.unary("~", a)?;
+                let sum = self.ctx.add(inv, vec![bool_sig(true)]);
+                resize(sum, width)
+            }
+            _ => anyhow::bail!("unsupported operator `{}`", op),
+        })
+    }
+
+    fn binary(&mut self, op: &str, a: Bus, b: Bus) -> Result<Bus> {
+        let width = a.len().max(b.len());
+        let (a, b) = (resize(a, width), resize(b, width));
+        Ok(match op {
+            "&" => self.ctx.bitwise("AND", a, b)?,
+            "|" => self.ctx.bitwise("OR", a, b)?,
+            "^" => self.ctx.bitwise("XOR", a, b)?,
+            "~&" | "~|" | "~^" | "^~" => {
+                let kind = match op {
+                    "~&" => "AND",
+                    "~|" => "OR",
+                    _ => "XOR",
+                };
+                let r = self.ctx.bitwise(kind, a, b)?;
+                self.unary("~", r)?
+            }
+            "&&" | "||" => {
+                let (a, b) = (self.truthy(a), self.truthy(b));
+                let kind = if op == "&&" { "AND" } else { "OR" };
+                vec![self.ctx.emit(kind, vec![a, b])]
+            }
+            "==" | "!=" => {
+                let ne = self.ctx.not_equal(a, b)?;
+                if op == "!=" {
+                    vec![ne]
+                } else {
+                    vec![self.ctx.emit("NOT", vec![ne])]
+                }
+            }
+            // The sum keeps its carry; assignments truncate to the target.
+            "+" => self.ctx.add(a, b),
+            "-" => resize(self.subtract(a, b), width),
+            "<" | "<=" | ">" | ">=" => {
+                // a - b sets bit `width` exactly when a >= b.
+                let (x, y) = if op == "<" || op == ">=" {
+                    (a, b)
+                } else {
+                    (b, a)
+                };
+                let geq = self.subtract(x, y)[width].clone();
+                if op == ">=" || op == "<=" {
+                    vec![geq]
+                } else {
+                    vec![self.ctx.emit("NOT", vec![geq])]
+                }
+            }
+            _ => anyhow::bail!("unsupported operator `{}`", op),
+        })
+    }
+
+    /// a + ~b + 1 over equal-width operands, one bit wider than them.
+    fn subtract(&mut self, a: Bus, b: Bus) -> Bus {
+        let width = a.len();
+        let inv = b
+            .into_iter()
+            .map(|x| self.ctx.emit("NOT", vec![x]))
+            .collect();
+        let partial = self.ctx.add(a, inv);
+        resize(self.ctx.add(partial, vec![bool_sig(true)]), width + 1)
+    }
+}
+
+/// Follow aliases from a reserved net bit to the signal driving it.
+fn resolve(
+    alias: &HashMap<String, String>,
+    names: &HashMap<String, String>,
+    sig: &str,
+) -> Result<String> {
+    let mut cur = sig.to_string();
+    let mut seen = HashSet::new();
+    while let Some(next) = alias.get(&cur) {
+        if !seen.insert(cur.clone()) {
+            anyhow::bail!("`{}` is assigned from itself", names[sig]);
+        }
+        cur = next.clone();
+    }
+    Ok(cur)
+}
+
+/// Parse Verilog source and lower its top-level module into a circuit. The
+/// top module is the one no other module instantiates; its input ports
+/// become levers (`a[0]`, `a[1]`, ... for vectors).
+pub fn compile(src: &str) -> Result<Circuit> {
+    let modules = Parser {
+        toks: lex(src)?,
+        pos: 0,
+    }
+    .modules()?;
+    let mut instantiated = HashSet::new();
+    let mut by_name = HashMap::new();
+    for m in &modules {
+        if by_name.insert(m.name.as_str(), m).is_some() {
+            anyhow::bail!("module `{}` is defined twice", m.name);
+        }
+        for item in &m.items {
+            if let Item::Instance { module, .. } = item {
+                instantiated.insert(module.as_str());
+            }
+        }
+    }
+    let tops: Vec<&Module> = modules
+        .iter()
+        .filter(|m| !instantiated.contains(m.name.as_str()))
+        .collect();
+    let top = match tops.as_slice() {
+        [top] => *top,
+        [] => anyhow::bail!("no top-level module found"),
+        _ => anyhow::bail!(
+            "multiple top-level modules: {}",
+            tops.iter()
+                .map(|m| m.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+
+    let mut elab = Elab {
+        ctx: Ctx::new(),
+        modules: by_name,
+        alias: HashMap::new(),
+        driven: HashSet::new(),
+        names: HashMap::new(),
+        depth: 0,
+    };
+    let scope = elab.module(top, "", HashMap::new())?;
+
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for port in &top.ports {
+        let net = &scope.nets[port];
+        for bit in &net.bits {
+            let name = elab.names[bit].clone();
+            if net.dir == Some(Dir::Input) {
+                let sig = elab.ctx.input(&name);
+                elab.drive(bit, sig)?;
+                inputs.push(name);
+            } else {
+                outputs.push((elab.ctx.emit("BUF", vec![bit.clone()]), name));
+            }
+        }
+    }
+
+    // Replace reserved net bits with the signals that drive them.
+    let Elab {
+        ctx, alias, names, ..
+    } = elab;
+    let mut circuit = ctx.finish(inputs, outputs);
+    let produced: HashSet<String> = circuit.gates.iter().map(|g| g.output.clone()).collect();
+    for g in &mut circuit.gates {
+        for i in &mut g.inputs {
+            let sig = resolve(&alias, &names, i)?;
+            if !produced.contains(&sig) {
+                let name = names.get(&sig).unwrap_or(&sig);
+                anyhow::bail!("`{}` is used but never driven", name);
+            }
+            *i = sig;
+        }
+    }
+    Ok(circuit)
+}